@@ -15,6 +15,11 @@ use std::hash::{Hash, Hasher};
 
 pub type Random = XorShiftRng;
 
+/// Version of the world generator. Has to be increased whenever the same
+/// seed generates different chunks, so that saved worlds don't get edges
+/// between old and new chunks.
+pub const GENERATOR_VERSION: u32 = 1;
+
 /// Creates a seeded RNG for use in world gen.
 ///
 /// This function takes 3 seed parameters which are hashed and mixed together.
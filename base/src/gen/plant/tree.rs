@@ -71,6 +71,18 @@ pub enum PlantType {
     Flower,
 }
 
+/// All plant types, in the order the plant list of a world has them.
+pub const PLANT_TYPES: &[PlantType] = &[
+    PlantType::WitheredTree,
+    PlantType::Shrub,
+    PlantType::Cactus,
+    PlantType::JungleTree,
+    PlantType::ClumpOfGrass,
+    PlantType::Conifer,
+    PlantType::OakTree,
+    PlantType::Flower,
+];
+
 impl PlantType {
     fn preset(&self) -> Preset {
         match *self {
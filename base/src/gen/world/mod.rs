@@ -1,7 +1,7 @@
 //! Procedurally generating the game world.
 pub mod biome;
 
-use gen::plant::tree::PlantType;
+use gen::plant::tree::{PlantType, PLANT_TYPES};
 use gen::world::biome::Biome;
 use gen::{seeded_rng, PlantGenerator};
 use noise::{open_simplex2, open_simplex3, PermutationTable};
@@ -13,6 +13,29 @@ use world::{GroundMaterial, PillarSection, Prop, CHUNK_SIZE, PILLAR_STEP_HEIGHT}
 /// Land "fill noise" scaling in x, y, and z direction.
 const LAND_NOISE_SCALE: (f32, f32, f32) = (0.03, 0.03, 0.05);
 
+/// Number of differently generated plants of each `PlantType` in a world.
+pub const PLANT_INSTANCES: usize = 5;
+
+/// Length of the plant list of a world. The plant indices of all props are
+/// below.
+pub const PLANT_COUNT: usize = PLANT_TYPES.len() * PLANT_INSTANCES;
+
+/// Returns the index of the given instance of a plant type in the plant list
+/// of a world.
+pub fn plant_index(plant_type: PlantType, instance: usize) -> usize {
+    let type_index = match plant_type {
+        PlantType::WitheredTree => 0,
+        PlantType::Shrub => 1,
+        PlantType::Cactus => 2,
+        PlantType::JungleTree => 3,
+        PlantType::ClumpOfGrass => 4,
+        PlantType::Conifer => 5,
+        PlantType::OakTree => 6,
+        PlantType::Flower => 7,
+    };
+    PLANT_TYPES.len() * instance + type_index
+}
+
 /// Main type to generate the game world. Implements the `ChunkProvider` trait
 /// (TODO, see #8).
 pub struct WorldGenerator {
@@ -191,19 +214,8 @@ impl ChunkProvider for WorldGenerator {
                 let tmp = current_biome.plant_distribution();
                 let plant_type = rng.choose(tmp).unwrap();
 
-                let type_index = match *plant_type {
-                    PlantType::WitheredTree => 0,
-                    PlantType::Shrub => 1,
-                    PlantType::Cactus => 2,
-                    PlantType::JungleTree => 3,
-                    PlantType::ClumpOfGrass => 4,
-                    PlantType::Conifer => 5,
-                    PlantType::OakTree => 6,
-                    PlantType::Flower => 7,
-                };
-
-                let plant_instance = rng.gen_range(0, 5);
-                let plant_index = 8 * plant_instance + type_index;
+                let plant_instance = rng.gen_range(0, PLANT_INSTANCES as i32);
+                let plant_index = plant_index(*plant_type, plant_instance as usize);
 
                 //     // put the tree at the highest position
                 let height = match sections.last() {
@@ -215,7 +227,7 @@ impl ChunkProvider for WorldGenerator {
                     baseline: height,
                     // for now, you can here set which plants should be placed
                     // all over the world
-                    plant_index: plant_index as usize,
+                    plant_index: plant_index,
                 });
             }
 
@@ -231,15 +243,10 @@ impl ChunkProvider for WorldGenerator {
         let mut rng = super::seeded_rng(self.seed, "TREE", 42);
 
         let mut vec = Vec::new();
-        for _ in 0..5 {
-            vec.push(PlantGenerator::new(PlantType::WitheredTree).generate(&mut rng));
-            vec.push(PlantGenerator::new(PlantType::Shrub).generate(&mut rng));
-            vec.push(PlantGenerator::new(PlantType::Cactus).generate(&mut rng));
-            vec.push(PlantGenerator::new(PlantType::JungleTree).generate(&mut rng));
-            vec.push(PlantGenerator::new(PlantType::ClumpOfGrass).generate(&mut rng));
-            vec.push(PlantGenerator::new(PlantType::Conifer).generate(&mut rng));
-            vec.push(PlantGenerator::new(PlantType::OakTree).generate(&mut rng));
-            vec.push(PlantGenerator::new(PlantType::Flower).generate(&mut rng));
+        for _ in 0..PLANT_INSTANCES {
+            for &plant_type in PLANT_TYPES {
+                vec.push(PlantGenerator::new(plant_type).generate(&mut rng));
+            }
         }
         vec
     }
@@ -3,9 +3,11 @@
 //! - the `math` module reexports everything from the `cgmath` crate and
 //!   defines a few own type
 //! - the world module is all about saving and managing the game world
+//! - the `net` module defines the protocol spoken between client and server
 
 #![allow(illegal_floating_point_literal_pattern)]
 
+extern crate flate2;
 pub extern crate noise;
 extern crate num_traits;
 pub extern crate rand;
@@ -14,5 +16,9 @@ extern crate log;
 
 pub mod gen;
 pub mod math;
+pub mod movement;
+pub mod net;
 pub mod prop;
+pub mod time;
+pub mod weather;
 pub mod world;
@@ -0,0 +1,558 @@
+//! Movement of players through the world.
+//!
+//! The server simulates every player from the inputs their client sends.
+//! Clients run the same code to show the result of their inputs immediately.
+//! `Prediction` keeps the inputs the server didn't confirm yet, and replays
+//! them on top of the server's state whenever the server disagrees with the
+//! prediction.
+
+use math::*;
+use std::collections::VecDeque;
+use std::f32;
+use world::{PillarIndex, World};
+
+const GRAVITY: f32 = 9.81;
+
+/// Height of the eyes above the ground.
+pub const EYE_HEIGHT: f32 = 1.75;
+
+/// Longer inputs are shortened to this many seconds, so that nobody can skip
+/// through walls with a single long step.
+pub const MAX_INPUT_DELTA: f32 = 0.1;
+
+const FORWARD_ACCELERATION: f32 = 60.5;
+const BACKWARD_ACCELERATION: f32 = 50.0;
+const SIDEWAYS_ACCELERATION: f32 = 50.0;
+const JUMP_VELOCITY: f32 = 0.7;
+
+/// Walking players climb steps up to this height without jumping.
+const STEP_SIZE: f32 = 1.0;
+
+/// Speeds of flying players in units per second.
+const FLY_SPEED: f32 = 12.0;
+const FLY_SPRINT_SPEED: f32 = 60.0;
+
+/// Two bodies closer than this are considered equal by `Prediction`.
+const PREDICTION_TOLERANCE: f32 = 0.001;
+
+/// What the player wants to do, as read from the keyboard and mouse.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Controls {
+    /// Between -1 (backward) and 1 (forward).
+    pub forward: f32,
+    /// Between -1 (left) and 1 (right).
+    pub right: f32,
+    /// Between -1 (down) and 1 (up). Only used while flying.
+    pub up: f32,
+    pub sprint: bool,
+    pub jump: bool,
+    /// Flying players ignore gravity and collisions.
+    pub fly: bool,
+    /// Orientation of the player, like in `Camera`.
+    pub theta: f32,
+    pub phi: f32,
+}
+
+impl Controls {
+    /// Clamps all values to their valid range.
+    pub fn sanitize(&mut self) {
+        fn clamp(value: f32, min: f32, max: f32) -> f32 {
+            if value.is_nan() {
+                0.0
+            } else {
+                value.max(min).min(max)
+            }
+        }
+
+        self.forward = clamp(self.forward, -1.0, 1.0);
+        self.right = clamp(self.right, -1.0, 1.0);
+        self.up = clamp(self.up, -1.0, 1.0);
+        self.theta = clamp(self.theta, 0.0, f32::consts::PI);
+        if !self.phi.is_finite() {
+            self.phi = 0.0;
+        }
+    }
+}
+
+/// The controls of one frame, numbered in the order they were made.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MoveInput {
+    /// Sequence number, increasing by one with every input.
+    pub seq: u32,
+    /// Length of the frame in seconds.
+    pub delta: f32,
+    pub controls: Controls,
+}
+
+/// The physical state of a player.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Body {
+    /// Position of the eyes.
+    pub position: Point3f,
+    /// Movement per step: `x` is forward, `y` is right and `z` is up.
+    pub velocity: Vector3f,
+    /// Time factors of the forward and sideways acceleration. They grow with
+    /// every step the player accelerates.
+    pub acceleration_timer: [f32; 2],
+}
+
+impl Body {
+    /// A resting body at the given position.
+    pub fn new(position: Point3f) -> Self {
+        Body {
+            position: position,
+            velocity: Vector3f::new(0.0, 0.0, 0.0),
+            acceleration_timer: [1.0, 1.0],
+        }
+    }
+
+    fn is_close_to(&self, other: &Body) -> bool {
+        (self.position - other.position).magnitude() < PREDICTION_TOLERANCE
+            && (self.velocity - other.velocity).magnitude() < PREDICTION_TOLERANCE
+            && self.acceleration_timer == other.acceleration_timer
+    }
+}
+
+/// Moves the body according to the input.
+pub fn step(body: &mut Body, input: &MoveInput, world: &World) {
+    let mut controls = input.controls;
+    controls.sanitize();
+    let delta = if input.delta > 0.0 {
+        input.delta.min(MAX_INPUT_DELTA)
+    } else {
+        return;
+    };
+
+    if controls.fly {
+        fly(body, &controls, delta);
+    } else {
+        walk(body, &controls, delta, world);
+    }
+}
+
+/// Moves the body freely.
+fn fly(body: &mut Body, controls: &Controls, delta: f32) {
+    let speed = if controls.sprint {
+        FLY_SPRINT_SPEED
+    } else {
+        FLY_SPEED
+    };
+    let (forward, right) = directions(controls.phi);
+    let horizontal = forward * controls.forward + right * controls.right;
+
+    body.position += Vector3f::new(horizontal.x, horizontal.y, controls.up) * speed * delta;
+    // Don't keep any momentum when landing
+    *body = Body::new(body.position);
+}
+
+/// Lets the body walk over the pillars of the world.
+fn walk(body: &mut Body, controls: &Controls, delta: f32, world: &World) {
+    // Current pillar floor (`height`) and the ceiling (`above`)
+    let (floor, above) = ground_at(world, body.position, Vector2f::new(0.0, 0.0));
+    let height = floor + EYE_HEIGHT;
+
+    if controls.jump && body.velocity.z == 0.0 {
+        body.velocity.z = JUMP_VELOCITY;
+    }
+
+    let acceleration_x = if controls.forward > 0.0 {
+        controls.forward * FORWARD_ACCELERATION
+    } else {
+        controls.forward * BACKWARD_ACCELERATION
+    };
+    let acceleration_y = controls.right * SIDEWAYS_ACCELERATION;
+    let sprint = if controls.sprint { 2.0 } else { 1.0 };
+
+    // Move forward or backward with the acceleration and delta.
+    // (1.0 - (-(timer * delta)).exp()) goes from 0 to 1, so the player has a
+    // maximum velocity.
+    let timer = &mut body.acceleration_timer;
+    let velocity = &mut body.velocity;
+    if acceleration_x != 0.0 {
+        velocity.x = acceleration_x * delta * delta * sprint * (1.0 - (-(timer[0] * delta)).exp());
+    } else {
+        slow_down(&mut velocity.x);
+    }
+    if timer[0] != 100.0 {
+        timer[0] += 1.0;
+    }
+    if acceleration_x == 0.0 {
+        timer[0] = 1.0;
+    }
+
+    // Move left or right
+    if acceleration_y != 0.0 {
+        velocity.y = acceleration_y * delta * delta * (1.0 - (-(timer[1] * delta)).exp());
+    } else {
+        slow_down(&mut velocity.y);
+    }
+    if timer[1] != 100.0 {
+        timer[1] += 1.0;
+    }
+    if acceleration_y == 0.0 {
+        timer[1] = 1.0;
+    } else {
+        slow_down(&mut velocity.y);
+    }
+
+    let position = &mut body.position;
+
+    // Jumping
+    if velocity.z != 0.0 {
+        velocity.z += (-delta * GRAVITY) * 0.2;
+
+        if position.z + velocity.z > above {
+            velocity.z = 0.0;
+        } else {
+            position.z += velocity.z;
+        }
+        // Needed: Update to reflect multiple level pillars
+        if position.z + velocity.z < height {
+            velocity.z = 0.0;
+        }
+    }
+
+    // Fall down if the player is higher than the pillar below them
+    if position.z > height && velocity.z == 0.0 {
+        velocity.z += (-delta * GRAVITY) / 16.0;
+        if position.z + velocity.z < height {
+            velocity.z = 0.0;
+        }
+        position.z -= velocity.z;
+    }
+
+    // The directions to the six pillars around the player, starting with the
+    // one in front of them and going clockwise
+    let angle = 60.0f32.to_radians();
+    let mut dirs = [Vector2f::new(controls.phi.cos(), controls.phi.sin()); 6];
+    for i in 1..6 {
+        let prev = dirs[i - 1];
+        dirs[i] = Vector2f::new(
+            prev.x * angle.cos() + prev.y * angle.sin(),
+            prev.x * (-(angle.sin())) + prev.y * angle.cos(),
+        );
+    }
+
+    // Floor height and free space above it for every pillar around the player
+    let pillars: Vec<_> = dirs
+        .iter()
+        .map(|&dir| {
+            let (floor, above) = ground_at(world, *position, dir);
+            (floor, above - floor)
+        })
+        .collect();
+    let floor_near = |dir: Vector2f, x: f32, y: f32| {
+        ground_at(world, *position, Vector2f::new(dir.x + x, dir.y + y)).0
+    };
+    let too_high = |floor: f32| floor > height + STEP_SIZE;
+
+    // Collision detection
+    // Moving forward: compare the front pillar and the two side pillars
+    if (velocity.x > 0.001
+        && (too_high(floor_near(dirs[0], -0.2, -0.1))
+            || too_high(floor_near(dirs[0], 0.2, 0.1))
+            || too_high(pillars[0].0)))
+        || pillars[0].1 < 3.0
+    {
+        if (velocity.y > 0.001 && too_high(pillars[1].0)) || pillars[1].1 < 3.0 {
+            velocity.x = 0.0;
+            velocity.y = 0.0;
+        }
+        if (velocity.y < -0.001 && too_high(pillars[5].0)) || pillars[5].1 < 3.0 {
+            velocity.x = 0.0;
+            velocity.y = 0.0;
+        } else {
+            velocity.x = 0.0;
+        }
+    }
+
+    // Moving right: compare the pillars to the right
+    if (velocity.y > 0.001 && (too_high(pillars[1].0) || too_high(pillars[2].0)))
+        || (pillars[1].1 < 3.0 || pillars[2].1 < 3.0)
+    {
+        velocity.y = 0.0;
+    }
+
+    // Moving backward: compare the pillar behind and the two side pillars
+    if (velocity.x < -0.001
+        && (too_high(floor_near(dirs[3], -0.2, -0.1))
+            || too_high(floor_near(dirs[3], 0.2, 0.1))
+            || too_high(pillars[3].0)))
+        || pillars[3].1 < 3.0
+    {
+        if (velocity.y > 0.001 && too_high(pillars[2].0)) || pillars[2].1 < 3.0 {
+            velocity.x = 0.0;
+            velocity.y = 0.0;
+        }
+        if (velocity.y < -0.001 && too_high(pillars[4].0)) || pillars[4].1 < 3.0 {
+            velocity.x = 0.0;
+            velocity.y = 0.0;
+        } else {
+            velocity.x = 0.0;
+        }
+    }
+
+    // Moving left: compare the pillars to the left
+    if (velocity.y < -0.001 && (too_high(pillars[4].0) || too_high(pillars[5].0)))
+        || (pillars[4].1 < 3.0 || pillars[5].1 < 3.0)
+    {
+        velocity.y = 0.0;
+    }
+
+    if position.z < height && velocity.z == 0.0 && height - position.z < 3.0 {
+        position.z = height;
+    }
+
+    let (forward, right) = directions(controls.phi);
+    *position += (forward * velocity.x + right * velocity.y).extend(0.0);
+}
+
+/// Slows down a movement without acceleration until it stops.
+fn slow_down(velocity: &mut f32) {
+    if *velocity > 0.0001 || *velocity < -0.0001 {
+        *velocity /= 1.4;
+    } else {
+        *velocity = 0.0;
+    }
+}
+
+/// Returns the horizontal unit vectors pointing forward and to the right of
+/// a player looking in direction `phi`.
+fn directions(phi: f32) -> (Vector2f, Vector2f) {
+    (
+        Vector2f::new(phi.cos(), phi.sin()),
+        Vector2f::new(phi.sin(), -phi.cos()),
+    )
+}
+
+/// Returns the floor below and the ceiling above the given position, moved
+/// horizontally by `offset`. Both are 0 if the pillar is not loaded.
+fn ground_at(world: &World, pos: Point3f, offset: Vector2f) -> (f32, f32) {
+    let real_pos = Point2f::new(pos.x + offset.x, pos.y + offset.y);
+    let sections = match world.pillar_at(PillarIndex(AxialPoint::from_real(real_pos))) {
+        Some(pillar) => pillar.sections(),
+        None => return (0.0, 0.0),
+    };
+
+    for (i, section) in sections.iter().enumerate() {
+        match sections.get(i + 1) {
+            Some(next) => {
+                if section.top.to_real() < pos.z && pos.z < next.bottom.to_real() {
+                    return (section.top.to_real(), next.bottom.to_real());
+                }
+            }
+            None => return (section.top.to_real(), f32::INFINITY),
+        }
+    }
+    (0.0, 0.0)
+}
+
+/// Predicts the movement of the local player until the server confirms it.
+#[derive(Debug)]
+pub struct Prediction {
+    body: Body,
+    next_seq: u32,
+    /// Inputs the server didn't confirm yet, each with the body we predicted
+    /// for after it.
+    pending: VecDeque<(MoveInput, Body)>,
+    /// Number of times the server disagreed with us.
+    corrections: u32,
+}
+
+impl Prediction {
+    pub fn new(body: Body) -> Self {
+        Prediction {
+            body: body,
+            next_seq: 1,
+            pending: VecDeque::new(),
+            corrections: 0,
+        }
+    }
+
+    /// Returns the predicted body.
+    pub fn body(&self) -> &Body {
+        &self.body
+    }
+
+    pub fn corrections(&self) -> u32 {
+        self.corrections
+    }
+
+    /// Applies the controls of a frame and returns the input that has to be
+    /// sent to the server.
+    pub fn predict(&mut self, controls: Controls, delta: f32, world: &World) -> MoveInput {
+        let input = MoveInput {
+            seq: self.next_seq,
+            delta: delta,
+            controls: controls,
+        };
+        self.next_seq = self.next_seq.wrapping_add(1);
+
+        step(&mut self.body, &input, world);
+        self.pending.push_back((input, self.body));
+        input
+    }
+
+    /// Handles the state the server calculated after the input with the
+    /// given sequence number. If it differs from the prediction, all
+    /// following inputs are replayed starting from the server's state.
+    /// Returns whether the prediction was corrected.
+    pub fn reconcile(&mut self, seq: u32, server_body: Body, world: &World) -> bool {
+        let mut predicted = None;
+        while self
+            .pending
+            .front()
+            .map_or(false, |&(input, _)| input.seq <= seq)
+        {
+            predicted = self.pending.pop_front().map(|(_, body)| body);
+        }
+
+        let correct = match predicted {
+            Some(body) => body.is_close_to(&server_body),
+            // We don't know that input (anymore), so only the current
+            // state can be checked
+            None => self.pending.is_empty() && self.body.is_close_to(&server_body),
+        };
+        if correct {
+            return false;
+        }
+
+        self.corrections += 1;
+        self.body = server_body;
+        for &mut (ref input, ref mut body) in &mut self.pending {
+            step(&mut self.body, input, world);
+            *body = self.body;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+fn test_world(wall: bool) -> World {
+    use gen::world::biome::Biome;
+    use world::{Chunk, ChunkIndex, GroundMaterial, HeightType, HexPillar, PillarSection};
+
+    let index = ChunkIndex(AxialPoint::new(0, 0));
+    let chunk = Chunk::with_pillars(index, |pos| {
+        let top = if wall && pos.to_real().x > 8.0 {
+            40
+        } else {
+            10
+        };
+        let section = PillarSection::new(
+            GroundMaterial::Grass,
+            HeightType::from_units(0),
+            HeightType::from_units(top),
+        );
+        HexPillar::new(vec![section], vec![], Biome::GrassLand)
+    });
+
+    let mut world = World::empty();
+    world.add_chunk(index, chunk).unwrap();
+    world
+}
+
+#[test]
+fn walls_stop_walking_players() {
+    let world = test_world(true);
+    let mut body = Body::new(Point3f::new(3.0, 3.0, 5.0 + EYE_HEIGHT));
+    let controls = Controls {
+        forward: 1.0,
+        theta: 1.5,
+        ..Controls::default()
+    };
+
+    for seq in 0..1000 {
+        let input = MoveInput {
+            seq: seq,
+            delta: 0.016,
+            controls: controls,
+        };
+        step(&mut body, &input, &world);
+    }
+    assert!(body.position.x > 5.0 && body.position.x < 8.0);
+    assert_eq!(body.position.z, 5.0 + EYE_HEIGHT);
+}
+
+#[test]
+fn predictions_converge_with_latency_and_jitter() {
+    use rand::{Rng, SeedableRng, XorShiftRng};
+
+    /// Messages arrive in order after 80ms plus up to 60ms jitter, like over
+    /// TCP.
+    struct Channel<T> {
+        queue: VecDeque<(u32, T)>,
+        rng: XorShiftRng,
+    }
+
+    impl<T> Channel<T> {
+        fn send(&mut self, now: u32, msg: T) {
+            let mut arrival = now + 80 + self.rng.gen_range(0, 60);
+            if let Some(&(last, _)) = self.queue.back() {
+                arrival = arrival.max(last);
+            }
+            self.queue.push_back((arrival, msg));
+        }
+
+        fn recv(&mut self, now: u32) -> Option<T> {
+            match self.queue.front() {
+                Some(&(arrival, _)) if arrival <= now => self.queue.pop_front().map(|(_, m)| m),
+                _ => None,
+            }
+        }
+    }
+
+    let server_world = test_world(true);
+    // The client didn't receive the chunk with the wall yet
+    let mut client_world = test_world(false);
+
+    let start = Body::new(Point3f::new(3.0, 3.0, 5.0 + EYE_HEIGHT));
+    let mut server_body = start;
+    let mut prediction = Prediction::new(start);
+    let mut to_server = Channel {
+        queue: VecDeque::new(),
+        rng: XorShiftRng::from_seed([1, 2, 3, 4]),
+    };
+    let mut to_client = Channel {
+        queue: VecDeque::new(),
+        rng: XorShiftRng::from_seed([5, 6, 7, 8]),
+    };
+    let mut corrections_while_walking = 0;
+
+    // 16ms frames: walk towards the wall for 6s, then stand still for 1s.
+    // The client learns about the wall after 4.5s.
+    for frame in 0..440 {
+        let now = frame * 16;
+        if now == 4496 {
+            client_world = test_world(true);
+        }
+        if now == 5504 {
+            corrections_while_walking = prediction.corrections();
+        }
+
+        let controls = Controls {
+            forward: if now < 6000 { 1.0 } else { 0.0 },
+            sprint: true,
+            theta: 1.5,
+            phi: 0.2,
+            ..Controls::default()
+        };
+        let input = prediction.predict(controls, 0.016, &client_world);
+        to_server.send(now, input);
+
+        while let Some(input) = to_server.recv(now) {
+            step(&mut server_body, &input, &server_world);
+            to_client.send(now, (input.seq, server_body));
+        }
+        while let Some((seq, body)) = to_client.recv(now) {
+            prediction.reconcile(seq, body, &client_world);
+        }
+    }
+
+    // The client walked through the wall at first and had to be corrected,
+    // but predicted correctly once it knew about the wall
+    assert!(corrections_while_walking > 0);
+    assert_eq!(prediction.corrections(), corrections_while_walking);
+    assert!(server_body.position.x < 8.0);
+    assert!(prediction.body().is_close_to(&server_body));
+}
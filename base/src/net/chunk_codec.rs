@@ -0,0 +1,368 @@
+//! Compact encoding of chunks, used for the network and for saved worlds.
+//!
+//! Most pillars of a chunk share a handful of materials and a single biome,
+//! so both are written once per chunk as a palette, and pillars refer to
+//! them by their index in the palette. Sections are written as runs: the gap
+//! below them and their length, both as varints, which take a single byte
+//! for most sections. The result can additionally be compressed with
+//! deflate.
+
+use super::codec::{invalid_data, read_varint, write_varint, Decode, Encode};
+use super::MAX_FRAME_LEN;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use gen::world::biome::Biome;
+use gen::world::PLANT_COUNT;
+use std::io::{self, Read, Write};
+use world::MAX_PILLAR_SECTIONS;
+use world::{Chunk, GroundMaterial, HeightType, HexPillar, PillarSection, Prop, CHUNK_SIZE};
+
+/// How the compact encoding of a chunk is compressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    /// Makes generated chunks about four times smaller, but takes more
+    /// time.
+    Deflate,
+}
+
+const FORMAT_PLAIN: u8 = 0;
+const FORMAT_DEFLATE: u8 = 1;
+
+/// Writes the chunk in the compact encoding.
+///
+/// The sections of every pillar have to be sorted from bottom to top, which
+/// they always are in the world.
+pub fn encode_chunk<W: Write>(
+    chunk: &Chunk,
+    compression: Compression,
+    w: &mut W,
+) -> io::Result<()> {
+    let mut body = Vec::new();
+    write_body(chunk, &mut body)?;
+
+    match compression {
+        Compression::None => {
+            FORMAT_PLAIN.encode(w)?;
+            w.write_all(&body)
+        }
+        Compression::Deflate => {
+            let mut encoder = DeflateEncoder::new(Vec::new(), ::flate2::Compression::default());
+            encoder.write_all(&body)?;
+            let compressed = encoder.finish()?;
+            FORMAT_DEFLATE.encode(w)?;
+            write_varint(w, compressed.len() as u64)?;
+            w.write_all(&compressed)
+        }
+    }
+}
+
+/// Reads a chunk written by `encode_chunk` with any compression.
+pub fn decode_chunk<R: Read>(r: &mut R) -> io::Result<Chunk> {
+    match u8::decode(r)? {
+        FORMAT_PLAIN => read_body(r),
+        FORMAT_DEFLATE => {
+            let len = read_varint(r)?;
+            if len > u64::from(MAX_FRAME_LEN) {
+                return Err(invalid_data("compressed chunk too long"));
+            }
+            let mut compressed = vec![0; len as usize];
+            r.read_exact(&mut compressed)?;
+
+            let mut decoder = DeflateDecoder::new(&compressed[..]);
+            let chunk = read_body(&mut decoder)?;
+            if decoder.read(&mut [0])? != 0 {
+                return Err(invalid_data("trailing bytes after compressed chunk"));
+            }
+            Ok(chunk)
+        }
+        _ => Err(invalid_data("unknown chunk format")),
+    }
+}
+
+fn write_body<W: Write>(chunk: &Chunk, w: &mut W) -> io::Result<()> {
+    let mut materials = Vec::new();
+    let mut biomes = Vec::new();
+    for pillar in &chunk.pillars {
+        for section in pillar.sections() {
+            if !materials.contains(&section.ground) {
+                materials.push(section.ground);
+            }
+        }
+        if !biomes.contains(pillar.biome()) {
+            biomes.push(pillar.biome().clone());
+        }
+    }
+
+    write_varint(w, materials.len() as u64)?;
+    for material in &materials {
+        material.encode(w)?;
+    }
+    write_varint(w, biomes.len() as u64)?;
+    for biome in &biomes {
+        biome.encode(w)?;
+    }
+
+    // The palettes contain everything, so the positions exist
+    let index_of = |palette_pos: Option<usize>| palette_pos.unwrap() as u64;
+    for pillar in &chunk.pillars {
+        write_varint(w, index_of(biomes.iter().position(|b| b == pillar.biome())))?;
+
+        // Decoding refuses more sections
+        if pillar.sections().len() > MAX_PILLAR_SECTIONS {
+            return Err(invalid_data("pillar has too many sections"));
+        }
+        write_varint(w, pillar.sections().len() as u64)?;
+        let mut last_top = 0;
+        for section in pillar.sections() {
+            let (bottom, top) = (section.bottom.units(), section.top.units());
+            if bottom < last_top {
+                return Err(invalid_data("pillar sections are not sorted"));
+            }
+            write_varint(
+                w,
+                index_of(materials.iter().position(|&m| m == section.ground)),
+            )?;
+            write_varint(w, u64::from(bottom - last_top))?;
+            write_varint(w, u64::from(top - bottom))?;
+            last_top = top;
+        }
+
+        write_varint(w, pillar.props().len() as u64)?;
+        for prop in pillar.props() {
+            write_varint(w, u64::from(prop.baseline.units()))?;
+            write_varint(w, prop.plant_index as u64)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_body<R: Read>(r: &mut R) -> io::Result<Chunk> {
+    let material_count = read_varint(r)?;
+    if material_count > 256 {
+        return Err(invalid_data("material palette too long"));
+    }
+    let materials = (0..material_count)
+        .map(|_| GroundMaterial::decode(r))
+        .collect::<io::Result<Vec<_>>>()?;
+    let biome_count = read_varint(r)?;
+    if biome_count > 256 {
+        return Err(invalid_data("biome palette too long"));
+    }
+    let biomes = (0..biome_count)
+        .map(|_| Biome::decode(r))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let count = (CHUNK_SIZE as usize).pow(2);
+    let mut pillars = Vec::with_capacity(count);
+    // Worlds have at most one plant per pillar
+    let mut props_left = count as u64;
+    for _ in 0..count {
+        let biome = biomes
+            .get(read_varint(r)? as usize)
+            .ok_or_else(|| invalid_data("biome not in palette"))?
+            .clone();
+
+        let section_count = read_varint(r)?;
+        if section_count > MAX_PILLAR_SECTIONS as u64 {
+            return Err(invalid_data("pillar has too many sections"));
+        }
+        let mut sections = Vec::with_capacity(section_count as usize);
+        let mut last_top = 0;
+        for _ in 0..section_count {
+            let ground = *materials
+                .get(read_varint(r)? as usize)
+                .ok_or_else(|| invalid_data("material not in palette"))?;
+            let bottom = add_height(last_top, read_varint(r)?)?;
+            let top = add_height(bottom, read_varint(r)?)?;
+            // `PillarSection::new` would panic on invalid sections
+            if bottom >= top {
+                return Err(invalid_data("pillar section with bottom >= top"));
+            }
+            sections.push(PillarSection::new(
+                ground,
+                HeightType::from_units(bottom),
+                HeightType::from_units(top),
+            ));
+            last_top = top;
+        }
+
+        let prop_count = read_varint(r)?;
+        if prop_count > props_left {
+            return Err(invalid_data("more props than pillars"));
+        }
+        props_left -= prop_count;
+        let mut props = Vec::new();
+        for _ in 0..prop_count {
+            let baseline = add_height(0, read_varint(r)?)?;
+            let plant_index = read_varint(r)?;
+            if plant_index >= PLANT_COUNT as u64 {
+                return Err(invalid_data("plant index not in plant list"));
+            }
+            props.push(Prop {
+                baseline: HeightType::from_units(baseline),
+                plant_index: plant_index as usize,
+            });
+        }
+
+        pillars.push(HexPillar::new(sections, props, biome));
+    }
+    Ok(Chunk::from_pillars(pillars))
+}
+
+/// Returns `height + delta`, or an error if that's not a valid height.
+fn add_height(height: u16, delta: u64) -> io::Result<u16> {
+    if delta > u64::from(u16::max_value() - height) {
+        return Err(invalid_data("height too large"));
+    }
+    Ok(height + delta as u16)
+}
+
+#[cfg(test)]
+fn encoded_len(chunk: &Chunk, compression: Compression) -> usize {
+    let mut buf = Vec::new();
+    encode_chunk(chunk, compression, &mut buf).unwrap();
+
+    let mut slice = &buf[..];
+    assert_eq!(&decode_chunk(&mut slice).unwrap(), chunk);
+    assert!(slice.is_empty(), "decoding did not consume all bytes");
+    buf.len()
+}
+
+#[test]
+fn compact_chunks_round_trip() {
+    use math::*;
+    use world::ChunkIndex;
+
+    let chunk = Chunk::with_pillars(ChunkIndex(AxialPoint::new(-1, 2)), |pos| {
+        let height = (pos.q.abs() * 40 + pos.r.abs()) as u16 + 1;
+        let sections = vec![
+            PillarSection::new(
+                GroundMaterial::Stone,
+                HeightType::from_units(0),
+                HeightType::from_units(height),
+            ),
+            PillarSection::new(
+                if pos.r % 2 == 0 {
+                    GroundMaterial::Grass
+                } else {
+                    GroundMaterial::Snow
+                },
+                HeightType::from_units(height + 200),
+                HeightType::from_units(u16::max_value()),
+            ),
+        ];
+        let props = (0..pos.q.abs() % 2)
+            .map(|_| Prop {
+                baseline: HeightType::from_units(height),
+                plant_index: pos.r.abs() as usize % PLANT_COUNT,
+            })
+            .collect();
+        let biome = if pos.q < 8 {
+            Biome::Forest
+        } else {
+            Biome::Desert
+        };
+        HexPillar::new(sections, props, biome)
+    });
+
+    encoded_len(&chunk, Compression::None);
+    encoded_len(&chunk, Compression::Deflate);
+    encoded_len(
+        &Chunk::from_pillars(vec![HexPillar::default(); 256]),
+        Compression::None,
+    );
+}
+
+#[test]
+fn invalid_compact_chunks_are_rejected() {
+    let chunk = Chunk::from_pillars(vec![HexPillar::default(); 256]);
+    for &compression in &[Compression::None, Compression::Deflate] {
+        let mut buf = Vec::new();
+        encode_chunk(&chunk, compression, &mut buf).unwrap();
+        for len in 0..buf.len() {
+            assert!(decode_chunk(&mut &buf[..len]).is_err(), "{} bytes", len);
+        }
+    }
+
+    // Palettes with dirt and grass land, then the first pillar followed by
+    // 255 empty ones
+    let chunk = |first: &[u8]| {
+        let mut buf = vec![FORMAT_PLAIN, 1, 0, 1, 0];
+        buf.extend_from_slice(first);
+        buf.extend_from_slice(&[0; 255 * 3]);
+        buf
+    };
+    // A section from 0 to 1 without props
+    let valid = chunk(&[0, 1, 0, 0, 1, 0]);
+    // A section reaching above the highest height
+    let overflow = chunk(&[0, 1, 0, 0xff, 0xff, 0x03, 2, 0]);
+    // A material missing in the palette
+    let missing = chunk(&[0, 1, 1, 0, 1, 0]);
+    // A prop with the last plant of the plant list, and one after it
+    let plant = chunk(&[0, 1, 0, 0, 1, 1, 1, PLANT_COUNT as u8 - 1]);
+    let no_plant = chunk(&[0, 1, 0, 0, 1, 1, 1, PLANT_COUNT as u8]);
+    // More props than pillars in the chunk
+    let props = chunk(&[0, 1, 0, 0, 1, 0x81, 0x02]);
+    // Pillars with the most sections they may have, one more, and a count
+    // which doesn't fit into memory
+    let sections = |count| {
+        let mut first = vec![0];
+        write_varint(&mut first, count as u64).unwrap();
+        for _ in 0..count {
+            first.extend_from_slice(&[0, 0, 1]);
+        }
+        first.push(0);
+        chunk(&first)
+    };
+    let huge = chunk(&[
+        0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f, 0, 0, 1,
+    ]);
+    assert!(decode_chunk(&mut &overflow[..]).is_err());
+    assert!(decode_chunk(&mut &missing[..]).is_err());
+    assert!(decode_chunk(&mut &plant[..]).is_ok());
+    assert!(decode_chunk(&mut &no_plant[..]).is_err());
+    assert!(decode_chunk(&mut &props[..]).is_err());
+    assert!(decode_chunk(&mut &sections(MAX_PILLAR_SECTIONS)[..]).is_ok());
+    assert!(decode_chunk(&mut &sections(MAX_PILLAR_SECTIONS + 1)[..]).is_err());
+    assert!(decode_chunk(&mut &huge[..]).is_err());
+    assert!(decode_chunk(&mut &[7][..]).is_err());
+    assert!(decode_chunk(&mut &valid[..]).is_ok());
+}
+
+/// Compares the sizes of generated chunks in the compact encoding to the
+/// plain encoding of all pillars.
+#[test]
+fn compact_chunks_are_smaller() {
+    use gen::WorldGenerator;
+    use math::*;
+    use world::{ChunkIndex, ChunkProvider};
+
+    let generator = WorldGenerator::with_seed(42);
+    let (mut naive, mut compact, mut deflated) = (0, 0, 0);
+    for &(q, r) in &[(0, 0), (3, -1), (-5, 8), (20, 20), (-40, 7)] {
+        let chunk = generator
+            .load_chunk(ChunkIndex(AxialPoint::new(q, r)))
+            .unwrap();
+        let mut buf = Vec::new();
+        for pillar in &chunk.pillars {
+            pillar.encode(&mut buf).unwrap();
+        }
+        naive += buf.len();
+        compact += encoded_len(&chunk, Compression::None);
+        deflated += encoded_len(&chunk, Compression::Deflate);
+    }
+
+    assert!(
+        compact * 2 < naive,
+        "{} bytes compact, {} naive",
+        compact,
+        naive
+    );
+    assert!(
+        deflated * 3 < compact,
+        "{} bytes deflated, {} compact",
+        deflated,
+        compact
+    );
+}
@@ -0,0 +1,500 @@
+//! Binary encoding of the values that are sent over the network.
+//!
+//! All integers are written in little endian byte order. Variable length
+//! values (strings, vectors) are prefixed with their length as `u32`.
+//! Chunks use their own compact encoding, see `chunk_codec`.
+
+use super::chunk_codec::{decode_chunk, encode_chunk, Compression};
+use gen::world::biome::Biome;
+use gen::world::PLANT_COUNT;
+use math::*;
+use std::cmp;
+use std::io::{self, Read, Write};
+use world::{Chunk, ChunkIndex, GroundMaterial, HeightType, HexPillar, PillarIndex};
+use world::{PillarSection, Prop};
+
+/// A type that can be written into a byte stream.
+pub trait Encode {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()>;
+}
+
+/// A type that can be read from a byte stream written by `Encode`.
+pub trait Decode: Sized {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self>;
+}
+
+/// Creates an error signaling that the received data is malformed.
+pub fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+macro_rules! impl_codec_for_int {
+    ($ty:ty, $len:expr) => {
+        impl Encode for $ty {
+            fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+                w.write_all(&self.to_le_bytes())
+            }
+        }
+
+        impl Decode for $ty {
+            fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+                let mut buf = [0; $len];
+                r.read_exact(&mut buf)?;
+                Ok(<$ty>::from_le_bytes(buf))
+            }
+        }
+    };
+}
+
+impl_codec_for_int!(u8, 1);
+impl_codec_for_int!(u16, 2);
+impl_codec_for_int!(u32, 4);
+impl_codec_for_int!(u64, 8);
+impl_codec_for_int!(i16, 2);
+impl_codec_for_int!(i32, 4);
+
+/// Writes the number as varint: seven bits per byte, starting with the
+/// lowest ones. The highest bit is set in all bytes but the last, so small
+/// numbers take a single byte.
+pub fn write_varint<W: Write>(w: &mut W, mut n: u64) -> io::Result<()> {
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            return byte.encode(w);
+        }
+        (byte | 0x80).encode(w)?;
+    }
+}
+
+/// Reads a number written by `write_varint`.
+pub fn read_varint<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut n = 0;
+    for i in 0..10 {
+        let byte = u8::decode(r)?;
+        let bits = u64::from(byte & 0x7f);
+        // The tenth byte may only hold the highest bit of a `u64`
+        if i == 9 && bits > 1 {
+            return Err(invalid_data("varint too large"));
+        }
+        n |= bits << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+    Err(invalid_data("varint too long"))
+}
+
+impl Encode for f32 {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.to_bits().encode(w)
+    }
+}
+
+impl Decode for f32 {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        u32::decode(r).map(f32::from_bits)
+    }
+}
+
+impl Encode for bool {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        (*self as u8).encode(w)
+    }
+}
+
+impl Decode for bool {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        match u8::decode(r)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid_data("invalid boolean value")),
+        }
+    }
+}
+
+impl Encode for String {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        (self.len() as u32).encode(w)?;
+        w.write_all(self.as_bytes())
+    }
+}
+
+impl Decode for String {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        let bytes = Vec::<u8>::decode(r)?;
+        String::from_utf8(bytes).map_err(|_| invalid_data("string is not valid UTF-8"))
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        (self.len() as u32).encode(w)?;
+        for item in self {
+            item.encode(w)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        let len = u32::decode(r)? as usize;
+
+        // The length comes from the other side, so we don't trust it with
+        // allocating memory up front.
+        let mut out = Vec::with_capacity(cmp::min(len, 1024));
+        for _ in 0..len {
+            out.push(T::decode(r)?);
+        }
+        Ok(out)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        match *self {
+            Some(ref v) => {
+                true.encode(w)?;
+                v.encode(w)
+            }
+            None => false.encode(w),
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        if bool::decode(r)? {
+            T::decode(r).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+// ===========================================================================
+// Math types
+// ===========================================================================
+
+impl Encode for Point3f {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.x.encode(w)?;
+        self.y.encode(w)?;
+        self.z.encode(w)
+    }
+}
+
+impl Decode for Point3f {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        Ok(Point3f::new(
+            f32::decode(r)?,
+            f32::decode(r)?,
+            f32::decode(r)?,
+        ))
+    }
+}
+
+impl Encode for AxialPoint {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.q.encode(w)?;
+        self.r.encode(w)
+    }
+}
+
+impl Decode for AxialPoint {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        Ok(AxialPoint::new(i32::decode(r)?, i32::decode(r)?))
+    }
+}
+
+// ===========================================================================
+// World types
+// ===========================================================================
+
+impl Encode for ChunkIndex {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.0.encode(w)
+    }
+}
+
+impl Decode for ChunkIndex {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        AxialPoint::decode(r).map(ChunkIndex)
+    }
+}
+
+impl Encode for PillarIndex {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.0.encode(w)
+    }
+}
+
+impl Decode for PillarIndex {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        AxialPoint::decode(r).map(PillarIndex)
+    }
+}
+
+impl Encode for HeightType {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.units().encode(w)
+    }
+}
+
+impl Decode for HeightType {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        u16::decode(r).map(HeightType::from_units)
+    }
+}
+
+impl Encode for GroundMaterial {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let tag: u8 = match *self {
+            GroundMaterial::Dirt => 0,
+            GroundMaterial::Grass => 1,
+            GroundMaterial::Stone => 2,
+            GroundMaterial::Sand => 3,
+            GroundMaterial::Snow => 4,
+            GroundMaterial::JungleGrass => 5,
+            GroundMaterial::Mulch => 6,
+            GroundMaterial::Debug => 7,
+        };
+        tag.encode(w)
+    }
+}
+
+impl Decode for GroundMaterial {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        match u8::decode(r)? {
+            0 => Ok(GroundMaterial::Dirt),
+            1 => Ok(GroundMaterial::Grass),
+            2 => Ok(GroundMaterial::Stone),
+            3 => Ok(GroundMaterial::Sand),
+            4 => Ok(GroundMaterial::Snow),
+            5 => Ok(GroundMaterial::JungleGrass),
+            6 => Ok(GroundMaterial::Mulch),
+            7 => Ok(GroundMaterial::Debug),
+            _ => Err(invalid_data("unknown ground material")),
+        }
+    }
+}
+
+impl Encode for Biome {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let tag: u8 = match *self {
+            Biome::GrassLand => 0,
+            Biome::Desert => 1,
+            Biome::Snow => 2,
+            Biome::Forest => 3,
+            Biome::RainForest => 4,
+            Biome::Savanna => 5,
+            Biome::Stone => 6,
+            Biome::Debug => 7,
+        };
+        tag.encode(w)
+    }
+}
+
+impl Decode for Biome {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        match u8::decode(r)? {
+            0 => Ok(Biome::GrassLand),
+            1 => Ok(Biome::Desert),
+            2 => Ok(Biome::Snow),
+            3 => Ok(Biome::Forest),
+            4 => Ok(Biome::RainForest),
+            5 => Ok(Biome::Savanna),
+            6 => Ok(Biome::Stone),
+            7 => Ok(Biome::Debug),
+            _ => Err(invalid_data("unknown biome")),
+        }
+    }
+}
+
+impl Encode for PillarSection {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.ground.encode(w)?;
+        self.bottom.encode(w)?;
+        self.top.encode(w)
+    }
+}
+
+impl Decode for PillarSection {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        let ground = GroundMaterial::decode(r)?;
+        let bottom = HeightType::decode(r)?;
+        let top = HeightType::decode(r)?;
+
+        // `PillarSection::new` would panic on invalid sections
+        if bottom >= top {
+            return Err(invalid_data("pillar section with bottom >= top"));
+        }
+        Ok(PillarSection::new(ground, bottom, top))
+    }
+}
+
+impl Encode for Prop {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.baseline.encode(w)?;
+        (self.plant_index as u32).encode(w)
+    }
+}
+
+impl Decode for Prop {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        let baseline = HeightType::decode(r)?;
+        let plant_index = u32::decode(r)? as usize;
+        if plant_index >= PLANT_COUNT {
+            return Err(invalid_data("plant index not in plant list"));
+        }
+        Ok(Prop {
+            baseline: baseline,
+            plant_index: plant_index,
+        })
+    }
+}
+
+impl Encode for HexPillar {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.biome().encode(w)?;
+        self.sections().to_vec().encode(w)?;
+        self.props().to_vec().encode(w)
+    }
+}
+
+impl Decode for HexPillar {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        let biome = Biome::decode(r)?;
+        let sections = Vec::decode(r)?;
+        let props = Vec::decode(r)?;
+        Ok(HexPillar::new(sections, props, biome))
+    }
+}
+
+/// Chunks are sent often and are large, so they use the compressed compact
+/// encoding.
+impl Encode for Chunk {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        encode_chunk(self, Compression::Deflate, w)
+    }
+}
+
+impl Decode for Chunk {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        decode_chunk(r)
+    }
+}
+
+#[cfg(test)]
+fn round_trip<T: Encode + Decode>(value: &T) -> T {
+    let mut buf = Vec::new();
+    value.encode(&mut buf).unwrap();
+    let mut slice = &buf[..];
+    let out = T::decode(&mut slice).unwrap();
+    assert!(slice.is_empty(), "decoding did not consume all bytes");
+    out
+}
+
+#[test]
+fn primitives_round_trip() {
+    assert_eq!(round_trip(&0xABu8), 0xAB);
+    assert_eq!(round_trip(&0xBEEFu16), 0xBEEF);
+    assert_eq!(round_trip(&0xDEADBEEFu32), 0xDEADBEEF);
+    assert_eq!(round_trip(&u64::max_value()), u64::max_value());
+    assert_eq!(round_trip(&-12345i32), -12345);
+    assert_eq!(round_trip(&-0.125f32), -0.125);
+    assert_eq!(round_trip(&true), true);
+    assert_eq!(round_trip(&"Plantex ✿".to_string()), "Plantex ✿");
+    assert_eq!(round_trip(&vec![1u16, 2, 3]), vec![1, 2, 3]);
+    assert_eq!(round_trip(&Some(7u8)), Some(7));
+    assert_eq!(round_trip(&None::<u8>), None);
+}
+
+#[test]
+fn varints_round_trip() {
+    for &n in &[
+        0,
+        1,
+        127,
+        128,
+        300,
+        16_383,
+        16_384,
+        u64::from(u32::max_value()),
+        u64::max_value(),
+    ] {
+        let mut buf = Vec::new();
+        write_varint(&mut buf, n).unwrap();
+        let mut slice = &buf[..];
+        assert_eq!(read_varint(&mut slice).unwrap(), n);
+        assert!(slice.is_empty());
+    }
+
+    let mut buf = Vec::new();
+    write_varint(&mut buf, 127).unwrap();
+    assert_eq!(buf.len(), 1);
+
+    // More than 64 bits and unterminated varints
+    let mut too_large = vec![0xff; 9];
+    too_large.push(0x02);
+    assert!(read_varint(&mut &too_large[..]).is_err());
+    assert!(read_varint(&mut &[0x80; 11][..]).is_err());
+    assert!(read_varint(&mut &[0x80][..]).is_err());
+}
+
+#[test]
+fn chunk_round_trip() {
+    let chunk = Chunk::with_pillars(ChunkIndex(AxialPoint::new(-1, 2)), |pos| {
+        let height = (pos.q.abs() + pos.r.abs()) as u16 + 1;
+        HexPillar::new(
+            vec![
+                PillarSection::new(
+                    GroundMaterial::Stone,
+                    HeightType::from_units(0),
+                    HeightType::from_units(height),
+                ),
+                PillarSection::new(
+                    GroundMaterial::Grass,
+                    HeightType::from_units(height + 4),
+                    HeightType::from_units(height + 6),
+                ),
+            ],
+            vec![Prop {
+                baseline: HeightType::from_units(height + 6),
+                plant_index: (pos.q.abs() % 8) as usize,
+            }],
+            Biome::Forest,
+        )
+    });
+
+    assert_eq!(round_trip(&chunk), chunk);
+}
+
+#[test]
+fn invalid_data_is_rejected() {
+    // unknown material
+    let mut slice: &[u8] = &[42];
+    assert!(GroundMaterial::decode(&mut slice).is_err());
+
+    // a section with `bottom >= top`
+    let mut buf = Vec::new();
+    GroundMaterial::Sand.encode(&mut buf).unwrap();
+    HeightType::from_units(5).encode(&mut buf).unwrap();
+    HeightType::from_units(5).encode(&mut buf).unwrap();
+    assert!(PillarSection::decode(&mut &buf[..]).is_err());
+
+    // a prop with a plant missing in the plant list
+    let mut buf = Vec::new();
+    Prop {
+        baseline: HeightType::from_units(5),
+        plant_index: PLANT_COUNT,
+    }
+    .encode(&mut buf)
+    .unwrap();
+    assert!(Prop::decode(&mut &buf[..]).is_err());
+
+    // truncated input
+    let mut slice: &[u8] = &[1, 2];
+    assert!(u32::decode(&mut slice).is_err());
+}
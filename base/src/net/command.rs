@@ -0,0 +1,395 @@
+//! Admin commands understood by the server.
+//!
+//! Commands are typed into the server console or sent by authorized players
+//! as `Message::Command`. The server answers every command with a short
+//! text.
+
+use super::codec::{invalid_data, Decode, Encode};
+use math::*;
+use std::fmt;
+use std::io::{self, Read, Write};
+use time::{DAY_LENGTH, MAX_TIME_SPEED};
+use weather::{Form, Strength, WeatherState};
+
+/// A command changing or querying the state of the server.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    /// Explains how to use all commands.
+    Help,
+    /// Lists the players online.
+    List,
+    /// Disconnects the player with the given name.
+    Kick(String),
+    /// Sends a chat message from the server to everyone.
+    Say(String),
+    /// Sets the time of the current day, in seconds since its start.
+    SetTime(f32),
+    /// Lets time pass at the given speed.
+    SetTimeSpeed(f32),
+    /// Sets the weather of the whole world, or lets every region decide its
+    /// weather again with `None`.
+    SetWeather(Option<WeatherState>),
+    /// Saves the world.
+    Save,
+    /// Saves the world and takes a snapshot of it with the given name.
+    CreateSnapshot(String),
+    /// Lists the snapshots of the world.
+    ListSnapshots,
+    /// Replaces the world by the snapshot with the given id. Only possible
+    /// while the server is stopped, so servers refuse it.
+    RestoreSnapshot(u32),
+    /// Deletes all but the given number of newest snapshots.
+    PruneSnapshots(u32),
+    /// Disconnects all players and stops the server.
+    Stop,
+    /// Shows the seed of the world.
+    Seed,
+    /// Moves the player running the command.
+    Teleport(TeleportTarget),
+    /// Proves that the player is one of the admins, with the admin password
+    /// of the server.
+    Auth(String),
+}
+
+/// Where a player is teleported to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TeleportTarget {
+    /// The position of the player with the given name.
+    Player(String),
+    Position(Point3f),
+}
+
+/// Explains how to use all commands.
+pub const COMMAND_HELP: &str = "commands:
+  help                           shows this list
+  list                           players online
+  kick <player>                  disconnects the player
+  say <text>                     sends a message to everyone
+  time set <seconds|morning|noon|evening|midnight>
+  time speed <speed>             game seconds per real second
+  weather set <clear|rain|snow|pollen> [weak|medium|heavy]
+  weather auto                   every region decides its weather again
+  save                           saves the world
+  snapshot create [name]         saves the world and takes a snapshot
+  snapshot list                  snapshots of the world
+  snapshot restore <id>          only while the server is stopped
+  snapshot prune <keep>          deletes all but the newest snapshots
+  stop                           stops the server
+  seed                           shows the seed of the world
+  tp <player|x y z>              moves you to a player or position
+  auth <password>                lets admins run the other commands";
+
+/// Why a command line couldn't be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseCommandError(String);
+
+impl fmt::Display for ParseCommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl ::std::error::Error for ParseCommandError {}
+
+fn error<T, S: Into<String>>(msg: S) -> Result<T, ParseCommandError> {
+    Err(ParseCommandError(msg.into()))
+}
+
+impl Command {
+    /// Returns whether only admins may run the command. Everyone may run
+    /// commands that only show something.
+    pub fn needs_admin(&self) -> bool {
+        match *self {
+            Command::Help | Command::List | Command::Seed | Command::Auth(_) => false,
+            _ => true,
+        }
+    }
+
+    /// Parses a command line like `time speed 10`.
+    pub fn parse(line: &str) -> Result<Command, ParseCommandError> {
+        let line = line.trim();
+        let (name, rest) = split_word(line);
+        let args: Vec<_> = rest.split_whitespace().collect();
+
+        let command = match (name, &args[..]) {
+            ("help", &[]) => Command::Help,
+            ("list", &[]) => Command::List,
+            ("kick", &[_, ..]) => Command::Kick(rest.to_string()),
+            ("say", &[_, ..]) => Command::Say(rest.to_string()),
+            ("time", &["set", time]) => Command::SetTime(parse_time_of_day(time)?),
+            ("time", &["speed", speed]) => match speed.parse::<f32>() {
+                Ok(speed) if (0.0..=MAX_TIME_SPEED).contains(&speed) => {
+                    Command::SetTimeSpeed(speed)
+                }
+                _ => {
+                    return error(format!(
+                        "time speed has to be between 0 and {}",
+                        MAX_TIME_SPEED
+                    ))
+                }
+            },
+            ("weather", &["set", form]) => Command::SetWeather(Some(parse_weather(form, None)?)),
+            ("weather", &["set", form, strength]) => {
+                Command::SetWeather(Some(parse_weather(form, Some(strength))?))
+            }
+            ("weather", &["auto"]) => Command::SetWeather(None),
+            ("save", &[]) => Command::Save,
+            ("snapshot", &["create", ..]) => {
+                Command::CreateSnapshot(split_word(rest).1.to_string())
+            }
+            ("snapshot", &["list"]) => Command::ListSnapshots,
+            ("snapshot", &["restore", id]) => match id.parse() {
+                Ok(id) => Command::RestoreSnapshot(id),
+                Err(_) => return error(format!("invalid snapshot id '{}'", id)),
+            },
+            ("snapshot", &["prune", keep]) => match keep.parse() {
+                Ok(keep) => Command::PruneSnapshots(keep),
+                Err(_) => return error("the number of snapshots to keep is invalid"),
+            },
+            ("stop", &[]) => Command::Stop,
+            ("seed", &[]) => Command::Seed,
+            ("tp", &[x, y, z]) => match (x.parse(), y.parse(), z.parse()) {
+                (Ok(x), Ok(y), Ok(z)) => {
+                    Command::Teleport(TeleportTarget::Position(Point3f::new(x, y, z)))
+                }
+                _ => Command::Teleport(TeleportTarget::Player(rest.to_string())),
+            },
+            ("tp", &[_, ..]) => Command::Teleport(TeleportTarget::Player(rest.to_string())),
+            ("auth", &[_, ..]) => Command::Auth(rest.to_string()),
+            ("help", _)
+            | ("list", _)
+            | ("kick", _)
+            | ("say", _)
+            | ("time", _)
+            | ("weather", _)
+            | ("save", _)
+            | ("snapshot", _)
+            | ("stop", _)
+            | ("seed", _)
+            | ("tp", _)
+            | ("auth", _) => {
+                return error(format!("invalid arguments for '{}'", name));
+            }
+            ("", _) => return error("empty command"),
+            _ => return error(format!("unknown command '{}'", name)),
+        };
+        Ok(command)
+    }
+}
+
+/// Splits off the first word of the line.
+fn split_word(line: &str) -> (&str, &str) {
+    match line.find(char::is_whitespace) {
+        Some(i) => (&line[..i], line[i..].trim()),
+        None => (line, ""),
+    }
+}
+
+fn parse_time_of_day(s: &str) -> Result<f32, ParseCommandError> {
+    let time = match s {
+        "midnight" => 0.0,
+        "morning" => DAY_LENGTH / 4.0,
+        "noon" => DAY_LENGTH / 2.0,
+        "evening" => DAY_LENGTH * 3.0 / 4.0,
+        _ => match s.parse::<f32>() {
+            Ok(t) if (0.0..DAY_LENGTH).contains(&t) => t,
+            _ => return error(format!("time has to be between 0 and {}", DAY_LENGTH)),
+        },
+    };
+    Ok(time)
+}
+
+fn parse_weather(form: &str, strength: Option<&str>) -> Result<WeatherState, ParseCommandError> {
+    let form = match form {
+        // The form doesn't matter without downfall
+        "clear" if strength.is_none() => {
+            return Ok(WeatherState {
+                form: Form::Rain,
+                strength: Strength::None,
+            })
+        }
+        "rain" => Form::Rain,
+        "snow" => Form::Snow,
+        "pollen" => Form::Pollen,
+        _ => return error(format!("unknown weather '{}'", form)),
+    };
+    let strength = match strength {
+        Some("weak") => Strength::Weak,
+        Some("medium") | None => Strength::Medium,
+        Some("heavy") => Strength::Heavy,
+        Some(s) => return error(format!("unknown weather strength '{}'", s)),
+    };
+    Ok(WeatherState {
+        form: form,
+        strength: strength,
+    })
+}
+
+const CMD_LIST: u8 = 0;
+const CMD_KICK: u8 = 1;
+const CMD_SAY: u8 = 2;
+const CMD_SET_TIME: u8 = 3;
+const CMD_SET_TIME_SPEED: u8 = 4;
+const CMD_SET_WEATHER: u8 = 5;
+const CMD_SAVE: u8 = 6;
+const CMD_STOP: u8 = 7;
+const CMD_SEED: u8 = 8;
+const CMD_HELP: u8 = 9;
+const CMD_TELEPORT_PLAYER: u8 = 10;
+const CMD_TELEPORT_POSITION: u8 = 11;
+const CMD_CREATE_SNAPSHOT: u8 = 12;
+const CMD_LIST_SNAPSHOTS: u8 = 13;
+const CMD_RESTORE_SNAPSHOT: u8 = 14;
+const CMD_PRUNE_SNAPSHOTS: u8 = 15;
+const CMD_AUTH: u8 = 16;
+
+impl Encode for Command {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        match *self {
+            Command::Help => CMD_HELP.encode(w),
+            Command::List => CMD_LIST.encode(w),
+            Command::Kick(ref name) => {
+                CMD_KICK.encode(w)?;
+                name.encode(w)
+            }
+            Command::Say(ref text) => {
+                CMD_SAY.encode(w)?;
+                text.encode(w)
+            }
+            Command::SetTime(time) => {
+                CMD_SET_TIME.encode(w)?;
+                time.encode(w)
+            }
+            Command::SetTimeSpeed(speed) => {
+                CMD_SET_TIME_SPEED.encode(w)?;
+                speed.encode(w)
+            }
+            Command::SetWeather(ref weather) => {
+                CMD_SET_WEATHER.encode(w)?;
+                weather.encode(w)
+            }
+            Command::Save => CMD_SAVE.encode(w),
+            Command::CreateSnapshot(ref name) => {
+                CMD_CREATE_SNAPSHOT.encode(w)?;
+                name.encode(w)
+            }
+            Command::ListSnapshots => CMD_LIST_SNAPSHOTS.encode(w),
+            Command::RestoreSnapshot(id) => {
+                CMD_RESTORE_SNAPSHOT.encode(w)?;
+                id.encode(w)
+            }
+            Command::PruneSnapshots(keep) => {
+                CMD_PRUNE_SNAPSHOTS.encode(w)?;
+                keep.encode(w)
+            }
+            Command::Stop => CMD_STOP.encode(w),
+            Command::Seed => CMD_SEED.encode(w),
+            Command::Teleport(TeleportTarget::Player(ref name)) => {
+                CMD_TELEPORT_PLAYER.encode(w)?;
+                name.encode(w)
+            }
+            Command::Teleport(TeleportTarget::Position(pos)) => {
+                CMD_TELEPORT_POSITION.encode(w)?;
+                pos.encode(w)
+            }
+            Command::Auth(ref password) => {
+                CMD_AUTH.encode(w)?;
+                password.encode(w)
+            }
+        }
+    }
+}
+
+impl Decode for Command {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        let command = match u8::decode(r)? {
+            CMD_HELP => Command::Help,
+            CMD_LIST => Command::List,
+            CMD_KICK => Command::Kick(String::decode(r)?),
+            CMD_SAY => Command::Say(String::decode(r)?),
+            CMD_SET_TIME => Command::SetTime(f32::decode(r)?),
+            CMD_SET_TIME_SPEED => Command::SetTimeSpeed(f32::decode(r)?),
+            CMD_SET_WEATHER => Command::SetWeather(Option::decode(r)?),
+            CMD_SAVE => Command::Save,
+            CMD_CREATE_SNAPSHOT => Command::CreateSnapshot(String::decode(r)?),
+            CMD_LIST_SNAPSHOTS => Command::ListSnapshots,
+            CMD_RESTORE_SNAPSHOT => Command::RestoreSnapshot(u32::decode(r)?),
+            CMD_PRUNE_SNAPSHOTS => Command::PruneSnapshots(u32::decode(r)?),
+            CMD_STOP => Command::Stop,
+            CMD_SEED => Command::Seed,
+            CMD_TELEPORT_PLAYER => Command::Teleport(TeleportTarget::Player(String::decode(r)?)),
+            CMD_TELEPORT_POSITION => {
+                Command::Teleport(TeleportTarget::Position(Point3f::decode(r)?))
+            }
+            CMD_AUTH => Command::Auth(String::decode(r)?),
+            _ => return Err(invalid_data("unknown command")),
+        };
+        Ok(command)
+    }
+}
+
+#[test]
+fn commands_are_parsed() {
+    let ok = |line| Command::parse(line).unwrap();
+    assert_eq!(ok("list"), Command::List);
+    assert_eq!(
+        ok("  kick  Some Player "),
+        Command::Kick("Some Player".into())
+    );
+    assert_eq!(ok("say hello  world"), Command::Say("hello  world".into()));
+    assert_eq!(ok("time set noon"), Command::SetTime(DAY_LENGTH / 2.0));
+    assert_eq!(ok("time set 10.5"), Command::SetTime(10.5));
+    assert_eq!(ok("time speed 100"), Command::SetTimeSpeed(100.0));
+    assert_eq!(
+        ok("weather set snow heavy"),
+        Command::SetWeather(Some(WeatherState {
+            form: Form::Snow,
+            strength: Strength::Heavy,
+        }))
+    );
+    assert_eq!(
+        ok("weather set clear"),
+        Command::SetWeather(Some(WeatherState {
+            form: Form::Rain,
+            strength: Strength::None,
+        }))
+    );
+    assert_eq!(ok("weather auto"), Command::SetWeather(None));
+    assert_eq!(ok("stop"), Command::Stop);
+    assert_eq!(
+        ok("snapshot create before the  big edit"),
+        Command::CreateSnapshot("before the  big edit".into())
+    );
+    assert_eq!(ok("snapshot create"), Command::CreateSnapshot("".into()));
+    assert_eq!(ok("snapshot restore 3"), Command::RestoreSnapshot(3));
+    assert_eq!(ok("snapshot prune 0"), Command::PruneSnapshots(0));
+    assert_eq!(
+        ok("tp 1 -2.5 30"),
+        Command::Teleport(TeleportTarget::Position(Point3f::new(1.0, -2.5, 30.0)))
+    );
+    assert_eq!(
+        ok("tp Lukas"),
+        Command::Teleport(TeleportTarget::Player("Lukas".into()))
+    );
+    assert_eq!(ok("auth  open sesame"), Command::Auth("open sesame".into()));
+
+    for line in &[
+        "",
+        "fly",
+        "list all",
+        "kick",
+        "time",
+        "time set 1000000",
+        "time speed -1",
+        "time speed NaN",
+        "weather set clear heavy",
+        "weather set hail",
+        "snapshot",
+        "snapshot restore",
+        "snapshot prune -1",
+        "tp",
+        "auth",
+    ] {
+        assert!(Command::parse(line).is_err(), "'{}' was accepted", line);
+    }
+}
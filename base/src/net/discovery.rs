@@ -0,0 +1,219 @@
+//! Finding servers in the local network.
+//!
+//! Servers listen for UDP datagrams on `DISCOVERY_PORT`. Clients broadcast a
+//! status query and every server answers with its `ServerStatus`. The same
+//! query sent to a single server asks for its status only.
+
+use super::codec::{invalid_data, Decode, Encode};
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// UDP port servers answer status queries on by default.
+pub const DISCOVERY_PORT: u16 = 34256;
+
+/// Content of a status query.
+const STATUS_QUERY: &[u8] = b"PLANTEX STATUS?";
+
+/// Start of every status reply, followed by the encoded `ServerStatus`.
+const STATUS_REPLY: &[u8] = b"PLANTEX STATUS!";
+
+/// Longer datagrams are cut off.
+const MAX_DATAGRAM_LEN: usize = 4096;
+
+/// Server names and messages of the day are cut off after this many bytes,
+/// so that status replies always fit into a datagram.
+pub const MAX_STATUS_TEXT_LEN: usize = 1024;
+
+/// What a server tells about itself.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerStatus {
+    pub name: String,
+    pub motd: String,
+    /// Number of players online.
+    pub players: u32,
+    pub max_players: u32,
+    /// The `PROTOCOL_VERSION` of the server.
+    pub version: u16,
+    pub seed: u64,
+    /// The TCP port players connect to.
+    pub port: u16,
+}
+
+/// A server which answered a status query.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FoundServer {
+    /// Address players connect to.
+    pub addr: SocketAddr,
+    pub status: ServerStatus,
+}
+
+/// Returns whether the datagram is a status query.
+pub fn is_status_query(datagram: &[u8]) -> bool {
+    datagram == STATUS_QUERY
+}
+
+/// Returns the datagram answering a status query. The name and message of
+/// the day are cut off after `MAX_STATUS_TEXT_LEN` bytes.
+pub fn encode_status_reply(status: &ServerStatus) -> Vec<u8> {
+    let status = ServerStatus {
+        name: truncate(&status.name).to_string(),
+        motd: truncate(&status.motd).to_string(),
+        ..status.clone()
+    };
+    let mut buf = STATUS_REPLY.to_vec();
+    // Writing to a `Vec` can't fail
+    status.encode(&mut buf).unwrap();
+    buf
+}
+
+/// Returns the longest start of the text which is at most
+/// `MAX_STATUS_TEXT_LEN` bytes long.
+fn truncate(text: &str) -> &str {
+    let mut len = text.len().min(MAX_STATUS_TEXT_LEN);
+    while !text.is_char_boundary(len) {
+        len -= 1;
+    }
+    &text[..len]
+}
+
+/// Decodes a datagram created by `encode_status_reply`.
+pub fn decode_status_reply(datagram: &[u8]) -> io::Result<ServerStatus> {
+    if !datagram.starts_with(STATUS_REPLY) {
+        return Err(invalid_data("not a status reply"));
+    }
+    let mut rest = &datagram[STATUS_REPLY.len()..];
+    let status = ServerStatus::decode(&mut rest)?;
+    if !rest.is_empty() {
+        return Err(invalid_data("trailing bytes after status reply"));
+    }
+    Ok(status)
+}
+
+/// Returns the address status queries are broadcast to.
+pub fn broadcast_addr() -> SocketAddr {
+    SocketAddr::new(Ipv4Addr::new(255, 255, 255, 255).into(), DISCOVERY_PORT)
+}
+
+/// Sends a status query to all given addresses, which may be broadcast
+/// addresses, and returns all servers which answered within `timeout`.
+pub fn discover(targets: &[SocketAddr], timeout: Duration) -> io::Result<Vec<FoundServer>> {
+    let socket = UdpSocket::bind((Ipv4Addr::new(0, 0, 0, 0), 0))?;
+    socket.set_broadcast(true)?;
+    for target in targets {
+        // There might be no network for broadcasts, other targets can
+        // still answer
+        if let Err(e) = socket.send_to(STATUS_QUERY, target) {
+            warn!("can't send status query to {}: {}", target, e);
+        }
+    }
+
+    let deadline = Instant::now() + timeout;
+    let mut found: Vec<FoundServer> = Vec::new();
+    let mut buf = [0; MAX_DATAGRAM_LEN];
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+        socket.set_read_timeout(Some(deadline - now))?;
+
+        let (len, from) = match socket.recv_from(&mut buf) {
+            Ok(res) => res,
+            Err(ref e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                break;
+            }
+            Err(e) => return Err(e),
+        };
+        match decode_status_reply(&buf[..len]) {
+            Ok(status) => {
+                let addr = SocketAddr::new(from.ip(), status.port);
+                // Servers reachable by several targets answer several times
+                if found.iter().all(|server| server.addr != addr) {
+                    found.push(FoundServer {
+                        addr: addr,
+                        status: status,
+                    });
+                }
+            }
+            Err(e) => debug!("ignoring invalid status reply from {}: {}", from, e),
+        }
+    }
+
+    Ok(found)
+}
+
+/// Asks the server answering status queries at the given address for its
+/// status.
+pub fn query_status(addr: SocketAddr, timeout: Duration) -> io::Result<FoundServer> {
+    discover(&[addr], timeout)?
+        .pop()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut, "server did not answer"))
+}
+
+impl Encode for ServerStatus {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.name.encode(w)?;
+        self.motd.encode(w)?;
+        self.players.encode(w)?;
+        self.max_players.encode(w)?;
+        self.version.encode(w)?;
+        self.seed.encode(w)?;
+        self.port.encode(w)
+    }
+}
+
+impl Decode for ServerStatus {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        Ok(ServerStatus {
+            name: String::decode(r)?,
+            motd: String::decode(r)?,
+            players: u32::decode(r)?,
+            max_players: u32::decode(r)?,
+            version: u16::decode(r)?,
+            seed: u64::decode(r)?,
+            port: u16::decode(r)?,
+        })
+    }
+}
+
+#[test]
+fn status_replies_round_trip() {
+    let status = ServerStatus {
+        name: "Plantex Server".into(),
+        motd: "Welcome!".into(),
+        players: 3,
+        max_players: 32,
+        version: 9,
+        seed: 42,
+        port: 34255,
+    };
+    let reply = encode_status_reply(&status);
+    assert_eq!(decode_status_reply(&reply).unwrap(), status);
+
+    assert!(is_status_query(STATUS_QUERY));
+    assert!(!is_status_query(&reply));
+    assert!(decode_status_reply(STATUS_QUERY).is_err());
+    assert!(decode_status_reply(&reply[..reply.len() - 1]).is_err());
+}
+
+#[test]
+fn long_status_replies_fit_into_a_datagram() {
+    let status = ServerStatus {
+        name: "ä".repeat(MAX_STATUS_TEXT_LEN),
+        motd: "x".repeat(3 * MAX_STATUS_TEXT_LEN),
+        players: 0,
+        max_players: 32,
+        version: 9,
+        seed: 42,
+        port: 34255,
+    };
+    let reply = encode_status_reply(&status);
+    assert!(reply.len() <= MAX_DATAGRAM_LEN);
+    let decoded = decode_status_reply(&reply).unwrap();
+    // The name is cut off between two characters
+    assert_eq!(decoded.name, "ä".repeat(MAX_STATUS_TEXT_LEN / 2));
+    assert_eq!(decoded.motd, "x".repeat(MAX_STATUS_TEXT_LEN));
+}
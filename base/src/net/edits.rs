@@ -0,0 +1,306 @@
+//! Replication of terrain edits.
+//!
+//! Clients apply their own edits immediately and send them to the server as
+//! `Message::PillarEdit`. The server applies the edits in the order it
+//! receives them and broadcasts every accepted edit as `Message::ChunkEdit`
+//! together with the new version of the changed chunk. As edits of different
+//! players can race each other, `EditSync` remembers the server's state of
+//! every chunk with unconfirmed edits of our own. Whenever the server's state
+//! changes, the unconfirmed edits are applied on top of it again.
+
+use std::collections::HashMap;
+use world::{Chunk, ChunkIndex, EditError, PillarEdit, World};
+
+/// The result of applying an edit sent by the server.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditUpdate {
+    /// Nothing visible changed.
+    Unchanged,
+    /// The given chunk changed and has to be redrawn.
+    Changed(ChunkIndex),
+    /// We missed edits of the given chunk and have to request it again.
+    Resync(ChunkIndex),
+}
+
+/// What we know about a chunk the server sent us.
+struct ChunkState {
+    /// Number of edits the server applied to the chunk.
+    version: u32,
+    /// Whether the chunk was added to the world yet.
+    added: bool,
+    /// Edits received before the chunk was added to the world.
+    queued: Vec<PillarEdit>,
+    /// The server's state of the chunk while we have unconfirmed edits in it.
+    confirmed: Option<Chunk>,
+    /// Set when we requested the chunk again, edits are ignored until it
+    /// arrives.
+    resyncing: bool,
+}
+
+/// Keeps the terrain edits of a client in sync with the server.
+pub struct EditSync {
+    chunks: HashMap<ChunkIndex, ChunkState>,
+    /// Our edits which the server didn't answer yet, in the order they were
+    /// sent.
+    pending: Vec<(u32, PillarEdit)>,
+    next_id: u32,
+}
+
+impl EditSync {
+    pub fn new() -> Self {
+        EditSync {
+            chunks: HashMap::new(),
+            pending: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Called when the server sent the given chunk. The chunk has to be added
+    /// to the world and passed to `chunk_added` afterwards.
+    pub fn chunk_received(&mut self, index: ChunkIndex, version: u32) {
+        self.chunks.insert(
+            index,
+            ChunkState {
+                version: version,
+                added: false,
+                queued: Vec::new(),
+                confirmed: None,
+                resyncing: false,
+            },
+        );
+    }
+
+    /// Called when a chunk sent by the server was added to the world. Applies
+    /// all edits received in the meantime and our unconfirmed edits.
+    pub fn chunk_added(&mut self, index: ChunkIndex, world: &mut World) {
+        let state = match self.chunks.get_mut(&index) {
+            Some(state) => state,
+            None => return,
+        };
+        state.added = true;
+        for edit in state.queued.drain(..) {
+            if let Err(e) = edit.apply(world) {
+                warn!("can't apply edit {:?} of the server: {}", edit, e);
+            }
+        }
+
+        state.confirmed = world.chunk_at(index).cloned();
+        self.reapply_pending(index, world);
+    }
+
+    /// Called when a chunk was removed from the world.
+    pub fn chunk_removed(&mut self, index: ChunkIndex) {
+        self.chunks.remove(&index);
+    }
+
+    /// Applies our own edit to the world. Returns the id the edit has to be
+    /// sent to the server with.
+    pub fn edit(&mut self, edit: PillarEdit, world: &mut World) -> Result<u32, EditError> {
+        let index = edit.chunk();
+        let state = match self.chunks.get_mut(&index) {
+            Some(state) if state.added => state,
+            _ => return Err(EditError::NotLoaded),
+        };
+
+        let before = match world.chunk_at(index) {
+            Some(chunk) if state.confirmed.is_none() => Some(chunk.clone()),
+            _ => None,
+        };
+        edit.apply(world)?;
+        if before.is_some() {
+            state.confirmed = before;
+        }
+
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.pending.push((id, edit));
+        Ok(id)
+    }
+
+    /// Applies an edit the server broadcast.
+    pub fn server_edit(
+        &mut self,
+        version: u32,
+        edit: PillarEdit,
+        origin: Option<u32>,
+        world: &mut World,
+    ) -> EditUpdate {
+        if let Some(id) = origin {
+            self.pending.retain(|&(other, _)| other != id);
+        }
+
+        let index = edit.chunk();
+        let res = match self.chunks.get_mut(&index) {
+            // We don't have the chunk anymore, or are about to get a fresh
+            // copy of it
+            None => return EditUpdate::Unchanged,
+            Some(ref state) if state.resyncing || version <= state.version => {
+                return EditUpdate::Unchanged;
+            }
+            Some(state) => {
+                if version != state.version + 1 {
+                    warn!(
+                        "missed edits of chunk {:?} (version {} after {})",
+                        index, version, state.version
+                    );
+                    state.resyncing = true;
+                    return EditUpdate::Resync(index);
+                }
+                state.version = version;
+
+                if !state.added {
+                    state.queued.push(edit);
+                    return EditUpdate::Unchanged;
+                }
+                match state.confirmed {
+                    Some(ref mut chunk) => edit.apply_to_chunk(chunk),
+                    None => edit.apply(world),
+                }
+            }
+        };
+
+        if let Err(e) = res {
+            warn!("can't apply edit {:?} of the server: {}", edit, e);
+            self.chunks.get_mut(&index).unwrap().resyncing = true;
+            return EditUpdate::Resync(index);
+        }
+        self.reapply_pending(index, world);
+        EditUpdate::Changed(index)
+    }
+
+    /// Called when the server rejected our edit with the given id. Returns
+    /// the chunk that changed, if any.
+    pub fn rejected(&mut self, id: u32, world: &mut World) -> Option<ChunkIndex> {
+        let pos = self.pending.iter().position(|&(other, _)| other == id)?;
+        let (_, edit) = self.pending.remove(pos);
+        let index = edit.chunk();
+        match self.chunks.get(&index) {
+            Some(state) if state.added => {
+                self.reapply_pending(index, world);
+                Some(index)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the number of our edits the server didn't answer yet.
+    pub fn pending_edits(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Replaces the chunk in the world with the server's state and applies
+    /// our unconfirmed edits on top of it.
+    fn reapply_pending(&mut self, index: ChunkIndex, world: &mut World) {
+        let state = match self.chunks.get_mut(&index) {
+            Some(state) => state,
+            None => return,
+        };
+        let mut chunk = match state.confirmed {
+            Some(ref confirmed) => confirmed.clone(),
+            None => return,
+        };
+
+        let mut has_pending = false;
+        for &(_, ref edit) in self.pending.iter().filter(|&&(_, e)| e.chunk() == index) {
+            has_pending = true;
+            // The server will reject edits which don't fit anymore
+            let _ = edit.apply_to_chunk(&mut chunk);
+        }
+        world.replace_chunk(index, chunk);
+        if !has_pending {
+            state.confirmed = None;
+        }
+    }
+}
+
+impl Default for EditSync {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test]
+fn racing_edits_converge() {
+    use math::AxialPoint;
+    use world::{EditKind, GroundMaterial, HeightType, HexPillar, PillarIndex, PillarSection};
+
+    let index = ChunkIndex(AxialPoint::new(-1, 0));
+    let pos = PillarIndex(AxialPoint::new(-3, 5));
+    let chunk = Chunk::with_pillars(index, |_| {
+        let ground = PillarSection::new(GroundMaterial::Dirt, HeightType(0), HeightType(10));
+        HexPillar::new(vec![ground], vec![], Default::default())
+    });
+    let edit = |height, kind| PillarEdit {
+        pos: pos,
+        height: HeightType(height),
+        kind: kind,
+    };
+    let add_stone = |height| edit(height, EditKind::Add(GroundMaterial::Stone));
+
+    // Two clients and the server all start with the same chunk
+    let mut server = World::empty();
+    server.replace_chunk(index, chunk.clone());
+    let mut world_a = World::empty();
+    let mut world_b = World::empty();
+    let mut sync_a = EditSync::new();
+    let mut sync_b = EditSync::new();
+    for &mut (ref mut world, ref mut sync) in
+        &mut [(&mut world_a, &mut sync_a), (&mut world_b, &mut sync_b)]
+    {
+        sync.chunk_received(index, 0);
+        world.replace_chunk(index, chunk.clone());
+        sync.chunk_added(index, world);
+    }
+
+    // Both clients add a step at the same height before hearing of each
+    // other, the second client also adds a step on top of its own.
+    let a0 = sync_a.edit(add_stone(10), &mut world_a).unwrap();
+    let b0 = sync_b.edit(add_stone(10), &mut world_b).unwrap();
+    let b1 = sync_b.edit(add_stone(11), &mut world_b).unwrap();
+
+    // The server receives the edit of the first client first
+    assert_eq!(add_stone(10).apply(&mut server), Ok(()));
+    assert_eq!(add_stone(10).apply(&mut server), Err(EditError::Occupied));
+    assert_eq!(add_stone(11).apply(&mut server), Ok(()));
+
+    let changed = EditUpdate::Changed(index);
+    let edit_a = sync_a.server_edit(1, add_stone(10), Some(a0), &mut world_a);
+    assert_eq!(edit_a, changed);
+    assert_eq!(
+        sync_a.server_edit(2, add_stone(11), None, &mut world_a),
+        changed
+    );
+
+    assert_eq!(
+        sync_b.server_edit(1, add_stone(10), None, &mut world_b),
+        changed
+    );
+    assert_eq!(sync_b.rejected(b0, &mut world_b), Some(index));
+    assert_eq!(world_b.chunk_at(index), server.chunk_at(index));
+    assert_eq!(
+        sync_b.server_edit(2, add_stone(11), Some(b1), &mut world_b),
+        changed
+    );
+
+    for &(ref world, ref sync) in &[(&world_a, &sync_a), (&world_b, &sync_b)] {
+        assert_eq!(world.chunk_at(index), server.chunk_at(index));
+        assert_eq!(sync.pending_edits(), 0);
+    }
+
+    // Missed edits are detected and a fresh copy of the chunk is requested
+    // only once
+    let remove = edit(0, EditKind::Remove);
+    assert_eq!(
+        sync_a.server_edit(2, remove, None, &mut world_a),
+        EditUpdate::Unchanged
+    );
+    assert_eq!(
+        sync_a.server_edit(4, remove, None, &mut world_a),
+        EditUpdate::Resync(index)
+    );
+    assert_eq!(
+        sync_a.server_edit(5, remove, None, &mut world_a),
+        EditUpdate::Unchanged
+    );
+    assert_eq!(world_a.chunk_at(index), server.chunk_at(index));
+}
@@ -0,0 +1,483 @@
+use super::codec::{invalid_data, Decode, Encode};
+use super::command::Command;
+use super::snapshot::Snapshot;
+use math::*;
+use movement::{Body, Controls, MoveInput};
+use std::io::{self, Read, Write};
+use time::GameTime;
+use weather::{Form, Strength, WeatherState};
+use world::{Chunk, ChunkIndex, EditKind, GroundMaterial, HeightType, PillarEdit, PillarIndex};
+
+/// All messages that can be exchanged between client and server.
+///
+/// Each message is encoded as a one byte tag followed by its fields. See
+/// `write_message` and `read_message` for the framing used on the wire.
+#[derive(Debug, PartialEq)]
+pub enum Message {
+    /// First message sent by the client after connecting. The server answers
+    /// with `Welcome` or rejects the client with `Disconnect`.
+    ///
+    /// The encoding of `Login` and `Disconnect` must never change, so that
+    /// clients with a different protocol version still understand why they
+    /// are rejected.
+    Login {
+        version: u16,
+        name: String,
+    },
+    /// The server's answer to a successful `Login`.
+    Welcome(Welcome),
+    /// Asks the server to send the chunk at the given position.
+    RequestChunk(ChunkIndex),
+    /// A chunk of the world and its version, sent by the server.
+    ChunkData(ChunkIndex, u32, Chunk),
+    /// The server won't send the requested chunk at the given position,
+    /// because it's too far away from the player or can't be loaded.
+    ChunkUnavailable(ChunkIndex),
+    /// The inputs of the last frames of the sending player. Clients collect
+    /// them and send them a few times per second.
+    Move(Vec<MoveInput>),
+    /// The server's state of the receiving player after the input with the
+    /// given sequence number.
+    MoveAck {
+        seq: u32,
+        body: Body,
+    },
+    /// The changes of the other players, sent regularly by the server.
+    Snapshot(Snapshot),
+    /// Asks the server to change a single pillar in the world. The server
+    /// either broadcasts the edit as `ChunkEdit` or answers with
+    /// `EditRejected`, both carrying the `id` chosen by the client.
+    PillarEdit {
+        id: u32,
+        edit: PillarEdit,
+    },
+    /// An edit the server applied to its world. Every edit increases the
+    /// version of the changed chunk by one. `origin` is the id of the edit
+    /// and only set when sent to the player who requested it.
+    ChunkEdit {
+        version: u32,
+        edit: PillarEdit,
+        origin: Option<u32>,
+    },
+    /// The server refused the edit with the given id.
+    EditRejected(u32),
+    /// The current time of day, sent regularly by the server and whenever the
+    /// speed of time changes.
+    TimeSync(GameTime),
+    /// Asks the server to run a command, the server answers with a `Chat`
+    /// message. Only admins who sent the admin password with `Command::Auth`
+    /// may run admin commands.
+    Command(Command),
+    /// Tells the player whether they may fly from now on, sent by the server
+    /// when that changes after the `Welcome`.
+    MayFly(bool),
+    /// The weather at the position of the receiving player, sent by the
+    /// server whenever it changes.
+    WeatherSync(WeatherState),
+    /// A chat message. When a client sends a message to the server, `sender`
+    /// is ignored and replaced by the name of the sending player.
+    Chat {
+        sender: String,
+        text: String,
+    },
+    /// Announces that the sending side closes the connection.
+    Disconnect {
+        reason: String,
+    },
+    /// Sent regularly by the server to check whether the client is still
+    /// there. The client answers with a `Pong` carrying the same number.
+    Ping(u32),
+    Pong(u32),
+}
+
+/// Chat messages may be at most this many characters long.
+pub const MAX_CHAT_LEN: usize = 200;
+
+/// A `Move` message may carry at most this many inputs, the server ignores
+/// the rest.
+pub const MAX_MOVE_INPUTS: usize = 64;
+
+/// Position and orientation of a player.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlayerState {
+    pub position: Point3f,
+    pub theta: f32,
+    pub phi: f32,
+}
+
+/// Everything a client needs to know about the world it joins.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Welcome {
+    /// Seed of the world. Clients need it to generate the plants.
+    pub seed: u64,
+    /// Where the player starts.
+    pub spawn: Point3f,
+    pub time: GameTime,
+    pub server_name: String,
+    /// Distance from the player up to which the server sends chunks.
+    pub view_radius: f32,
+    /// Whether the player may fly. Otherwise the server ignores
+    /// `Controls::fly`.
+    pub may_fly: bool,
+}
+
+const TAG_LOGIN: u8 = 0;
+const TAG_REQUEST_CHUNK: u8 = 1;
+const TAG_CHUNK_DATA: u8 = 2;
+const TAG_MOVE: u8 = 3;
+const TAG_PILLAR_EDIT: u8 = 4;
+const TAG_TIME_SYNC: u8 = 5;
+const TAG_WEATHER_SYNC: u8 = 6;
+const TAG_CHAT: u8 = 7;
+const TAG_DISCONNECT: u8 = 8;
+const TAG_WELCOME: u8 = 9;
+const TAG_PING: u8 = 10;
+const TAG_PONG: u8 = 11;
+const TAG_SNAPSHOT: u8 = 12;
+const TAG_MOVE_ACK: u8 = 13;
+const TAG_CHUNK_EDIT: u8 = 14;
+const TAG_EDIT_REJECTED: u8 = 15;
+const TAG_COMMAND: u8 = 16;
+const TAG_CHUNK_UNAVAILABLE: u8 = 17;
+const TAG_MAY_FLY: u8 = 18;
+
+impl Encode for Message {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        match *self {
+            Message::Login { version, ref name } => {
+                TAG_LOGIN.encode(w)?;
+                version.encode(w)?;
+                name.encode(w)
+            }
+            Message::Welcome(ref welcome) => {
+                TAG_WELCOME.encode(w)?;
+                welcome.encode(w)
+            }
+            Message::RequestChunk(index) => {
+                TAG_REQUEST_CHUNK.encode(w)?;
+                index.encode(w)
+            }
+            Message::ChunkData(index, version, ref chunk) => {
+                TAG_CHUNK_DATA.encode(w)?;
+                index.encode(w)?;
+                version.encode(w)?;
+                chunk.encode(w)
+            }
+            Message::ChunkUnavailable(index) => {
+                TAG_CHUNK_UNAVAILABLE.encode(w)?;
+                index.encode(w)
+            }
+            Message::Move(ref inputs) => {
+                TAG_MOVE.encode(w)?;
+                inputs.encode(w)
+            }
+            Message::MoveAck { seq, ref body } => {
+                TAG_MOVE_ACK.encode(w)?;
+                seq.encode(w)?;
+                body.encode(w)
+            }
+            Message::Snapshot(ref snapshot) => {
+                TAG_SNAPSHOT.encode(w)?;
+                snapshot.encode(w)
+            }
+            Message::PillarEdit { id, ref edit } => {
+                TAG_PILLAR_EDIT.encode(w)?;
+                id.encode(w)?;
+                edit.encode(w)
+            }
+            Message::ChunkEdit {
+                version,
+                ref edit,
+                origin,
+            } => {
+                TAG_CHUNK_EDIT.encode(w)?;
+                version.encode(w)?;
+                edit.encode(w)?;
+                origin.encode(w)
+            }
+            Message::EditRejected(id) => {
+                TAG_EDIT_REJECTED.encode(w)?;
+                id.encode(w)
+            }
+            Message::TimeSync(ref time) => {
+                TAG_TIME_SYNC.encode(w)?;
+                time.encode(w)
+            }
+            Message::Command(ref command) => {
+                TAG_COMMAND.encode(w)?;
+                command.encode(w)
+            }
+            Message::MayFly(may_fly) => {
+                TAG_MAY_FLY.encode(w)?;
+                may_fly.encode(w)
+            }
+            Message::WeatherSync(ref weather) => {
+                TAG_WEATHER_SYNC.encode(w)?;
+                weather.encode(w)
+            }
+            Message::Chat {
+                ref sender,
+                ref text,
+            } => {
+                TAG_CHAT.encode(w)?;
+                sender.encode(w)?;
+                text.encode(w)
+            }
+            Message::Disconnect { ref reason } => {
+                TAG_DISCONNECT.encode(w)?;
+                reason.encode(w)
+            }
+            Message::Ping(n) => {
+                TAG_PING.encode(w)?;
+                n.encode(w)
+            }
+            Message::Pong(n) => {
+                TAG_PONG.encode(w)?;
+                n.encode(w)
+            }
+        }
+    }
+}
+
+impl Decode for Message {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        let msg = match u8::decode(r)? {
+            TAG_LOGIN => Message::Login {
+                version: u16::decode(r)?,
+                name: String::decode(r)?,
+            },
+            TAG_WELCOME => Message::Welcome(Welcome::decode(r)?),
+            TAG_REQUEST_CHUNK => Message::RequestChunk(ChunkIndex::decode(r)?),
+            TAG_CHUNK_DATA => {
+                Message::ChunkData(ChunkIndex::decode(r)?, u32::decode(r)?, Chunk::decode(r)?)
+            }
+            TAG_CHUNK_UNAVAILABLE => Message::ChunkUnavailable(ChunkIndex::decode(r)?),
+            TAG_MOVE => Message::Move(Vec::decode(r)?),
+            TAG_MOVE_ACK => Message::MoveAck {
+                seq: u32::decode(r)?,
+                body: Body::decode(r)?,
+            },
+            TAG_SNAPSHOT => Message::Snapshot(Snapshot::decode(r)?),
+            TAG_PILLAR_EDIT => Message::PillarEdit {
+                id: u32::decode(r)?,
+                edit: PillarEdit::decode(r)?,
+            },
+            TAG_CHUNK_EDIT => Message::ChunkEdit {
+                version: u32::decode(r)?,
+                edit: PillarEdit::decode(r)?,
+                origin: Option::decode(r)?,
+            },
+            TAG_EDIT_REJECTED => Message::EditRejected(u32::decode(r)?),
+            TAG_TIME_SYNC => Message::TimeSync(GameTime::decode(r)?),
+            TAG_COMMAND => Message::Command(Command::decode(r)?),
+            TAG_MAY_FLY => Message::MayFly(bool::decode(r)?),
+            TAG_WEATHER_SYNC => Message::WeatherSync(WeatherState::decode(r)?),
+            TAG_CHAT => Message::Chat {
+                sender: String::decode(r)?,
+                text: String::decode(r)?,
+            },
+            TAG_DISCONNECT => Message::Disconnect {
+                reason: String::decode(r)?,
+            },
+            TAG_PING => Message::Ping(u32::decode(r)?),
+            TAG_PONG => Message::Pong(u32::decode(r)?),
+            _ => return Err(invalid_data("unknown message type")),
+        };
+        Ok(msg)
+    }
+}
+
+impl Encode for PlayerState {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.position.encode(w)?;
+        self.theta.encode(w)?;
+        self.phi.encode(w)
+    }
+}
+
+impl Decode for PlayerState {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        Ok(PlayerState {
+            position: Point3f::decode(r)?,
+            theta: f32::decode(r)?,
+            phi: f32::decode(r)?,
+        })
+    }
+}
+
+impl Encode for Controls {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.forward.encode(w)?;
+        self.right.encode(w)?;
+        self.up.encode(w)?;
+        let flags = self.sprint as u8 | (self.jump as u8) << 1 | (self.fly as u8) << 2;
+        flags.encode(w)?;
+        self.theta.encode(w)?;
+        self.phi.encode(w)
+    }
+}
+
+impl Decode for Controls {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        let forward = f32::decode(r)?;
+        let right = f32::decode(r)?;
+        let up = f32::decode(r)?;
+        let flags = u8::decode(r)?;
+
+        Ok(Controls {
+            forward: forward,
+            right: right,
+            up: up,
+            sprint: flags & 1 != 0,
+            jump: flags & 2 != 0,
+            fly: flags & 4 != 0,
+            theta: f32::decode(r)?,
+            phi: f32::decode(r)?,
+        })
+    }
+}
+
+impl Encode for MoveInput {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.seq.encode(w)?;
+        self.delta.encode(w)?;
+        self.controls.encode(w)
+    }
+}
+
+impl Decode for MoveInput {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        Ok(MoveInput {
+            seq: u32::decode(r)?,
+            delta: f32::decode(r)?,
+            controls: Controls::decode(r)?,
+        })
+    }
+}
+
+impl Encode for Body {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.position.encode(w)?;
+        self.velocity.x.encode(w)?;
+        self.velocity.y.encode(w)?;
+        self.velocity.z.encode(w)?;
+        self.acceleration_timer[0].encode(w)?;
+        self.acceleration_timer[1].encode(w)
+    }
+}
+
+impl Decode for Body {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        Ok(Body {
+            position: Point3f::decode(r)?,
+            velocity: Vector3f::new(f32::decode(r)?, f32::decode(r)?, f32::decode(r)?),
+            acceleration_timer: [f32::decode(r)?, f32::decode(r)?],
+        })
+    }
+}
+
+impl Encode for PillarEdit {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.pos.encode(w)?;
+        self.height.encode(w)?;
+        match self.kind {
+            EditKind::Remove => 0u8.encode(w),
+            EditKind::Add(material) => {
+                1u8.encode(w)?;
+                material.encode(w)
+            }
+        }
+    }
+}
+
+impl Decode for PillarEdit {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        let pos = PillarIndex::decode(r)?;
+        let height = HeightType::decode(r)?;
+        let kind = match u8::decode(r)? {
+            0 => EditKind::Remove,
+            1 => EditKind::Add(GroundMaterial::decode(r)?),
+            _ => return Err(invalid_data("unknown pillar edit")),
+        };
+
+        Ok(PillarEdit {
+            pos: pos,
+            height: height,
+            kind: kind,
+        })
+    }
+}
+
+impl Encode for Welcome {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.seed.encode(w)?;
+        self.spawn.encode(w)?;
+        self.time.encode(w)?;
+        self.server_name.encode(w)?;
+        self.view_radius.encode(w)?;
+        self.may_fly.encode(w)
+    }
+}
+
+impl Decode for Welcome {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        Ok(Welcome {
+            seed: u64::decode(r)?,
+            spawn: Point3f::decode(r)?,
+            time: GameTime::decode(r)?,
+            server_name: String::decode(r)?,
+            view_radius: f32::decode(r)?,
+            may_fly: bool::decode(r)?,
+        })
+    }
+}
+
+impl Encode for GameTime {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.year.encode(w)?;
+        self.day.encode(w)?;
+        self.time_on_day.encode(w)?;
+        self.speed.encode(w)
+    }
+}
+
+impl Decode for GameTime {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        Ok(GameTime {
+            year: u32::decode(r)?,
+            day: u32::decode(r)?,
+            time_on_day: f32::decode(r)?,
+            speed: f32::decode(r)?,
+        })
+    }
+}
+
+impl Encode for WeatherState {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        (self.form as u8).encode(w)?;
+        (self.strength as u8).encode(w)
+    }
+}
+
+impl Decode for WeatherState {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        let form = match u8::decode(r)? {
+            1 => Form::Rain,
+            2 => Form::Snow,
+            3 => Form::Pollen,
+            _ => return Err(invalid_data("unknown weather form")),
+        };
+        let strength = match u8::decode(r)? {
+            0 => Strength::None,
+            1 => Strength::Weak,
+            2 => Strength::Medium,
+            3 => Strength::Heavy,
+            _ => return Err(invalid_data("unknown weather strength")),
+        };
+
+        Ok(WeatherState {
+            form: form,
+            strength: strength,
+        })
+    }
+}
@@ -0,0 +1,355 @@
+//! The binary protocol spoken between client and server.
+//!
+//! Every message is sent as a frame: the length of the encoded message as
+//! `u32` (little endian) followed by the encoded message itself. Right after
+//! connecting, the client logs in with `Message::Login`, which carries its
+//! `PROTOCOL_VERSION`.
+
+mod chunk_codec;
+mod codec;
+mod command;
+mod discovery;
+mod edits;
+mod message;
+mod provider;
+mod snapshot;
+
+pub use self::chunk_codec::*;
+pub use self::codec::*;
+pub use self::command::*;
+pub use self::discovery::*;
+pub use self::edits::*;
+pub use self::message::*;
+pub use self::provider::*;
+pub use self::snapshot::*;
+
+use std::cmp;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// Version of the protocol. Has to be increased with every change to the
+/// encoding of messages.
+pub const PROTOCOL_VERSION: u16 = 14;
+
+/// Frames longer than this are rejected, so that a broken or malicious peer
+/// can't make us allocate arbitrary amounts of memory.
+pub const MAX_FRAME_LEN: u32 = 16 * 1024 * 1024;
+
+/// Encodes the message as one length-prefixed frame. Frames can be sent
+/// several times, or measured before sending them.
+pub fn encode_frame(msg: &Message) -> io::Result<Vec<u8>> {
+    // Reserve space for the length and fill it in afterwards
+    let mut buf = vec![0; 4];
+    msg.encode(&mut buf)?;
+    let len = (buf.len() - 4) as u32;
+    if len > MAX_FRAME_LEN {
+        return Err(invalid_data("message too long"));
+    }
+    buf[..4].copy_from_slice(&len.to_le_bytes());
+    Ok(buf)
+}
+
+/// Writes the message as one length-prefixed frame.
+pub fn write_message<W: Write>(w: &mut W, msg: &Message) -> io::Result<()> {
+    // The whole frame is written at once
+    let frame = encode_frame(msg)?;
+    w.write_all(&frame)?;
+    w.flush()
+}
+
+/// Reads one frame written by `write_message`.
+pub fn read_message<R: Read>(r: &mut R) -> io::Result<Message> {
+    let len = u32::decode(r)?;
+    if len > MAX_FRAME_LEN {
+        return Err(invalid_data("frame too long"));
+    }
+
+    // The length comes from the other side, so the buffer only grows with
+    // the bytes which actually arrive
+    let mut buf = Vec::with_capacity(cmp::min(len as usize, 1024));
+    r.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() < len as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "frame is cut off",
+        ));
+    }
+
+    let mut frame = &buf[..];
+    let msg = Message::decode(&mut frame)?;
+    if !frame.is_empty() {
+        return Err(invalid_data("trailing bytes after message"));
+    }
+    Ok(msg)
+}
+
+/// A TCP connection which sends and receives `Message`s.
+///
+/// Clones of a connection can be used from different threads. Messages sent
+/// by different clones never interleave.
+pub struct Connection {
+    stream: TcpStream,
+    /// Shared by all clones, held while writing a frame.
+    write_lock: Arc<Mutex<()>>,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> Self {
+        // We send many small messages that should arrive as soon as possible
+        if let Err(e) = stream.set_nodelay(true) {
+            warn!("failed to set TCP_NODELAY: {}", e);
+        }
+
+        Connection {
+            stream: stream,
+            write_lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// Opens a connection to the given address.
+    pub fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        TcpStream::connect(addr).map(Connection::new)
+    }
+
+    /// Sends a message, blocking until it is written.
+    pub fn send(&self, msg: &Message) -> io::Result<()> {
+        // A panic while holding the lock can't leave anything inconsistent
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        write_message(&mut &self.stream, msg)
+    }
+
+    /// Sends a frame created by `encode_frame`, blocking until it is
+    /// written.
+    pub fn send_frame(&self, frame: &[u8]) -> io::Result<()> {
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut stream = &self.stream;
+        stream.write_all(frame)?;
+        stream.flush()
+    }
+
+    /// Receives a message, blocking until one arrives.
+    pub fn recv(&self) -> io::Result<Message> {
+        read_message(&mut &self.stream)
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+
+    pub fn try_clone(&self) -> io::Result<Self> {
+        self.stream.try_clone().map(|stream| Connection {
+            stream: stream,
+            write_lock: self.write_lock.clone(),
+        })
+    }
+
+    /// Closes both directions of the connection. Blocking `recv` calls on
+    /// clones of this connection will return.
+    pub fn shutdown(&self) {
+        // This only fails if the connection is already closed
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+
+    /// Spawns a thread which receives messages and forwards them to the
+    /// returned channel.
+    ///
+    /// The first error (which includes the peer closing the connection) is
+    /// forwarded, too, and stops the thread. The thread also stops once the
+    /// receiver is dropped and the next message arrives.
+    pub fn spawn_reader(&self, name: String) -> io::Result<Receiver<io::Result<Message>>> {
+        let conn = self.try_clone()?;
+        let (sender, recv) = channel();
+
+        thread::Builder::new().name(name).spawn(move || loop {
+            let res = conn.recv();
+            let failed = res.is_err();
+            if sender.send(res).is_err() || failed {
+                break;
+            }
+        })?;
+
+        Ok(recv)
+    }
+}
+
+#[test]
+fn frames_round_trip() {
+    use math::*;
+    use movement::{Body, Controls, MoveInput};
+    use time::GameTime;
+    use weather::{Form, Strength, WeatherState};
+    use world::{ChunkIndex, EditKind, GroundMaterial, HeightType, PillarEdit, PillarIndex};
+
+    let messages = vec![
+        Message::Login {
+            version: PROTOCOL_VERSION,
+            name: "Lukas".into(),
+        },
+        Message::Welcome(Welcome {
+            seed: 42,
+            spawn: Point3f::new(15.0, 10.0, 50.0),
+            time: GameTime::default(),
+            server_name: "Plantex Server".into(),
+            view_radius: 160.0,
+            may_fly: true,
+        }),
+        Message::RequestChunk(ChunkIndex(AxialPoint::new(3, -7))),
+        Message::ChunkUnavailable(ChunkIndex(AxialPoint::new(100, 0))),
+        Message::Move(vec![
+            MoveInput {
+                seq: 17,
+                delta: 0.016,
+                controls: Controls {
+                    forward: 1.0,
+                    right: -0.5,
+                    up: 0.0,
+                    sprint: true,
+                    jump: false,
+                    fly: true,
+                    theta: 1.5,
+                    phi: -0.3,
+                },
+            },
+            MoveInput {
+                seq: 18,
+                delta: 0.02,
+                controls: Controls::default(),
+            },
+        ]),
+        Message::MoveAck {
+            seq: 18,
+            body: Body {
+                position: Point3f::new(1.0, -2.5, 30.0),
+                velocity: Vector3f::new(0.5, 0.0, -0.1),
+                acceleration_timer: [12.0, 1.0],
+            },
+        },
+        Message::Snapshot(Snapshot {
+            time: 1200,
+            players: vec![
+                PlayerUpdate {
+                    id: 3,
+                    change: PlayerChange::Joined {
+                        name: "Lukas".into(),
+                        state: PlayerState {
+                            position: Point3f::new(1.0, -2.5, 30.0),
+                            theta: 1.5,
+                            phi: -0.3,
+                        },
+                    },
+                },
+                PlayerUpdate {
+                    id: 1 << 32,
+                    change: PlayerChange::Moved(StateDelta {
+                        position: [-3, 0, 200],
+                        theta: 1,
+                        phi: -7,
+                    }),
+                },
+                PlayerUpdate {
+                    id: 4,
+                    change: PlayerChange::Teleported(PlayerState {
+                        position: Point3f::new(-900.0, 0.0, 12.0),
+                        theta: 0.5,
+                        phi: 3.0,
+                    }),
+                },
+                PlayerUpdate {
+                    id: 5,
+                    change: PlayerChange::Left,
+                },
+            ],
+        }),
+        Message::PillarEdit {
+            id: 7,
+            edit: PillarEdit {
+                pos: PillarIndex(AxialPoint::new(-20, 4)),
+                height: HeightType::from_units(17),
+                kind: EditKind::Add(GroundMaterial::Sand),
+            },
+        },
+        Message::ChunkEdit {
+            version: 12,
+            edit: PillarEdit {
+                pos: PillarIndex(AxialPoint::new(0, 0)),
+                height: HeightType::from_units(3),
+                kind: EditKind::Remove,
+            },
+            origin: Some(8),
+        },
+        Message::EditRejected(9),
+        Message::TimeSync(GameTime {
+            year: 2,
+            day: 5,
+            time_on_day: 360.5,
+            speed: 1.0,
+        }),
+        Message::Command(Command::SetTimeSpeed(100.0)),
+        Message::Command(Command::Kick("Lukas".into())),
+        Message::Command(Command::CreateSnapshot("before".into())),
+        Message::Command(Command::PruneSnapshots(3)),
+        Message::Command(Command::Auth("secret".into())),
+        Message::MayFly(true),
+        Message::Command(Command::Teleport(TeleportTarget::Position(Point3f::new(
+            1.0, 2.0, 3.0,
+        )))),
+        Message::WeatherSync(WeatherState {
+            form: Form::Snow,
+            strength: Strength::Heavy,
+        }),
+        Message::Chat {
+            sender: "Lukas".into(),
+            text: "Hello World!".into(),
+        },
+        Message::Disconnect {
+            reason: "bye".into(),
+        },
+        Message::Ping(7),
+        Message::Pong(7),
+    ];
+
+    let mut stream = Vec::new();
+    for msg in &messages {
+        write_message(&mut stream, msg).unwrap();
+    }
+
+    let mut reader = &stream[..];
+    for msg in &messages {
+        assert_eq!(&read_message(&mut reader).unwrap(), msg);
+    }
+    assert!(reader.is_empty());
+}
+
+#[test]
+fn broken_frames_are_rejected() {
+    use math::AxialPoint;
+    use world::ChunkIndex;
+
+    let mut stream = Vec::new();
+    let msg = Message::RequestChunk(ChunkIndex(AxialPoint::new(1, 1)));
+    write_message(&mut stream, &msg).unwrap();
+
+    // truncated frame
+    let mut reader = &stream[..stream.len() - 1];
+    assert!(read_message(&mut reader).is_err());
+
+    // frame with trailing garbage
+    let mut longer = stream.clone();
+    longer[0] += 1;
+    longer.push(0);
+    assert!(read_message(&mut &longer[..]).is_err());
+
+    // frame which is way too long
+    let mut reader: &[u8] = &[0xFF, 0xFF, 0xFF, 0xFF, 0];
+    assert!(read_message(&mut reader).is_err());
+
+    // frame of the longest length, but whose data never arrives
+    let mut reader = Vec::new();
+    MAX_FRAME_LEN.encode(&mut reader).unwrap();
+    reader.push(0);
+    let err = read_message(&mut &reader[..]).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+}
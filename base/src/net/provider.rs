@@ -0,0 +1,442 @@
+use super::{Connection, Message};
+use prop::plant::Plant;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use world::{Chunk, ChunkIndex, ChunkProvider};
+
+/// How long we wait for the server to answer a chunk request before asking
+/// again.
+pub const DEFAULT_CHUNK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often a chunk request is repeated before giving up.
+pub const DEFAULT_CHUNK_RETRIES: u32 = 3;
+
+/// At most this many chunks are kept in the stash, the oldest ones are
+/// dropped first.
+const MAX_STASHED_CHUNKS: usize = 64;
+
+/// At most this many chunks are requested by `prefetch_chunks()` at once, so
+/// that their answers fit into the stash.
+pub const MAX_PREFETCHED_CHUNKS: usize = 32;
+
+/// Stashed chunks older than this are dropped, they are probably outdated.
+const STASH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What the owner of the connection forwards to the `NetworkProvider`.
+#[derive(Debug)]
+pub enum ChunkUpdate {
+    /// The server sent a chunk (`Message::ChunkData`).
+    Data(ChunkIndex, Chunk),
+    /// The server changed the chunk (`Message::ChunkEdit`), so copies of it
+    /// received earlier are outdated.
+    Edited(ChunkIndex),
+    /// The server refused to send the chunk (`Message::ChunkUnavailable`).
+    Unavailable(ChunkIndex),
+}
+
+/// How waiting for a requested chunk ended.
+enum Answer {
+    Chunk(Chunk),
+    Unavailable,
+    Timeout,
+    Disconnected,
+}
+
+/// A `ChunkProvider` which requests chunks from the server.
+///
+/// The provider only sends requests over the connection. Since all other
+/// messages are received by the owner of the connection, it has to forward
+/// every chunk and every edit to the channel passed to `new()`.
+pub struct NetworkProvider {
+    server: Connection,
+    updates: Receiver<ChunkUpdate>,
+    /// Chunks that arrived while we were waiting for a different one, oldest
+    /// first, with the time they arrived. This happens when the answer to a
+    /// request arrives after it timed out.
+    stash: RefCell<VecDeque<(ChunkIndex, Instant, Chunk)>>,
+    /// Chunks requested from the server and when, which weren't answered
+    /// yet.
+    in_flight: RefCell<HashMap<ChunkIndex, Instant>>,
+    plant_list: Vec<Plant>,
+    timeout: Duration,
+    retries: u32,
+}
+
+impl NetworkProvider {
+    /// Creates a provider sending requests over `server` and receiving the
+    /// answers from `updates`.
+    ///
+    /// The server only sends the plant indices of the props in a chunk, so the
+    /// provider needs the same plant list the server uses.
+    pub fn new(server: Connection, updates: Receiver<ChunkUpdate>, plant_list: Vec<Plant>) -> Self {
+        NetworkProvider {
+            server: server,
+            updates: updates,
+            stash: RefCell::new(VecDeque::new()),
+            in_flight: RefCell::new(HashMap::new()),
+            plant_list: plant_list,
+            timeout: DEFAULT_CHUNK_TIMEOUT,
+            retries: DEFAULT_CHUNK_RETRIES,
+        }
+    }
+
+    /// Sets how long to wait for an answer and how often to repeat a request
+    /// before `load_chunk` gives up.
+    pub fn with_timeout(mut self, timeout: Duration, retries: u32) -> Self {
+        self.timeout = timeout;
+        self.retries = retries;
+        self
+    }
+
+    /// Requests the chunk at `pos` unless a request for it is still pending.
+    /// Returns when the request was sent.
+    fn request(&self, pos: ChunkIndex) -> io::Result<Instant> {
+        let now = Instant::now();
+        let mut in_flight = self.in_flight.borrow_mut();
+        let timeout = self.timeout;
+        in_flight.retain(|_, &mut sent| now - sent < timeout);
+        if let Some(&sent) = in_flight.get(&pos) {
+            return Ok(sent);
+        }
+
+        self.server.send(&Message::RequestChunk(pos))?;
+        in_flight.insert(pos, now);
+        Ok(now)
+    }
+
+    /// Waits until the server answers the request for the chunk at `pos` or
+    /// the timeout expires.
+    fn wait_for_chunk(&self, pos: ChunkIndex, deadline: Instant) -> Answer {
+        let answer = self.wait_until(pos, deadline);
+        self.in_flight.borrow_mut().remove(&pos);
+        answer
+    }
+
+    fn wait_until(&self, pos: ChunkIndex, deadline: Instant) -> Answer {
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Answer::Timeout;
+            }
+
+            match self.updates.recv_timeout(deadline - now) {
+                Ok(ChunkUpdate::Data(index, chunk)) if index == pos => return Answer::Chunk(chunk),
+                Ok(ChunkUpdate::Unavailable(index)) if index == pos => return Answer::Unavailable,
+                Ok(update) => self.handle_update(update),
+                Err(RecvTimeoutError::Timeout) => return Answer::Timeout,
+                Err(RecvTimeoutError::Disconnected) => return Answer::Disconnected,
+            }
+        }
+    }
+
+    /// Stashes received chunks and drops the outdated ones.
+    fn handle_update(&self, update: ChunkUpdate) {
+        let mut stash = self.stash.borrow_mut();
+        match update {
+            ChunkUpdate::Data(index, chunk) => {
+                self.in_flight.borrow_mut().remove(&index);
+                let now = Instant::now();
+                stash.retain(|&(other, time, _)| other != index && now - time < STASH_TIMEOUT);
+                stash.push_back((index, now, chunk));
+                if stash.len() > MAX_STASHED_CHUNKS {
+                    stash.pop_front();
+                }
+            }
+            ChunkUpdate::Edited(index) => stash.retain(|&(other, _, _)| other != index),
+            // Refusals of prefetched chunks, or of requests which already
+            // timed out
+            ChunkUpdate::Unavailable(index) => {
+                self.in_flight.borrow_mut().remove(&index);
+            }
+        }
+    }
+
+    /// Takes the chunk from the stash if it's there and not too old.
+    fn take_stashed(&self, pos: ChunkIndex) -> Option<Chunk> {
+        // Updates which arrived since the last call might replace or outdate
+        // stashed chunks
+        while let Ok(update) = self.updates.try_recv() {
+            self.handle_update(update);
+        }
+        let mut stash = self.stash.borrow_mut();
+        let idx = stash.iter().position(|&(index, _, _)| index == pos)?;
+        match stash.remove(idx) {
+            Some((_, time, chunk)) if time.elapsed() < STASH_TIMEOUT => Some(chunk),
+            _ => None,
+        }
+    }
+}
+
+impl ChunkProvider for NetworkProvider {
+    fn load_chunk(&self, pos: ChunkIndex) -> Option<Chunk> {
+        if let Some(chunk) = self.take_stashed(pos) {
+            return Some(chunk);
+        }
+
+        for attempt in 0..self.retries + 1 {
+            if attempt > 0 {
+                debug!("chunk request for {:?} timed out, retrying", pos);
+            }
+
+            let sent = match self.request(pos) {
+                Ok(sent) => sent,
+                Err(e) => {
+                    warn!("failed to request chunk {:?}: {}", pos, e);
+                    return None;
+                }
+            };
+
+            match self.wait_for_chunk(pos, sent + self.timeout) {
+                Answer::Chunk(chunk) => return Some(chunk),
+                Answer::Unavailable => {
+                    debug!("server refused to send chunk {:?}", pos);
+                    return None;
+                }
+                Answer::Timeout => {}
+                Answer::Disconnected => {
+                    warn!("connection closed while waiting for chunk {:?}", pos);
+                    return None;
+                }
+            }
+        }
+
+        warn!(
+            "server did not send chunk {:?} after {} attempts",
+            pos,
+            self.retries + 1
+        );
+        None
+    }
+
+    fn is_chunk_loadable(&self, _: ChunkIndex) -> bool {
+        // The server generates chunks which don't exist yet
+        true
+    }
+
+    fn get_plant_list(&self) -> Vec<Plant> {
+        self.plant_list.clone()
+    }
+
+    fn prefetch_chunks(&self, chunks: &[ChunkIndex]) {
+        // Answers which already arrived don't have to be requested again
+        while let Ok(update) = self.updates.try_recv() {
+            self.handle_update(update);
+        }
+        for &pos in chunks.iter().take(MAX_PREFETCHED_CHUNKS) {
+            if self
+                .stash
+                .borrow()
+                .iter()
+                .any(|&(index, _, _)| index == pos)
+            {
+                continue;
+            }
+            if let Err(e) = self.request(pos) {
+                warn!("failed to request chunk {:?}: {}", pos, e);
+                return;
+            }
+        }
+    }
+}
+
+#[test]
+fn chunks_are_requested_again_after_timeout() {
+    use math::*;
+    use std::net::TcpListener;
+    use std::sync::mpsc::channel;
+    use std::thread;
+    use world::HexPillar;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // A server which ignores the first request and answers the second one
+    let server = thread::spawn(move || {
+        let conn = Connection::new(listener.accept().unwrap().0);
+        let first = conn.recv().unwrap();
+        let second = conn.recv().unwrap();
+        assert_eq!(first, second);
+
+        if let Message::RequestChunk(index) = second {
+            let chunk = Chunk::with_pillars(index, |_| HexPillar::default());
+            conn.send(&Message::ChunkData(index, 0, chunk)).unwrap();
+        }
+    });
+
+    let conn = Connection::connect(addr).unwrap();
+    let (chunk_sender, chunk_recv) = channel();
+    let reader = conn.try_clone().unwrap();
+    thread::spawn(move || {
+        while let Ok(Message::ChunkData(index, _, chunk)) = reader.recv() {
+            chunk_sender.send(ChunkUpdate::Data(index, chunk)).unwrap();
+        }
+    });
+
+    let provider = NetworkProvider::new(conn, chunk_recv, Vec::new())
+        .with_timeout(Duration::from_millis(100), 1);
+    let index = ChunkIndex(AxialPoint::new(2, -1));
+    let expected = Chunk::with_pillars(index, |_| HexPillar::default());
+    assert_eq!(provider.load_chunk(index), Some(expected));
+
+    server.join().unwrap();
+}
+
+#[test]
+fn outdated_stashed_chunks_are_dropped() {
+    use math::*;
+    use std::net::TcpListener;
+    use std::sync::mpsc::channel;
+    use std::thread;
+    use world::{GroundMaterial, HeightType, HexPillar, PillarSection};
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // A server which answers every request with a chunk of stone pillars
+    let server = thread::spawn(move || {
+        let conn = Connection::new(listener.accept().unwrap().0);
+        let mut answered = 0;
+        while let Ok(Message::RequestChunk(index)) = conn.recv() {
+            let chunk = Chunk::with_pillars(index, |_| {
+                let section = PillarSection::new(
+                    GroundMaterial::Stone,
+                    HeightType::from_units(0),
+                    HeightType::from_units(1),
+                );
+                HexPillar::new(vec![section], Vec::new(), Default::default())
+            });
+            conn.send(&Message::ChunkData(index, 1, chunk)).unwrap();
+            answered += 1;
+        }
+        answered
+    });
+
+    let conn = Connection::connect(addr).unwrap();
+    let (updates, update_recv) = channel();
+    let reader = conn.try_clone().unwrap();
+    let answers = updates.clone();
+    thread::spawn(move || {
+        while let Ok(Message::ChunkData(index, _, chunk)) = reader.recv() {
+            answers.send(ChunkUpdate::Data(index, chunk)).unwrap();
+        }
+    });
+    let closer = conn.try_clone().unwrap();
+    let provider =
+        NetworkProvider::new(conn, update_recv, Vec::new()).with_timeout(Duration::from_secs(5), 0);
+
+    // Far more chunks arrive than the stash holds
+    let index = |q| ChunkIndex(AxialPoint::new(q, 0));
+    let empty = |q| Chunk::with_pillars(index(q), |_| HexPillar::default());
+    for q in 0..MAX_STASHED_CHUNKS as i32 * 2 {
+        updates.send(ChunkUpdate::Data(index(q), empty(q))).unwrap();
+    }
+    updates.send(ChunkUpdate::Edited(index(100))).unwrap();
+    assert_eq!(provider.load_chunk(index(127)), Some(empty(127)));
+    assert_eq!(provider.stash.borrow().len(), MAX_STASHED_CHUNKS - 2);
+
+    // The edited chunk and the dropped oldest chunks are requested again
+    // instead of taken from the stash
+    assert_ne!(provider.load_chunk(index(100)), Some(empty(100)));
+    assert_ne!(provider.load_chunk(index(0)), Some(empty(0)));
+    assert_eq!(provider.load_chunk(index(64)), Some(empty(64)));
+
+    closer.shutdown();
+    assert_eq!(server.join().unwrap(), 2);
+}
+
+#[test]
+fn refused_chunks_are_not_requested_again() {
+    use math::*;
+    use std::net::TcpListener;
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // A server which refuses every request
+    let server = thread::spawn(move || {
+        let conn = Connection::new(listener.accept().unwrap().0);
+        let mut refused = 0;
+        while let Ok(Message::RequestChunk(index)) = conn.recv() {
+            conn.send(&Message::ChunkUnavailable(index)).unwrap();
+            refused += 1;
+        }
+        refused
+    });
+
+    let conn = Connection::connect(addr).unwrap();
+    let (chunk_sender, chunk_recv) = channel();
+    let reader = conn.try_clone().unwrap();
+    thread::spawn(move || {
+        while let Ok(Message::ChunkUnavailable(index)) = reader.recv() {
+            chunk_sender.send(ChunkUpdate::Unavailable(index)).unwrap();
+        }
+    });
+    let closer = conn.try_clone().unwrap();
+    let provider =
+        NetworkProvider::new(conn, chunk_recv, Vec::new()).with_timeout(Duration::from_secs(5), 3);
+
+    let start = Instant::now();
+    assert_eq!(
+        provider.load_chunk(ChunkIndex(AxialPoint::new(100, 0))),
+        None
+    );
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    closer.shutdown();
+    assert_eq!(server.join().unwrap(), 1);
+}
+
+#[test]
+fn prefetched_chunks_are_requested_once() {
+    use math::*;
+    use std::net::TcpListener;
+    use std::sync::mpsc::channel;
+    use std::thread;
+    use world::HexPillar;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // A server which answers all requests in the reverse order
+    let server = thread::spawn(move || {
+        let conn = Connection::new(listener.accept().unwrap().0);
+        let mut requests = Vec::new();
+        while let Ok(Message::RequestChunk(index)) = conn.recv() {
+            requests.push(index);
+            if requests.len() == 3 {
+                for &index in requests.iter().rev() {
+                    let chunk = Chunk::with_pillars(index, |_| HexPillar::default());
+                    conn.send(&Message::ChunkData(index, 0, chunk)).unwrap();
+                }
+            }
+        }
+        requests.len()
+    });
+
+    let conn = Connection::connect(addr).unwrap();
+    let (chunk_sender, chunk_recv) = channel();
+    let reader = conn.try_clone().unwrap();
+    thread::spawn(move || {
+        while let Ok(Message::ChunkData(index, _, chunk)) = reader.recv() {
+            chunk_sender.send(ChunkUpdate::Data(index, chunk)).unwrap();
+        }
+    });
+    let closer = conn.try_clone().unwrap();
+    let provider =
+        NetworkProvider::new(conn, chunk_recv, Vec::new()).with_timeout(Duration::from_secs(5), 0);
+
+    let chunks: Vec<_> = (0..3).map(|q| ChunkIndex(AxialPoint::new(q, 0))).collect();
+    provider.prefetch_chunks(&chunks);
+    for &index in &chunks {
+        let expected = Chunk::with_pillars(index, |_| HexPillar::default());
+        assert_eq!(provider.load_chunk(index), Some(expected));
+    }
+
+    closer.shutdown();
+    assert_eq!(server.join().unwrap(), 3);
+}
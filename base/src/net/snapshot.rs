@@ -0,0 +1,513 @@
+//! Replication of player states.
+//!
+//! The server regularly sends every client a `Snapshot` with the changes of
+//! all other players since the previous snapshot. It remembers what each
+//! client knows in `KnownPlayers`, so that movements can be sent as small
+//! fixed point deltas. Clients collect the snapshots in `RemotePlayers` and
+//! show the other players slightly in the past, interpolated between two
+//! snapshots.
+
+use super::codec::{invalid_data, Decode, Encode};
+use super::message::PlayerState;
+use math::*;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+/// Positions in deltas are multiples of this.
+const POSITION_STEP: f32 = 1.0 / 64.0;
+
+/// Angles in deltas are multiples of this.
+const ANGLE_STEP: f32 = 1.0 / 4096.0;
+
+/// Remote players are shown this far in the past, so that there usually is
+/// a newer snapshot to interpolate to.
+pub const INTERPOLATION_DELAY: Duration = Duration::from_millis(150);
+
+/// How fast the estimated server clock follows the times of new snapshots.
+/// Smaller values hide more network jitter, but adapt slower.
+const CLOCK_SMOOTHING: f64 = 0.1;
+
+/// The changes of the player states since the previous snapshot.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    /// Server time in milliseconds at which the snapshot was taken.
+    pub time: u64,
+    /// Players who didn't change are left out.
+    pub players: Vec<PlayerUpdate>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlayerUpdate {
+    /// Identifies the player for as long as they are connected.
+    pub id: u64,
+    pub change: PlayerChange,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PlayerChange {
+    /// The receiver doesn't know the player yet.
+    Joined { name: String, state: PlayerState },
+    /// The player moved since the last snapshot.
+    Moved(StateDelta),
+    /// The player moved too far to describe it with a delta.
+    Teleported(PlayerState),
+    /// The player left the server.
+    Left,
+}
+
+/// The difference between two player states in fixed point.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StateDelta {
+    pub position: [i16; 3],
+    pub theta: i16,
+    pub phi: i16,
+}
+
+impl StateDelta {
+    /// Returns the delta from `from` to `to`, or `None` if they are too far
+    /// apart. Applying the delta to `from` only results in `to` up to the
+    /// precision of the delta.
+    pub fn between(from: &PlayerState, to: &PlayerState) -> Option<Self> {
+        fn quantize(diff: f32, step: f32) -> Option<i16> {
+            let steps = (diff / step).round();
+            if steps.abs() <= i16::max_value() as f32 {
+                Some(steps as i16)
+            } else {
+                None
+            }
+        }
+
+        Some(StateDelta {
+            position: [
+                quantize(to.position.x - from.position.x, POSITION_STEP)?,
+                quantize(to.position.y - from.position.y, POSITION_STEP)?,
+                quantize(to.position.z - from.position.z, POSITION_STEP)?,
+            ],
+            theta: quantize(to.theta - from.theta, ANGLE_STEP)?,
+            phi: quantize(to.phi - from.phi, ANGLE_STEP)?,
+        })
+    }
+
+    pub fn is_zero(&self) -> bool {
+        *self == StateDelta::default()
+    }
+
+    pub fn apply(&self, state: &PlayerState) -> PlayerState {
+        let offset = Vector3f::new(
+            self.position[0] as f32,
+            self.position[1] as f32,
+            self.position[2] as f32,
+        ) * POSITION_STEP;
+
+        PlayerState {
+            position: state.position + offset,
+            theta: state.theta + self.theta as f32 * ANGLE_STEP,
+            phi: state.phi + self.phi as f32 * ANGLE_STEP,
+        }
+    }
+}
+
+/// What a client knows about the other players. The server keeps one for
+/// every client to find out what has to be sent in the next snapshot.
+#[derive(Debug, Default)]
+pub struct KnownPlayers {
+    /// The states as the client reconstructs them from the deltas.
+    states: HashMap<u64, PlayerState>,
+}
+
+impl KnownPlayers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compares the current players with what the client knows and returns
+    /// the updates that have to be sent. Afterwards, the client is assumed
+    /// to know about all updates.
+    pub fn diff<'a, I>(&mut self, players: I) -> Vec<PlayerUpdate>
+    where
+        I: IntoIterator<Item = (u64, &'a str, PlayerState)>,
+    {
+        let mut updates = Vec::new();
+        let mut current = HashMap::new();
+
+        for (id, name, state) in players {
+            let change = match self.states.get(&id) {
+                None => Some(PlayerChange::Joined {
+                    name: name.to_string(),
+                    state: state,
+                }),
+                Some(known) => match StateDelta::between(known, &state) {
+                    Some(ref delta) if delta.is_zero() => None,
+                    Some(delta) => Some(PlayerChange::Moved(delta)),
+                    None => Some(PlayerChange::Teleported(state)),
+                },
+            };
+
+            // Remember the state exactly as the client will see it, so that
+            // rounding errors don't add up
+            let known = match change {
+                Some(PlayerChange::Moved(ref delta)) => delta.apply(&self.states[&id]),
+                Some(_) => state,
+                None => self.states[&id],
+            };
+            current.insert(id, known);
+
+            if let Some(change) = change {
+                updates.push(PlayerUpdate {
+                    id: id,
+                    change: change,
+                });
+            }
+        }
+
+        for &id in self.states.keys() {
+            if !current.contains_key(&id) {
+                updates.push(PlayerUpdate {
+                    id: id,
+                    change: PlayerChange::Left,
+                });
+            }
+        }
+
+        self.states = current;
+        updates
+    }
+}
+
+/// A player on the server as seen by a client.
+#[derive(Debug)]
+pub struct RemotePlayer {
+    pub name: String,
+    /// Recent states with the server time they belong to, oldest first.
+    samples: VecDeque<(u64, PlayerState)>,
+}
+
+impl RemotePlayer {
+    /// Returns the state at the given server time, interpolated between the
+    /// two closest snapshots.
+    pub fn state_at(&self, time: f64) -> PlayerState {
+        let mut prev: Option<(u64, PlayerState)> = None;
+        for &(sample_time, state) in &self.samples {
+            if sample_time as f64 > time {
+                let (prev_time, prev_state) = match prev {
+                    Some(prev) => prev,
+                    None => return state,
+                };
+                let t = ((time - prev_time as f64) / (sample_time - prev_time) as f64) as f32;
+                return PlayerState {
+                    position: prev_state.position + (state.position - prev_state.position) * t,
+                    theta: prev_state.theta + (state.theta - prev_state.theta) * t,
+                    phi: prev_state.phi + (state.phi - prev_state.phi) * t,
+                };
+            }
+            prev = Some((sample_time, state));
+        }
+        self.latest()
+    }
+
+    fn latest(&self) -> PlayerState {
+        self.samples.back().unwrap().1
+    }
+}
+
+/// All other players on the server, built from the snapshots the server
+/// sends.
+#[derive(Debug, Default)]
+pub struct RemotePlayers {
+    players: HashMap<u64, RemotePlayer>,
+    /// When the first snapshot arrived and the estimated difference between
+    /// the server clock and ours in milliseconds.
+    clock: Option<(Instant, f64)>,
+}
+
+impl RemotePlayers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies a snapshot which arrived at `now`.
+    pub fn apply(&mut self, snapshot: Snapshot, now: Instant) {
+        let clock = match self.clock {
+            Some((start, offset)) => {
+                let sample = snapshot.time as f64 - millis_since(start, now);
+                (start, offset + (sample - offset) * CLOCK_SMOOTHING)
+            }
+            None => (now, snapshot.time as f64),
+        };
+        self.clock = Some(clock);
+
+        // Players missing in the snapshot didn't move
+        for player in self.players.values_mut() {
+            let latest = player.latest();
+            player.samples.push_back((snapshot.time, latest));
+        }
+
+        for update in snapshot.players {
+            match update.change {
+                PlayerChange::Joined { name, state } => {
+                    let mut samples = VecDeque::new();
+                    samples.push_back((snapshot.time, state));
+                    self.players.insert(
+                        update.id,
+                        RemotePlayer {
+                            name: name,
+                            samples: samples,
+                        },
+                    );
+                }
+                PlayerChange::Moved(delta) => match self.players.get_mut(&update.id) {
+                    Some(player) => {
+                        let latest = &mut player.samples.back_mut().unwrap().1;
+                        *latest = delta.apply(latest);
+                    }
+                    None => warn!("received movement of unknown player {}", update.id),
+                },
+                PlayerChange::Teleported(state) => {
+                    if let Some(player) = self.players.get_mut(&update.id) {
+                        // Don't interpolate across the teleport
+                        player.samples.clear();
+                        player.samples.push_back((snapshot.time, state));
+                    }
+                }
+                PlayerChange::Left => {
+                    self.players.remove(&update.id);
+                }
+            }
+        }
+
+        // Only keep the samples needed for interpolating from now on
+        let render_time = self.render_time(now);
+        for player in self.players.values_mut() {
+            while player.samples.len() > 2 && player.samples[1].0 as f64 <= render_time {
+                player.samples.pop_front();
+            }
+        }
+    }
+
+    /// Returns the server time in milliseconds the players should be shown
+    /// at.
+    pub fn render_time(&self, now: Instant) -> f64 {
+        match self.clock {
+            Some((start, offset)) => {
+                millis_since(start, now) + offset - millis(INTERPOLATION_DELAY)
+            }
+            None => 0.0,
+        }
+    }
+
+    /// Returns the interpolated states of all players at `now`.
+    pub fn states(&self, now: Instant) -> Vec<PlayerState> {
+        let time = self.render_time(now);
+        self.players
+            .values()
+            .map(|player| player.state_at(time))
+            .collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<&RemotePlayer> {
+        self.players.get(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty()
+    }
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs() as f64 * 1000.0 + d.subsec_nanos() as f64 / 1_000_000.0
+}
+
+fn millis_since(start: Instant, now: Instant) -> f64 {
+    if now > start {
+        millis(now - start)
+    } else {
+        0.0
+    }
+}
+
+const CHANGE_JOINED: u8 = 0;
+const CHANGE_MOVED: u8 = 1;
+const CHANGE_TELEPORTED: u8 = 2;
+const CHANGE_LEFT: u8 = 3;
+
+impl Encode for Snapshot {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.time.encode(w)?;
+        self.players.encode(w)
+    }
+}
+
+impl Decode for Snapshot {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        Ok(Snapshot {
+            time: u64::decode(r)?,
+            players: Vec::decode(r)?,
+        })
+    }
+}
+
+impl Encode for PlayerUpdate {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.id.encode(w)?;
+        match self.change {
+            PlayerChange::Joined {
+                ref name,
+                ref state,
+            } => {
+                CHANGE_JOINED.encode(w)?;
+                name.encode(w)?;
+                state.encode(w)
+            }
+            PlayerChange::Moved(ref delta) => {
+                CHANGE_MOVED.encode(w)?;
+                for coord in &delta.position {
+                    coord.encode(w)?;
+                }
+                delta.theta.encode(w)?;
+                delta.phi.encode(w)
+            }
+            PlayerChange::Teleported(ref state) => {
+                CHANGE_TELEPORTED.encode(w)?;
+                state.encode(w)
+            }
+            PlayerChange::Left => CHANGE_LEFT.encode(w),
+        }
+    }
+}
+
+impl Decode for PlayerUpdate {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        let id = u64::decode(r)?;
+        let change = match u8::decode(r)? {
+            CHANGE_JOINED => PlayerChange::Joined {
+                name: String::decode(r)?,
+                state: PlayerState::decode(r)?,
+            },
+            CHANGE_MOVED => PlayerChange::Moved(StateDelta {
+                position: [i16::decode(r)?, i16::decode(r)?, i16::decode(r)?],
+                theta: i16::decode(r)?,
+                phi: i16::decode(r)?,
+            }),
+            CHANGE_TELEPORTED => PlayerChange::Teleported(PlayerState::decode(r)?),
+            CHANGE_LEFT => PlayerChange::Left,
+            _ => return Err(invalid_data("unknown player change")),
+        };
+
+        Ok(PlayerUpdate {
+            id: id,
+            change: change,
+        })
+    }
+}
+
+#[cfg(test)]
+fn state(x: f32, y: f32) -> PlayerState {
+    PlayerState {
+        position: Point3f::new(x, y, 50.0),
+        theta: 1.5,
+        phi: 0.0,
+    }
+}
+
+#[test]
+fn deltas_do_not_accumulate_errors() {
+    let mut known = KnownPlayers::new();
+    let mut remote = RemotePlayers::new();
+    let now = Instant::now();
+
+    // Tiny steps below the delta precision must not get lost either
+    for i in 0..1000 {
+        let x = i as f32 * 0.003;
+        let updates = known.diff(vec![(1, "Lukas", state(x, -x))]);
+        if i == 0 {
+            assert_eq!(updates.len(), 1);
+        }
+        remote.apply(
+            Snapshot {
+                time: i * 100,
+                players: updates,
+            },
+            now + Duration::from_millis(i * 100),
+        );
+    }
+
+    let latest = remote.get(1).unwrap().latest();
+    assert!((latest.position.x - 2.997).abs() <= POSITION_STEP);
+    assert!((latest.position.y + 2.997).abs() <= POSITION_STEP);
+}
+
+#[test]
+fn players_join_teleport_and_leave() {
+    let mut known = KnownPlayers::new();
+
+    let updates = known.diff(vec![(1, "a", state(0.0, 0.0)), (2, "b", state(0.0, 0.0))]);
+    assert_eq!(updates.len(), 2);
+    assert!(
+        known.diff(vec![(1, "a", state(0.0, 0.0))])
+            == vec![PlayerUpdate {
+                id: 2,
+                change: PlayerChange::Left,
+            }]
+    );
+
+    let updates = known.diff(vec![(1, "a", state(5000.0, 0.0))]);
+    assert_eq!(
+        updates[0].change,
+        PlayerChange::Teleported(state(5000.0, 0.0))
+    );
+    assert!(known.diff(vec![(1, "a", state(5000.0, 0.0))]).is_empty());
+}
+
+#[test]
+fn interpolation_converges_despite_jitter() {
+    let mut known = KnownPlayers::new();
+    let mut remote = RemotePlayers::new();
+    let start = Instant::now();
+    let jitter = [0, 35, 5, 20, 0, 40, 10, 30, 15, 25];
+
+    // The player walks one unit per 100ms along the x axis. Snapshots are
+    // sent every 100ms and arrive 50ms later plus some jitter.
+    let mut last_x = -1.0;
+    for i in 0..200u64 {
+        let updates = known.diff(vec![(7, "walker", state(i as f32, 0.0))]);
+        let arrival = start + Duration::from_millis(i * 100 + 50 + jitter[i as usize % 10]);
+        remote.apply(
+            Snapshot {
+                time: i * 100,
+                players: updates,
+            },
+            arrival,
+        );
+
+        // Sample between snapshots, like a renderer would
+        for &offset in &[0, 30, 60] {
+            let now = arrival + Duration::from_millis(offset);
+            let x = remote.states(now)[0].position.x;
+            assert!(
+                x >= last_x - 0.2,
+                "player jumped back from {} to {}",
+                last_x,
+                x
+            );
+            last_x = x;
+
+            if i > 100 {
+                // The server clock is estimated from the arrival times, so
+                // the shown position lags behind by the delay only
+                let render_time = (i * 100 + offset) as f32 - 150.0;
+                let expected = render_time / 100.0;
+                assert!(
+                    (x - expected).abs() < 0.5,
+                    "expected x near {}, got {}",
+                    expected,
+                    x
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Types to represent the in-game time.
+
+/// Length of a day in seconds (at normal speed). Noon is at half of it.
+pub const DAY_LENGTH: f32 = 720.0;
+
+/// Number of days in a year.
+pub const YEAR_LENGTH: u32 = 12;
+
+/// Time passes at this speed by default.
+pub const DEFAULT_TIME_SPEED: f32 = 1.0;
+
+/// The fastest speed the server lets time pass at.
+pub const MAX_TIME_SPEED: f32 = 1000.0;
+
+/// A `SyncedClock` catches up this fraction of its difference to the server
+/// per real second.
+const CLOCK_CORRECTION_RATE: f64 = 0.5;
+
+/// A `SyncedClock` jumps to the server's time instead of catching up if it's
+/// off by more than this many game seconds.
+const MAX_CLOCK_ERROR: f64 = 60.0;
+
+/// A point in game time together with the speed at which time passes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GameTime {
+    pub year: u32,
+    /// Day of the year, less than `YEAR_LENGTH`.
+    pub day: u32,
+    /// Seconds since the start of the day, less than `DAY_LENGTH`.
+    pub time_on_day: f32,
+    /// How many game seconds pass per real second.
+    pub speed: f32,
+}
+
+impl Default for GameTime {
+    fn default() -> Self {
+        GameTime {
+            year: 0,
+            day: 0,
+            time_on_day: DAY_LENGTH / 3.0,
+            speed: DEFAULT_TIME_SPEED,
+        }
+    }
+}
+
+impl GameTime {
+    /// Lets `delta` real seconds pass.
+    pub fn advance(&mut self, delta: f32) {
+        self.time_on_day += delta * self.speed;
+        while self.time_on_day >= DAY_LENGTH {
+            // Removes one day from time_on_day
+            self.time_on_day -= DAY_LENGTH;
+            self.day += 1;
+            if self.day >= YEAR_LENGTH {
+                self.day = 0;
+                self.year += 1;
+            }
+        }
+    }
+
+    /// Returns the number of game seconds since the start of year 0.
+    pub fn seconds(&self) -> f64 {
+        let days = u64::from(self.year) * u64::from(YEAR_LENGTH) + u64::from(self.day);
+        days as f64 * f64::from(DAY_LENGTH) + f64::from(self.time_on_day)
+    }
+
+    /// Sets the time to the given number of game seconds since the start of
+    /// year 0. The speed is kept.
+    pub fn set_seconds(&mut self, seconds: f64) {
+        let seconds = seconds.max(0.0);
+        let days = (seconds / f64::from(DAY_LENGTH)).floor();
+        self.year = (days as u64 / u64::from(YEAR_LENGTH)) as u32;
+        self.day = (days as u64 % u64::from(YEAR_LENGTH)) as u32;
+        // Rounding to `f32` must not reach the end of the day
+        let time_on_day = (seconds - days * f64::from(DAY_LENGTH)) as f32;
+        self.time_on_day = time_on_day.min(DAY_LENGTH - 0.001);
+    }
+}
+
+/// A local copy of the server's clock.
+///
+/// The clock runs on its own between the time syncs of the server. When a
+/// sync arrives, the clock doesn't jump to the new time but catches up
+/// smoothly, so that the sun doesn't move in steps.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SyncedClock {
+    time: GameTime,
+    /// Game seconds we still have to catch up with the server.
+    error: f64,
+}
+
+impl SyncedClock {
+    pub fn new(time: GameTime) -> Self {
+        SyncedClock {
+            time: time,
+            error: 0.0,
+        }
+    }
+
+    pub fn time(&self) -> GameTime {
+        self.time
+    }
+
+    /// Called with every time the server sent us.
+    pub fn sync(&mut self, server: GameTime) {
+        self.time.speed = server.speed;
+        self.error = server.seconds() - self.time.seconds();
+        if self.error.abs() > MAX_CLOCK_ERROR {
+            self.time = server;
+            self.error = 0.0;
+        }
+    }
+
+    /// Lets `delta` real seconds pass.
+    pub fn advance(&mut self, delta: f32) {
+        self.time.advance(delta);
+
+        let correction = self.error * (f64::from(delta) * CLOCK_CORRECTION_RATE).min(1.0);
+        let seconds = self.time.seconds();
+        self.time.set_seconds(seconds + correction);
+        self.error -= correction;
+    }
+}
+
+#[test]
+fn advance_wraps_days_and_years() {
+    let mut time = GameTime {
+        year: 3,
+        day: YEAR_LENGTH - 1,
+        time_on_day: DAY_LENGTH - 1.0,
+        speed: 2.0,
+    };
+    time.advance(1.0);
+
+    assert_eq!(time.year, 4);
+    assert_eq!(time.day, 0);
+    assert_eq!(time.time_on_day, 1.0);
+}
+
+#[test]
+fn synced_clock_catches_up_smoothly() {
+    let mut server = GameTime::default();
+    let mut clock = SyncedClock::new(server);
+
+    // Our clock runs a bit too slow, e.g. because of lags
+    for i in 0..600 {
+        server.advance(0.1);
+        let before = clock.time().seconds();
+        clock.advance(0.09);
+        let step = clock.time().seconds() - before;
+        assert!(step > 0.0 && step < 0.5, "clock jumped by {}", step);
+        if i % 50 == 0 {
+            clock.sync(server);
+        }
+    }
+    assert!((clock.time().seconds() - server.seconds()).abs() < 1.0);
+
+    // Large differences are not caught up slowly
+    server.set_seconds(server.seconds() + 3.0 * f64::from(DAY_LENGTH));
+    server.speed = 100.0;
+    clock.sync(server);
+    assert_eq!(clock.time(), server);
+}
+
+#[test]
+fn seconds_round_trip() {
+    let time = GameTime {
+        year: 7,
+        day: 11,
+        time_on_day: 700.5,
+        speed: 3.0,
+    };
+    let mut other = GameTime::default();
+    other.set_seconds(time.seconds());
+    assert_eq!(other.year, time.year);
+    assert_eq!(other.day, time.day);
+    assert!((other.time_on_day - time.time_on_day).abs() < 0.01);
+}
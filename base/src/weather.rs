@@ -0,0 +1,126 @@
+//! Types describing the weather in the game world.
+//!
+
+use gen::seeded_rng;
+use gen::world::biome::Biome;
+use rand::Rng;
+use time::GameTime;
+use world::PillarIndex;
+
+/// The kind of downfall.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Form {
+    Rain = 1,
+    Snow = 2,
+    Pollen = 3,
+}
+
+/// How heavy the downfall is.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Strength {
+    None = 0,
+    Weak = 1,
+    Medium = 2,
+    Heavy = 3,
+}
+
+/// Side length of a weather region in chunks. Players in the same region see
+/// the same weather.
+pub const REGION_SIZE: i32 = 4;
+
+/// The weather of every region changes every this many game seconds.
+pub const WEATHER_PERIOD: f64 = 120.0;
+
+/// The downfall at some place.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WeatherState {
+    pub form: Form,
+    pub strength: Strength,
+}
+
+/// Decides the weather of the world. The weather only depends on the world
+/// seed, the region, the biome and the time, so it's the same for everyone
+/// asking.
+#[derive(Clone, Copy, Debug)]
+pub struct WeatherModel {
+    seed: u64,
+}
+
+impl WeatherModel {
+    pub fn with_seed(seed: u64) -> Self {
+        WeatherModel { seed: seed }
+    }
+
+    /// Returns the weather at the given pillar of the given biome.
+    pub fn weather_at(&self, pos: PillarIndex, biome: &Biome, time: &GameTime) -> WeatherState {
+        let chunk = pos.chunk().0;
+        let region = (
+            chunk.q.div_euclid(REGION_SIZE),
+            chunk.r.div_euclid(REGION_SIZE),
+        );
+        let period = (time.seconds() / WEATHER_PERIOD) as u64;
+        let chance = seeded_rng(self.seed, "weather", (region, period)).gen::<f32>() * 100.0;
+
+        // Chances of the weak, medium and heavy downfall in percent
+        let (form, weak, medium, heavy) = match *biome {
+            Biome::GrassLand => (Form::Rain, 5.0, 3.0, 2.0),
+            Biome::Desert => (Form::Rain, 1.0, 1.0, 2.0),
+            Biome::Snow => (Form::Snow, 20.0, 30.0, 25.0),
+            Biome::Forest => (Form::Pollen, 15.0, 20.0, 30.0),
+            Biome::RainForest => (Form::Rain, 21.0, 30.0, 30.0),
+            Biome::Savanna => (Form::Pollen, 5.0, 2.0, 2.0),
+            Biome::Stone => (Form::Rain, 7.0, 5.0, 5.0),
+            Biome::Debug => (Form::Rain, 0.0, 0.0, 0.0),
+        };
+        let strength = if chance < weak {
+            Strength::Weak
+        } else if chance < weak + medium {
+            Strength::Medium
+        } else if chance < weak + medium + heavy {
+            Strength::Heavy
+        } else {
+            Strength::None
+        };
+
+        WeatherState {
+            form: form,
+            strength: strength,
+        }
+    }
+}
+
+#[test]
+fn weather_is_deterministic_per_region_and_period() {
+    use math::AxialPoint;
+
+    let model = WeatherModel::with_seed(42);
+    let time = |seconds| {
+        let mut time = GameTime::default();
+        time.set_seconds(seconds);
+        time
+    };
+    let pos = |q, r| PillarIndex(AxialPoint::new(q, r));
+    let size = REGION_SIZE * 16;
+
+    // Snow is common enough to see changes over a few periods
+    let weather = |pos, seconds| model.weather_at(pos, &Biome::Snow, &time(seconds));
+    let first = weather(pos(0, 0), 10.0);
+    assert_eq!(
+        first,
+        WeatherModel::with_seed(42).weather_at(pos(0, 0), &Biome::Snow, &time(10.0))
+    );
+    assert_eq!(
+        first,
+        weather(pos(size - 1, size - 1), 10.0 + WEATHER_PERIOD / 2.0)
+    );
+    assert_eq!(first.form, Form::Snow);
+
+    let strengths: Vec<_> = (0..50)
+        .map(|i| weather(pos(0, 0), i as f64 * WEATHER_PERIOD).strength)
+        .collect();
+    assert!(strengths.iter().any(|&s| s != strengths[0]));
+    let regions: Vec<_> = (0..50)
+        .map(|i| weather(pos(-i * size, 3 * i * size), 10.0).strength)
+        .collect();
+    assert!(regions.iter().any(|&s| s != regions[0]));
+}
@@ -0,0 +1,247 @@
+use super::{
+    Chunk, ChunkIndex, GroundMaterial, HeightType, HexPillar, PillarIndex, PillarSection, World,
+    MAX_PILLAR_SECTIONS,
+};
+use std::error::Error;
+use std::fmt;
+
+/// The change of a pillar at the given height.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PillarEdit {
+    pub pos: PillarIndex,
+    pub height: HeightType,
+    pub kind: EditKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EditKind {
+    /// Removes the section step at the given height.
+    Remove,
+    /// Adds a section step of the given material at the given height.
+    Add(GroundMaterial),
+}
+
+/// Reasons why an edit can't be applied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditError {
+    /// The chunk of the edited pillar is not loaded.
+    NotLoaded,
+    /// There is no section at the height which could be removed.
+    NothingToRemove,
+    /// There already is a section at the height.
+    Occupied,
+    /// The pillar would have more than `MAX_PILLAR_SECTIONS` sections.
+    TooManySections,
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match *self {
+            EditError::NotLoaded => "chunk is not loaded",
+            EditError::NothingToRemove => "nothing to remove",
+            EditError::Occupied => "position is occupied",
+            EditError::TooManySections => "pillar has too many sections",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl Error for EditError {}
+
+impl PillarEdit {
+    /// Returns the index of the chunk this edit changes.
+    pub fn chunk(&self) -> ChunkIndex {
+        self.pos.chunk()
+    }
+
+    /// Applies the edit to the pillar in the world.
+    pub fn apply(&self, world: &mut World) -> Result<(), EditError> {
+        match world.pillar_at_mut(self.pos) {
+            Some(pillar) => self.apply_to_pillar(pillar),
+            None => Err(EditError::NotLoaded),
+        }
+    }
+
+    /// Applies the edit to the given chunk, which has to be the chunk of the
+    /// edited pillar.
+    pub fn apply_to_chunk(&self, chunk: &mut Chunk) -> Result<(), EditError> {
+        self.apply_to_pillar(&mut chunk[self.pos.pos_in_chunk()])
+    }
+
+    /// Applies the edit to the given pillar.
+    ///
+    /// Sections are split or shrunk when a step is removed and neighbouring
+    /// sections of the same material are merged when a step is added. Props
+    /// which would float in the air or be buried are removed.
+    pub fn apply_to_pillar(&self, pillar: &mut HexPillar) -> Result<(), EditError> {
+        let h = self.height;
+        let above = HeightType(h.0.checked_add(1).ok_or(EditError::Occupied)?);
+
+        match self.kind {
+            EditKind::Remove => {
+                let sections = pillar.sections_mut();
+                let i = sections
+                    .iter()
+                    .position(|s| s.bottom <= h && h < s.top)
+                    .ok_or(EditError::NothingToRemove)?;
+                let splits = sections[i].bottom < h && above < sections[i].top;
+                if splits && sections.len() >= MAX_PILLAR_SECTIONS {
+                    return Err(EditError::TooManySections);
+                }
+                let section = sections.remove(i);
+                if above < section.top {
+                    sections.insert(i, PillarSection::new(section.ground, above, section.top));
+                }
+                if section.bottom < h {
+                    sections.insert(i, PillarSection::new(section.ground, section.bottom, h));
+                }
+
+                // Props standing on the removed step would float in the air
+                if above == section.top {
+                    pillar.props_mut().retain(|p| p.baseline != above);
+                }
+            }
+            EditKind::Add(ground) => {
+                let sections = pillar.sections_mut();
+                if sections.iter().any(|s| s.bottom <= h && h < s.top) {
+                    return Err(EditError::Occupied);
+                }
+                let merges = sections
+                    .iter()
+                    .any(|s| s.ground == ground && (s.top == h || s.bottom == above));
+                if !merges && sections.len() >= MAX_PILLAR_SECTIONS {
+                    return Err(EditError::TooManySections);
+                }
+
+                let i = sections
+                    .iter()
+                    .position(|s| s.bottom > h)
+                    .unwrap_or(sections.len());
+                sections.insert(i, PillarSection::new(ground, h, above));
+
+                let merges_above = sections
+                    .get(i + 1)
+                    .map_or(false, |s| s.bottom == above && s.ground == ground);
+                if merges_above {
+                    let next = sections.remove(i + 1);
+                    sections[i].top = next.top;
+                }
+                let merges_below =
+                    i > 0 && sections[i - 1].top == h && sections[i - 1].ground == ground;
+                if merges_below {
+                    let section = sections.remove(i);
+                    sections[i - 1].top = section.top;
+                }
+
+                // Props standing where the step is added would be buried
+                pillar.props_mut().retain(|p| p.baseline != h);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn removing_splits_and_adding_merges() {
+    use super::Prop;
+    use gen::world::biome::Biome;
+    use math::AxialPoint;
+
+    let stone = GroundMaterial::Stone;
+    let section = |bottom, top| PillarSection::new(stone, HeightType(bottom), HeightType(top));
+    let mut pillar = HexPillar::new(
+        vec![section(0, 10)],
+        vec![Prop {
+            baseline: HeightType(10),
+            plant_index: 0,
+        }],
+        Biome::GrassLand,
+    );
+    let edit = |height, kind| PillarEdit {
+        pos: PillarIndex(AxialPoint::new(0, 0)),
+        height: HeightType(height),
+        kind: kind,
+    };
+
+    assert_eq!(
+        edit(4, EditKind::Remove).apply_to_pillar(&mut pillar),
+        Ok(())
+    );
+    assert_eq!(pillar.sections(), &[section(0, 4), section(5, 10)][..]);
+    assert_eq!(
+        edit(4, EditKind::Remove).apply_to_pillar(&mut pillar),
+        Err(EditError::NothingToRemove)
+    );
+    assert_eq!(
+        edit(9, EditKind::Remove).apply_to_pillar(&mut pillar),
+        Ok(())
+    );
+    assert_eq!(pillar.sections(), &[section(0, 4), section(5, 9)][..]);
+    assert!(pillar.props().is_empty());
+
+    assert_eq!(
+        edit(0, EditKind::Add(stone)).apply_to_pillar(&mut pillar),
+        Err(EditError::Occupied)
+    );
+    assert_eq!(
+        edit(4, EditKind::Add(stone)).apply_to_pillar(&mut pillar),
+        Ok(())
+    );
+    assert_eq!(pillar.sections(), &[section(0, 9)][..]);
+    assert_eq!(
+        edit(12, EditKind::Add(GroundMaterial::Sand)).apply_to_pillar(&mut pillar),
+        Ok(())
+    );
+    assert_eq!(pillar.sections().len(), 2);
+    assert_eq!(
+        edit(12, EditKind::Remove).apply_to_pillar(&mut pillar),
+        Ok(())
+    );
+    assert_eq!(pillar.sections(), &[section(0, 9)][..]);
+}
+
+#[test]
+fn pillars_keep_their_section_limit() {
+    let stone = GroundMaterial::Stone;
+    // Every other step is filled
+    let sections = (0..MAX_PILLAR_SECTIONS as u16)
+        .map(|i| PillarSection::new(stone, HeightType(2 * i), HeightType(2 * i + 1)))
+        .collect();
+    let mut pillar = HexPillar::new(sections, vec![], Default::default());
+    let edit = |height, kind| PillarEdit {
+        pos: PillarIndex(::math::AxialPoint::new(0, 0)),
+        height: HeightType(height),
+        kind: kind,
+    };
+
+    // The highest section is below `top`, so steps above it are on their own
+    let top = 2 * MAX_PILLAR_SECTIONS as u16 - 1;
+    assert_eq!(
+        edit(top + 2, EditKind::Add(stone)).apply_to_pillar(&mut pillar),
+        Err(EditError::TooManySections)
+    );
+    // Adding next to a section of the same material merges them
+    assert_eq!(
+        edit(top, EditKind::Add(stone)).apply_to_pillar(&mut pillar),
+        Ok(())
+    );
+    assert_eq!(
+        edit(top, EditKind::Remove).apply_to_pillar(&mut pillar),
+        Ok(())
+    );
+    assert_eq!(
+        edit(1, EditKind::Add(stone)).apply_to_pillar(&mut pillar),
+        Ok(())
+    );
+    assert_eq!(
+        edit(top + 2, EditKind::Add(GroundMaterial::Sand)).apply_to_pillar(&mut pillar),
+        Ok(())
+    );
+    assert_eq!(pillar.sections().len(), MAX_PILLAR_SECTIONS);
+    // Removing a step from the middle of a section would split it
+    assert_eq!(
+        edit(1, EditKind::Remove).apply_to_pillar(&mut pillar),
+        Err(EditError::TooManySections)
+    );
+}
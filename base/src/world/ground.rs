@@ -1,4 +1,4 @@
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum GroundMaterial {
     Dirt,
     Grass,
@@ -10,6 +10,18 @@ pub enum GroundMaterial {
     Debug,
 }
 
+/// All ground materials.
+pub const GROUND_MATERIALS: &[GroundMaterial] = &[
+    GroundMaterial::Dirt,
+    GroundMaterial::Grass,
+    GroundMaterial::Stone,
+    GroundMaterial::Sand,
+    GroundMaterial::Snow,
+    GroundMaterial::JungleGrass,
+    GroundMaterial::Mulch,
+    GroundMaterial::Debug,
+];
+
 impl GroundMaterial {
     // Returns color of Texture in RGB
     pub fn get_color(&self) -> [f32; 3] {
@@ -1,11 +1,15 @@
 use super::{GroundMaterial, HeightType};
 use gen::world::biome::Biome;
 
+/// Pillars have at most this many sections. Generated pillars have far
+/// fewer, edits can't add more, and chunks with more aren't decoded.
+pub const MAX_PILLAR_SECTIONS: usize = 256;
+
 /// Represents one pillar of hexgonal shape in the game world.
 ///
 /// A pillar consists of multiple sections (each of which has a material) and
 /// optionally props (plants, objects, ...).
-#[derive(Clone, Default, Debug)]
+#[derive(Clone, Default, Debug, PartialEq)]
 pub struct HexPillar {
     sections: Vec<PillarSection>,
     props: Vec<Prop>,
@@ -36,13 +40,18 @@ impl HexPillar {
         &self.props
     }
 
+    /// Returns a mutable reference to this pillar's props.
+    pub fn props_mut(&mut self) -> &mut Vec<Prop> {
+        &mut self.props
+    }
+
     pub fn biome(&self) -> &Biome {
         &self.biome
     }
 }
 
 /// Represents one section of a hex pillar.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct PillarSection {
     pub ground: GroundMaterial,
     pub bottom: HeightType,
@@ -63,7 +72,7 @@ impl PillarSection {
 }
 
 /// A prop in a hex pillar
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Prop {
     /// The height/baseline at which the prop starts
     pub baseline: HeightType,
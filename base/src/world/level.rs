@@ -0,0 +1,280 @@
+//! The directory a world is saved in.
+//!
+//! ```text
+//! <save dir>/level      everything but the chunks, see `Level`
+//! <save dir>/regions/   the chunks, see `RegionProvider`
+//! ```
+
+use super::storage::{self, Blob, Migration};
+use gen::GENERATOR_VERSION;
+use math::*;
+use net::{invalid_data, Decode, Encode};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use time::GameTime;
+use weather::WeatherState;
+
+/// Name of the metadata file in the save directory.
+pub const LEVEL_FILE: &str = "level";
+
+/// Name of the directory with the region files in the save directory.
+pub const REGION_DIR: &str = "regions";
+
+/// Start of every level file.
+const LEVEL_MAGIC: &[u8] = b"PLXL";
+
+/// Version of the level file format, increased with every change.
+///
+/// Version 1 stored the level data without a blob, so without a checksum.
+const LEVEL_FILE_VERSION: u16 = 2;
+
+/// Version of the level data in the blob, increased with every change to the
+/// encoding of `Level`.
+const LEVEL_VERSION: u16 = 1;
+
+/// `LEVEL_MIGRATIONS[i]` upgrades level data of version `i + 1` to `i + 2`.
+const LEVEL_MIGRATIONS: &[Migration] = &[];
+
+/// Everything about a saved world except for its chunks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Level {
+    /// Seed the world is generated from.
+    pub seed: u64,
+    /// The `GENERATOR_VERSION` chunks of the world were generated with.
+    pub generator_version: u32,
+    pub time: GameTime,
+    /// Weather set for the whole world, `None` if every region decides its
+    /// weather.
+    pub weather: Option<WeatherState>,
+    /// Where each player was when they left.
+    pub players: BTreeMap<String, Point3f>,
+}
+
+impl Level {
+    /// Creates the level of a new world.
+    pub fn new(seed: u64) -> Self {
+        Level {
+            seed: seed,
+            generator_version: GENERATOR_VERSION,
+            time: GameTime::default(),
+            weather: None,
+            players: BTreeMap::new(),
+        }
+    }
+
+    /// Reads the level file in the given save directory.
+    pub fn load<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let file = fs::read(dir.as_ref().join(LEVEL_FILE))?;
+        if !file.starts_with(LEVEL_MAGIC) {
+            return Err(invalid_data("not a level file"));
+        }
+        let mut r = &file[LEVEL_MAGIC.len()..];
+
+        let file_version =
+            u16::decode(&mut r).map_err(|_| invalid_data("damaged data: truncated"))?;
+        let blob = match file_version {
+            // The data of version 1 files is the first version of the level
+            // data, just not in a blob
+            1 => Blob {
+                version: 1,
+                data: r.to_vec(),
+            },
+            LEVEL_FILE_VERSION => {
+                let blob = storage::read_blob(&mut r)?;
+                if !r.is_empty() {
+                    return Err(invalid_data("trailing bytes after level"));
+                }
+                blob
+            }
+            _ => return Err(invalid_data("unsupported level file version")),
+        };
+
+        let data = storage::migrate(blob, LEVEL_MIGRATIONS)?;
+        let mut r = &data[..];
+        let level = Level::decode(&mut r).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => invalid_data("damaged data: truncated"),
+            _ => e,
+        })?;
+        if !r.is_empty() {
+            return Err(invalid_data("trailing bytes after level"));
+        }
+        Ok(level)
+    }
+
+    /// Writes the level file into the given save directory, which is created
+    /// if necessary. The old file is replaced atomically.
+    pub fn save<P: AsRef<Path>>(&self, dir: P) -> io::Result<()> {
+        fs::create_dir_all(dir.as_ref())?;
+        let mut data = Vec::new();
+        self.encode(&mut data)?;
+        let mut file = LEVEL_MAGIC.to_vec();
+        LEVEL_FILE_VERSION.encode(&mut file)?;
+        storage::write_blob(&mut file, LEVEL_VERSION, &data)?;
+        storage::write_atomic(&dir.as_ref().join(LEVEL_FILE), &file)
+    }
+
+    /// Opens the world in the given save directory, or creates a new world
+    /// with the given seed if there is none.
+    ///
+    /// Worlds generated by an older generator are only opened if `upgrade` is
+    /// set. Their saved chunks are kept, but new chunks are generated by the
+    /// current generator, so there might be edges between them. Worlds of a
+    /// newer generator are never opened.
+    pub fn open_or_create<P: AsRef<Path>>(dir: P, seed: u64, upgrade: bool) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        if !dir.join(LEVEL_FILE).exists() {
+            info!("creating a new world in {}", dir.display());
+            let level = Level::new(seed);
+            level.save(dir)?;
+            return Ok(level);
+        }
+
+        let mut level = Level::load(dir)?;
+        let outdated = level.generator_version < GENERATOR_VERSION;
+        if level.generator_version > GENERATOR_VERSION || (outdated && !upgrade) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "the world in {} was generated by generator version {}, but this is version \
+                     {}",
+                    dir.display(),
+                    level.generator_version,
+                    GENERATOR_VERSION
+                ),
+            ));
+        }
+        if outdated {
+            warn!(
+                "upgrading the world in {} from generator version {} to {}",
+                dir.display(),
+                level.generator_version,
+                GENERATOR_VERSION
+            );
+            level.generator_version = GENERATOR_VERSION;
+            level.save(dir)?;
+        }
+        info!("opened the world in {}", dir.display());
+        Ok(level)
+    }
+}
+
+impl Encode for Level {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.seed.encode(w)?;
+        self.generator_version.encode(w)?;
+        self.time.encode(w)?;
+        self.weather.encode(w)?;
+        (self.players.len() as u32).encode(w)?;
+        for (name, pos) in &self.players {
+            name.encode(w)?;
+            pos.encode(w)?;
+        }
+        Ok(())
+    }
+}
+
+impl Decode for Level {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        let mut level = Level {
+            seed: u64::decode(r)?,
+            generator_version: u32::decode(r)?,
+            time: GameTime::decode(r)?,
+            weather: Option::decode(r)?,
+            players: BTreeMap::new(),
+        };
+        for _ in 0..u32::decode(r)? {
+            let name = String::decode(r)?;
+            level.players.insert(name, Point3f::decode(r)?);
+        }
+        Ok(level)
+    }
+}
+
+#[test]
+fn levels_are_saved_and_loaded() {
+    use weather::{Form, Strength};
+
+    let dir = storage::test_dir("level");
+    let mut level = Level::open_or_create(&dir, 7, false).unwrap();
+    assert_eq!(level, Level::new(7));
+
+    level.time.day = 3;
+    level.time.time_on_day = 100.5;
+    level.weather = Some(WeatherState {
+        form: Form::Snow,
+        strength: Strength::Heavy,
+    });
+    level
+        .players
+        .insert("Lukas".into(), Point3f::new(1.0, -2.0, 30.0));
+    level.save(&dir).unwrap();
+
+    // The seed of an existing world wins
+    assert_eq!(Level::open_or_create(&dir, 8, false).unwrap(), level);
+
+    fs::write(dir.join(LEVEL_FILE), b"PLXL\x00").unwrap();
+    assert!(Level::load(&dir).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn other_generator_versions_are_refused() {
+    let dir = storage::test_dir("level-version");
+    let mut level = Level::new(7);
+
+    level.generator_version = GENERATOR_VERSION + 1;
+    level.save(&dir).unwrap();
+    assert!(Level::open_or_create(&dir, 7, false).is_err());
+    assert!(Level::open_or_create(&dir, 7, true).is_err());
+
+    level.generator_version = GENERATOR_VERSION - 1;
+    level.save(&dir).unwrap();
+    assert!(Level::open_or_create(&dir, 7, false).is_err());
+    let upgraded = Level::open_or_create(&dir, 7, true).unwrap();
+    assert_eq!(upgraded.generator_version, GENERATOR_VERSION);
+    assert_eq!(Level::load(&dir).unwrap(), upgraded);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn damaged_level_files_are_detected() {
+    let dir = storage::test_dir("damaged-level");
+    let mut level = Level::new(7);
+    level
+        .players
+        .insert("Lukas".into(), Point3f::new(1.0, -2.0, 30.0));
+    level.save(&dir).unwrap();
+    let path = dir.join(LEVEL_FILE);
+    let saved = fs::read(&path).unwrap();
+
+    for len in LEVEL_MAGIC.len()..saved.len() {
+        fs::write(&path, &saved[..len]).unwrap();
+        let err = Level::load(&dir).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+    let mut damaged = saved.clone();
+    let last = damaged.len() - 1;
+    damaged[last] ^= 1;
+    fs::write(&path, &damaged).unwrap();
+    assert!(Level::load(&dir).is_err());
+    assert!(Level::open_or_create(&dir, 7, false).is_err());
+
+    // Version 1 files are still read
+    let mut old = LEVEL_MAGIC.to_vec();
+    1u16.encode(&mut old).unwrap();
+    level.encode(&mut old).unwrap();
+    fs::write(&path, &old).unwrap();
+    assert_eq!(Level::load(&dir).unwrap(), level);
+    fs::write(&path, &old[..old.len() - 1]).unwrap();
+    assert!(Level::load(&dir).is_err());
+
+    // Newer files are refused
+    let mut newer = saved.clone();
+    newer[LEVEL_MAGIC.len()] = LEVEL_FILE_VERSION as u8 + 1;
+    fs::write(&path, &newer).unwrap();
+    assert!(Level::load(&dir).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
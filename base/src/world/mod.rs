@@ -1,18 +1,29 @@
 //! Types and constants to represent a game world.
 //!
 use math;
+use math::{AxialVector, MetricSpace, Point2f};
+use std::cmp::Ordering;
 use std::fmt;
 
 pub mod chunk;
+mod edit;
 pub mod ground;
 mod hex_pillar;
+mod level;
 mod provider;
+mod region;
+mod snapshots;
+pub mod storage;
 mod world;
 
 pub use self::chunk::Chunk;
+pub use self::edit::*;
 pub use self::ground::*;
 pub use self::hex_pillar::*;
+pub use self::level::{Level, LEVEL_FILE, REGION_DIR};
 pub use self::provider::*;
+pub use self::region::{RegionProvider, REGION_SIZE};
+pub use self::snapshots::{SnapshotInfo, Snapshots, WorldLock, LOCK_FILE, SNAPSHOT_DIR};
 pub use self::world::World;
 
 /// Outer radius of the hexagons (from center to corner)
@@ -30,6 +41,11 @@ pub const PILLAR_STEP_HEIGHT: f32 = 0.5;
 /// chunk is `CHUNK_SIZE`².
 pub const CHUNK_SIZE: u16 = 16;
 
+/// Servers send chunks and their edits up to this many chunk lengths beyond
+/// the view radius, because clients only check now and then which chunks are
+/// in range.
+pub const CHUNK_RANGE_MARGIN: f32 = 1.0;
+
 /// This type is used to index into one dimension of the world. Thus we can
 /// "only" index `(PillarIndexComponent::max_value() -
 /// PillarIndexComponent::min_value())`² many hex pillars.
@@ -40,6 +56,23 @@ pub type PillarIndexComponent = math::AxialType;
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct PillarIndex(pub math::AxialPoint);
 
+impl PillarIndex {
+    /// Returns the index of the chunk the pillar belongs to.
+    pub fn chunk(&self) -> ChunkIndex {
+        let size = CHUNK_SIZE as i32;
+        ChunkIndex(math::AxialPoint::new(
+            self.0.q.div_euclid(size),
+            self.0.r.div_euclid(size),
+        ))
+    }
+
+    /// Returns the position of the pillar inside of its chunk.
+    pub fn pos_in_chunk(&self) -> math::AxialPoint {
+        let size = CHUNK_SIZE as i32;
+        math::AxialPoint::new(self.0.q.rem_euclid(size), self.0.r.rem_euclid(size))
+    }
+}
+
 /// A new-type to index chunks. This is different from the `PillarIndex` type
 /// which always represents a pillar position. So two different `PillarIndex`es
 /// could refer to two pillars in the same chunk, while two different
@@ -47,6 +80,52 @@ pub struct PillarIndex(pub math::AxialPoint);
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct ChunkIndex(pub math::AxialPoint);
 
+impl ChunkIndex {
+    /// Returns the index of the chunk containing the given position.
+    pub fn at(pos: Point2f) -> Self {
+        PillarIndex(math::AxialPoint::from_real(pos)).chunk()
+    }
+
+    /// Returns the position of the center of the chunk.
+    pub fn center(&self) -> Point2f {
+        let half = CHUNK_SIZE as i32 / 2;
+        (self.0 * CHUNK_SIZE as i32 + AxialVector::new(half, half)).to_real()
+    }
+
+    /// Returns whether the center of the chunk is less than `radius` chunk
+    /// lengths away from the given position.
+    ///
+    /// Clients load the chunks in range of their player, and servers send
+    /// the chunks in range of a player, with `CHUNK_RANGE_MARGIN` added.
+    pub fn is_in_range(&self, pos: Point2f, radius: f32) -> bool {
+        self.center().distance(pos) < radius * CHUNK_SIZE as f32
+    }
+}
+
+/// Returns all chunks in range of the given position, see
+/// `ChunkIndex::is_in_range`, the nearest first.
+pub fn chunks_in_range(pos: Point2f, radius: f32) -> Vec<ChunkIndex> {
+    let center = ChunkIndex::at(pos);
+    // Chunks are wider than `CHUNK_SIZE`, so this covers the skewed corners
+    let reach = radius.ceil() as i32 + 1;
+    let mut chunks = Vec::new();
+    for q in -reach..reach + 1 {
+        for r in -reach..reach + 1 {
+            let index = ChunkIndex(center.0 + AxialVector::new(q, r));
+            if index.is_in_range(pos, radius) {
+                chunks.push(index);
+            }
+        }
+    }
+    let distance = |index: &ChunkIndex| index.center().distance2(pos);
+    chunks.sort_by(|a, b| {
+        distance(a)
+            .partial_cmp(&distance(b))
+            .unwrap_or(Ordering::Equal)
+    });
+    chunks
+}
+
 /// Represents a discretized height.
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
 pub struct HeightType(pub u16);
@@ -78,3 +157,37 @@ impl fmt::Debug for HeightType {
         write!(f, "[{} -> {}]", self.0, self.to_real())
     }
 }
+
+#[test]
+fn chunks_in_range_are_found() {
+    let pos = Point2f::new(-100.0, -40.0);
+    // Negative positions are rounded down, not towards zero
+    let own = ChunkIndex::at(pos);
+    let pillar = math::AxialPoint::from_real(pos) - own.0 * CHUNK_SIZE as i32;
+    assert!(
+        pillar.q >= 0 && pillar.q < CHUNK_SIZE as i32,
+        "{:?}",
+        pillar
+    );
+    assert!(
+        pillar.r >= 0 && pillar.r < CHUNK_SIZE as i32,
+        "{:?}",
+        pillar
+    );
+
+    let chunks = chunks_in_range(pos, 3.0);
+    assert_eq!(chunks[0], own);
+    // Pillars are wider than one unit, so a circle with a radius of three
+    // chunk lengths covers about 11 chunk areas
+    assert!(chunks.len() > 8 && chunks.len() < 16, "{}", chunks.len());
+    let distances: Vec<_> = chunks.iter().map(|c| c.center().distance(pos)).collect();
+    assert!(distances.windows(2).all(|d| d[0] <= d[1]));
+
+    // No chunk in range is missed
+    for q in -20..20 {
+        for r in -20..20 {
+            let index = ChunkIndex(math::AxialPoint::new(q, r));
+            assert_eq!(index.is_in_range(pos, 3.0), chunks.contains(&index));
+        }
+    }
+}
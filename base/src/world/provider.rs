@@ -23,6 +23,11 @@ pub trait ChunkProvider: Send {
 
     /// Returns a Vector of all specific plants to be rendered in the world.
     fn get_plant_list(&self) -> Vec<Plant>;
+
+    /// Hints that the given chunks will be loaded soon, so that providers
+    /// which have to wait for chunks can start fetching them at once. Does
+    /// nothing by default.
+    fn prefetch_chunks(&self, _: &[ChunkIndex]) {}
 }
 
 /// A dummy provider that always fails to provide a chunk.
@@ -44,14 +49,35 @@ impl ChunkProvider for NullProvider {
 }
 
 /// A fallback provider that holds two chunk providers with one being primary
-/// and one fallback. If the chunk load from the primary fails the fallback
-/// is being called.
+/// and one fallback. Chunks the primary doesn't have are loaded from the
+/// fallback. Chunks the primary has, but fails to load, aren't loaded at all,
+/// so that a damaged saved chunk is never overwritten by a replacement.
+///
+/// For example, `FallbackProvider<RegionProvider, WorldGenerator>` loads
+/// saved chunks and generates all others.
 #[derive(Clone, Debug, Copy)]
 pub struct FallbackProvider<P, F> {
     primary: P,
     fallback: F,
 }
 
+impl<P, F> FallbackProvider<P, F> {
+    pub fn new(primary: P, fallback: F) -> Self {
+        FallbackProvider {
+            primary: primary,
+            fallback: fallback,
+        }
+    }
+
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    pub fn fallback(&self) -> &F {
+        &self.fallback
+    }
+}
+
 impl<P: ChunkProvider, F: ChunkProvider> ChunkProvider for FallbackProvider<P, F> {
     fn load_chunk(&self, pos: ChunkIndex) -> Option<Chunk> {
         if self.primary.is_chunk_loadable(pos) {
@@ -65,7 +91,14 @@ impl<P: ChunkProvider, F: ChunkProvider> ChunkProvider for FallbackProvider<P, F
         self.primary.is_chunk_loadable(pos) || self.fallback.is_chunk_loadable(pos)
     }
 
+    /// Returns the plants of the primary provider, or the ones of the
+    /// fallback if the primary has none.
     fn get_plant_list(&self) -> Vec<Plant> {
-        self.primary.get_plant_list()
+        let plants = self.primary.get_plant_list();
+        if plants.is_empty() {
+            self.fallback.get_plant_list()
+        } else {
+            plants
+        }
     }
 }
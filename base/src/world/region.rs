@@ -0,0 +1,610 @@
+use super::storage::{self, Blob, Migration, BLOB_HEADER_LEN};
+use super::{Chunk, ChunkIndex, ChunkProvider};
+use math::*;
+use net::{decode_chunk, encode_chunk, invalid_data, Compression, Decode, Encode};
+use prop::plant::Plant;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+/// Number of chunks along each axis of a region.
+pub const REGION_SIZE: i32 = 16;
+
+/// Number of chunks in a region.
+const CHUNKS_PER_REGION: usize = (REGION_SIZE * REGION_SIZE) as usize;
+
+/// Start of every region file.
+const REGION_MAGIC: &[u8] = b"PLXR";
+
+/// Version of the region file format, increased with every change.
+///
+/// Version 1 stored the chunks without blobs. Such files are still read and
+/// are rewritten in the current version when a chunk is saved into them.
+const REGION_VERSION: u16 = 2;
+
+/// Length of the header: magic, version, two unused bytes which align the
+/// table entries and the offset table.
+const HEADER_LEN: u64 = 4 + 2 + 2 + CHUNKS_PER_REGION as u64 * 8;
+
+/// Length of the header of version 1 files, without the unused bytes.
+const V1_HEADER_LEN: u64 = 4 + 2 + CHUNKS_PER_REGION as u64 * 8;
+
+/// Version of the chunk data in the blobs, increased with every change to the
+/// compact chunk encoding. Version 1 region files store the same chunk data,
+/// just not in blobs.
+const CHUNK_VERSION: u16 = 1;
+
+/// `CHUNK_MIGRATIONS[i]` upgrades chunk data of version `i + 1` to `i + 2`.
+const CHUNK_MIGRATIONS: &[Migration] = &[];
+
+/// Region files are rewritten without the chunks which were replaced once
+/// those take up more space than the saved chunks, and at least this many
+/// bytes.
+const MIN_GARBAGE_LEN: u64 = 64 * 1024;
+
+/// Where a chunk is stored in its region file. Chunks which are not saved
+/// have an offset of 0.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Slot {
+    offset: u32,
+    len: u32,
+}
+
+/// The offset table of a region file.
+#[derive(Clone, Debug)]
+struct Table {
+    /// Version of the file format.
+    version: u16,
+    slots: Vec<Slot>,
+}
+
+impl Table {
+    fn new() -> Self {
+        Table {
+            version: REGION_VERSION,
+            slots: vec![Slot::default(); CHUNKS_PER_REGION],
+        }
+    }
+
+    /// Returns the number of bytes used by saved chunks.
+    fn used_len(&self) -> u64 {
+        self.slots.iter().map(|slot| u64::from(slot.len)).sum()
+    }
+}
+
+/// Offset tables of regions, `None` for regions without a file.
+type Tables = HashMap<RegionIndex, Option<Table>>;
+
+/// Identifies a group of `REGION_SIZE`² chunks saved in the same file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct RegionIndex(AxialPoint);
+
+impl RegionIndex {
+    /// Returns the region of the chunk and the position of the chunk in the
+    /// offset table.
+    fn of(chunk: ChunkIndex) -> (Self, usize) {
+        let (q, r) = (chunk.0.q, chunk.0.r);
+        let region = RegionIndex(AxialPoint::new(
+            q.div_euclid(REGION_SIZE),
+            r.div_euclid(REGION_SIZE),
+        ));
+        let slot = r.rem_euclid(REGION_SIZE) * REGION_SIZE + q.rem_euclid(REGION_SIZE);
+        (region, slot as usize)
+    }
+
+    fn file_name(&self) -> String {
+        format!("r.{}.{}.region", self.0.q, self.0.r)
+    }
+}
+
+/// Loads and saves chunks in region files inside of a directory.
+///
+/// Every region file starts with a header holding an offset table with the
+/// position and length of every chunk of the region, followed by the chunks
+/// as blobs of their compact encoding. Saved chunks are appended to the file
+/// and synced before the offset table points to them, so a crash while
+/// saving leaves the old chunk behind. Once the replaced chunks take up too
+/// much space, the file is rewritten atomically without them.
+///
+/// The provider can be shared between threads, e.g. a thread loading chunks
+/// and another one saving them.
+pub struct RegionProvider {
+    dir: PathBuf,
+    /// Offset tables of all regions used so far. Also held while writing to
+    /// a file.
+    tables: Mutex<Tables>,
+}
+
+impl RegionProvider {
+    /// Uses the region files in the given directory, which is created if it
+    /// doesn't exist.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(RegionProvider {
+            dir: dir.as_ref().to_path_buf(),
+            tables: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Saves the chunk, replacing the saved chunk at the same position.
+    pub fn save_chunk(&self, index: ChunkIndex, chunk: &Chunk) -> io::Result<()> {
+        let mut data = Vec::new();
+        encode_chunk(chunk, Compression::Deflate, &mut data)?;
+        let mut blob = Vec::with_capacity(BLOB_HEADER_LEN + data.len());
+        storage::write_blob(&mut blob, CHUNK_VERSION, &data)?;
+
+        let (region, slot) = RegionIndex::of(index);
+        let mut tables = self.lock_tables();
+        let path = self.dir.join(region.file_name());
+        let mut table = match self.table(&mut tables, region)? {
+            Some(table) => table,
+            None => {
+                // A new file, or one with only part of a header
+                let table = Table::new();
+                storage::write_atomic(&path, &header(&table))?;
+                table
+            }
+        };
+
+        let mut file = OpenOptions::new().read(true).write(true).open(&path)?;
+        let file_len = file.metadata()?.len();
+        let garbage = file_len.saturating_sub(HEADER_LEN + table.used_len());
+        if table.version != REGION_VERSION || garbage > table.used_len().max(MIN_GARBAGE_LEN) {
+            table = self.rewrite(region, &table)?;
+            tables.insert(region, Some(table.clone()));
+            file = OpenOptions::new().read(true).write(true).open(&path)?;
+        }
+
+        let offset = file.seek(SeekFrom::End(0))?;
+        if offset + blob.len() as u64 > u64::from(u32::max_value()) {
+            return Err(io::Error::new(io::ErrorKind::Other, "region file is full"));
+        }
+        file.write_all(&blob)?;
+        file.sync_data()?;
+
+        let new = Slot {
+            offset: offset as u32,
+            len: blob.len() as u32,
+        };
+        file.seek(SeekFrom::Start(slot_position(slot)))?;
+        new.offset.encode(&mut file)?;
+        new.len.encode(&mut file)?;
+        file.sync_data()?;
+
+        table.slots[slot] = new;
+        tables.insert(region, Some(table));
+        Ok(())
+    }
+
+    /// Reads the chunk from its region file, returns `None` if it's not
+    /// saved.
+    pub fn read_chunk(&self, index: ChunkIndex) -> io::Result<Option<Chunk>> {
+        let (region, slot) = RegionIndex::of(index);
+        let mut tables = self.lock_tables();
+        let (version, entry) = match self.table(&mut tables, region)? {
+            Some(table) => (table.version, table.slots[slot]),
+            None => return Ok(None),
+        };
+        if entry.offset == 0 {
+            return Ok(None);
+        }
+
+        let file = File::open(self.dir.join(region.file_name()))?;
+        let blob = read_slot(&file, version, entry)?;
+        drop(tables);
+
+        let data = storage::migrate(blob, CHUNK_MIGRATIONS)?;
+        let mut slice = &data[..];
+        let chunk = decode_chunk(&mut slice)?;
+        if !slice.is_empty() {
+            return Err(invalid_data("trailing bytes after chunk"));
+        }
+        Ok(Some(chunk))
+    }
+
+    /// Returns whether the chunk is saved.
+    pub fn contains(&self, index: ChunkIndex) -> io::Result<bool> {
+        let (region, slot) = RegionIndex::of(index);
+        let mut tables = self.lock_tables();
+        Ok(self
+            .table(&mut tables, region)?
+            .map_or(false, |table| table.slots[slot].offset != 0))
+    }
+
+    fn lock_tables(&self) -> MutexGuard<'_, Tables> {
+        // Tables are only replaced as a whole, so they are never inconsistent
+        self.tables.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the offset table of the region, reading it if it's not known
+    /// yet.
+    fn table(&self, tables: &mut Tables, region: RegionIndex) -> io::Result<Option<Table>> {
+        if let Some(table) = tables.get(&region) {
+            return Ok(table.clone());
+        }
+
+        let table = match File::open(self.dir.join(region.file_name())) {
+            Ok(mut file) => read_header(&mut file)?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        tables.insert(region, table.clone());
+        Ok(table)
+    }
+
+    /// Replaces the region file by one in the current version which only
+    /// contains the saved chunks, and returns its table. Damaged chunks are
+    /// copied as they are, so that they are still reported when loading
+    /// them.
+    fn rewrite(&self, region: RegionIndex, table: &Table) -> io::Result<Table> {
+        debug!(
+            "rewriting region file {} of version {}",
+            region.file_name(),
+            table.version
+        );
+        let path = self.dir.join(region.file_name());
+        let mut file = File::open(&path)?;
+        let file_len = file.metadata()?.len();
+
+        let mut new = Table::new();
+        let mut chunks = Vec::new();
+        for (i, &slot) in table.slots.iter().enumerate() {
+            if slot.offset == 0 {
+                continue;
+            }
+            let end = u64::from(slot.offset) + u64::from(slot.len);
+            if end > file_len {
+                warn!("dropping truncated chunk {} of {}", i, path.display());
+                continue;
+            }
+            let mut data = vec![0; slot.len as usize];
+            file.seek(SeekFrom::Start(u64::from(slot.offset)))?;
+            file.read_exact(&mut data)?;
+            if table.version == 1 {
+                let mut blob = Vec::with_capacity(BLOB_HEADER_LEN + data.len());
+                storage::write_blob(&mut blob, 1, &data)?;
+                data = blob;
+            }
+
+            new.slots[i] = Slot {
+                offset: (HEADER_LEN + chunks.len() as u64) as u32,
+                len: data.len() as u32,
+            };
+            chunks.extend_from_slice(&data);
+        }
+
+        let mut content = header(&new);
+        content.extend_from_slice(&chunks);
+        storage::write_atomic(&path, &content)?;
+        Ok(new)
+    }
+}
+
+impl ChunkProvider for RegionProvider {
+    fn load_chunk(&self, pos: ChunkIndex) -> Option<Chunk> {
+        match self.read_chunk(pos) {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                warn!(
+                    "can't load chunk {:?} from {}: {}",
+                    pos,
+                    self.dir.display(),
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// Chunks in a damaged region file might be saved, so they count as
+    /// loadable. Loading them reports the error.
+    fn is_chunk_loadable(&self, pos: ChunkIndex) -> bool {
+        self.contains(pos).unwrap_or(true)
+    }
+
+    /// Plants aren't saved, they are generated from the seed.
+    fn get_plant_list(&self) -> Vec<Plant> {
+        Vec::new()
+    }
+}
+
+/// Returns the position of the entry of the slot in the offset table.
+fn slot_position(slot: usize) -> u64 {
+    4 + 2 + 2 + slot as u64 * 8
+}
+
+/// Returns the header of a region file with the given table.
+fn header(table: &Table) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_LEN as usize);
+    // Writing to a `Vec` can't fail
+    header.extend_from_slice(REGION_MAGIC);
+    REGION_VERSION.encode(&mut header).unwrap();
+    0u16.encode(&mut header).unwrap();
+    for slot in &table.slots {
+        slot.offset.encode(&mut header).unwrap();
+        slot.len.encode(&mut header).unwrap();
+    }
+    header
+}
+
+/// Reads the header of a region file. Returns `None` for files with an
+/// incomplete header, which can only be written by a crash while creating
+/// the file.
+fn read_header(file: &mut File) -> io::Result<Option<Table>> {
+    let mut start = [0; 6];
+    if file.metadata()?.len() < V1_HEADER_LEN {
+        return Ok(None);
+    }
+    file.read_exact(&mut start)?;
+    if &start[..4] != REGION_MAGIC {
+        return Err(invalid_data("not a region file"));
+    }
+    let version = u16::decode(&mut &start[4..])?;
+    let header_len = match version {
+        1 => V1_HEADER_LEN,
+        REGION_VERSION => HEADER_LEN,
+        _ => return Err(invalid_data("unsupported region file version")),
+    };
+    if file.metadata()?.len() < header_len {
+        return Ok(None);
+    }
+
+    let mut rest = vec![0; (header_len - 6) as usize];
+    file.read_exact(&mut rest)?;
+    let mut r = &rest[..];
+    if version != 1 {
+        u16::decode(&mut r)?;
+    }
+    let mut table = Table {
+        version: version,
+        slots: Vec::with_capacity(CHUNKS_PER_REGION),
+    };
+    for _ in 0..CHUNKS_PER_REGION {
+        table.slots.push(Slot {
+            offset: u32::decode(&mut r)?,
+            len: u32::decode(&mut r)?,
+        });
+    }
+    Ok(Some(table))
+}
+
+/// Reads the data of a saved chunk.
+fn read_slot(mut file: &File, version: u16, slot: Slot) -> io::Result<Blob> {
+    file.seek(SeekFrom::Start(u64::from(slot.offset)))?;
+    let mut r = file.take(u64::from(slot.len));
+    if version == 1 {
+        let mut data = Vec::with_capacity(slot.len as usize);
+        r.read_to_end(&mut data)?;
+        if data.len() != slot.len as usize {
+            return Err(invalid_data("damaged data: truncated"));
+        }
+        return Ok(Blob {
+            version: 1,
+            data: data,
+        });
+    }
+
+    let blob = storage::read_blob(&mut r)?;
+    if BLOB_HEADER_LEN + blob.data.len() != slot.len as usize {
+        return Err(invalid_data("damaged data: length mismatch"));
+    }
+    Ok(blob)
+}
+
+#[test]
+fn chunks_are_saved_and_loaded() {
+    use gen::WorldGenerator;
+    use world::{FallbackProvider, HexPillar};
+
+    let dir = storage::test_dir("regions");
+    let generator = WorldGenerator::with_seed(42);
+    let provider = RegionProvider::open(&dir).unwrap();
+
+    // Chunks in different regions, some with negative coordinates
+    let indices: Vec<_> = [(0, 0), (1, 0), (15, 15), (16, 0), (-1, -1), (-17, 3)]
+        .iter()
+        .map(|&(q, r)| ChunkIndex(AxialPoint::new(q, r)))
+        .collect();
+    for &index in &indices {
+        assert!(!provider.is_chunk_loadable(index));
+        provider
+            .save_chunk(index, &generator.load_chunk(index).unwrap())
+            .unwrap();
+        assert!(provider.is_chunk_loadable(index));
+    }
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 4);
+
+    // Chunks are replaced
+    let empty = Chunk::from_pillars(vec![HexPillar::default(); CHUNKS_PER_REGION]);
+    provider.save_chunk(indices[0], &empty).unwrap();
+    provider
+        .save_chunk(indices[0], &generator.load_chunk(indices[5]).unwrap())
+        .unwrap();
+
+    // Everything is read back from the files by a new provider
+    let provider = RegionProvider::open(&dir).unwrap();
+    assert_eq!(
+        provider.load_chunk(indices[0]),
+        generator.load_chunk(indices[5])
+    );
+    for &index in &indices[1..] {
+        assert_eq!(provider.load_chunk(index), generator.load_chunk(index));
+    }
+    let unsaved = ChunkIndex(AxialPoint::new(2, 0));
+    assert_eq!(provider.load_chunk(unsaved), None);
+
+    // Saved chunks are preferred, the others are generated
+    let provider = FallbackProvider::new(provider, WorldGenerator::with_seed(42));
+    assert_eq!(
+        provider.load_chunk(indices[0]),
+        generator.load_chunk(indices[5])
+    );
+    assert_eq!(provider.load_chunk(unsaved), generator.load_chunk(unsaved));
+    assert!(provider.is_chunk_loadable(unsaved));
+    assert!(!provider.get_plant_list().is_empty());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn broken_region_files_are_detected() {
+    use gen::WorldGenerator;
+    use world::FallbackProvider;
+
+    let dir = storage::test_dir("broken-regions");
+    let provider = RegionProvider::open(&dir).unwrap();
+    let index = ChunkIndex(AxialPoint::new(3, 4));
+    let chunk = Chunk::from_pillars(vec![Default::default(); CHUNKS_PER_REGION]);
+    provider.save_chunk(index, &chunk).unwrap();
+    let path = dir.join(RegionIndex::of(index).0.file_name());
+
+    // Damaged chunk data
+    let mut data = fs::read(&path).unwrap();
+    let len = data.len();
+    data[len - 3] ^= 0xff;
+    fs::write(&path, &data).unwrap();
+    let provider = RegionProvider::open(&dir).unwrap();
+    assert!(provider.read_chunk(index).is_err());
+    assert_eq!(provider.load_chunk(index), None);
+
+    // Damaged chunks aren't replaced by generated ones
+    let generated = FallbackProvider::new(provider, WorldGenerator::with_seed(42));
+    assert_eq!(generated.load_chunk(index), None);
+    assert!(generated
+        .load_chunk(ChunkIndex(AxialPoint::new(3, 5)))
+        .is_some());
+    assert_eq!(fs::read(&path).unwrap(), data);
+
+    // Not a region file at all
+    fs::write(&path, vec![0; HEADER_LEN as usize]).unwrap();
+    let provider = RegionProvider::open(&dir).unwrap();
+    assert!(provider.read_chunk(index).is_err());
+    assert!(provider.is_chunk_loadable(index));
+    assert!(provider.save_chunk(index, &chunk).is_err());
+    let generated = FallbackProvider::new(provider, WorldGenerator::with_seed(42));
+    assert_eq!(generated.load_chunk(index), None);
+
+    // A file with an incomplete header is replaced
+    fs::write(&path, REGION_MAGIC).unwrap();
+    let provider = RegionProvider::open(&dir).unwrap();
+    assert!(!provider.is_chunk_loadable(index));
+    provider.save_chunk(index, &chunk).unwrap();
+    assert_eq!(provider.load_chunk(index), Some(chunk));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn interrupted_saves_keep_the_old_chunk() {
+    let dir = storage::test_dir("interrupted-regions");
+    let provider = RegionProvider::open(&dir).unwrap();
+    let index = ChunkIndex(AxialPoint::new(3, 4));
+    let other = ChunkIndex(AxialPoint::new(5, 4));
+    let chunk = Chunk::from_pillars(vec![Default::default(); CHUNKS_PER_REGION]);
+    provider.save_chunk(index, &chunk).unwrap();
+    provider.save_chunk(other, &chunk).unwrap();
+    let path = dir.join(RegionIndex::of(index).0.file_name());
+    let saved = fs::read(&path).unwrap();
+
+    // A crash while appending a chunk leaves part of it behind
+    let mut data = saved.clone();
+    data.extend_from_slice(&saved[saved.len() - 20..]);
+    fs::write(&path, &data).unwrap();
+    let provider = RegionProvider::open(&dir).unwrap();
+    assert_eq!(provider.read_chunk(index).unwrap(), Some(chunk.clone()));
+    provider.save_chunk(index, &chunk).unwrap();
+    assert_eq!(provider.read_chunk(index).unwrap(), Some(chunk.clone()));
+
+    // A truncated file is reported, the chunks before the end still load
+    fs::write(&path, &saved[..saved.len() - 10]).unwrap();
+    let provider = RegionProvider::open(&dir).unwrap();
+    let err = provider.read_chunk(other).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(err.to_string().contains("truncated"), "{}", err);
+    assert_eq!(provider.read_chunk(index).unwrap(), Some(chunk.clone()));
+
+    // Saving into the truncated file works, the damaged chunk stays reported
+    provider.save_chunk(index, &chunk).unwrap();
+    assert!(provider.read_chunk(other).is_err());
+    assert_eq!(provider.read_chunk(index).unwrap(), Some(chunk));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn replaced_chunks_are_dropped() {
+    use gen::WorldGenerator;
+
+    let dir = storage::test_dir("compacted-regions");
+    let provider = RegionProvider::open(&dir).unwrap();
+    let generator = WorldGenerator::with_seed(42);
+    let index = ChunkIndex(AxialPoint::new(0, 0));
+    let chunk = generator.load_chunk(index).unwrap();
+    let path = dir.join(RegionIndex::of(index).0.file_name());
+
+    let mut max_len = 0;
+    for _ in 0..200 {
+        provider.save_chunk(index, &chunk).unwrap();
+        max_len = max_len.max(fs::metadata(&path).unwrap().len());
+    }
+    assert!(max_len < HEADER_LEN + 2 * MIN_GARBAGE_LEN, "{}", max_len);
+    let provider = RegionProvider::open(&dir).unwrap();
+    assert_eq!(provider.read_chunk(index).unwrap(), Some(chunk));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn old_region_files_are_migrated() {
+    use gen::WorldGenerator;
+
+    let dir = storage::test_dir("old-regions");
+    fs::create_dir_all(&dir).unwrap();
+    let generator = WorldGenerator::with_seed(42);
+    let old = ChunkIndex(AxialPoint::new(1, 2));
+    let new = ChunkIndex(AxialPoint::new(2, 2));
+    let path = dir.join(RegionIndex::of(old).0.file_name());
+
+    // A version 1 file: no unused header bytes, no blobs
+    let mut data = Vec::new();
+    encode_chunk(
+        &generator.load_chunk(old).unwrap(),
+        Compression::Deflate,
+        &mut data,
+    )
+    .unwrap();
+    let mut file = REGION_MAGIC.to_vec();
+    1u16.encode(&mut file).unwrap();
+    for i in 0..CHUNKS_PER_REGION {
+        let slot = if i == RegionIndex::of(old).1 {
+            (V1_HEADER_LEN as u32, data.len() as u32)
+        } else {
+            (0, 0)
+        };
+        slot.0.encode(&mut file).unwrap();
+        slot.1.encode(&mut file).unwrap();
+    }
+    file.extend_from_slice(&data);
+    fs::write(&path, &file).unwrap();
+
+    let provider = RegionProvider::open(&dir).unwrap();
+    assert_eq!(provider.load_chunk(old), generator.load_chunk(old));
+
+    // Saving a chunk upgrades the whole file
+    provider
+        .save_chunk(new, &generator.load_chunk(new).unwrap())
+        .unwrap();
+    let file = fs::read(&path).unwrap();
+    assert_eq!(u16::decode(&mut &file[4..6]).unwrap(), REGION_VERSION);
+    let provider = RegionProvider::open(&dir).unwrap();
+    assert_eq!(provider.load_chunk(old), generator.load_chunk(old));
+    assert_eq!(provider.load_chunk(new), generator.load_chunk(new));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
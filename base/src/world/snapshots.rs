@@ -0,0 +1,592 @@
+//! Snapshots of saved worlds.
+//!
+//! A snapshot is a copy of the level file and all region files. Files are
+//! stored once by their content in `<save dir>/snapshots/objects`, so a
+//! snapshot only takes space for the files which changed since the last one.
+//! Every snapshot is a small manifest listing its files:
+//!
+//! ```text
+//! <save dir>/snapshots/<id>.snapshot   the manifest of a snapshot
+//! <save dir>/snapshots/objects/        the content of all files
+//! ```
+
+use super::level::{LEVEL_FILE, REGION_DIR};
+use super::storage::{self, Migration};
+use flate2::Crc;
+use net::{invalid_data, Decode, Encode};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{self, File, TryLockError};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Name of the directory with the snapshots in the save directory.
+pub const SNAPSHOT_DIR: &str = "snapshots";
+
+/// Name of the file a server locks while it uses the world.
+pub const LOCK_FILE: &str = "session.lock";
+
+/// Name of the directory with the content of the files in the snapshot
+/// directory.
+const OBJECT_DIR: &str = "objects";
+
+/// Extension of the manifests.
+const MANIFEST_EXTENSION: &str = "snapshot";
+
+/// Start of every manifest.
+const MANIFEST_MAGIC: &[u8] = b"PLXS";
+
+/// Version of the manifest format, increased with every change.
+const MANIFEST_VERSION: u16 = 1;
+
+/// `MANIFEST_MIGRATIONS[i]` upgrades manifests of version `i + 1` to `i + 2`.
+const MANIFEST_MIGRATIONS: &[Migration] = &[];
+
+/// Exclusive access to a saved world, released when dropped.
+///
+/// The lock is held by the operating system, so it's released even if the
+/// process crashes.
+pub struct WorldLock {
+    _file: File,
+}
+
+impl WorldLock {
+    /// Locks the world in the given save directory. Fails with `WouldBlock`
+    /// if it's already locked, e.g. by a running server.
+    pub fn acquire<P: AsRef<Path>>(save_dir: P) -> io::Result<Self> {
+        fs::create_dir_all(save_dir.as_ref())?;
+        let file = File::create(save_dir.as_ref().join(LOCK_FILE))?;
+        match file.try_lock() {
+            Ok(()) => Ok(WorldLock { _file: file }),
+            Err(TryLockError::WouldBlock) => Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!(
+                    "the world in {} is used by a running server",
+                    save_dir.as_ref().display()
+                ),
+            )),
+            Err(TryLockError::Error(e)) => Err(e),
+        }
+    }
+}
+
+/// Describes a snapshot.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SnapshotInfo {
+    /// Increases with every snapshot of the world.
+    pub id: u32,
+    /// Seconds since the Unix epoch.
+    pub created: u64,
+    pub name: String,
+    /// Number of files in the snapshot.
+    pub files: usize,
+    /// Total length of the files in bytes.
+    pub len: u64,
+}
+
+impl fmt::Display for SnapshotInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let age = now.saturating_sub(self.created);
+        write!(f, "#{}", self.id)?;
+        if !self.name.is_empty() {
+            write!(f, " '{}'", self.name)?;
+        }
+        write!(f, " ({} files, {} KiB, ", self.files, self.len / 1024)?;
+        match age {
+            0..=119 => write!(f, "{} seconds ago)", age),
+            120..=7199 => write!(f, "{} minutes ago)", age / 60),
+            7200..=172_799 => write!(f, "{} hours ago)", age / 3600),
+            _ => write!(f, "{} days ago)", age / 86400),
+        }
+    }
+}
+
+/// A file of a snapshot.
+#[derive(Clone, Debug, PartialEq)]
+struct Entry {
+    /// Path relative to the save directory, with `/` as separator.
+    path: String,
+    /// Name of the object with the content.
+    object: String,
+    len: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Manifest {
+    info: SnapshotInfo,
+    entries: Vec<Entry>,
+}
+
+/// Creates, lists, restores and prunes the snapshots of a saved world.
+pub struct Snapshots {
+    save_dir: PathBuf,
+}
+
+impl Snapshots {
+    pub fn new<P: AsRef<Path>>(save_dir: P) -> Self {
+        Snapshots {
+            save_dir: save_dir.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Takes a snapshot of the saved world.
+    ///
+    /// Nothing may write to the world meanwhile: either hold its
+    /// `WorldLock`, or let the server owning it take the snapshot after
+    /// saving.
+    pub fn create(&self, name: &str) -> io::Result<SnapshotInfo> {
+        let objects = self.dir().join(OBJECT_DIR);
+        fs::create_dir_all(&objects)?;
+
+        let mut paths = vec![LEVEL_FILE.to_string()];
+        match fs::read_dir(self.save_dir.join(REGION_DIR)) {
+            Ok(dir) => {
+                for file in dir {
+                    let path = file?.path();
+                    if !storage::is_temporary_file(&path) {
+                        let name = path.file_name().unwrap_or_default().to_string_lossy();
+                        paths.push(format!("{}/{}", REGION_DIR, name));
+                    }
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        paths.sort();
+
+        let mut entries = Vec::with_capacity(paths.len());
+        for path in paths {
+            let content = fs::read(self.save_dir.join(&path))?;
+            let object = object_name(&content);
+            let object_path = objects.join(&object);
+            if !is_stored(&object_path, &content)? {
+                if object_path.exists() {
+                    warn!("replacing damaged snapshot file {}", object_path.display());
+                }
+                storage::write_atomic(&object_path, &content)?;
+            }
+            entries.push(Entry {
+                path: path,
+                object: object,
+                len: content.len() as u64,
+            });
+        }
+
+        // Damaged manifests keep their id, too
+        let id = self.manifest_ids()?.last().map_or(1, |&id| id + 1);
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let manifest = Manifest {
+            info: SnapshotInfo {
+                id: id,
+                created: created,
+                name: name.to_string(),
+                files: entries.len(),
+                len: entries.iter().map(|e| e.len).sum(),
+            },
+            entries: entries,
+        };
+
+        // The snapshot exists once its manifest is written
+        let mut data = Vec::new();
+        manifest.encode(&mut data)?;
+        let mut file = MANIFEST_MAGIC.to_vec();
+        storage::write_blob(&mut file, MANIFEST_VERSION, &data)?;
+        storage::write_atomic(&self.manifest_path(id), &file)?;
+        info!("created snapshot {} of {}", id, self.save_dir.display());
+        Ok(manifest.info)
+    }
+
+    /// Returns all snapshots, the oldest first. Snapshots with a damaged
+    /// manifest are left out, see `damaged()`.
+    pub fn list(&self) -> io::Result<Vec<SnapshotInfo>> {
+        Ok(self
+            .manifests()?
+            .0
+            .into_iter()
+            .map(|manifest| manifest.info)
+            .collect())
+    }
+
+    /// Returns the ids of the snapshots whose manifest is damaged. They
+    /// can't be restored, and are deleted by `prune()`.
+    pub fn damaged(&self) -> io::Result<Vec<u32>> {
+        Ok(self.manifests()?.1)
+    }
+
+    /// Replaces the saved world by the given snapshot. Refuses if the world
+    /// is locked, e.g. by a running server.
+    ///
+    /// Every file is replaced atomically, but the world as a whole isn't: if
+    /// the restore is interrupted, it has to be run again.
+    pub fn restore(&self, id: u32) -> io::Result<SnapshotInfo> {
+        let _lock = WorldLock::acquire(&self.save_dir)?;
+        let manifest = self.read_manifest(&self.manifest_path(id))?;
+
+        // Check everything before anything is changed
+        let objects = self.dir().join(OBJECT_DIR);
+        for entry in &manifest.entries {
+            let content = fs::read(objects.join(&entry.object))?;
+            if !is_save_file(&entry.path) || object_name(&content) != entry.object {
+                return Err(invalid_data("damaged snapshot"));
+            }
+        }
+
+        let regions = self.save_dir.join(REGION_DIR);
+        fs::create_dir_all(&regions)?;
+        for entry in &manifest.entries {
+            let content = fs::read(objects.join(&entry.object))?;
+            storage::write_atomic(&self.save_dir.join(&entry.path), &content)?;
+        }
+
+        // Regions which were created after the snapshot
+        let restored: HashSet<_> = manifest.entries.iter().map(|e| &e.path).collect();
+        for file in fs::read_dir(&regions)? {
+            let path = file?.path();
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            if !restored.contains(&format!("{}/{}", REGION_DIR, name)) {
+                fs::remove_file(&path)?;
+            }
+        }
+        info!("restored snapshot {} of {}", id, self.save_dir.display());
+        Ok(manifest.info)
+    }
+
+    /// Deletes all but the newest `keep` snapshots, all snapshots with a
+    /// damaged manifest and the files only they used. Returns the deleted
+    /// snapshots with an intact manifest.
+    pub fn prune(&self, keep: usize) -> io::Result<Vec<SnapshotInfo>> {
+        let (mut manifests, damaged) = self.manifests()?;
+        let old = manifests.len().saturating_sub(keep);
+        let pruned: Vec<_> = manifests.drain(..old).map(|m| m.info).collect();
+        for info in &pruned {
+            fs::remove_file(self.manifest_path(info.id))?;
+        }
+        for &id in &damaged {
+            warn!("deleting snapshot {} with a damaged manifest", id);
+            fs::remove_file(self.manifest_path(id))?;
+        }
+
+        let used: HashSet<_> = manifests
+            .iter()
+            .flat_map(|m| m.entries.iter().map(|e| e.object.clone()))
+            .collect();
+        match fs::read_dir(self.dir().join(OBJECT_DIR)) {
+            Ok(dir) => {
+                for file in dir {
+                    let path = file?.path();
+                    let name = path.file_name().unwrap_or_default().to_string_lossy();
+                    if !used.contains(&*name) {
+                        fs::remove_file(&path)?;
+                    }
+                }
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        Ok(pruned)
+    }
+
+    fn dir(&self) -> PathBuf {
+        self.save_dir.join(SNAPSHOT_DIR)
+    }
+
+    fn manifest_path(&self, id: u32) -> PathBuf {
+        self.dir().join(format!("{}.{}", id, MANIFEST_EXTENSION))
+    }
+
+    /// Returns the ids of all manifests, sorted, judging by their file
+    /// names.
+    fn manifest_ids(&self) -> io::Result<Vec<u32>> {
+        let dir = match fs::read_dir(self.dir()) {
+            Ok(dir) => dir,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+
+        let mut ids = Vec::new();
+        for file in dir {
+            let path = file?.path();
+            if path
+                .extension()
+                .map_or(false, |ext| ext == MANIFEST_EXTENSION)
+            {
+                let id = path.file_stem().and_then(|s| s.to_str()).map(str::parse);
+                if let Some(Ok(id)) = id {
+                    ids.push(id);
+                }
+            }
+        }
+        ids.sort();
+        Ok(ids)
+    }
+
+    /// Reads all manifests, sorted by their id. Returns the intact manifests
+    /// and the ids of the damaged ones.
+    fn manifests(&self) -> io::Result<(Vec<Manifest>, Vec<u32>)> {
+        let mut manifests = Vec::new();
+        let mut damaged = Vec::new();
+        for id in self.manifest_ids()? {
+            match self.read_manifest(&self.manifest_path(id)) {
+                Ok(manifest) => manifests.push(manifest),
+                Err(ref e)
+                    if e.kind() == io::ErrorKind::InvalidData
+                        || e.kind() == io::ErrorKind::UnexpectedEof =>
+                {
+                    warn!("the manifest of snapshot {} is damaged: {}", id, e);
+                    damaged.push(id);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok((manifests, damaged))
+    }
+
+    fn read_manifest(&self, path: &Path) -> io::Result<Manifest> {
+        let file = match fs::read(path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "there is no such snapshot",
+                ));
+            }
+            Err(e) => return Err(e),
+        };
+        if !file.starts_with(MANIFEST_MAGIC) {
+            return Err(invalid_data("not a snapshot manifest"));
+        }
+        let mut r = &file[MANIFEST_MAGIC.len()..];
+        let blob = storage::read_blob(&mut r)?;
+        let data = storage::migrate(blob, MANIFEST_MIGRATIONS)?;
+        Manifest::decode(&mut &data[..])
+    }
+}
+
+/// Returns whether the path names a file which belongs into a snapshot.
+/// Damaged manifests must not write anywhere else.
+fn is_save_file(path: &str) -> bool {
+    let parts: Vec<_> = path.split('/').collect();
+    match parts[..] {
+        [LEVEL_FILE] => true,
+        [REGION_DIR, name] => !name.is_empty() && !name.starts_with('.') && !name.contains('\\'),
+        _ => false,
+    }
+}
+
+/// Returns whether the file exists and has exactly the given content.
+fn is_stored(path: &Path, content: &[u8]) -> io::Result<bool> {
+    match fs::metadata(path) {
+        Ok(ref meta) if meta.len() == content.len() as u64 => {}
+        Ok(_) => return Ok(false),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    }
+    Ok(fs::read(path)? == content)
+}
+
+/// Returns the name the content is stored under. Two 32-bit hashes and the
+/// length make different contents with the same name very unlikely.
+fn object_name(content: &[u8]) -> String {
+    let mut crc = Crc::new();
+    crc.update(content);
+
+    // FNV-1a, which unlike the hashers of the standard library stays the
+    // same between versions
+    let mut fnv: u32 = 0x811c_9dc5;
+    for &byte in content {
+        fnv ^= u32::from(byte);
+        fnv = fnv.wrapping_mul(0x0100_0193);
+    }
+    format!("{:08x}{:08x}-{}", crc.sum(), fnv, content.len())
+}
+
+impl Encode for SnapshotInfo {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.id.encode(w)?;
+        self.created.encode(w)?;
+        self.name.encode(w)?;
+        (self.files as u64).encode(w)?;
+        self.len.encode(w)
+    }
+}
+
+impl Decode for SnapshotInfo {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        Ok(SnapshotInfo {
+            id: u32::decode(r)?,
+            created: u64::decode(r)?,
+            name: String::decode(r)?,
+            files: u64::decode(r)? as usize,
+            len: u64::decode(r)?,
+        })
+    }
+}
+
+impl Encode for Manifest {
+    fn encode<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        self.info.encode(w)?;
+        (self.entries.len() as u32).encode(w)?;
+        for entry in &self.entries {
+            entry.path.encode(w)?;
+            entry.object.encode(w)?;
+            entry.len.encode(w)?;
+        }
+        Ok(())
+    }
+}
+
+impl Decode for Manifest {
+    fn decode<R: Read>(r: &mut R) -> io::Result<Self> {
+        let info = SnapshotInfo::decode(r)?;
+        let mut entries = Vec::new();
+        for _ in 0..u32::decode(r)? {
+            entries.push(Entry {
+                path: String::decode(r)?,
+                object: String::decode(r)?,
+                len: u64::decode(r)?,
+            });
+        }
+        Ok(Manifest {
+            info: info,
+            entries: entries,
+        })
+    }
+}
+
+#[test]
+fn snapshots_are_restored_and_pruned() {
+    use super::{Chunk, ChunkIndex, Level, RegionProvider};
+    use math::*;
+
+    let dir = storage::test_dir("snapshots");
+    let snapshots = Snapshots::new(&dir);
+    assert_eq!(snapshots.list().unwrap(), vec![]);
+
+    let mut level = Level::new(7);
+    level.save(&dir).unwrap();
+    let regions = RegionProvider::open(dir.join(REGION_DIR)).unwrap();
+    let index = ChunkIndex(AxialPoint::new(0, 0));
+    let far = ChunkIndex(AxialPoint::new(100, 0));
+    let chunk = Chunk::from_pillars(vec![Default::default(); 256]);
+    regions.save_chunk(index, &chunk).unwrap();
+    let first = snapshots.create("before").unwrap();
+    assert_eq!((first.id, first.files), (1, 2));
+
+    // Unchanged files are stored only once
+    let objects = || {
+        fs::read_dir(dir.join(SNAPSHOT_DIR).join(OBJECT_DIR))
+            .unwrap()
+            .count()
+    };
+    let second = snapshots.create("").unwrap();
+    assert_eq!(second.id, 2);
+    assert_eq!(objects(), 2);
+
+    // The big edit
+    level.seed = 8;
+    level.save(&dir).unwrap();
+    regions.save_chunk(far, &chunk).unwrap();
+    drop(regions);
+    assert_eq!(objects(), 2);
+
+    // Restoring is refused while the world is locked
+    let lock = WorldLock::acquire(&dir).unwrap();
+    let err = snapshots.restore(first.id).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+    assert!(WorldLock::acquire(&dir).is_err());
+    drop(lock);
+
+    assert_eq!(snapshots.restore(first.id).unwrap(), first);
+    assert_eq!(Level::load(&dir).unwrap(), Level::new(7));
+    let regions = RegionProvider::open(dir.join(REGION_DIR)).unwrap();
+    assert_eq!(regions.read_chunk(index).unwrap(), Some(chunk));
+    assert_eq!(regions.read_chunk(far).unwrap(), None);
+    assert!(snapshots.restore(7).is_err());
+
+    let third = snapshots.create("after").unwrap();
+    assert_eq!(
+        snapshots.list().unwrap(),
+        vec![first.clone(), second.clone(), third.clone()]
+    );
+    assert_eq!(snapshots.prune(1).unwrap(), vec![first, second]);
+    assert_eq!(snapshots.list().unwrap(), vec![third]);
+    assert_eq!(objects(), 2);
+    assert_eq!(snapshots.prune(0).unwrap().len(), 1);
+    assert_eq!(objects(), 0);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn damaged_snapshots_are_not_restored() {
+    let dir = storage::test_dir("damaged-snapshots");
+    super::Level::new(7).save(&dir).unwrap();
+    let snapshots = Snapshots::new(&dir);
+    let info = snapshots.create("").unwrap();
+
+    // A damaged object
+    let objects = dir.join(SNAPSHOT_DIR).join(OBJECT_DIR);
+    let object = fs::read_dir(&objects)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    let intact = fs::read(&object).unwrap();
+    let mut content = intact.clone();
+    content[0] ^= 1;
+    fs::write(&object, &content).unwrap();
+    assert!(snapshots.restore(info.id).is_err());
+
+    // New snapshots repair it instead of using it
+    let repaired = snapshots.create("repaired").unwrap();
+    assert_eq!(fs::read(&object).unwrap(), intact);
+    snapshots.restore(repaired.id).unwrap();
+    snapshots.restore(info.id).unwrap();
+    fs::write(&object, &intact[..intact.len() - 1]).unwrap();
+    snapshots.create("repaired again").unwrap();
+    assert_eq!(fs::read(&object).unwrap(), intact);
+
+    // A damaged manifest
+    let path = snapshots.manifest_path(info.id);
+    let mut manifest = fs::read(&path).unwrap();
+    let last = manifest.len() - 1;
+    manifest[last] ^= 1;
+    fs::write(&path, &manifest).unwrap();
+    assert!(snapshots.restore(info.id).is_err());
+    assert_eq!(snapshots.list().unwrap().len(), 2);
+    assert_eq!(snapshots.damaged().unwrap(), vec![info.id]);
+
+    // New snapshots still work
+    let new = snapshots.create("new").unwrap();
+    assert_eq!(new.id, info.id + 3);
+    assert_eq!(snapshots.list().unwrap().last(), Some(&new));
+    snapshots.restore(new.id).unwrap();
+
+    // A truncated manifest is damaged, too
+    fs::write(&path, &manifest[..manifest.len() / 2]).unwrap();
+    assert_eq!(snapshots.damaged().unwrap(), vec![info.id]);
+
+    // Pruning deletes damaged manifests, too
+    assert_eq!(snapshots.prune(1).unwrap().len(), 2);
+    assert!(!path.exists());
+    assert_eq!(snapshots.damaged().unwrap(), vec![]);
+    assert_eq!(snapshots.list().unwrap(), vec![new.clone()]);
+    snapshots.restore(new.id).unwrap();
+
+    assert!(is_save_file("level"));
+    assert!(is_save_file("regions/r.0.0.region"));
+    assert!(!is_save_file("../level"));
+    assert!(!is_save_file("regions/.."));
+    assert!(!is_save_file("regions/a/b"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
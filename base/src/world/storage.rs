@@ -0,0 +1,207 @@
+//! Building blocks of crash-safe save files.
+//!
+//! Everything saved is wrapped into a blob holding the format version of its
+//! content and a CRC-32 checksum, so that damaged or truncated data is
+//! reported instead of being decoded into garbage. Files which are replaced
+//! as a whole are written with `write_atomic`, so that a crash leaves either
+//! the old or the new file behind.
+
+use flate2::Crc;
+use net::{invalid_data, Decode, Encode};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+/// Length of the header in front of the content of a blob: version, length
+/// and checksum.
+pub const BLOB_HEADER_LEN: usize = 2 + 4 + 4;
+
+/// Blobs can't be longer than this, longer lengths are read as damaged data
+/// instead of being allocated.
+const MAX_BLOB_LEN: u32 = 64 * 1024 * 1024;
+
+/// Upgrades data from one format version to the next.
+pub type Migration = fn(Vec<u8>) -> io::Result<Vec<u8>>;
+
+/// Saved data together with the format version it was written in.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Blob {
+    pub version: u16,
+    pub data: Vec<u8>,
+}
+
+/// Writes the data as blob of the given format version.
+pub fn write_blob<W: Write>(w: &mut W, version: u16, data: &[u8]) -> io::Result<()> {
+    if data.len() > MAX_BLOB_LEN as usize {
+        return Err(io::Error::new(io::ErrorKind::Other, "data is too long"));
+    }
+    let len = data.len() as u32;
+    version.encode(w)?;
+    len.encode(w)?;
+    checksum(version, len, data).encode(w)?;
+    w.write_all(data)
+}
+
+/// Reads a blob written by `write_blob` and checks its checksum.
+pub fn read_blob<R: Read>(r: &mut R) -> io::Result<Blob> {
+    let read = |r: &mut R| -> io::Result<Blob> {
+        let version = u16::decode(r)?;
+        let len = u32::decode(r)?;
+        let sum = u32::decode(r)?;
+        if len > MAX_BLOB_LEN {
+            return Err(invalid_data("damaged data: invalid length"));
+        }
+        let mut data = vec![0; len as usize];
+        r.read_exact(&mut data)?;
+        if checksum(version, len, &data) != sum {
+            return Err(invalid_data("damaged data: checksum mismatch"));
+        }
+        Ok(Blob {
+            version: version,
+            data: data,
+        })
+    };
+    read(r).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => invalid_data("damaged data: truncated"),
+        _ => e,
+    })
+}
+
+/// Upgrades data of the given format version to the current one.
+/// `migrations[i]` upgrades version `i + 1` to `i + 2`, so the current
+/// version is `migrations.len() + 1`.
+pub fn migrate(blob: Blob, migrations: &[Migration]) -> io::Result<Vec<u8>> {
+    let current = migrations.len() + 1;
+    let version = blob.version as usize;
+    if version == 0 || version > current {
+        return Err(invalid_data(&format!(
+            "unsupported format version {}, the newest known is {}",
+            version, current
+        )));
+    }
+
+    let mut data = blob.data;
+    for (i, migration) in migrations.iter().enumerate().skip(version - 1) {
+        debug!("migrating data from version {} to {}", i + 1, i + 2);
+        data = migration(data)?;
+    }
+    Ok(data)
+}
+
+/// Replaces the file with the given content. The content is written to a
+/// temporary file next to it first, which is renamed once it's complete.
+pub fn write_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&tmp_path, path)?;
+
+    // The rename itself is only durable once the directory is synced
+    #[cfg(unix)]
+    {
+        if let Some(dir) = path.parent() {
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            File::open(dir)?.sync_all()?;
+        }
+    }
+    Ok(())
+}
+
+/// Returns whether the file was left behind by an interrupted
+/// `write_atomic`.
+pub fn is_temporary_file(path: &Path) -> bool {
+    path.extension().map_or(false, |ext| ext == "tmp")
+}
+
+/// Returns the checksum of a blob, which covers its version and length, too.
+fn checksum(version: u16, len: u32, data: &[u8]) -> u32 {
+    let mut header = Vec::with_capacity(6);
+    // Writing to a `Vec` can't fail
+    version.encode(&mut header).unwrap();
+    len.encode(&mut header).unwrap();
+
+    let mut crc = Crc::new();
+    crc.update(&header);
+    crc.update(data);
+    crc.sum()
+}
+
+/// Returns an empty temporary directory for a test.
+#[cfg(test)]
+pub fn test_dir(name: &str) -> ::std::path::PathBuf {
+    let dir = ::std::env::temp_dir().join(format!("plantex-{}-{}", name, ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn damaged_blobs_are_detected() {
+    let mut buf = Vec::new();
+    write_blob(&mut buf, 3, b"some data").unwrap();
+    let blob = read_blob(&mut &buf[..]).unwrap();
+    assert_eq!(blob.version, 3);
+    assert_eq!(blob.data, b"some data");
+
+    // Every flipped bit and every truncation is noticed
+    for i in 0..buf.len() {
+        let mut damaged = buf.clone();
+        damaged[i] ^= 0x10;
+        let err = read_blob(&mut &damaged[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+    for len in 0..buf.len() {
+        let err = read_blob(&mut &buf[..len]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("truncated"), "{}", err);
+    }
+}
+
+#[test]
+fn data_is_migrated_step_by_step() {
+    fn add_a(mut data: Vec<u8>) -> io::Result<Vec<u8>> {
+        data.push(b'a');
+        Ok(data)
+    }
+    fn add_b(mut data: Vec<u8>) -> io::Result<Vec<u8>> {
+        data.push(b'b');
+        Ok(data)
+    }
+    let migrations: &[Migration] = &[add_a, add_b];
+    let blob = |version| Blob {
+        version: version,
+        data: b"-".to_vec(),
+    };
+
+    assert_eq!(migrate(blob(1), migrations).unwrap(), b"-ab");
+    assert_eq!(migrate(blob(2), migrations).unwrap(), b"-b");
+    assert_eq!(migrate(blob(3), migrations).unwrap(), b"-");
+    assert!(migrate(blob(0), migrations).is_err());
+    assert!(migrate(blob(4), migrations).is_err());
+}
+
+#[test]
+fn files_are_replaced_atomically() {
+    let dir = test_dir("atomic");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("file");
+
+    write_atomic(&path, b"old").unwrap();
+    // A temporary file of an interrupted write doesn't matter
+    fs::write(dir.join("file.tmp"), b"half").unwrap();
+    write_atomic(&path, b"new").unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"new");
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+    assert!(is_temporary_file(&dir.join("file.tmp")));
+    assert!(!is_temporary_file(&path));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
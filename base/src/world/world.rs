@@ -1,5 +1,4 @@
 use super::{Chunk, ChunkIndex, HexPillar, PillarIndex};
-use math::*;
 use std::collections::HashMap;
 
 /// Represents a whole game world consisting of multiple `Chunk`s.
@@ -38,85 +37,35 @@ impl World {
         }
     }
 
+    /// Removes the chunk at the given position from the world and returns it.
+    pub fn remove_chunk(&mut self, index: ChunkIndex) -> Option<Chunk> {
+        self.chunks.remove(&index)
+    }
+
     /// Returns the hex pillar at the given world position, iff the
     /// corresponding chunk is loaded.
     pub fn pillar_at(&self, pos: PillarIndex) -> Option<&HexPillar> {
-        let chunk_size = super::CHUNK_SIZE as i32;
-        let mut new_pos = pos;
-        // TODO: use `/` operator once it's implemented
-        // let chunk_pos = pos / (super::CHUNK_SIZE as i32);
-        if new_pos.0.q < 0 {
-            new_pos.0.q -= 15;
-        }
-        if new_pos.0.r < 0 {
-            new_pos.0.r -= 15;
-        }
-        let chunk_pos = new_pos.0 / chunk_size;
-        let out = self.chunks.get(&ChunkIndex(chunk_pos)).map(|chunk| {
-            let mut inner_pos = pos.0 % chunk_size;
-            if inner_pos.q < 0 {
-                inner_pos.q += chunk_size;
-            }
-            if inner_pos.r < 0 {
-                inner_pos.r += chunk_size;
-            }
-            &chunk[inner_pos]
-        });
-
-        if out.is_none() {
-            debug!(
-                "chunk {:?} is not loaded (position request {:?})",
-                chunk_pos, pos
-            );
-        }
-        out
+        self.chunk_from_pillar(pos)
+            .map(|chunk| &chunk[pos.pos_in_chunk()])
     }
 
     /// Returns the hex pillar at the given world position, iff the
     /// corresponding chunk is loaded.
     pub fn pillar_at_mut(&mut self, pos: PillarIndex) -> Option<&mut HexPillar> {
-        let chunk_size = super::CHUNK_SIZE as i32;
-        let mut new_pos = pos;
-        // TODO: use `/` operator once it's implemented
-        // let chunk_pos = pos / (super::CHUNK_SIZE as i32);
-        if new_pos.0.q < 0 {
-            new_pos.0.q -= 15;
-        }
-        if new_pos.0.r < 0 {
-            new_pos.0.r -= 15;
-        }
-        let chunk_pos = new_pos.0 / chunk_size;
-        let out = self.chunks.get_mut(&ChunkIndex(chunk_pos)).map(|chunk| {
-            let mut inner_pos = pos.0 % chunk_size;
-            if inner_pos.q < 0 {
-                inner_pos.q += chunk_size;
-            }
-            if inner_pos.r < 0 {
-                inner_pos.r += chunk_size;
-            }
-            if chunk_pos.q != chunk_pos.r {
-                ::std::mem::swap(&mut inner_pos.r, &mut inner_pos.q);
-            }
-            &mut chunk[inner_pos]
-        });
-
+        let out = self.chunks.get_mut(&pos.chunk());
         if out.is_none() {
             debug!(
                 "chunk {:?} is not loaded (position request {:?})",
-                chunk_pos, pos
+                pos.chunk(),
+                pos
             );
         }
-        out
+        out.map(|chunk| &mut chunk[pos.pos_in_chunk()])
     }
 
     /// Returns the chunk in which the given pillar exists.
     pub fn chunk_from_pillar(&self, pos: PillarIndex) -> Option<&Chunk> {
-        let tmp = AxialPoint::new(
-            pos.0.q / (super::CHUNK_SIZE as i32),
-            pos.0.r / (super::CHUNK_SIZE as i32),
-        );
-        let chunk_pos = ChunkIndex(tmp);
-        self.chunk_at(chunk_pos)
+        self.chunk_at(pos.chunk())
     }
 
     /// Returns the requested chunk.
@@ -130,3 +79,35 @@ impl World {
         out
     }
 }
+
+#[test]
+fn pillars_are_found_in_all_chunks() {
+    use super::{GroundMaterial, HeightType, PillarSection};
+    use gen::world::biome::Biome;
+    use math::AxialPoint;
+
+    // Every pillar remembers its position in the height of its section
+    let pillar = |pos: AxialPoint| {
+        let height = HeightType((pos.q + 100) as u16 * 200 + (pos.r + 100) as u16);
+        let section = PillarSection::new(GroundMaterial::Stone, HeightType(0), height);
+        HexPillar::new(vec![section], vec![], Biome::default())
+    };
+    let mut world = World::empty();
+    for &(q, r) in &[(0, 0), (1, 0), (0, -1), (-2, -1)] {
+        let index = ChunkIndex(AxialPoint::new(q, r));
+        world.replace_chunk(index, Chunk::with_pillars(index, pillar));
+    }
+
+    for &(q, r) in &[(0, 0), (17, 3), (3, -15), (5, -1), (-20, -9), (-32, -1)] {
+        let pos = AxialPoint::new(q, r);
+        let expected = pillar(pos);
+        assert_eq!(world.pillar_at(PillarIndex(pos)), Some(&expected));
+        assert_eq!(
+            world.pillar_at_mut(PillarIndex(pos)),
+            Some(&mut expected.clone())
+        );
+    }
+    assert!(world
+        .pillar_at(PillarIndex(AxialPoint::new(16, 16)))
+        .is_none());
+}
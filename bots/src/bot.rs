@@ -0,0 +1,374 @@
+use base::gen::{seeded_rng, Random};
+use base::math::*;
+use base::movement::{Controls, MoveInput};
+use base::net::{Connection, Encode, Message, Welcome, PROTOCOL_VERSION};
+use base::world::{chunks_in_range, ChunkIndex, EditKind, GroundMaterial, PillarEdit};
+use base::world::{PillarIndex, World};
+use rand::Rng;
+use stats::BotStats;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::io;
+use std::mem;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+use {BotConfig, PathKind};
+
+/// Bots simulate this many frames per second, like a client would render.
+const FRAME_TIME: Duration = Duration::from_millis(16);
+
+/// Inputs are sent as often as the client sends them.
+const INPUT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Chunks which didn't arrive after this long are requested again.
+const CHUNK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the bots check which chunks they need.
+const CHUNK_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Turning speed of bots walking in circles, in radians per second.
+const CIRCLE_TURN_SPEED: f32 = 0.3;
+
+/// A simulated player.
+struct Bot<'a> {
+    config: &'a BotConfig,
+    conn: Connection,
+    messages: Receiver<io::Result<Message>>,
+    stats: BotStats,
+    rng: Random,
+
+    /// Position of the eyes, as acknowledged by the server.
+    position: Point3f,
+    controls: Controls,
+    next_turn: Instant,
+    next_seq: u32,
+    unsent_inputs: Vec<MoveInput>,
+    next_input_send: Instant,
+    /// Sequence number of the last input of every batch waiting for its
+    /// `MoveAck`, with the time the batch was sent.
+    unacked_batches: Vec<(u32, Instant)>,
+
+    /// Radius around the bot in which the server sends chunks, in chunks.
+    view_radius: f32,
+    /// The chunks received so far, kept up to date with all edits.
+    world: World,
+    /// Chunks requested but not received yet, with the time of the request.
+    pending_chunks: HashMap<ChunkIndex, Instant>,
+    next_chunk_check: Instant,
+
+    next_edit_id: u32,
+    next_edit: Instant,
+    /// The last step this bot added, which it removes with its next edit.
+    added_step: Option<PillarEdit>,
+}
+
+/// Connects a bot, lets it play until `stop_at` and returns what happened.
+pub fn run_bot(id: usize, config: &BotConfig, stop_at: Instant) -> BotStats {
+    let name = format!("{}{}", config.name_prefix, id);
+    let mut stats = BotStats {
+        name: name.clone(),
+        ..BotStats::default()
+    };
+
+    let start = Instant::now();
+    let (conn, welcome) = match login(config, &name) {
+        Ok(res) => res,
+        Err(e) => {
+            warn!("{} couldn't log in: {}", name, e);
+            stats.disconnect = Some(format!("login failed: {}", e));
+            return stats;
+        }
+    };
+    stats.login_time = Some(start.elapsed());
+    debug!("{} logged in after {:?}", name, start.elapsed());
+
+    let messages = match conn.spawn_reader(format!("{} reader", name)) {
+        Ok(messages) => messages,
+        Err(e) => {
+            stats.disconnect = Some(format!("can't start reader: {}", e));
+            return stats;
+        }
+    };
+
+    let mut rng = seeded_rng(config.seed, "bot", id);
+    let now = Instant::now();
+    let edit_delay = config
+        .edit_interval
+        .map(|i| i + Duration::from_millis(rng.gen_range(0, 1000)))
+        .unwrap_or(Duration::from_secs(0));
+    let mut bot = Bot {
+        config: config,
+        conn: conn,
+        messages: messages,
+        stats: stats,
+        position: welcome.spawn,
+        controls: Controls {
+            forward: 1.0,
+            phi: rng.gen_range(0.0, 2.0 * PI),
+            ..Controls::default()
+        },
+        rng: rng,
+        next_turn: now,
+        next_seq: 1,
+        unsent_inputs: Vec::new(),
+        next_input_send: now + INPUT_INTERVAL,
+        unacked_batches: Vec::new(),
+        view_radius: welcome.view_radius,
+        world: World::empty(),
+        pending_chunks: HashMap::new(),
+        next_chunk_check: now,
+        next_edit_id: 1,
+        next_edit: now + edit_delay,
+        added_step: None,
+    };
+
+    if let Err(e) = bot.run(stop_at) {
+        warn!("{} lost its connection: {}", bot.stats.name, e);
+        bot.stats.disconnect = Some(e);
+    }
+    // The server might already be gone, so errors are ignored
+    let _ = bot.conn.send(&Message::Disconnect {
+        reason: "bot finished".into(),
+    });
+    bot.conn.shutdown();
+    bot.stats
+}
+
+/// Connects to the server and logs in. Returns the connection and the
+/// server's welcome.
+fn login(config: &BotConfig, name: &str) -> io::Result<(Connection, Welcome)> {
+    let conn = Connection::connect(config.server)?;
+    conn.send(&Message::Login {
+        version: PROTOCOL_VERSION,
+        name: name.to_string(),
+    })?;
+
+    match conn.recv()? {
+        Message::Welcome(welcome) => Ok((conn, welcome)),
+        Message::Disconnect { reason } => Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("server rejected login: {}", reason),
+        )),
+        msg => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("server answered login with {:?}", msg),
+        )),
+    }
+}
+
+impl<'a> Bot<'a> {
+    /// Plays until `stop_at`. Returns why the connection was lost if that
+    /// happened before.
+    fn run(&mut self, stop_at: Instant) -> Result<(), String> {
+        let mut last_frame = Instant::now();
+        while Instant::now() < stop_at {
+            thread::sleep(FRAME_TIME);
+            let now = Instant::now();
+            let delta = now - last_frame;
+            last_frame = now;
+
+            self.handle_messages()?;
+            self.steer(now, delta);
+            self.unsent_inputs.push(MoveInput {
+                seq: self.next_seq,
+                delta: delta.as_secs() as f32 + delta.subsec_nanos() as f32 * 1e-9,
+                controls: self.controls,
+            });
+            self.next_seq += 1;
+
+            let res = self
+                .send_inputs(now)
+                .and_then(|_| self.request_chunks(now))
+                .and_then(|_| self.edit_terrain(now));
+            if let Err(e) = res {
+                return Err(format!("send failed: {}", e));
+            }
+        }
+        Ok(())
+    }
+
+    /// Changes the direction the bot walks in.
+    fn steer(&mut self, now: Instant, delta: Duration) {
+        match self.config.path {
+            PathKind::Random => {
+                if now >= self.next_turn {
+                    self.controls.phi = self.rng.gen_range(0.0, 2.0 * PI);
+                    // Jumping now and then gets bots over small cliffs
+                    self.controls.jump = self.rng.gen_weighted_bool(3);
+                    self.next_turn = now + Duration::from_millis(self.rng.gen_range(2000, 6000));
+                }
+            }
+            PathKind::Circle => {
+                let delta = delta.as_secs() as f32 + delta.subsec_nanos() as f32 * 1e-9;
+                self.controls.phi = (self.controls.phi + CIRCLE_TURN_SPEED * delta) % (2.0 * PI);
+            }
+        }
+    }
+
+    fn send_inputs(&mut self, now: Instant) -> io::Result<()> {
+        if now < self.next_input_send {
+            return Ok(());
+        }
+        let inputs = mem::replace(&mut self.unsent_inputs, Vec::new());
+        if let Some(last) = inputs.last() {
+            self.unacked_batches.push((last.seq, now));
+        }
+        self.next_input_send = now + INPUT_INTERVAL;
+        self.conn.send(&Message::Move(inputs))
+    }
+
+    /// Requests all chunks in range which the bot neither has nor waits for,
+    /// and forgets the chunks out of range, like the client does.
+    fn request_chunks(&mut self, now: Instant) -> io::Result<()> {
+        if now < self.next_chunk_check {
+            return Ok(());
+        }
+        self.next_chunk_check = now + CHUNK_CHECK_INTERVAL;
+
+        let pos = Point2f::new(self.position.x, self.position.y);
+        let radius = self.view_radius;
+        let out_of_range: Vec<_> = self
+            .world
+            .chunks
+            .keys()
+            .cloned()
+            .filter(|&index| !index.is_in_range(pos, radius))
+            .collect();
+        for index in out_of_range {
+            self.world.remove_chunk(index);
+        }
+        self.pending_chunks
+            .retain(|&index, _| index.is_in_range(pos, radius));
+
+        for index in chunks_in_range(pos, radius) {
+            if self.world.chunks.contains_key(&index) {
+                continue;
+            }
+            let requested = self.pending_chunks.get(&index).cloned();
+            if requested.map_or(true, |time| now - time > CHUNK_TIMEOUT) {
+                self.conn.send(&Message::RequestChunk(index))?;
+                self.pending_chunks.insert(index, now);
+                self.stats.chunks_requested += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds a step on the pillar the bot stands on, or removes the step it
+    /// added last.
+    fn edit_terrain(&mut self, now: Instant) -> io::Result<()> {
+        let interval = match self.config.edit_interval {
+            Some(interval) if now >= self.next_edit => interval,
+            _ => return Ok(()),
+        };
+        self.next_edit = now + interval;
+
+        let edit = match self.added_step.take() {
+            Some(added) => PillarEdit {
+                kind: EditKind::Remove,
+                ..added
+            },
+            None => {
+                let pos = PillarIndex(AxialPoint::from_real(Point2f::new(
+                    self.position.x,
+                    self.position.y,
+                )));
+                let top = self
+                    .world
+                    .pillar_at(pos)
+                    .and_then(|pillar| pillar.sections().last().map(|s| s.top));
+                match top {
+                    Some(top) => {
+                        let edit = PillarEdit {
+                            pos: pos,
+                            height: top,
+                            kind: EditKind::Add(GroundMaterial::Stone),
+                        };
+                        self.added_step = Some(edit);
+                        edit
+                    }
+                    // The chunk didn't arrive yet
+                    None => return Ok(()),
+                }
+            }
+        };
+
+        let id = self.next_edit_id;
+        self.next_edit_id += 1;
+        self.stats.edits_sent += 1;
+        self.conn.send(&Message::PillarEdit { id: id, edit: edit })
+    }
+
+    /// Handles all messages the server sent since the last frame.
+    fn handle_messages(&mut self) -> Result<(), String> {
+        loop {
+            let msg = match self.messages.try_recv() {
+                Ok(Ok(msg)) => msg,
+                Ok(Err(e)) => return Err(e.to_string()),
+                Err(TryRecvError::Empty) => return Ok(()),
+                Err(TryRecvError::Disconnected) => return Err("reader thread stopped".into()),
+            };
+            let now = Instant::now();
+
+            let res = match msg {
+                Message::ChunkData(index, version, chunk) => {
+                    let mut buf = Vec::new();
+                    // Only used for the statistics, so the chunk is encoded
+                    // on its own
+                    if chunk.encode(&mut buf).is_ok() {
+                        self.stats.chunk_bytes += buf.len() as u64;
+                    }
+                    self.stats.chunks_received += 1;
+                    if let Some(requested) = self.pending_chunks.remove(&index) {
+                        self.stats.chunk_latencies.push(now - requested);
+                    }
+                    trace!(
+                        "{} received chunk {:?} v{}",
+                        self.stats.name,
+                        index,
+                        version
+                    );
+                    self.world.replace_chunk(index, chunk);
+                    Ok(())
+                }
+                Message::ChunkUnavailable(index) => {
+                    // The chunk stays pending, so it's only requested again
+                    // after the timeout
+                    debug!("{}: server refused chunk {:?}", self.stats.name, index);
+                    Ok(())
+                }
+                Message::ChunkEdit { edit, origin, .. } => {
+                    if origin.is_some() {
+                        self.stats.edits_confirmed += 1;
+                    }
+                    // Edits of chunks we already dropped don't matter
+                    let _ = edit.apply(&mut self.world);
+                    Ok(())
+                }
+                Message::EditRejected(id) => {
+                    debug!("{}: server rejected edit {}", self.stats.name, id);
+                    self.stats.edits_rejected += 1;
+                    Ok(())
+                }
+                Message::MoveAck { seq, body } => {
+                    self.position = body.position;
+                    if let Some(&(_, sent)) = self.unacked_batches.iter().find(|b| b.0 == seq) {
+                        self.stats.move_latencies.push(now - sent);
+                    }
+                    self.unacked_batches.retain(|&(last, _)| last > seq);
+                    Ok(())
+                }
+                Message::Ping(n) => self.conn.send(&Message::Pong(n)),
+                Message::Disconnect { reason } => {
+                    return Err(format!("disconnected by server: {}", reason));
+                }
+                // Bots don't care about other players, time, weather and chat
+                _ => Ok(()),
+            };
+            if let Err(e) = res {
+                return Err(format!("send failed: {}", e));
+            }
+        }
+    }
+}
@@ -0,0 +1,212 @@
+extern crate clap;
+
+use self::clap::{App, Arg, ArgMatches};
+use std::error::Error as StdError;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+/// How the bots choose where to walk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PathKind {
+    /// Every bot walks straight and turns into a random direction every few
+    /// seconds.
+    Random,
+    /// Every bot walks in a circle.
+    Circle,
+}
+
+/// Settings of a load test.
+#[derive(Clone, Debug)]
+pub struct BotConfig {
+    /// The server to test.
+    pub server: SocketAddr,
+    /// Number of simulated players.
+    pub bots: usize,
+    /// How long the bots play.
+    pub duration: Duration,
+    /// Time between connecting two bots.
+    pub connect_interval: Duration,
+    pub path: PathKind,
+    /// Every bot edits the terrain this often, never with `None`.
+    pub edit_interval: Option<Duration>,
+    /// Seed of the random paths.
+    pub seed: u64,
+    /// Bots are named like this followed by their number.
+    pub name_prefix: String,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        BotConfig {
+            server: SocketAddr::new(Ipv4Addr::new(127, 0, 0, 1).into(), 34255),
+            bots: 10,
+            duration: Duration::from_secs(60),
+            connect_interval: Duration::from_millis(50),
+            path: PathKind::Random,
+            edit_interval: Some(Duration::from_secs(2)),
+            seed: 42,
+            name_prefix: "bot".to_string(),
+        }
+    }
+}
+
+impl BotConfig {
+    /// Creates the config from the default values overridden by the command
+    /// line.
+    pub fn load_config() -> Result<BotConfig, Box<dyn StdError>> {
+        let matches = app().get_matches();
+        let config = config_command(BotConfig::default(), &matches)?;
+        debug!("final config: {:?}", config);
+        Ok(config)
+    }
+}
+
+fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new("Plantex Bots")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Load tests a Plantex server with simulated players")
+        .arg(
+            Arg::with_name("Server")
+                .help("'Address of the server'")
+                .takes_value(true)
+                .long("server"),
+        )
+        .arg(
+            Arg::with_name("Bots")
+                .help("'Number of simulated players'")
+                .takes_value(true)
+                .long("bots"),
+        )
+        .arg(
+            Arg::with_name("Duration")
+                .help("'How long the bots play, in seconds'")
+                .takes_value(true)
+                .long("duration"),
+        )
+        .arg(
+            Arg::with_name("ConnectInterval")
+                .help("'Milliseconds between connecting two bots'")
+                .takes_value(true)
+                .long("connect-interval"),
+        )
+        .arg(
+            Arg::with_name("Path")
+                .help("[random, circle] 'How the bots walk'")
+                .takes_value(true)
+                .long("path"),
+        )
+        .arg(
+            Arg::with_name("EditInterval")
+                .help("'Milliseconds between two edits of a bot, 0 for no edits'")
+                .takes_value(true)
+                .long("edit-interval"),
+        )
+        .arg(
+            Arg::with_name("Seed")
+                .help("'Seed of the random paths'")
+                .takes_value(true)
+                .long("seed"),
+        )
+        .arg(
+            Arg::with_name("Name")
+                .help("'Prefix of the bot names'")
+                .takes_value(true)
+                .long("name"),
+        )
+}
+
+/// read configuration from command line
+/// overwrite config values with command line values
+/// return updated config
+fn config_command(
+    mut config: BotConfig,
+    matches: &ArgMatches,
+) -> Result<BotConfig, Box<dyn StdError>> {
+    // server address
+    if let Some(server) = matches.value_of("Server") {
+        match server.parse() {
+            Ok(addr) => config.server = addr,
+            _ => return Err("Server address from command line is invalid".into()),
+        }
+    }
+
+    // number of bots
+    if let Some(bots) = matches.value_of("Bots") {
+        match bots.parse::<usize>() {
+            Ok(n) if n >= 1 => config.bots = n,
+            _ => return Err("Number of bots from command line is invalid".into()),
+        }
+    }
+
+    // duration
+    if let Some(duration) = matches.value_of("Duration") {
+        match duration.parse::<u64>() {
+            Ok(n) if n >= 1 => config.duration = Duration::from_secs(n),
+            _ => return Err("Duration from command line is invalid".into()),
+        }
+    }
+
+    // time between connecting two bots
+    if let Some(interval) = matches.value_of("ConnectInterval") {
+        match interval.parse::<u64>() {
+            Ok(n) => config.connect_interval = Duration::from_millis(n),
+            _ => return Err("Connect interval from command line is invalid".into()),
+        }
+    }
+
+    // paths
+    if let Some(path) = matches.value_of("Path") {
+        match path {
+            "random" => config.path = PathKind::Random,
+            "circle" => config.path = PathKind::Circle,
+            _ => return Err("Path from command line is invalid".into()),
+        }
+    }
+
+    // edits
+    if let Some(interval) = matches.value_of("EditInterval") {
+        match interval.parse::<u64>() {
+            Ok(0) => config.edit_interval = None,
+            Ok(n) => config.edit_interval = Some(Duration::from_millis(n)),
+            _ => return Err("Edit interval from command line is invalid".into()),
+        }
+    }
+
+    // seed
+    if let Some(seed) = matches.value_of("Seed") {
+        match seed.parse::<u64>() {
+            Ok(n) => config.seed = n,
+            _ => return Err("Seed from command line is invalid".into()),
+        }
+    }
+
+    // bot names
+    if let Some(name) = matches.value_of("Name") {
+        if name.trim().is_empty() {
+            return Err("Name from command line is empty".into());
+        }
+        config.name_prefix = name.to_string();
+    }
+
+    Ok(config)
+}
+
+#[test]
+fn command_line_overrides_defaults() {
+    let matches = app().get_matches_from(vec![
+        "plantex-bots",
+        "--bots",
+        "50",
+        "--path",
+        "circle",
+        "--edit-interval",
+        "0",
+    ]);
+    let config = config_command(BotConfig::default(), &matches).unwrap();
+    assert_eq!(config.bots, 50);
+    assert_eq!(config.path, PathKind::Circle);
+    assert_eq!(config.edit_interval, None);
+
+    let matches = app().get_matches_from(vec!["plantex-bots", "--bots", "0"]);
+    assert!(config_command(BotConfig::default(), &matches).is_err());
+}
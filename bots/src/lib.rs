@@ -0,0 +1,52 @@
+//! Simulated players for load testing a server.
+//!
+//! Every bot connects on its own, walks around, requests the chunks around
+//! it and edits the terrain now and then, like a real client would. Only
+//! `base` is used, so the bots run on machines without a GPU.
+
+extern crate base;
+#[macro_use]
+extern crate log;
+extern crate rand;
+
+mod bot;
+mod config;
+mod stats;
+
+pub use config::{BotConfig, PathKind};
+pub use stats::{BotStats, LatencySummary, Report};
+
+use std::thread;
+use std::time::Instant;
+
+/// Connects all bots one after another, lets them play for the configured
+/// duration and returns what happened.
+pub fn run_bots(config: &BotConfig) -> Report {
+    let start = Instant::now();
+    let stop_at = start + config.duration;
+
+    let mut handles = Vec::new();
+    for id in 0..config.bots {
+        if id > 0 {
+            thread::sleep(config.connect_interval);
+        }
+        let config = config.clone();
+        let res = thread::Builder::new()
+            .name(format!("{}{}", config.name_prefix, id))
+            .spawn(move || bot::run_bot(id, &config, stop_at));
+        match res {
+            Ok(handle) => handles.push(handle),
+            Err(e) => error!("can't start bot {}: {}", id, e),
+        }
+    }
+    info!("started {} bots", handles.len());
+
+    let bots = handles
+        .into_iter()
+        .filter_map(|handle| handle.join().ok())
+        .collect();
+    Report {
+        duration: start.elapsed(),
+        bots: bots,
+    }
+}
@@ -0,0 +1,182 @@
+use std::fmt;
+use std::time::Duration;
+
+/// What happened to a single bot during the test.
+#[derive(Clone, Debug, Default)]
+pub struct BotStats {
+    pub name: String,
+    /// Time between connecting and receiving the `Welcome`, `None` if the bot
+    /// couldn't log in.
+    pub login_time: Option<Duration>,
+    /// Time between sending inputs and receiving their `MoveAck`.
+    pub move_latencies: Vec<Duration>,
+    /// Time between requesting a chunk and receiving it.
+    pub chunk_latencies: Vec<Duration>,
+    pub chunks_requested: u32,
+    /// All chunks received, including ones sent again after edits.
+    pub chunks_received: u32,
+    pub chunk_bytes: u64,
+    pub edits_sent: u32,
+    pub edits_confirmed: u32,
+    pub edits_rejected: u32,
+    /// Why the bot lost its connection before the end of the test.
+    pub disconnect: Option<String>,
+}
+
+/// Minimum, median, 95th percentile and maximum of a set of durations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LatencySummary {
+    pub count: usize,
+    pub min: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub max: Duration,
+}
+
+impl LatencySummary {
+    /// Returns `None` for an empty set.
+    pub fn new<I: IntoIterator<Item = Duration>>(latencies: I) -> Option<Self> {
+        let mut sorted: Vec<_> = latencies.into_iter().collect();
+        if sorted.is_empty() {
+            return None;
+        }
+        sorted.sort();
+        let percentile = |p: usize| sorted[(sorted.len() - 1) * p / 100];
+        Some(LatencySummary {
+            count: sorted.len(),
+            min: sorted[0],
+            p50: percentile(50),
+            p95: percentile(95),
+            max: sorted[sorted.len() - 1],
+        })
+    }
+}
+
+impl fmt::Display for LatencySummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "min {:.1}ms, p50 {:.1}ms, p95 {:.1}ms, max {:.1}ms ({} samples)",
+            millis(self.min),
+            millis(self.p50),
+            millis(self.p95),
+            millis(self.max),
+            self.count
+        )
+    }
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs() as f64 * 1000.0 + d.subsec_nanos() as f64 / 1_000_000.0
+}
+
+/// The results of all bots.
+#[derive(Clone, Debug)]
+pub struct Report {
+    /// How long the bots played.
+    pub duration: Duration,
+    pub bots: Vec<BotStats>,
+}
+
+impl Report {
+    /// Number of bots which logged in.
+    pub fn logged_in(&self) -> usize {
+        self.bots.iter().filter(|b| b.login_time.is_some()).count()
+    }
+
+    /// Bots which lost their connection, with the reason.
+    pub fn disconnects(&self) -> Vec<(&str, &str)> {
+        self.bots
+            .iter()
+            .filter_map(|b| b.disconnect.as_ref().map(|r| (b.name.as_str(), r.as_str())))
+            .collect()
+    }
+
+    pub fn chunks_received(&self) -> u32 {
+        self.bots.iter().map(|b| b.chunks_received).sum()
+    }
+
+    /// Chunks received by all bots together per second.
+    pub fn chunks_per_second(&self) -> f64 {
+        self.chunks_received() as f64 / (millis(self.duration) / 1000.0).max(0.001)
+    }
+
+    pub fn move_latency(&self) -> Option<LatencySummary> {
+        LatencySummary::new(
+            self.bots
+                .iter()
+                .flat_map(|b| b.move_latencies.iter().cloned()),
+        )
+    }
+
+    pub fn chunk_latency(&self) -> Option<LatencySummary> {
+        LatencySummary::new(
+            self.bots
+                .iter()
+                .flat_map(|b| b.chunk_latencies.iter().cloned()),
+        )
+    }
+
+    pub fn login_latency(&self) -> Option<LatencySummary> {
+        LatencySummary::new(self.bots.iter().filter_map(|b| b.login_time))
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn line(f: &mut fmt::Formatter, name: &str, s: Option<LatencySummary>) -> fmt::Result {
+            match s {
+                Some(s) => writeln!(f, "{:<16}{}", name, s),
+                None => writeln!(f, "{:<16}no samples", name),
+            }
+        }
+        let sum = |get: fn(&BotStats) -> u64| self.bots.iter().map(get).sum::<u64>();
+
+        writeln!(
+            f,
+            "{} of {} bots logged in, played for {:.1}s",
+            self.logged_in(),
+            self.bots.len(),
+            millis(self.duration) / 1000.0
+        )?;
+        line(f, "login:", self.login_latency())?;
+        line(f, "move latency:", self.move_latency())?;
+        line(f, "chunk latency:", self.chunk_latency())?;
+        writeln!(
+            f,
+            "{:<16}{} requested, {} received, {:.1}/s, {:.1} KiB/s",
+            "chunks:",
+            sum(|b| b.chunks_requested as u64),
+            self.chunks_received(),
+            self.chunks_per_second(),
+            sum(|b| b.chunk_bytes) as f64 / 1024.0 / (millis(self.duration) / 1000.0).max(0.001)
+        )?;
+        writeln!(
+            f,
+            "{:<16}{} sent, {} confirmed, {} rejected",
+            "edits:",
+            sum(|b| b.edits_sent as u64),
+            sum(|b| b.edits_confirmed as u64),
+            sum(|b| b.edits_rejected as u64)
+        )?;
+
+        let disconnects = self.disconnects();
+        write!(f, "{:<16}{}", "disconnects:", disconnects.len())?;
+        for (name, reason) in disconnects {
+            write!(f, "\n  {}: {}", name, reason)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn latencies_are_summarized() {
+    assert_eq!(LatencySummary::new(vec![]), None);
+
+    let summary = LatencySummary::new((1..101).rev().map(Duration::from_millis)).unwrap();
+    assert_eq!(summary.count, 100);
+    assert_eq!(summary.min, Duration::from_millis(1));
+    assert_eq!(summary.p50, Duration::from_millis(50));
+    assert_eq!(summary.p95, Duration::from_millis(95));
+    assert_eq!(summary.max, Duration::from_millis(100));
+}
@@ -9,7 +9,7 @@
 
 # config
 COLS=100
-FOLDER="base client plantex plantex-server server"
+FOLDER="base bots client plantex plantex-bots plantex-export plantex-server plantex-snapshots server tests"
 FILES='.+\.\(rs\|vert\|tesc\|tese\|geom\|frag\|comp\)'
 
 
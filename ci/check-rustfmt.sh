@@ -6,7 +6,7 @@ set -o errexit -o nounset
 echo ""
 echo "=== Checking Rust style with rustfmt... =============="
 
-FOLDER="base client plantex plantex-server server"
+FOLDER="base bots client plantex plantex-bots plantex-export plantex-server plantex-snapshots server tests"
 FILES='.+\.rs'
 
 ERROR=0
@@ -10,7 +10,7 @@ echo ""
 echo "=== Building Plantex ==============="
 cargo build
 
-for crate in base client server plantex; do
+for crate in base bots client server plantex; do
     echo ""
     echo "=== Testing $crate... =============="
     cargo test -p $crate
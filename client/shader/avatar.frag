@@ -0,0 +1,30 @@
+#version 150
+
+in vec3 x_normal;
+in vec3 x_color;
+in vec3 pos;
+
+out vec3 color;
+
+// Vector from the camera to the sun
+uniform vec3 sun_dir;
+uniform vec3 sun_color;
+uniform vec3 sky_light;
+
+void main() {
+    float diffuse = max(0.0, dot(-normalize(sun_dir), normalize(x_normal)));
+    vec3 tmp_color = x_color * sky_light + x_color * diffuse * sun_color;
+
+    // apply fog to final color, like for the plants
+    float distance = (length(pos) / 130) * (length(pos) / 130);
+    if (distance > 1) {
+        distance = 1;
+    }
+    float fog_time = -(sun_dir.z / 3) * 30;
+    if (fog_time < 0) {
+        fog_time = 0;
+    }
+
+    vec3 fog_color = vec3(0.05 + fog_time, 0.05 + fog_time, 0.1 + fog_time);
+    color = mix(tmp_color, fog_color, distance / 1.5);
+}
@@ -0,0 +1,21 @@
+#version 150
+
+in vec3 position;
+in vec3 normal;
+in vec3 color;
+
+uniform mat4 proj_matrix;
+uniform mat4 view_matrix;
+uniform mat4 model;
+
+out vec3 x_normal;
+out vec3 x_color;
+out vec3 pos;
+
+void main() {
+    vec4 world_pos = model * vec4(position, 1);
+    gl_Position = proj_matrix * view_matrix * world_pos;
+    x_normal = mat3(model) * normal;
+    x_color = color;
+    pos = (view_matrix * world_pos).xyz;
+}
@@ -0,0 +1,12 @@
+#version 150
+
+in vec2 x_tex_coords;
+in vec4 x_color;
+
+uniform sampler2D u_font;
+
+out vec4 color;
+
+void main() {
+    color = x_color * texture(u_font, x_tex_coords);
+}
@@ -0,0 +1,17 @@
+#version 150
+
+in vec2 i_position;
+in vec2 i_tex_coords;
+in vec4 i_color;
+
+// Size of the window in pixels
+uniform vec2 u_resolution;
+
+out vec2 x_tex_coords;
+out vec4 x_color;
+
+void main() {
+    gl_Position = vec4(i_position / u_resolution * 2.0 - 1.0, 0.0, 1.0);
+    x_tex_coords = i_tex_coords;
+    x_color = i_color;
+}
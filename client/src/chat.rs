@@ -0,0 +1,122 @@
+use super::event_manager::*;
+use base::net::MAX_CHAT_LEN;
+use glium::glutin::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Received lines are shown for this long.
+const LINE_DURATION: Duration = Duration::from_secs(10);
+
+/// Number of received lines kept.
+const MAX_LINES: usize = 10;
+
+/// The chat log and the line the player is typing.
+///
+/// Pressing return or `/` starts typing a line, return sends it and escape
+/// cancels it. While typing, no other handler receives key presses. Key
+/// releases are still forwarded, so that the player doesn't keep walking.
+pub struct Chat {
+    /// Received lines and when they arrived, the newest last.
+    lines: VecDeque<(String, Instant)>,
+    /// The line the player is typing, `None` if they aren't typing.
+    input: Option<String>,
+    /// The line the player finished typing, until it is taken.
+    submitted: Option<String>,
+}
+
+impl Chat {
+    pub fn new() -> Self {
+        Chat {
+            lines: VecDeque::new(),
+            input: None,
+            submitted: None,
+        }
+    }
+
+    /// Adds a line to the chat log.
+    pub fn add_line(&mut self, line: String) {
+        info!("{}", line);
+        if self.lines.len() == MAX_LINES {
+            self.lines.pop_front();
+        }
+        self.lines.push_back((line, Instant::now()));
+    }
+
+    /// Returns the line the player finished typing since the last call, if
+    /// any.
+    pub fn take_line(&mut self) -> Option<String> {
+        self.submitted.take()
+    }
+
+    /// Returns the line the player is typing, `None` if they aren't typing.
+    pub fn input(&self) -> Option<&str> {
+        self.input.as_ref().map(|s| s.as_str())
+    }
+
+    /// Returns the lines which should be shown, the oldest first. All lines
+    /// are shown while the player is typing, only recent ones otherwise.
+    pub fn visible_lines(&self) -> Vec<&str> {
+        let now = Instant::now();
+        self.lines
+            .iter()
+            .filter(|&&(_, time)| self.input.is_some() || now - time < LINE_DURATION)
+            .map(|&(ref line, _)| line.as_str())
+            .collect()
+    }
+}
+
+impl EventHandler for Chat {
+    fn handle_event(&mut self, e: &Event) -> EventResponse {
+        let event = match e {
+            Event::WindowEvent { event, .. } => event,
+            _ => return EventResponse::NotHandled,
+        };
+        let pressed = match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(key),
+                        ..
+                    },
+                ..
+            } => Some(*key),
+            _ => None,
+        };
+
+        let input = match self.input {
+            Some(ref mut input) => input,
+            None => {
+                return match pressed {
+                    // The `/` itself arrives as character afterwards
+                    Some(VirtualKeyCode::Return) | Some(VirtualKeyCode::Slash) => {
+                        self.input = Some(String::new());
+                        EventResponse::Break
+                    }
+                    _ => EventResponse::NotHandled,
+                };
+            }
+        };
+
+        match (event, pressed) {
+            (&WindowEvent::ReceivedCharacter(c), _) => {
+                if !c.is_control() && input.chars().count() < MAX_CHAT_LEN {
+                    input.push(c);
+                }
+            }
+            (_, Some(VirtualKeyCode::Back)) => {
+                input.pop();
+            }
+            (_, Some(VirtualKeyCode::Return)) => {
+                let line = self.input.take().unwrap_or_default();
+                if !line.trim().is_empty() {
+                    self.submitted = Some(line);
+                }
+            }
+            (_, Some(VirtualKeyCode::Escape)) => self.input = None,
+            (_, Some(_)) => {}
+            _ => return EventResponse::NotHandled,
+        }
+        EventResponse::Break
+    }
+}
@@ -9,6 +9,7 @@ use base::math::*;
 use std::error::Error as StdError;
 use std::fs::File;
 use std::io::{Read, Write};
+use std::net::SocketAddr;
 use std::path::Path;
 use std::string::String;
 
@@ -21,6 +22,11 @@ pub struct Config {
     pub bloom: bool,
     pub vsync: bool,
     pub highlight_pillar: bool,
+    pub player_name: String,
+    /// Server to join, a local server is started if not set.
+    pub server: Option<SocketAddr>,
+    /// Only list the servers in the local network instead of playing.
+    pub discover: bool,
     pub seed: u64, /* view range
                     * anti aliasing
                     * Controls
@@ -79,6 +85,23 @@ impl Config {
                     .takes_value(true)
                     .long("seed"),
             )
+            .arg(
+                Arg::with_name("PlayerName")
+                    .help("'Name shown to other players'")
+                    .takes_value(true)
+                    .long("name"),
+            )
+            .arg(
+                Arg::with_name("Connect")
+                    .help("'Address of the server to join instead of a local one'")
+                    .takes_value(true)
+                    .long("connect"),
+            )
+            .arg(
+                Arg::with_name("Discover")
+                    .help("'Lists the servers in the local network'")
+                    .long("discover"),
+            )
             .arg(
                 Arg::with_name("File")
                     .help("Takes config file")
@@ -108,6 +131,7 @@ vsync = false
 
 [Game_settings]
 seed = 42
+player_name = "Player"
 highlight_pillar = true
             "#;
 
@@ -135,6 +159,9 @@ impl Default for Config {
             bloom: true,
             vsync: false,
             highlight_pillar: true,
+            player_name: "Player".to_string(),
+            server: None,
+            discover: false,
             seed: 42,
         }
     }
@@ -285,6 +312,14 @@ fn config_toml(
             }
             None => return Err("seed in config file is invalid".into()),
         };
+
+        // player name (optional, older config files don't have it)
+        if let Some(name) = value.lookup("Game_settings.player_name") {
+            match name.as_str() {
+                Some(n) if !n.trim().is_empty() => default_config.player_name = n.to_string(),
+                _ => return Err("player_name in config file is invalid".into()),
+            }
+        }
     }
 
     Ok(default_config)
@@ -365,6 +400,25 @@ fn config_command(
         }
     }
 
+    // player name
+    if let Some(name) = matches.value_of("PlayerName") {
+        if name.trim().is_empty() {
+            return Err("Player name from command line is empty".into());
+        }
+        toml_config.player_name = name.to_string();
+    }
+
+    // server to join
+    if let Some(server) = matches.value_of("Connect") {
+        match server.parse() {
+            Ok(addr) => toml_config.server = Some(addr),
+            _ => return Err("Server address from command line is invalid".into()),
+        }
+    }
+
+    // list servers only
+    toml_config.discover = matches.is_present("Discover");
+
     Ok(toml_config)
 }
 
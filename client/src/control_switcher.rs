@@ -1,5 +1,7 @@
 use super::camera::*;
 use super::event_manager::*;
+use base::math::*;
+use base::movement::Controls;
 use ghost::Ghost;
 use glium::glutin::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
 use player::Player;
@@ -9,14 +11,17 @@ pub struct ControlSwitcher {
     player: Player,
     ghost: Ghost,
     is_ghost: bool,
+    /// Whether the server lets us fly, otherwise we stay a `player`.
+    may_fly: bool,
 }
 
 impl ControlSwitcher {
-    pub fn new(player: Player, ghost: Ghost) -> Self {
+    pub fn new(player: Player, ghost: Ghost, may_fly: bool) -> Self {
         ControlSwitcher {
             player: player,
             ghost: ghost,
-            is_ghost: true,
+            is_ghost: may_fly,
+            may_fly: may_fly,
         }
     }
     /// Return current `Camera`
@@ -28,19 +33,40 @@ impl ControlSwitcher {
         }
     }
 
-    /// Run camera `update` function
-    pub fn update(&mut self, delta: f32) {
+    /// Moves both cameras to the given position
+    pub fn set_position(&mut self, pos: Point3f) {
+        let mut cam = self.player.get_camera();
+        cam.position = pos;
+        self.player.set_camera(cam);
+
+        let mut cam = self.ghost.get_camera();
+        cam.position = pos;
+        self.ghost.set_camera(cam);
+    }
+
+    /// Returns the controls of the current camera for this frame
+    pub fn controls(&mut self) -> Controls {
         if self.is_ghost {
-            self.ghost.update(delta);
+            self.ghost.controls()
         } else {
-            self.player.update(delta);
+            self.player.controls()
         }
     }
 
+    /// Changes whether the server lets us fly, landing if it doesn't.
+    pub fn set_may_fly(&mut self, may_fly: bool) {
+        if !may_fly && self.is_ghost {
+            self.switch_cam();
+        }
+        self.may_fly = may_fly;
+    }
+
     /// Switch current camera between `ghost` and `player`
     /// Return to original location of `player`
     pub fn switch_cam(&mut self) {
-        if self.is_ghost {
+        if !self.may_fly {
+            info!("the server doesn't let us fly");
+        } else if self.is_ghost {
             self.player.set_camera(self.ghost.get_camera());
             self.is_ghost = false;
         } else {
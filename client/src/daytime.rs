@@ -1,37 +1,21 @@
 use super::event_manager::*;
 use base::math::*;
+use base::time::{GameTime, SyncedClock, DAY_LENGTH, DEFAULT_TIME_SPEED, YEAR_LENGTH};
 use glium::glutin::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
 use std::f32::consts;
 
-#[derive(Debug)]
+/// The time of day, which follows the clock of the server.
+#[derive(Debug, Default)]
 pub struct DayTime {
-    time_year: u32,
-    time_day: u32,
-    time_on_day: f32,
-    speed: f32,
+    clock: SyncedClock,
+    /// The time speed to ask the server for.
+    speed_request: Option<f32>,
 }
 
-// `DEFAULT_TIME_SPEED` is 1.0 so the time goes at normal speed
 // `PLUS_TIME_SPEED` is the factor with which the time is sped up, when the
-// speed-up key is pressed
-const DEFAULT_TIME_SPEED: f32 = 1.0;
+// speed-up key is pressed. The server has to allow it.
 const PLUS_TIME_SPEED: f32 = 100.0;
 
-impl Default for DayTime {
-    fn default() -> DayTime {
-        DayTime {
-            time_year: 0,
-            time_day: 0,
-            time_on_day: DAY_LENGTH / 3.0,
-            speed: DEFAULT_TIME_SPEED,
-        }
-    }
-}
-
-// 360 == Mittag
-const DAY_LENGTH: f32 = 720.0;
-const YEAR_LENGTH: u32 = 12;
-
 // lengthens the day by a static offset
 const DAY_LENGTHER: f32 = 0.0;
 
@@ -40,10 +24,35 @@ const SUN_DISTANCE: f32 = 300.0;
 
 impl DayTime {
     pub fn set_time(&mut self, time_year: u32, time_day: u32, time_on_day: f32) {
-        self.time_year = time_year;
-        self.time_day = time_day;
-        self.time_on_day = time_on_day;
-        self.speed = DEFAULT_TIME_SPEED;
+        self.clock = SyncedClock::new(GameTime {
+            year: time_year,
+            day: time_day,
+            time_on_day: time_on_day,
+            speed: DEFAULT_TIME_SPEED,
+        });
+    }
+
+    /// Sets time and speed, e.g. to the values the server sent us.
+    pub fn set_game_time(&mut self, time: GameTime) {
+        self.clock = SyncedClock::new(time);
+    }
+
+    /// Smoothly adjusts the time to the time the server sent us.
+    pub fn sync(&mut self, time: GameTime) {
+        self.clock.sync(time);
+    }
+
+    pub fn get_game_time(&self) -> GameTime {
+        self.clock.time()
+    }
+
+    /// Returns the time speed the player asked for since the last call.
+    pub fn take_speed_request(&mut self) -> Option<f32> {
+        self.speed_request.take()
+    }
+
+    fn time(&self) -> GameTime {
+        self.clock.time()
     }
 
     // Bei tag sind die RGBWerte bei 3000, leicht rötlich
@@ -54,10 +63,10 @@ impl DayTime {
         let offset_factor = 1.1;
         let max = 30.0 / offset_factor;
 
-        let factor = if self.time_on_day <= (DAY_LENGTH / 2.0) {
-            max * (self.time_on_day / (DAY_LENGTH / 2.0))
+        let factor = if self.time().time_on_day <= (DAY_LENGTH / 2.0) {
+            max * (self.time().time_on_day / (DAY_LENGTH / 2.0))
         } else {
-            max - (max * ((self.time_on_day - DAY_LENGTH / 2.0) / (DAY_LENGTH / 2.0)))
+            max - (max * ((self.time().time_on_day - DAY_LENGTH / 2.0) / (DAY_LENGTH / 2.0)))
         };
 
         vec.x = offset_factor * factor;
@@ -75,10 +84,10 @@ impl DayTime {
         let offset_factor = 1.1;
         let max = 1.0 / offset_factor;
 
-        let mut factor = if self.time_on_day <= (DAY_LENGTH / 2.0) {
-            max * (self.time_on_day / (DAY_LENGTH / 2.0))
+        let mut factor = if self.time().time_on_day <= (DAY_LENGTH / 2.0) {
+            max * (self.time().time_on_day / (DAY_LENGTH / 2.0))
         } else {
-            max - (max * 0.5 * ((self.time_on_day - DAY_LENGTH / 2.0) / (DAY_LENGTH / 2.0)))
+            max - (max * 0.5 * ((self.time().time_on_day - DAY_LENGTH / 2.0) / (DAY_LENGTH / 2.0)))
         };
 
         if factor < 0.4 {
@@ -93,15 +102,15 @@ impl DayTime {
     }
 
     pub fn get_time_year(&self) -> u32 {
-        self.time_year
+        self.time().year
     }
 
     pub fn get_time_day(&self) -> u32 {
-        self.time_day
+        self.time().day
     }
 
     pub fn get_time_on_day(&self) -> f32 {
-        self.time_on_day
+        self.time().time_on_day
     }
 
     /// Updates time with the use of `delta` as additionally passed time
@@ -111,19 +120,12 @@ impl DayTime {
         // Output of Time
         debug!(
             "Year: {} Day: {} Time: {}",
-            self.time_year, self.time_day, self.time_on_day
+            self.time().year,
+            self.time().day,
+            self.time().time_on_day
         );
 
-        // Checks if one day has passed
-        self.time_on_day += delta * self.speed;
-        if (self.time_on_day) >= DAY_LENGTH {
-            self.time_on_day -= DAY_LENGTH; // Removes one day from time_on_day
-            self.time_day += 1;
-            if (self.time_day) >= YEAR_LENGTH {
-                self.time_day = 0;
-                self.time_year += 1;
-            }
-        }
+        self.clock.advance(delta);
     }
 
     /// returns the position of the sun corresponding to time
@@ -135,20 +137,20 @@ impl DayTime {
         let theta;
         let phi;
 
-        let mut month_diff = self.time_day as f32 - half_year;
+        let mut month_diff = self.time().day as f32 - half_year;
         if month_diff < 0.0 {
             month_diff *= -1.0
         }
 
-        if self.time_on_day < half_day {
+        if self.time().time_on_day < half_day {
             // pre noon
             // sun rising
-            theta = consts::PI - consts::PI * (self.time_on_day / half_day);
+            theta = consts::PI - consts::PI * (self.time().time_on_day / half_day);
             phi = 0.0;
         } else {
             // after noon
             // sun going down
-            theta = consts::PI * ((self.time_on_day - half_day) / half_day);
+            theta = consts::PI * ((self.time().time_on_day - half_day) / half_day);
             phi = consts::PI;
         }
 
@@ -192,7 +194,7 @@ impl EventHandler for DayTime {
                 virtual_keycode: Some(VirtualKeyCode::Add),
                 ..
             } => {
-                self.speed = PLUS_TIME_SPEED;
+                self.speed_request = Some(PLUS_TIME_SPEED);
                 EventResponse::Continue
             }
             KeyboardInput {
@@ -200,7 +202,7 @@ impl EventHandler for DayTime {
                 virtual_keycode: Some(VirtualKeyCode::Add),
                 ..
             } => {
-                self.speed = DEFAULT_TIME_SPEED;
+                self.speed_request = Some(DEFAULT_TIME_SPEED);
                 EventResponse::Continue
             }
             _ => EventResponse::NotHandled,
@@ -0,0 +1,199 @@
+use super::{y_up, Mesh};
+use base::net::Encode;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// `componentType` of `f32` and `u32` accessors.
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+
+/// `target` of buffer views with vertex attributes and with indices.
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+/// Writes the mesh as glTF 2.0 file. The binary data is embedded as base64
+/// data URI, so that the file is everything which has to be copied.
+pub fn write_gltf(mesh: &Mesh, path: &Path) -> io::Result<()> {
+    // The buffer holds all positions, then all normals, then the indices of
+    // each part
+    let mut buffer = Vec::new();
+    let mut min = [::std::f32::INFINITY; 3];
+    let mut max = [::std::f32::NEG_INFINITY; 3];
+    for pos in &mesh.positions {
+        let pos = y_up([pos.x, pos.y, pos.z]);
+        for i in 0..3 {
+            min[i] = min[i].min(pos[i]);
+            max[i] = max[i].max(pos[i]);
+            pos[i].encode(&mut buffer)?;
+        }
+    }
+    for normal in &mesh.normals {
+        for &c in &y_up([normal.x, normal.y, normal.z]) {
+            c.encode(&mut buffer)?;
+        }
+    }
+
+    let vertex_count = mesh.positions.len();
+    let attribute_len = vertex_count * 3 * 4;
+    let mut views = vec![
+        buffer_view(0, attribute_len, ARRAY_BUFFER),
+        buffer_view(attribute_len, attribute_len, ARRAY_BUFFER),
+    ];
+    let mut accessors = vec![
+        format!(
+            "{{\"bufferView\":0,\"componentType\":{},\"count\":{},\"type\":\"VEC3\",\
+             \"min\":[{},{},{}],\"max\":[{},{},{}]}}",
+            FLOAT, vertex_count, min[0], min[1], min[2], max[0], max[1], max[2]
+        ),
+        format!(
+            "{{\"bufferView\":1,\"componentType\":{},\"count\":{},\"type\":\"VEC3\"}}",
+            FLOAT, vertex_count
+        ),
+    ];
+    let mut primitives = Vec::new();
+    let mut materials = Vec::new();
+    for (i, part) in mesh.parts.iter().enumerate() {
+        let offset = buffer.len();
+        for idx in &part.indices {
+            idx.encode(&mut buffer)?;
+        }
+        views.push(buffer_view(
+            offset,
+            buffer.len() - offset,
+            ELEMENT_ARRAY_BUFFER,
+        ));
+        accessors.push(format!(
+            "{{\"bufferView\":{},\"componentType\":{},\"count\":{},\"type\":\"SCALAR\"}}",
+            views.len() - 1,
+            UNSIGNED_INT,
+            part.indices.len()
+        ));
+        primitives.push(format!(
+            "{{\"attributes\":{{\"POSITION\":0,\"NORMAL\":1}},\"indices\":{},\"material\":{}}}",
+            accessors.len() - 1,
+            i
+        ));
+
+        let [r, g, b] = part.material.color;
+        materials.push(format!(
+            "{{\"name\":{:?},\"pbrMetallicRoughness\":{{\"baseColorFactor\":[{},{},{},1],\
+             \"metallicFactor\":0,\"roughnessFactor\":1}}}}",
+            part.material.name, r, g, b
+        ));
+    }
+
+    let mut file = BufWriter::new(File::create(path)?);
+    write!(
+        file,
+        "{{\"asset\":{{\"version\":\"2.0\",\"generator\":\"Plantex\"}},\"scene\":0,\
+         \"scenes\":[{{\"nodes\":[0]}}],\"nodes\":[{{\"mesh\":0}}],\
+         \"meshes\":[{{\"primitives\":[{}]}}],\"materials\":[{}],\"accessors\":[{}],\
+         \"bufferViews\":[{}],\"buffers\":[{{\"byteLength\":{},\
+         \"uri\":\"data:application/octet-stream;base64,{}\"}}]}}",
+        primitives.join(","),
+        materials.join(","),
+        accessors.join(","),
+        views.join(","),
+        buffer.len(),
+        base64(&buffer)
+    )?;
+    writeln!(file)?;
+    file.flush()
+}
+
+fn buffer_view(offset: usize, len: usize, target: u32) -> String {
+    format!(
+        "{{\"buffer\":0,\"byteOffset\":{},\"byteLength\":{},\"target\":{}}}",
+        offset, len, target
+    )
+}
+
+/// Encodes the data as base64 with padding.
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut out = String::with_capacity((data.len() + 2) / 3 * 4);
+    for group in data.chunks(3) {
+        let bits = group
+            .iter()
+            .enumerate()
+            .fold(0u32, |bits, (i, &b)| bits | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= group.len() {
+                out.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[test]
+fn base64_matches_known_vectors() {
+    assert_eq!(base64(b""), "");
+    assert_eq!(base64(b"f"), "Zg==");
+    assert_eq!(base64(b"fo"), "Zm8=");
+    assert_eq!(base64(b"foo"), "Zm9v");
+    assert_eq!(base64(b"foob"), "Zm9vYg==");
+    assert_eq!(base64(b"fooba"), "Zm9vYmE=");
+    assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    assert_eq!(base64(&[0xfb, 0xff, 0xbf]), "+/+/");
+    assert_eq!(base64(&[0, 0]), "AAA=");
+}
+
+#[test]
+fn meshes_are_written_as_gltf() {
+    let mesh = super::test_mesh();
+    let path = super::test_path("mesh.gltf");
+    write_gltf(&mesh, &path).unwrap();
+    let gltf = ::std::fs::read_to_string(&path).unwrap();
+
+    // Positions and normals are y-up, followed by the indices of each part
+    let mut expected = Vec::new();
+    for pos in &[
+        [1.0, 2.0, 1.0],
+        [3.0, 2.0, 1.0],
+        [1.0, 2.0, -2.0],
+        [3.0, 2.0, -2.0],
+    ] {
+        for c in pos {
+            (*c as f32).encode(&mut expected).unwrap();
+        }
+    }
+    for _ in 0..4 {
+        for &c in &y_up([0.0, 0.0, 1.0]) {
+            c.encode(&mut expected).unwrap();
+        }
+    }
+    for idx in &[0u32, 1, 2, 1, 3, 2] {
+        idx.encode(&mut expected).unwrap();
+    }
+    assert_eq!(expected.len(), 4 * 12 * 2 + 6 * 4);
+    assert!(gltf.contains(&format!(
+        "\"buffers\":[{{\"byteLength\":{},\"uri\":\"data:application/octet-stream;base64,{}\"}}]",
+        expected.len(),
+        base64(&expected)
+    )));
+
+    assert!(gltf.contains(&format!(
+        "{{\"bufferView\":0,\"componentType\":{},\"count\":4,\"type\":\"VEC3\",\
+         \"min\":[1,2,-2],\"max\":[3,2,1]}}",
+        FLOAT
+    )));
+    assert!(gltf.contains(&format!(
+        "{{\"bufferView\":1,\"componentType\":{},\"count\":4,\"type\":\"VEC3\"}}",
+        FLOAT
+    )));
+    for &(view, offset) in &[(2, 96), (3, 108)] {
+        assert!(gltf.contains(&format!(
+            "{{\"bufferView\":{},\"componentType\":{},\"count\":3,\"type\":\"SCALAR\"}}",
+            view, UNSIGNED_INT
+        )));
+        assert!(gltf.contains(&buffer_view(offset, 12, ELEMENT_ARRAY_BUFFER)));
+    }
+    assert!(gltf.contains(&buffer_view(48, 48, ARRAY_BUFFER)));
+    assert!(gltf.contains("\"material\":1}"));
+    assert!(gltf.contains("\"name\":\"Green\""));
+}
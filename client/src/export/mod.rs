@@ -0,0 +1,153 @@
+//! Exporting parts of the game as 3D models, to use them in other programs.
+//!
+//! The geometry is built by the same functions which build the vertex
+//! buffers for rendering, but no GL context is needed. Models are written as
+//! OBJ (with a MTL file next to it) or as self-contained glTF file. Both
+//! formats use a y-up coordinate system, while Plantex uses z-up, so
+//! positions are rotated when they are written.
+
+mod gltf;
+mod obj;
+mod plant;
+mod terrain;
+mod tool;
+
+pub use self::plant::plant_mesh;
+pub use self::terrain::terrain_mesh;
+pub use self::tool::run_export_tool;
+
+use base::math::*;
+use std::io;
+use std::path::Path;
+
+/// Color of a part of a mesh.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    /// Name of the material, without whitespace.
+    pub name: String,
+    /// Diffuse color in RGB.
+    pub color: [f32; 3],
+}
+
+/// The triangles of a mesh having the same material.
+#[derive(Clone, Debug)]
+pub struct Part {
+    pub material: Material,
+    /// Three vertex indices per triangle, counter-clockwise when looking at
+    /// the front.
+    pub indices: Vec<u32>,
+}
+
+/// Triangles in the coordinate system of the game, grouped by material.
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub positions: Vec<Point3f>,
+    pub normals: Vec<Vector3f>,
+    pub parts: Vec<Part>,
+}
+
+impl Mesh {
+    pub fn new() -> Self {
+        Mesh::default()
+    }
+
+    /// Adds a vertex and returns its index.
+    pub fn add_vertex(&mut self, position: Point3f, normal: Vector3f) -> u32 {
+        self.positions.push(position);
+        self.normals.push(normal);
+        (self.positions.len() - 1) as u32
+    }
+
+    /// Returns the indices of the part with the given material, adding the
+    /// part if there is none yet.
+    pub fn part_mut(&mut self, material: &Material) -> &mut Vec<u32> {
+        let idx = match self
+            .parts
+            .iter()
+            .position(|part| part.material == *material)
+        {
+            Some(idx) => idx,
+            None => {
+                self.parts.push(Part {
+                    material: material.clone(),
+                    indices: Vec::new(),
+                });
+                self.parts.len() - 1
+            }
+        };
+        &mut self.parts[idx].indices
+    }
+
+    /// Returns the number of triangles.
+    pub fn triangle_count(&self) -> usize {
+        self.parts.iter().map(|part| part.indices.len() / 3).sum()
+    }
+
+    /// Writes the mesh to the given file, in the format given by the file
+    /// extension: `obj` or `gltf`.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        if self.triangle_count() == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "there is nothing to export",
+            ));
+        }
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("obj") => obj::write_obj(self, path),
+            Some("gltf") => gltf::write_gltf(self, path),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "can't tell the format of {}, the file has to end with .obj or .gltf",
+                    path.display()
+                ),
+            )),
+        }
+    }
+}
+
+/// Converts a vector of the game to the y-up coordinate system of the
+/// exported files.
+fn y_up(v: [f32; 3]) -> [f32; 3] {
+    [v[0], v[2], -v[1]]
+}
+
+/// Returns a path in an empty temporary directory for a test.
+#[cfg(test)]
+fn test_path(name: &str) -> ::std::path::PathBuf {
+    let dir = ::std::env::temp_dir().join(format!("plantex-export-{}", ::std::process::id()));
+    ::std::fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+/// Returns a square of two triangles with different materials, facing up.
+#[cfg(test)]
+fn test_mesh() -> Mesh {
+    let mut mesh = Mesh::new();
+    let up = Vector3f::new(0.0, 0.0, 1.0);
+    for &(x, y) in &[(1.0, -1.0), (3.0, -1.0), (1.0, 2.0), (3.0, 2.0)] {
+        mesh.add_vertex(Point3f::new(x, y, 2.0), up);
+    }
+    let red = Material {
+        name: "Red".into(),
+        color: [1.0, 0.0, 0.0],
+    };
+    let green = Material {
+        name: "Green".into(),
+        color: [0.0, 1.0, 0.0],
+    };
+    mesh.part_mut(&red).extend_from_slice(&[0, 1, 2]);
+    mesh.part_mut(&green).extend_from_slice(&[1, 3, 2]);
+    mesh
+}
+
+#[test]
+fn meshes_are_only_written_in_known_formats() {
+    let mesh = test_mesh();
+    assert_eq!(mesh.triangle_count(), 2);
+    assert!(mesh.write(test_path("mesh.stl")).is_err());
+    assert!(Mesh::new().write(test_path("empty.obj")).is_err());
+    assert_eq!(y_up([1.0, 2.0, 3.0]), [1.0, 3.0, -2.0]);
+}
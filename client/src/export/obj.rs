@@ -0,0 +1,101 @@
+use super::{y_up, Mesh};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Writes the mesh as Wavefront OBJ file, and its materials into a MTL file
+/// with the same name next to it.
+pub fn write_obj(mesh: &Mesh, path: &Path) -> io::Result<()> {
+    let mtl_path = path.with_extension("mtl");
+    let mtl_name = mtl_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("materials.mtl");
+
+    let mut mtl = BufWriter::new(File::create(&mtl_path)?);
+    writeln!(mtl, "# Materials of {}", path.display())?;
+    for part in &mesh.parts {
+        let [r, g, b] = part.material.color;
+        writeln!(mtl)?;
+        writeln!(mtl, "newmtl {}", part.material.name)?;
+        writeln!(mtl, "Ka 0 0 0")?;
+        writeln!(mtl, "Kd {} {} {}", r, g, b)?;
+        writeln!(mtl, "Ks 0 0 0")?;
+        writeln!(mtl, "d 1")?;
+        writeln!(mtl, "illum 1")?;
+    }
+    mtl.flush()?;
+
+    let mut obj = BufWriter::new(File::create(path)?);
+    writeln!(obj, "# Exported from Plantex")?;
+    writeln!(obj, "mtllib {}", mtl_name)?;
+    for pos in &mesh.positions {
+        let [x, y, z] = y_up([pos.x, pos.y, pos.z]);
+        writeln!(obj, "v {} {} {}", x, y, z)?;
+    }
+    for normal in &mesh.normals {
+        let [x, y, z] = y_up([normal.x, normal.y, normal.z]);
+        writeln!(obj, "vn {} {} {}", x, y, z)?;
+    }
+    for part in &mesh.parts {
+        writeln!(obj, "usemtl {}", part.material.name)?;
+        // OBJ counts vertices from 1
+        for tri in part.indices.chunks(3) {
+            let (a, b, c) = (tri[0] + 1, tri[1] + 1, tri[2] + 1);
+            writeln!(obj, "f {}//{} {}//{} {}//{}", a, a, b, b, c, c)?;
+        }
+    }
+    obj.flush()
+}
+
+#[test]
+fn meshes_are_written_as_obj() {
+    use std::fs;
+
+    let path = super::test_path("mesh.obj");
+    write_obj(&super::test_mesh(), &path).unwrap();
+    let obj = fs::read_to_string(&path).unwrap();
+    let mtl = fs::read_to_string(path.with_extension("mtl")).unwrap();
+
+    let lines = |prefix: &str| -> Vec<Vec<f32>> {
+        obj.lines()
+            .filter(|line| line.starts_with(prefix))
+            .map(|line| {
+                line[prefix.len()..]
+                    .split(' ')
+                    .map(|n| n.parse().unwrap())
+                    .collect()
+            })
+            .collect()
+    };
+    // Plantex is z-up, OBJ is y-up
+    assert_eq!(
+        lines("v "),
+        vec![
+            vec![1.0, 2.0, 1.0],
+            vec![3.0, 2.0, 1.0],
+            vec![1.0, 2.0, -2.0],
+            vec![3.0, 2.0, -2.0],
+        ]
+    );
+    assert_eq!(lines("vn "), vec![vec![0.0, 1.0, 0.0]; 4]);
+
+    // Faces count from 1 and keep their winding
+    let faces: Vec<_> = obj
+        .lines()
+        .filter(|line| line.starts_with("f ") || line.starts_with("usemtl "))
+        .collect();
+    assert_eq!(
+        faces,
+        vec![
+            "usemtl Red",
+            "f 1//1 2//2 3//3",
+            "usemtl Green",
+            "f 2//2 4//4 3//3",
+        ]
+    );
+    assert!(obj.contains("\nmtllib mesh.mtl\n"));
+
+    assert!(mtl.contains("\nnewmtl Red\nKa 0 0 0\nKd 1 0 0\n"));
+    assert!(mtl.contains("\nnewmtl Green\nKa 0 0 0\nKd 0 1 0\n"));
+}
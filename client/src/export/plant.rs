@@ -0,0 +1,88 @@
+use super::{Material, Mesh};
+use base::math::*;
+use base::prop::plant::{Plant, Tree};
+use util::ToArr;
+use view::gen_branch_buffer;
+
+/// Returns the plant as mesh standing on the origin, with one material for
+/// its trunk and one for its leaves.
+pub fn plant_mesh(plant: &Plant) -> Mesh {
+    let mut mesh = Mesh::new();
+    match *plant {
+        Plant::Tree(Tree {
+            ref branches,
+            trunk_color,
+            leaf_color,
+        }) => {
+            let trunk = Material {
+                name: "Trunk".into(),
+                color: trunk_color.to_arr(),
+            };
+            let leaves = Material {
+                name: "Leaves".into(),
+                color: leaf_color.to_arr(),
+            };
+
+            for branch in branches {
+                let (material, color) = if branch.is_trunk {
+                    (&trunk, trunk_color)
+                } else {
+                    (&leaves, leaf_color)
+                };
+                let mut vertices = Vec::new();
+                let mut indices = Vec::new();
+                gen_branch_buffer(&branch.points, &mut vertices, &mut indices, color);
+
+                let first = mesh.positions.len() as u32;
+                for v in &vertices {
+                    mesh.add_vertex(
+                        Point3f::new(v.position[0], v.position[1], v.position[2]),
+                        Vector3f::new(v.normal[0], v.normal[1], v.normal[2]),
+                    );
+                }
+                // The game draws clockwise triangles, so their order is
+                // reversed
+                for tri in indices.chunks(3) {
+                    mesh.part_mut(material).extend_from_slice(&[
+                        first + tri[0],
+                        first + tri[2],
+                        first + tri[1],
+                    ]);
+                }
+            }
+        }
+    }
+    mesh
+}
+
+#[test]
+fn plants_of_every_type_are_exported() {
+    use base::gen::plant::tree::PLANT_TYPES;
+    use base::gen::world::plant_index;
+    use base::gen::WorldGenerator;
+    use base::world::ChunkProvider;
+
+    let plants = WorldGenerator::with_seed(42).get_plant_list();
+    let again = WorldGenerator::with_seed(42).get_plant_list();
+    for &plant_type in PLANT_TYPES {
+        let mesh = plant_mesh(&plants[plant_index(plant_type, 0)]);
+        assert!(mesh.triangle_count() > 0, "{:?}", plant_type);
+        assert_eq!(mesh.normals.len(), mesh.positions.len());
+        for part in &mesh.parts {
+            assert!(part
+                .indices
+                .iter()
+                .all(|&i| (i as usize) < mesh.positions.len()));
+        }
+
+        // The same seed gives the same plants
+        let other = plant_mesh(&again[plant_index(plant_type, 0)]);
+        assert_eq!(mesh.positions, other.positions);
+        assert_eq!(mesh.normals, other.normals);
+        assert_eq!(mesh.parts.len(), other.parts.len());
+        for (part, other) in mesh.parts.iter().zip(&other.parts) {
+            assert_eq!(part.material, other.material);
+            assert_eq!(part.indices, other.indices);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use super::{Material, Mesh};
+use base::math::*;
+use base::world::{ChunkIndex, ChunkProvider, GroundMaterial, CHUNK_SIZE, GROUND_MATERIALS};
+use world::get_vertices;
+
+/// Returns the terrain of all chunks from `min` to `max` (both included) as
+/// mesh with one material per ground material. Chunks the provider can't
+/// load are left out.
+pub fn terrain_mesh<P: ChunkProvider>(provider: &P, min: ChunkIndex, max: ChunkIndex) -> Mesh {
+    let mut mesh = Mesh::new();
+    for q in min.0.q..max.0.q + 1 {
+        for r in min.0.r..max.0.r + 1 {
+            let index = ChunkIndex(AxialPoint::new(q, r));
+            let chunk = match provider.load_chunk(index) {
+                Some(chunk) => chunk,
+                None => {
+                    warn!("chunk {:?} can't be loaded, leaving it out", index);
+                    continue;
+                }
+            };
+
+            // Chunk vertices are relative to the first pillar of the chunk
+            let offset = (index.0 * CHUNK_SIZE as i32).to_real();
+            let (vertices, indices) = get_vertices(&chunk);
+            let first = mesh.positions.len() as u32;
+            for v in &vertices {
+                mesh.add_vertex(
+                    Point3f::new(
+                        v.position[0] + offset.x,
+                        v.position[1] + offset.y,
+                        v.position[2],
+                    ),
+                    Vector3f::new(v.normal[0], v.normal[1], v.normal[2]),
+                );
+            }
+            // All vertices of a face have the same material. The game draws
+            // clockwise triangles, so their order is reversed.
+            for tri in indices.chunks(3) {
+                // Sides between sections of the same height have no area
+                let pos = |i: u32| vertices[i as usize].position;
+                if pos(tri[0]) == pos(tri[1])
+                    || pos(tri[1]) == pos(tri[2])
+                    || pos(tri[2]) == pos(tri[0])
+                {
+                    continue;
+                }
+                let material = ground_material(vertices[tri[0] as usize].material_color);
+                mesh.part_mut(&material).extend_from_slice(&[
+                    first + tri[0],
+                    first + tri[2],
+                    first + tri[1],
+                ]);
+            }
+        }
+    }
+    mesh
+}
+
+/// Returns the material of the ground with the given color.
+fn ground_material(color: [f32; 3]) -> Material {
+    let name = GROUND_MATERIALS
+        .iter()
+        .find(|ground| ground.get_color() == color)
+        .map_or("Ground".to_string(), |ground: &GroundMaterial| {
+            format!("{:?}", ground)
+        });
+    Material {
+        name: name,
+        color: color,
+    }
+}
+
+#[test]
+fn terrain_of_a_chunk_is_exported() {
+    use base::gen::WorldGenerator;
+    use base::world::NullProvider;
+
+    let index = ChunkIndex(AxialPoint::new(1, -1));
+    assert_eq!(
+        terrain_mesh(&NullProvider, index, index).triangle_count(),
+        0
+    );
+
+    let generator = WorldGenerator::with_seed(42);
+    let mesh = terrain_mesh(&generator, index, index);
+    let (vertices, _) = get_vertices(&generator.load_chunk(index).unwrap());
+    assert!(mesh.triangle_count() > 0);
+    assert_eq!(mesh.positions.len(), vertices.len());
+    assert_eq!(mesh.normals.len(), vertices.len());
+
+    // Vertices are moved to the position of the chunk
+    let offset = (index.0 * CHUNK_SIZE as i32).to_real();
+    assert_eq!(
+        mesh.positions[0],
+        Point3f::new(
+            vertices[0].position[0] + offset.x,
+            vertices[0].position[1] + offset.y,
+            vertices[0].position[2],
+        )
+    );
+
+    // All triangles are counter-clockwise when looking at their front
+    for part in &mesh.parts {
+        assert!(GROUND_MATERIALS
+            .iter()
+            .any(|ground| format!("{:?}", ground) == part.material.name));
+        for tri in part.indices.chunks(3) {
+            let pos = |i: u32| mesh.positions[i as usize];
+            let face = (pos(tri[1]) - pos(tri[0])).cross(pos(tri[2]) - pos(tri[0]));
+            assert!(face.dot(mesh.normals[tri[0] as usize]) > 0.0);
+        }
+    }
+}
@@ -0,0 +1,207 @@
+extern crate clap;
+
+use self::clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use super::{plant_mesh, terrain_mesh};
+use base::gen::plant::tree::PLANT_TYPES;
+use base::gen::world::{plant_index, PLANT_INSTANCES};
+use base::gen::WorldGenerator;
+use base::math::*;
+use base::world::{ChunkIndex, ChunkProvider};
+use std::error::Error as StdError;
+
+/// Runs the `plantex-export` tool with the arguments of this process and
+/// returns what it has to say.
+pub fn run_export_tool() -> Result<String, Box<dyn StdError>> {
+    run(&app().get_matches())
+}
+
+fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new("Plantex Export")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Exports parts of a Plantex world as OBJ or glTF file")
+        .setting(AppSettings::SubcommandRequired)
+        .subcommand(
+            SubCommand::with_name("terrain")
+                .about("Exports the terrain of a range of chunks")
+                .arg(
+                    Arg::with_name("Seed")
+                        .help("'Seed the world is generated from'")
+                        .takes_value(true)
+                        .required(true)
+                        .long("seed"),
+                )
+                .arg(
+                    Arg::with_name("From")
+                        .help("'First chunk to export, as q,r'")
+                        .takes_value(true)
+                        .allow_hyphen_values(true)
+                        .default_value("0,0")
+                        .long("from"),
+                )
+                .arg(
+                    Arg::with_name("To")
+                        .help("'Last chunk to export, as q,r'")
+                        .takes_value(true)
+                        .allow_hyphen_values(true)
+                        .default_value("0,0")
+                        .long("to"),
+                )
+                .arg(
+                    Arg::with_name("Output")
+                        .help("'File to write, ending with .obj or .gltf'")
+                        .takes_value(true)
+                        .required(true)
+                        .long("output"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("plant")
+                .about("Exports a plant of a world")
+                .arg(
+                    Arg::with_name("Seed")
+                        .help("'Seed the world is generated from'")
+                        .takes_value(true)
+                        .required(true)
+                        .long("seed"),
+                )
+                .arg(
+                    Arg::with_name("Type")
+                        .help("'Type of the plant, like OakTree'")
+                        .takes_value(true)
+                        .required(true)
+                        .long("type"),
+                )
+                .arg(
+                    Arg::with_name("Instance")
+                        .help("'Which of the plants of this type to export'")
+                        .takes_value(true)
+                        .default_value("0")
+                        .long("instance"),
+                )
+                .arg(
+                    Arg::with_name("Output")
+                        .help("'File to write, ending with .obj or .gltf'")
+                        .takes_value(true)
+                        .required(true)
+                        .long("output"),
+                ),
+        )
+}
+
+fn run(matches: &ArgMatches) -> Result<String, Box<dyn StdError>> {
+    match matches.subcommand() {
+        ("terrain", Some(sub)) => {
+            let seed = parse_seed(sub)?;
+            let from = parse_chunk(sub.value_of("From").unwrap_or("0,0"))?;
+            let to = parse_chunk(sub.value_of("To").unwrap_or("0,0"))?;
+            let min = ChunkIndex(AxialPoint::new(from.0.q.min(to.0.q), from.0.r.min(to.0.r)));
+            let max = ChunkIndex(AxialPoint::new(from.0.q.max(to.0.q), from.0.r.max(to.0.r)));
+            let output = sub.value_of("Output").unwrap_or("");
+
+            let mesh = terrain_mesh(&WorldGenerator::with_seed(seed), min, max);
+            mesh.write(output)?;
+            Ok(format!(
+                "wrote {} triangles of {} chunks to {}",
+                mesh.triangle_count(),
+                (max.0.q - min.0.q + 1) * (max.0.r - min.0.r + 1),
+                output
+            ))
+        }
+        ("plant", Some(sub)) => {
+            let seed = parse_seed(sub)?;
+            let name = sub.value_of("Type").unwrap_or("");
+            let plant_type = match PLANT_TYPES
+                .iter()
+                .find(|t| format!("{:?}", t).eq_ignore_ascii_case(name))
+            {
+                Some(&plant_type) => plant_type,
+                None => {
+                    let names: Vec<_> = PLANT_TYPES.iter().map(|t| format!("{:?}", t)).collect();
+                    return Err(format!(
+                        "there is no plant type '{}', only {}",
+                        name,
+                        names.join(", ")
+                    )
+                    .into());
+                }
+            };
+            let instance = match sub.value_of("Instance").unwrap_or("0").parse::<usize>() {
+                Ok(instance) if instance < PLANT_INSTANCES => instance,
+                _ => {
+                    return Err(format!(
+                        "the instance has to be a number below {}",
+                        PLANT_INSTANCES
+                    )
+                    .into())
+                }
+            };
+            let output = sub.value_of("Output").unwrap_or("");
+
+            let plants = WorldGenerator::with_seed(seed).get_plant_list();
+            let mesh = plant_mesh(&plants[plant_index(plant_type, instance)]);
+            mesh.write(output)?;
+            Ok(format!(
+                "wrote {} triangles of {:?} {} to {}",
+                mesh.triangle_count(),
+                plant_type,
+                instance,
+                output
+            ))
+        }
+        _ => Err("unknown command, see --help".into()),
+    }
+}
+
+fn parse_seed(matches: &ArgMatches) -> Result<u64, Box<dyn StdError>> {
+    match matches.value_of("Seed").unwrap_or("").parse::<u64>() {
+        Ok(seed) => Ok(seed),
+        Err(_) => Err("the seed has to be a number".into()),
+    }
+}
+
+/// Parses a chunk index written as `q,r`.
+fn parse_chunk(s: &str) -> Result<ChunkIndex, Box<dyn StdError>> {
+    let coords: Vec<_> = s.split(',').map(|c| c.trim().parse::<i32>()).collect();
+    match coords.as_slice() {
+        [Ok(q), Ok(r)] => Ok(ChunkIndex(AxialPoint::new(*q, *r))),
+        _ => Err(format!("'{}' is no chunk, chunks are given as q,r", s).into()),
+    }
+}
+
+#[test]
+fn plants_are_exported_from_the_command_line() {
+    let output = super::test_path("plant.obj");
+    let run_args = |args: &[&str]| {
+        let mut all = vec![
+            "plantex-export",
+            "plant",
+            "--output",
+            output.to_str().unwrap(),
+        ];
+        all.extend_from_slice(args);
+        run(&app().get_matches_from(all)).map_err(|e| e.to_string())
+    };
+
+    for plant_type in PLANT_TYPES {
+        let name = format!("{:?}", plant_type);
+        let reply = run_args(&["--seed", "42", "--type", &name]).unwrap();
+        assert!(reply.starts_with("wrote "), "{}", reply);
+        assert!(output.exists());
+    }
+
+    let reply = run_args(&["--seed", "42", "--type", "Fern"]).unwrap_err();
+    assert!(
+        reply.starts_with("there is no plant type 'Fern'"),
+        "{}",
+        reply
+    );
+    let name = format!("{:?}", PLANT_TYPES[0]);
+    let too_big = format!("--instance={}", PLANT_INSTANCES);
+    for instance in &[too_big.as_str(), "--instance=-1", "--instance=first"] {
+        let reply = run_args(&["--seed", "42", "--type", &name, instance]);
+        assert!(reply
+            .unwrap_err()
+            .starts_with("the instance has to be a number"));
+    }
+    assert!(run_args(&["--seed", "forty", "--type", &name]).is_err());
+}
@@ -1,15 +1,20 @@
 use super::weather::Weather;
-use super::DayTime;
 use super::Renderer;
+use super::{Chat, DayTime};
 use super::{Config, GameContext, WorldManager};
 use base::gen::WorldGenerator;
 use base::math::*;
+use base::movement::{Body, MoveInput, Prediction};
+use base::net::{
+    ChunkUpdate, Command, Connection, Message, NetworkProvider, RemotePlayers, Welcome,
+};
+use base::net::{MAX_MOVE_INPUTS, PROTOCOL_VERSION};
 use base::world;
 use base::world::ChunkProvider;
 use base::world::HexPillar;
-use base::world::PillarIndex;
 use base::world::PillarSection;
 use base::world::World;
+use base::world::{EditKind, HeightType, PillarEdit, PillarIndex};
 use camera::Camera;
 use config::WindowMode;
 use control_switcher::ControlSwitcher;
@@ -18,48 +23,93 @@ use ghost::Ghost;
 use glium::{self, glutin, Display};
 use player::Player;
 use std::error::Error;
-use std::net::{SocketAddr, TcpStream};
+use std::io;
+use std::mem;
+use std::net::SocketAddr;
 use std::rc::Rc;
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
 use std::time::{Duration, Instant};
-use view::{SkyView, Sun};
+use terrain_editor::{EditAction, TerrainEditor};
+use view::{AvatarView, ChatView, SkyView, Sun};
+
+/// The inputs of the player are collected and sent to the server this often.
+const INPUT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The connection is considered lost if the server doesn't send anything for
+/// this long.
+const SERVER_TIMEOUT: Duration = Duration::from_secs(30);
 
 pub struct Game {
     renderer: Renderer,
     event_manager: EventManager,
     world_manager: WorldManager,
-    #[allow(dead_code)]
-    server: TcpStream,
+    server: Connection,
+    server_messages: Receiver<io::Result<Message>>,
+    /// Forwards chunks sent by the server, and edits of them, to the
+    /// `NetworkProvider`.
+    chunk_sender: Sender<ChunkUpdate>,
+    /// Predicts our movement until the server confirms it.
+    prediction: Prediction,
+    /// Inputs which weren't sent to the server yet.
+    unsent_inputs: Vec<MoveInput>,
+    next_input_send: Instant,
+    last_server_message: Instant,
+    remote_players: RemotePlayers,
+    avatars: AvatarView,
+    chat: Chat,
+    chat_view: ChatView,
     sun: Sun,
     sky_view: SkyView,
     daytime: DayTime,
     weather: Weather,
     control_switcher: ControlSwitcher,
+    terrain_editor: TerrainEditor,
 }
 
 impl Game {
     pub fn new(config: Config, server: SocketAddr) -> Result<Self, Box<dyn Error>> {
         info!("connecting to {}", server);
-        let server = TcpStream::connect(server)?;
+        let (server, welcome) = connect_to_server(server, &config.player_name)?;
+        info!("joined '{}'", welcome.server_name);
+        let server_messages = server.spawn_reader("Server reader".into())?;
+        let (chunk_sender, chunk_recv) = channel();
+        let provider = create_chunk_provider(&server, chunk_recv, welcome.seed)?;
         let events_loop = glutin::EventsLoop::new();
         let facade = create_context(&events_loop, &config)?;
         let context = Rc::new(GameContext::new(facade, config.clone()));
-        let world_manager =
-            WorldManager::new(create_chunk_provider(context.get_config()), context.clone());
+        let world_manager = WorldManager::new(provider, welcome.view_radius, context.clone());
         let world_weather = Weather::new(context.clone());
 
+        let mut daytime = DayTime::default();
+        daytime.set_game_time(welcome.time);
+        let mut control_switcher = ControlSwitcher::new(
+            Player::new(context.clone()),
+            Ghost::new(context.clone()),
+            welcome.may_fly,
+        );
+        control_switcher.set_position(welcome.spawn);
+
         Ok(Game {
             renderer: Renderer::new(context.clone()),
             event_manager: EventManager::new(events_loop),
             world_manager: world_manager.clone(),
             server: server,
+            server_messages: server_messages,
+            chunk_sender: chunk_sender,
+            prediction: Prediction::new(Body::new(welcome.spawn)),
+            unsent_inputs: Vec::new(),
+            next_input_send: Instant::now(),
+            last_server_message: Instant::now(),
+            remote_players: RemotePlayers::new(),
+            avatars: AvatarView::new(context.clone()),
+            chat: Chat::new(),
+            chat_view: ChatView::new(context.clone()),
             sun: Sun::new(context.clone()),
             sky_view: SkyView::new(context.clone()),
-            daytime: DayTime::default(),
+            daytime: daytime,
             weather: world_weather,
-            control_switcher: ControlSwitcher::new(
-                Player::new(context.clone(), world_manager),
-                Ghost::new(context.clone()),
-            ),
+            control_switcher: control_switcher,
+            terrain_editor: TerrainEditor::new(),
         })
     }
 
@@ -77,6 +127,10 @@ impl Game {
         info!("| |   | | (_| | | | | ||  __/>  < ");
         info!("\\_|   |_|\\__,_|_| |_|\\__\\___/_/\\_\\");
         loop {
+            if !self.handle_server_messages()? {
+                break;
+            }
+
             self.world_manager
                 .update_world(self.control_switcher.get_camera().position);
 
@@ -99,6 +153,9 @@ impl Game {
             self.daytime.update(delta);
             self.sky_view.update(self.daytime.get_sun_position());
             self.sun.update(self.daytime.get_sun_position());
+            self.avatars
+                .update(self.remote_players.states(Instant::now()));
+            self.chat_view.update(&self.chat);
 
             // Check for pillar outline highlight switch
             if self
@@ -114,7 +171,6 @@ impl Game {
                 );
                 match vec {
                     Some(n) => {
-                        // self.remove_hexagon_at(n.1, n.0.z);
                         let mut view = self.world_manager.get_mut_view();
                         view.outline.display = true;
                         view.outline.pos = n.0;
@@ -133,18 +189,46 @@ impl Game {
                 &self.sun,
                 &mut self.weather,
                 &self.sky_view,
+                &self.avatars,
+                &self.chat_view,
             )?;
 
             let event_resp = self.event_manager.poll_events(vec![
+                // Has to come first to get all keys while the player types
+                &mut self.chat,
                 &mut CloseHandler,
                 &mut self.control_switcher,
                 &mut self.daytime,
+                &mut self.terrain_editor,
             ]);
             if event_resp == EventResponse::Quit {
                 break;
             }
+            if let Some(speed) = self.daytime.take_speed_request() {
+                self.server
+                    .send(&Message::Command(Command::SetTimeSpeed(speed)))?;
+            }
+            if let Some(action) = self.terrain_editor.take_action() {
+                self.edit_terrain(action)?;
+            }
+            if let Some(line) = self.chat.take_line() {
+                self.send_chat_line(&line)?;
+            }
+
+            let controls = self.control_switcher.controls();
+            let input = self
+                .prediction
+                .predict(controls, delta, &self.world_manager.get_world());
+            self.unsent_inputs.push(input);
+            self.control_switcher
+                .set_position(self.prediction.body().position);
 
-            self.control_switcher.update(delta);
+            if self.next_input_send <= Instant::now() || self.unsent_inputs.len() >= MAX_MOVE_INPUTS
+            {
+                let inputs = mem::replace(&mut self.unsent_inputs, Vec::new());
+                self.server.send(&Message::Move(inputs))?;
+                self.next_input_send = Instant::now() + INPUT_INTERVAL;
+            }
 
             frames += 1;
             if next_fps_measure < Instant::now() {
@@ -154,8 +238,175 @@ impl Game {
             }
         }
 
+        // The server might already be gone, so errors are ignored
+        let _ = self.server.send(&Message::Disconnect {
+            reason: "quit".into(),
+        });
+        self.server.shutdown();
+
+        Ok(())
+    }
+
+    /// Sends a line the player typed to the server. Lines starting with `/`
+    /// are commands, everything else is a chat message.
+    fn send_chat_line(&mut self, line: &str) -> Result<(), Box<dyn Error>> {
+        if line.starts_with('/') {
+            match Command::parse(&line[1..]) {
+                Ok(command) => self.server.send(&Message::Command(command))?,
+                Err(e) => self.chat.add_line(e.to_string()),
+            }
+        } else {
+            self.server.send(&Message::Chat {
+                sender: String::new(),
+                text: line.to_string(),
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Applies the edit to the pillar section the player looks at and sends
+    /// it to the server.
+    fn edit_terrain(&mut self, action: EditAction) -> Result<(), Box<dyn Error>> {
+        let edit = {
+            let world = self.world_manager.get_world();
+            let camera = self.control_switcher.get_camera();
+            let (pos, height) = match get_pillarsectionpos_looking_at(&world, camera) {
+                Some((_, pos, height)) => (PillarIndex(pos), height),
+                None => return Ok(()),
+            };
+            let section = match world
+                .pillar_at(pos)
+                .and_then(|pillar| get_pillar_section_at_position(pillar, height))
+            {
+                Some(section) => section,
+                None => return Ok(()),
+            };
+
+            match action {
+                EditAction::Remove => PillarEdit {
+                    pos: pos,
+                    height: HeightType::from_units(HeightType::from_real(height).round() as u16),
+                    kind: EditKind::Remove,
+                },
+                EditAction::Add => PillarEdit {
+                    pos: pos,
+                    height: section.top,
+                    kind: EditKind::Add(section.ground),
+                },
+            }
+        };
+
+        match self.world_manager.edit(edit) {
+            Ok(id) => self
+                .server
+                .send(&Message::PillarEdit { id: id, edit: edit })?,
+            Err(e) => debug!("can't apply edit {:?}: {}", edit, e),
+        }
         Ok(())
     }
+
+    /// Handles all messages the server sent since the last frame. Returns
+    /// `false` if the server closed the connection.
+    fn handle_server_messages(&mut self) -> Result<bool, Box<dyn Error>> {
+        loop {
+            let msg = match self.server_messages.try_recv() {
+                Ok(msg) => msg,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    return Err("server reader thread stopped".into());
+                }
+            };
+
+            if msg.is_ok() {
+                self.last_server_message = Instant::now();
+            }
+
+            match msg {
+                Ok(Message::ChunkData(index, version, chunk)) => {
+                    if let Some(chunk) = self.world_manager.chunk_received(index, version, chunk) {
+                        // The provider might already be gone when we are
+                        // shutting down
+                        let _ = self.chunk_sender.send(ChunkUpdate::Data(index, chunk));
+                    }
+                }
+                Ok(Message::ChunkUnavailable(index)) => {
+                    let _ = self.chunk_sender.send(ChunkUpdate::Unavailable(index));
+                }
+                Ok(Message::ChunkEdit {
+                    version,
+                    edit,
+                    origin,
+                }) => {
+                    let _ = self.chunk_sender.send(ChunkUpdate::Edited(edit.chunk()));
+                    if let Some(index) = self.world_manager.server_edit(version, edit, origin) {
+                        self.server.send(&Message::RequestChunk(index))?;
+                    }
+                }
+                Ok(Message::EditRejected(id)) => {
+                    debug!("server rejected our edit {}", id);
+                    self.world_manager.edit_rejected(id);
+                }
+                Ok(Message::MoveAck { seq, body }) => {
+                    let world = self.world_manager.get_world();
+                    if self.prediction.reconcile(seq, body, &world) {
+                        debug!("server corrected our position to {:?}", body.position);
+                    }
+                }
+                Ok(Message::Snapshot(snapshot)) => {
+                    self.remote_players.apply(snapshot, Instant::now());
+                }
+                Ok(Message::TimeSync(time)) => self.daytime.sync(time),
+                Ok(Message::WeatherSync(state)) => self.weather.set_state(state),
+                Ok(Message::MayFly(may_fly)) => self.control_switcher.set_may_fly(may_fly),
+                Ok(Message::Chat { sender, text }) => {
+                    self.chat.add_line(format!("<{}> {}", sender, text))
+                }
+                Ok(Message::Ping(n)) => self.server.send(&Message::Pong(n))?,
+                Ok(Message::Disconnect { reason }) => {
+                    info!("server closed the connection: {}", reason);
+                    return Ok(false);
+                }
+                Ok(msg) => warn!("received unexpected message from server: {:?}", msg),
+                Err(e) => {
+                    error!("lost connection to server: {}", e);
+                    return Err(e.into());
+                }
+            }
+        }
+
+        // The server pings us regularly, so it's gone if we don't hear
+        // anything for too long
+        if self.last_server_message.elapsed() > SERVER_TIMEOUT {
+            error!("server did not respond for {:?}", SERVER_TIMEOUT);
+            return Err("server timed out".into());
+        }
+        Ok(true)
+    }
+}
+
+/// Connects to the server and logs in with the given name. Returns the
+/// connection and what the server told us about its world.
+fn connect_to_server(
+    addr: SocketAddr,
+    name: &str,
+) -> Result<(Connection, Welcome), Box<dyn Error>> {
+    let server = Connection::connect(addr)?;
+    server.send(&Message::Login {
+        version: PROTOCOL_VERSION,
+        name: name.to_string(),
+    })?;
+
+    match server.recv()? {
+        Message::Welcome(welcome) => Ok((server, welcome)),
+        Message::Disconnect { reason } => {
+            error!("the server rejected us: {}", reason);
+            Err(format!("server rejected login: {}", reason).into())
+        }
+        msg => {
+            error!("the server answered our login with {:?}", msg);
+            Err("server did not answer the login".into())
+        }
+    }
 }
 
 fn get_pillarsectionpos_looking_at(
@@ -209,8 +460,19 @@ fn get_pillar_section_at_position(pillar: &HexPillar, pos_z: f32) -> Option<&Pil
     None
 }
 
-fn create_chunk_provider(config: &Config) -> Box<dyn ChunkProvider> {
-    Box::new(WorldGenerator::with_seed(config.seed))
+/// Creates a provider which loads all chunks from the server. Plants are
+/// generated locally from the seed of the server's world.
+fn create_chunk_provider(
+    server: &Connection,
+    chunks: Receiver<ChunkUpdate>,
+    seed: u64,
+) -> Result<Box<dyn ChunkProvider>, Box<dyn Error>> {
+    let plant_list = WorldGenerator::with_seed(seed).get_plant_list();
+    Ok(Box::new(NetworkProvider::new(
+        server.try_clone()?,
+        chunks,
+        plant_list,
+    )))
 }
 
 /// Creates the OpenGL context and prints useful information about the
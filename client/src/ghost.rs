@@ -1,6 +1,7 @@
 use super::camera::*;
 use super::event_manager::*;
 use super::GameContext;
+use base::movement::Controls;
 use glium::glutin::{
     dpi::LogicalPosition, ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode,
     WindowEvent,
@@ -10,7 +11,7 @@ use std::rc::Rc;
 pub struct Ghost {
     cam: Camera,
     context: Rc<GameContext>,
-    speed: f32,
+    sprint: bool,
     forward: bool,
     backward: bool,
     left: bool,
@@ -20,16 +21,12 @@ pub struct Ghost {
     mouselock: bool,
 }
 
-// Speed per second
-const DEFAULT_SPEED: f32 = 12.0;
-const SHIFT_SPEED: f32 = 60.0;
-
 impl Ghost {
     pub fn new(context: Rc<GameContext>) -> Self {
         Ghost {
             cam: Camera::new(context.get_config().resolution.aspect_ratio()),
             context: context,
-            speed: DEFAULT_SPEED,
+            sprint: false,
             forward: false,
             backward: false,
             left: false,
@@ -39,26 +36,22 @@ impl Ghost {
             mouselock: false,
         }
     }
-    pub fn update(&mut self, delta: f32) {
-        let factored_speed = self.speed * delta;
-
-        if self.forward {
-            self.cam.move_forward(factored_speed);
-        }
-        if self.backward {
-            self.cam.move_backward(factored_speed);
-        }
-        if self.left {
-            self.cam.move_left(factored_speed);
+    /// Returns the controls for the current frame. The ghost flies, so it
+    /// isn't stopped by anything.
+    pub fn controls(&self) -> Controls {
+        fn axis(positive: bool, negative: bool) -> f32 {
+            positive as u8 as f32 - negative as u8 as f32
         }
-        if self.right {
-            self.cam.move_right(factored_speed);
-        }
-        if self.up {
-            self.cam.move_up(factored_speed);
-        }
-        if self.down {
-            self.cam.move_down(factored_speed);
+
+        Controls {
+            forward: axis(self.forward, self.backward),
+            right: axis(self.right, self.left),
+            up: axis(self.up, self.down),
+            sprint: self.sprint,
+            jump: false,
+            fly: true,
+            theta: self.cam.theta,
+            phi: self.cam.phi,
         }
     }
 
@@ -242,7 +235,7 @@ impl EventHandler for Ghost {
                     },
                 ..
             } => {
-                self.speed = SHIFT_SPEED;
+                self.sprint = true;
                 EventResponse::Continue
             }
             WindowEvent::KeyboardInput {
@@ -254,7 +247,7 @@ impl EventHandler for Ghost {
                     },
                 ..
             } => {
-                self.speed = DEFAULT_SPEED;
+                self.sprint = false;
                 EventResponse::Continue
             }
             WindowEvent::KeyboardInput {
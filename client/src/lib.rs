@@ -13,16 +13,19 @@ extern crate noise;
 extern crate log;
 
 mod camera;
+mod chat;
 mod config;
 mod control_switcher;
 pub mod daytime;
 mod event_manager;
+pub mod export;
 mod frustum;
 mod game;
 mod game_context;
 mod ghost;
 mod player;
 mod renderer;
+mod terrain_editor;
 pub mod util;
 pub mod view;
 mod weather;
@@ -30,6 +33,7 @@ mod world;
 mod world_manager;
 
 pub use camera::Camera;
+pub use chat::Chat;
 pub use config::Config;
 pub use daytime::*;
 pub use event_manager::*;
@@ -40,11 +44,24 @@ pub use game_context::GameContext;
 pub use renderer::Renderer;
 pub use world_manager::WorldManager;
 
+use base::net::{broadcast_addr, discover, FoundServer, DISCOVERY_PORT};
 use game::Game;
 use std::error::Error;
-use std::net::SocketAddr;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+/// How long we wait for servers in the local network to answer.
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(1);
 
 pub fn start_game(config: Config, server: SocketAddr) -> Result<(), Box<dyn Error>> {
     let game = Game::new(config, server)?;
     game.run()
 }
+
+/// Returns the servers in the local network, including the ones running on
+/// this computer.
+pub fn discover_servers() -> io::Result<Vec<FoundServer>> {
+    let local = SocketAddr::new(Ipv4Addr::new(127, 0, 0, 1).into(), DISCOVERY_PORT);
+    discover(&[broadcast_addr(), local], DISCOVERY_TIMEOUT)
+}
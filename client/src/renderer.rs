@@ -18,6 +18,7 @@ use std::error::Error;
 use std::rc::Rc;
 use view::SkyView;
 use view::Sun;
+use view::{AvatarView, ChatView};
 use world::WorldView;
 use DayTime;
 
@@ -329,6 +330,8 @@ impl Renderer {
         sun: &Sun,
         weather: &mut Weather,
         sky_view: &SkyView,
+        avatars: &AvatarView,
+        chat: &ChatView,
     ) -> Result<(), Box<dyn Error>> {
         // info!("------------ {:?}", daytime.get_sky_light());
         // ===================================================================
@@ -386,6 +389,7 @@ impl Renderer {
                 daytime,
                 sun_dir,
             );
+            avatars.draw(&mut hdr_buffer, camera, daytime, sun_dir);
             sky_view.draw_skydome(&mut hdr_buffer, camera, daytime);
 
             sun.draw_sun(&mut hdr_buffer, camera);
@@ -444,6 +448,7 @@ impl Renderer {
             &uniforms,
             &Default::default(),
         )?;
+        chat.draw(&mut target);
 
         target.finish()?;
 
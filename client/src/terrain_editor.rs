@@ -0,0 +1,52 @@
+use super::event_manager::*;
+use glium::glutin::{ElementState, Event, MouseButton, WindowEvent};
+
+/// What the player wants to do with the pillar section they look at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EditAction {
+    /// Removes the step the player looks at.
+    Remove,
+    /// Adds a step on top of the section the player looks at.
+    Add,
+}
+
+/// Collects the terrain edits requested with the mouse: the right button
+/// removes a step, the middle button adds one.
+pub struct TerrainEditor {
+    action: Option<EditAction>,
+}
+
+impl TerrainEditor {
+    pub fn new() -> Self {
+        TerrainEditor { action: None }
+    }
+
+    /// Returns the action requested since the last call, if any.
+    pub fn take_action(&mut self) -> Option<EditAction> {
+        self.action.take()
+    }
+}
+
+impl EventHandler for TerrainEditor {
+    fn handle_event(&mut self, e: &Event) -> EventResponse {
+        let action = match e {
+            Event::WindowEvent {
+                event:
+                    WindowEvent::MouseInput {
+                        state: ElementState::Pressed,
+                        button,
+                        ..
+                    },
+                ..
+            } => match button {
+                MouseButton::Right => EditAction::Remove,
+                MouseButton::Middle => EditAction::Add,
+                _ => return EventResponse::NotHandled,
+            },
+            _ => return EventResponse::NotHandled,
+        };
+
+        self.action = Some(action);
+        EventResponse::Break
+    }
+}
@@ -0,0 +1,177 @@
+use base::math::*;
+use base::net::PlayerState;
+use glium::index::PrimitiveType;
+use glium::{self, DrawParameters, IndexBuffer, Program, VertexBuffer};
+use std::rc::Rc;
+use util::ToArr;
+use Camera;
+use DayTime;
+use GameContext;
+
+/// Height of the camera above the feet of a player.
+const EYE_HEIGHT: f32 = 1.75;
+
+const BODY_COLOR: [f32; 3] = [0.15, 0.25, 0.6];
+const HEAD_COLOR: [f32; 3] = [0.8, 0.6, 0.45];
+const NOSE_COLOR: [f32; 3] = [0.6, 0.1, 0.1];
+
+/// Draws a simple figure for every other player on the server.
+pub struct AvatarView {
+    vertex_buffer: VertexBuffer<Vertex>,
+    index_buffer: IndexBuffer<u32>,
+    program: Program,
+    players: Vec<PlayerState>,
+}
+
+impl AvatarView {
+    pub fn new(context: Rc<GameContext>) -> Self {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+
+        // Everything relative to the eyes, looking along the x axis
+        add_box(
+            &mut vertices,
+            &mut indices,
+            [-0.15, -0.3, -EYE_HEIGHT],
+            [0.15, 0.3, -0.3],
+            BODY_COLOR,
+        );
+        add_box(
+            &mut vertices,
+            &mut indices,
+            [-0.2, -0.2, -0.3],
+            [0.2, 0.2, 0.15],
+            HEAD_COLOR,
+        );
+        add_box(
+            &mut vertices,
+            &mut indices,
+            [0.2, -0.05, -0.1],
+            [0.3, 0.05, 0.0],
+            NOSE_COLOR,
+        );
+
+        AvatarView {
+            vertex_buffer: VertexBuffer::new(context.get_facade(), &vertices).unwrap(),
+            index_buffer: IndexBuffer::new(
+                context.get_facade(),
+                PrimitiveType::TrianglesList,
+                &indices,
+            )
+            .unwrap(),
+            program: context.load_program("avatar").unwrap(),
+            players: Vec::new(),
+        }
+    }
+
+    /// Sets the players to draw.
+    pub fn update(&mut self, players: Vec<PlayerState>) {
+        self.players = players;
+    }
+
+    pub fn draw<S: glium::Surface>(
+        &self,
+        surface: &mut S,
+        camera: &Camera,
+        daytime: &DayTime,
+        sun_dir: Vector3f,
+    ) {
+        let params = DrawParameters {
+            depth: glium::Depth {
+                write: true,
+                test: glium::DepthTest::IfLess,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        for player in &self.players {
+            let model = Matrix4::from_translation(player.position.to_vec())
+                * Matrix4::from_angle_z(rad(player.phi));
+            let uniforms = uniform! {
+                proj_matrix: camera.proj_matrix().to_arr(),
+                view_matrix: camera.view_matrix().to_arr(),
+                model: model.to_arr(),
+                sun_dir: sun_dir.to_arr(),
+                sun_color: daytime.get_sun_color().to_arr(),
+                sky_light: daytime.get_sky_light().to_arr(),
+            };
+
+            surface
+                .draw(
+                    &self.vertex_buffer,
+                    &self.index_buffer,
+                    &self.program,
+                    &uniforms,
+                    &params,
+                )
+                .unwrap();
+        }
+    }
+}
+
+/// Adds an axis aligned box between the two corners.
+fn add_box(
+    vertices: &mut Vec<Vertex>,
+    indices: &mut Vec<u32>,
+    min: [f32; 3],
+    max: [f32; 3],
+    color: [f32; 3],
+) {
+    // Every side as normal and its four corners, counter-clockwise when seen
+    // from outside
+    let sides = [
+        (
+            [1.0, 0.0, 0.0],
+            [[1, 0, 0], [1, 1, 0], [1, 1, 1], [1, 0, 1]],
+        ),
+        (
+            [-1.0, 0.0, 0.0],
+            [[0, 1, 0], [0, 0, 0], [0, 0, 1], [0, 1, 1]],
+        ),
+        (
+            [0.0, 1.0, 0.0],
+            [[1, 1, 0], [0, 1, 0], [0, 1, 1], [1, 1, 1]],
+        ),
+        (
+            [0.0, -1.0, 0.0],
+            [[0, 0, 0], [1, 0, 0], [1, 0, 1], [0, 0, 1]],
+        ),
+        (
+            [0.0, 0.0, 1.0],
+            [[0, 0, 1], [1, 0, 1], [1, 1, 1], [0, 1, 1]],
+        ),
+        (
+            [0.0, 0.0, -1.0],
+            [[0, 1, 0], [1, 1, 0], [1, 0, 0], [0, 0, 0]],
+        ),
+    ];
+
+    for &(normal, corners) in &sides {
+        let first = vertices.len() as u32;
+        for corner in &corners {
+            let pick = |axis: usize| {
+                if corner[axis] == 0 {
+                    min[axis]
+                } else {
+                    max[axis]
+                }
+            };
+            vertices.push(Vertex {
+                position: [pick(0), pick(1), pick(2)],
+                normal: normal,
+                color: color,
+            });
+        }
+        indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Vertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+    color: [f32; 3],
+}
+
+implement_vertex!(Vertex, position, normal, color);
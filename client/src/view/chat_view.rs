@@ -0,0 +1,167 @@
+use super::font::{self, BLOCK_GLYPH, GLYPH_COUNT, GLYPH_HEIGHT, GLYPH_WIDTH};
+use glium::draw_parameters::Blend;
+use glium::texture::RawImage2d;
+use glium::uniforms::{MagnifySamplerFilter, MinifySamplerFilter};
+use glium::{self, DrawParameters, Program, Texture2d, VertexBuffer};
+use std::rc::Rc;
+use Chat;
+use GameContext;
+
+/// Screen pixels per font pixel.
+const SCALE: f32 = 2.0;
+
+/// Space between the text and the edges of the window, in screen pixels.
+const MARGIN: f32 = 10.0;
+
+/// Size of a character including the space around it, in font pixels.
+const CELL_WIDTH: f32 = GLYPH_WIDTH as f32 + 1.0;
+const CELL_HEIGHT: f32 = GLYPH_HEIGHT as f32 + 3.0;
+
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+const INPUT_COLOR: [f32; 4] = [1.0, 1.0, 0.6, 1.0];
+const BACKGROUND_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 0.4];
+
+/// Draws the chat log and the line the player is typing in the lower left
+/// corner of the window.
+pub struct ChatView {
+    context: Rc<GameContext>,
+    program: Program,
+    /// All glyphs of the font next to each other.
+    font: Texture2d,
+    /// The text of the current frame, rebuilt by `update`.
+    vertices: Vec<Vertex>,
+}
+
+impl ChatView {
+    pub fn new(context: Rc<GameContext>) -> Self {
+        let width = GLYPH_COUNT * GLYPH_WIDTH;
+        let mut pixels = Vec::with_capacity((width * GLYPH_HEIGHT * 4) as usize);
+        // Textures start with their bottom row
+        for y in (0..GLYPH_HEIGHT).rev() {
+            for x in 0..width {
+                let alpha = if font::is_set(x / GLYPH_WIDTH, x % GLYPH_WIDTH, y) {
+                    255
+                } else {
+                    0
+                };
+                pixels.extend_from_slice(&[255, 255, 255, alpha]);
+            }
+        }
+        let image = RawImage2d::from_raw_rgba(pixels, (width, GLYPH_HEIGHT));
+
+        ChatView {
+            program: context.load_program("text").unwrap(),
+            font: Texture2d::new(context.get_facade(), image).unwrap(),
+            context: context,
+            vertices: Vec::new(),
+        }
+    }
+
+    /// Lays out the text of the chat for the next frame.
+    pub fn update(&mut self, chat: &Chat) {
+        self.vertices.clear();
+        let (width, _) = self.context.get_facade().get_framebuffer_dimensions();
+        let columns = ((width as f32 - 2.0 * MARGIN) / (CELL_WIDTH * SCALE)).max(1.0) as usize;
+
+        // Lines are laid out from the bottom up
+        let mut rows: Vec<(String, [f32; 4])> = Vec::new();
+        if let Some(input) = chat.input() {
+            let line = format!("> {}_", input);
+            for row in wrap(&line, columns).into_iter().rev() {
+                rows.push((row, INPUT_COLOR));
+            }
+        }
+        for line in chat.visible_lines().into_iter().rev() {
+            for row in wrap(line, columns).into_iter().rev() {
+                rows.push((row, TEXT_COLOR));
+            }
+        }
+
+        for (i, &(ref row, color)) in rows.iter().enumerate() {
+            let y = MARGIN + i as f32 * CELL_HEIGHT * SCALE;
+            let len = row.chars().count() as f32;
+            self.push_quad(
+                [MARGIN - SCALE, y],
+                [(len * CELL_WIDTH + 1.0) * SCALE, CELL_HEIGHT * SCALE],
+                BLOCK_GLYPH,
+                BACKGROUND_COLOR,
+            );
+            for (j, c) in row.chars().enumerate() {
+                let pos = [MARGIN + j as f32 * CELL_WIDTH * SCALE, y + 2.0 * SCALE];
+                let size = [GLYPH_WIDTH as f32 * SCALE, GLYPH_HEIGHT as f32 * SCALE];
+                self.push_quad(pos, size, font::glyph_index(c), color);
+            }
+        }
+    }
+
+    pub fn draw<S: glium::Surface>(&self, surface: &mut S) {
+        if self.vertices.is_empty() {
+            return;
+        }
+        let vbuf = VertexBuffer::new(self.context.get_facade(), &self.vertices).unwrap();
+        let (width, height) = self.context.get_facade().get_framebuffer_dimensions();
+        let uniforms = uniform! {
+            u_resolution: [width as f32, height as f32],
+            u_font: self.font
+                .sampled()
+                .magnify_filter(MagnifySamplerFilter::Nearest)
+                .minify_filter(MinifySamplerFilter::Nearest),
+        };
+        let params = DrawParameters {
+            blend: Blend::alpha_blending(),
+            ..Default::default()
+        };
+
+        surface
+            .draw(
+                &vbuf,
+                &glium::index::NoIndices(glium::index::PrimitiveType::TrianglesList),
+                &self.program,
+                &uniforms,
+                &params,
+            )
+            .unwrap();
+    }
+
+    /// Adds a rectangle showing the glyph. Positions and sizes are in screen
+    /// pixels, starting in the lower left corner.
+    fn push_quad(&mut self, pos: [f32; 2], size: [f32; 2], glyph: u32, color: [f32; 4]) {
+        let u0 = glyph as f32 / GLYPH_COUNT as f32;
+        let u1 = (glyph + 1) as f32 / GLYPH_COUNT as f32;
+        let corner = |x: f32, y: f32| Vertex {
+            i_position: [pos[0] + x * size[0], pos[1] + y * size[1]],
+            i_tex_coords: [u0 + x * (u1 - u0), y],
+            i_color: color,
+        };
+        let quad = [
+            corner(0.0, 0.0),
+            corner(1.0, 0.0),
+            corner(1.0, 1.0),
+            corner(0.0, 0.0),
+            corner(1.0, 1.0),
+            corner(0.0, 1.0),
+        ];
+        self.vertices.extend_from_slice(&quad);
+    }
+}
+
+/// Splits the line into rows of at most `columns` characters.
+fn wrap(line: &str, columns: usize) -> Vec<String> {
+    let chars: Vec<_> = line.chars().collect();
+    if chars.is_empty() {
+        return vec![String::new()];
+    }
+    chars
+        .chunks(columns)
+        .map(|row| row.iter().collect())
+        .collect()
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Vertex {
+    i_position: [f32; 2],
+    i_tex_coords: [f32; 2],
+    i_color: [f32; 4],
+}
+
+implement_vertex!(Vertex, i_position, i_tex_coords, i_color);
@@ -1,8 +1,13 @@
+mod avatar_view;
+mod chat_view;
+mod font;
 mod plant_renderer;
 mod plant_view;
 mod sky_view;
 mod sun_view;
 
+pub use self::avatar_view::*;
+pub use self::chat_view::*;
 pub use self::plant_renderer::*;
 pub use self::plant_view::*;
 pub use self::sky_view::*;
@@ -187,7 +187,7 @@ pub struct Vertex {
 implement_vertex!(Vertex, position, color, normal);
 
 /// generates VertexBuffer and IndexBuffer for Plants
-fn gen_branch_buffer(
+pub fn gen_branch_buffer(
     old_cps: &[ControlPoint],
     vertices: &mut Vec<Vertex>,
     indices: &mut Vec<u32>,
@@ -1,8 +1,8 @@
 extern crate rand;
 
 use super::camera::Camera;
-use base::gen::world::biome;
 use base::math::*;
+use base::weather::{Form, Strength, WeatherState};
 use base::world::PillarIndex;
 use glium::draw_parameters::{BlendingFunction, DepthTest};
 use glium::{self, DrawParameters, LinearBlendingFactor, Program, VertexBuffer};
@@ -21,21 +21,6 @@ pub struct Vertex {
 }
 implement_vertex!(Vertex, point);
 
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub enum Form {
-    Rain = 1,
-    Snow = 2,
-    Pollen = 3,
-}
-
-#[derive(Debug, Copy, Clone, PartialEq)]
-pub enum Strength {
-    None = 0,
-    Weak = 1,
-    Medium = 2,
-    Heavy = 3,
-}
-
 #[derive(Copy, Clone)]
 pub struct Particle {
     position: Point3<f32>,
@@ -156,8 +141,8 @@ pub struct Weather {
     wind: Vector2f,
     wind_speed: f32,
     delta_time: f32,
-    weather_time: f32,
-    last_biome: biome::Biome,
+    /// The weather the server sent us.
+    target: WeatherState,
     change: bool,
     sun_color: Vector3f,
     sky_light: Vector3f,
@@ -200,14 +185,21 @@ impl Weather {
             wind: Vector2f::new(rand::random::<f32>(), rand::random::<f32>()),
             wind_speed: (rand::random::<f32>() + 0.2) * 5.0,
             delta_time: 0.0,
-            weather_time: 0.0,
-            last_biome: biome::Biome::GrassLand,
+            target: WeatherState {
+                form: Form::Rain,
+                strength: Strength::None,
+            },
             change: false,
             sun_color: Vector3f::new(0.0, 0.0, 0.0),
             sky_light: Vector3f::new(0.0, 0.0, 0.0),
         }
     }
 
+    /// Sets the weather the server sent us.
+    pub fn set_state(&mut self, state: WeatherState) {
+        self.target = state;
+    }
+
     /// Draws particles on the screen
     pub fn draw<S: glium::Surface>(&mut self, surface: &mut S, camera: &Camera) {
         if self.strength == Strength::None && self.particles.len() == 0 {
@@ -294,8 +286,7 @@ impl Weather {
     /// updates particles in terms of lifetime and position dependend on their
     /// form and deletes
     /// particles that are in the back of the player or in caves. Also
-    /// responsible for setting the
-    /// apropriate weather.
+    /// switches to the weather set by `set_state`.
     pub fn update(
         &mut self,
         camera: &Camera,
@@ -306,141 +297,13 @@ impl Weather {
         self.sun_color = daytime.get_sun_color();
         self.sky_light = daytime.get_sky_light();
 
-        // Toggle downfall
-        let world = world_manager.get_world();
-        let relevant_pos = Point2f::new(camera.position.x, camera.position.y);
-        let pillar_index = PillarIndex(AxialPoint::from_real(relevant_pos));
-        let pillar_vec = world.pillar_at(pillar_index);
-
-        if pillar_vec.is_some() && !self.change {
-            let biome = pillar_vec.unwrap().biome();
-            self.weather_time += delta;
-
-            if *biome != self.last_biome && self.weather_time > 5.0 || self.weather_time >= 120.0 {
-                let chance = rand::random::<f32>() * 100.0;
-                match *biome {
-                    biome::Biome::GrassLand => {
-                        if (self.form == Form::Pollen || self.form == Form::Snow)
-                            && self.particles.len() > 0
-                        {
-                            self.change = true;
-                            return;
-                        }
-                        self.form = Form::Rain;
-                        self.last_biome = biome::Biome::GrassLand;
-                        self.strength = match chance {
-                            0.0..=5.0 => Strength::Weak,
-                            5.0..=8.0 => Strength::Medium,
-                            8.0..=10.0 => Strength::Heavy,
-                            _ => Strength::None,
-                        };
-                    }
-
-                    biome::Biome::Desert => {
-                        if (self.form == Form::Pollen || self.form == Form::Snow)
-                            && self.particles.len() > 0
-                        {
-                            self.change = true;
-                            return;
-                        }
-                        self.form = Form::Rain;
-                        self.last_biome = biome::Biome::Desert;
-                        self.strength = match chance {
-                            0.0..=1.0 => Strength::Weak,
-                            1.0..=2.0 => Strength::Medium,
-                            2.0..=4.0 => Strength::Heavy,
-                            _ => Strength::None,
-                        };
-                    }
-
-                    biome::Biome::Snow => {
-                        if (self.form == Form::Pollen || self.form == Form::Rain)
-                            && self.particles.len() > 0
-                        {
-                            self.change = true;
-                            return;
-                        }
-                        self.form = Form::Snow;
-                        self.last_biome = biome::Biome::Snow;
-                        self.strength = match chance {
-                            0.0..=20.0 => Strength::Weak,
-                            20.0..=50.0 => Strength::Medium,
-                            50.0..=75.0 => Strength::Heavy,
-                            _ => Strength::None,
-                        };
-                    }
-
-                    biome::Biome::Forest => {
-                        if (self.form == Form::Rain || self.form == Form::Snow)
-                            && self.particles.len() > 0
-                        {
-                            self.change = true;
-                            return;
-                        }
-                        self.form = Form::Pollen;
-                        self.last_biome = biome::Biome::Forest;
-                        self.strength = match chance {
-                            0.0..=15.0 => Strength::Weak,
-                            15.0..=35.0 => Strength::Medium,
-                            35.0..=65.0 => Strength::Heavy,
-                            _ => Strength::None,
-                        };
-                    }
-
-                    biome::Biome::RainForest => {
-                        if (self.form == Form::Pollen || self.form == Form::Snow)
-                            && self.particles.len() > 0
-                        {
-                            self.change = true;
-                            return;
-                        }
-                        self.form = Form::Rain;
-                        self.last_biome = biome::Biome::RainForest;
-                        self.strength = match chance {
-                            0.0..=21.0 => Strength::Weak,
-                            21.0..=51.0 => Strength::Medium,
-                            51.0..=81.0 => Strength::Heavy,
-                            _ => Strength::None,
-                        };
-                    }
-
-                    biome::Biome::Savanna => {
-                        if (self.form == Form::Rain || self.form == Form::Snow)
-                            && self.particles.len() > 0
-                        {
-                            self.change = true;
-                            return;
-                        }
-                        self.form = Form::Pollen;
-                        self.last_biome = biome::Biome::Savanna;
-                        self.strength = match chance {
-                            0.0..=5.0 => Strength::Weak,
-                            5.0..=7.0 => Strength::Medium,
-                            7.0..=9.0 => Strength::Heavy,
-                            _ => Strength::None,
-                        };
-                    }
-
-                    biome::Biome::Stone => {
-                        if (self.form == Form::Pollen || self.form == Form::Snow)
-                            && self.particles.len() > 0
-                        {
-                            self.change = true;
-                            return;
-                        }
-                        self.form = Form::Rain;
-                        self.last_biome = biome::Biome::Stone;
-                        self.strength = match chance {
-                            0.0..=7.0 => Strength::Weak,
-                            7.0..=12.0 => Strength::Medium,
-                            12.0..=17.0 => Strength::Heavy,
-                            _ => Strength::None,
-                        };
-                    }
-                    biome::Biome::Debug => (),
-                }
-                self.weather_time = 0.0;
-            }
+        // Switch to the weather of the server. Particles of another form
+        // have to disappear first.
+        if self.target.form != self.form && self.particles.len() > 0 {
+            self.change = true;
+        } else if !self.change {
+            self.form = self.target.form;
+            self.strength = self.target.strength;
         }
 
         // stops method if no weather is set
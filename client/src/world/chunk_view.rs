@@ -358,7 +358,7 @@ impl EdgeDir {
 /// with the neighbor pillar under special circumstances. Another optimization
 /// is a bit more ugly: we could connect side pieces with the same position and
 /// orientation. Sadly this "creates" geometry inside our blobs of world.
-fn get_vertices(chunk: &Chunk) -> (Vec<Vertex>, Vec<u32>) {
+pub fn get_vertices(chunk: &Chunk) -> (Vec<Vertex>, Vec<u32>) {
     // Make a crude guess how many vertices we will need. This assumes that the
     // chunk has at least one pillar section per pillar.
     //
@@ -6,4 +6,5 @@ mod tex_generator;
 mod world_view;
 
 pub use self::chunk_renderer::*;
+pub use self::chunk_view::get_vertices;
 pub use self::world_view::WorldView;
@@ -48,6 +48,11 @@ impl WorldView {
     }
 
     pub fn refresh_chunk<F: Facade>(&mut self, chunk_pos: ChunkIndex, chunk: &Chunk, facade: &F) {
+        // The chunk might have been drawn before and its plants changed
+        for plant_view in self.plant_views.values_mut() {
+            plant_view.remove_instance_at_pos(chunk_pos);
+        }
+
         self.chunks.insert(
             chunk_pos,
             ChunkView::from_chunk(
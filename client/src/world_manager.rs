@@ -1,16 +1,22 @@
 use super::GameContext;
 use base::math::*;
-use base::world::{Chunk, ChunkProvider, World};
-use base::world::{ChunkIndex, CHUNK_SIZE};
+use base::net::{EditSync, EditUpdate};
+use base::world::{chunks_in_range, ChunkIndex, CHUNK_RANGE_MARGIN, CHUNK_SIZE};
+use base::world::{Chunk, ChunkProvider, EditError, PillarEdit, World};
 use std::cell::RefMut;
 use std::cell::{Ref, RefCell};
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::mem::replace;
 use std::rc::Rc;
 use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
 use std::thread;
 use world::WorldView;
 
+/// The chunks in range are determined again once the player moved this far.
+/// The server sends chunks a bit beyond the view radius, so this has to be
+/// less than that margin.
+const RANGE_UPDATE_DISTANCE: f32 = CHUNK_RANGE_MARGIN * CHUNK_SIZE as f32 / 2.0;
+
 #[derive(Clone)]
 pub struct WorldManager {
     shared: Rc<RefCell<Shared>>,
@@ -21,14 +27,23 @@ pub struct WorldManager {
 struct Shared {
     world: World,
     world_view: WorldView,
-    provided_chunks: Receiver<(ChunkIndex, Chunk)>,
+    /// Answers of the worker thread, `None` if the chunk couldn't be loaded.
+    provided_chunks: Receiver<(ChunkIndex, Option<Chunk>)>,
     sent_requests: HashSet<ChunkIndex>,
     load_distance: f32,
-    player_chunk: ChunkIndex,
+    /// Position of the player when the chunks in range were last determined.
+    range_center: Point2f,
+    edits: EditSync,
 }
 
 impl WorldManager {
-    pub fn new(provider: Box<dyn ChunkProvider>, game_context: Rc<GameContext>) -> Self {
+    /// Creates a world manager which loads all chunks up to `load_distance`
+    /// chunks away from the player.
+    pub fn new(
+        provider: Box<dyn ChunkProvider>,
+        load_distance: f32,
+        game_context: Rc<GameContext>,
+    ) -> Self {
         // Create two channels to send chunk positions and receive chunks.
         let (chunk_request_sender, chunk_request_recv) = channel();
         let (chunk_sender, chunk_recv) = channel();
@@ -39,9 +54,9 @@ impl WorldManager {
                 world_view: WorldView::new(game_context.clone(), provider.get_plant_list()),
                 sent_requests: HashSet::new(),
                 provided_chunks: chunk_recv,
-                // TODO: load this from the config!
-                load_distance: 10.0,
-                player_chunk: ChunkIndex(AxialPoint::new(0, 0)),
+                load_distance: load_distance,
+                range_center: Point2f::new(0.0, 0.0),
+                edits: EditSync::new(),
             })),
             chunk_requests: chunk_request_sender,
             context: game_context,
@@ -54,46 +69,26 @@ impl WorldManager {
             worker_thread(provider, chunk_request_recv, chunk_sender);
         });
 
-        this.update_player_chunk();
+        this.update_chunks_in_range();
         this
     }
 
-    /// Called when the player moves to a different chunk.
+    /// Called when the player moved far enough.
     ///
     /// This unloads all currently loaded chunks that are too far away from the
     /// player, and loads all chunks close enough to the player (if they aren't
     /// already requested).
-    fn update_player_chunk(&self) {
+    fn update_chunks_in_range(&self) {
         let mut shared = self.shared.borrow_mut();
         let load_distance = shared.load_distance;
-        let player_chunk = shared.player_chunk.0;
-        let radius = load_distance as i32;
-
-        let is_chunk_in_range = |chunk_pos: AxialPoint| {
-            let chunk_center = chunk_pos * CHUNK_SIZE as i32
-                + AxialVector::new(CHUNK_SIZE as i32 / 2, CHUNK_SIZE as i32 / 2);
-            let player_center = player_chunk * CHUNK_SIZE as i32
-                + AxialVector::new(CHUNK_SIZE as i32 / 2, CHUNK_SIZE as i32 / 2);
-            (chunk_center - player_center)
-                .to_real()
-                .distance(Vector2f::zero())
-                < load_distance * CHUNK_SIZE as f32
-        };
-
-        // Load new range
-        for qd in -radius..radius + 1 {
-            for rd in -radius..radius + 1 {
-                let chunk_pos = AxialPoint::new(player_chunk.q + qd, player_chunk.r + rd);
-                if is_chunk_in_range(chunk_pos) {
-                    let chunk_index = ChunkIndex(chunk_pos);
-
-                    if !shared.world.chunks.contains_key(&chunk_index) {
-                        if !shared.sent_requests.contains(&chunk_index) {
-                            self.chunk_requests.send(chunk_index).unwrap();
-                            shared.sent_requests.insert(chunk_index);
-                        }
-                    }
-                }
+        let center = shared.range_center;
+
+        // Load new range, nearest chunks first
+        for chunk_index in chunks_in_range(center, load_distance) {
+            if !shared.world.chunks.contains_key(&chunk_index)
+                && shared.sent_requests.insert(chunk_index)
+            {
+                self.chunk_requests.send(chunk_index).unwrap();
             }
         }
 
@@ -101,13 +96,13 @@ impl WorldManager {
         let chunks = replace(&mut shared.world.chunks, HashMap::new());
         let mut new_chunks = HashMap::new();
         for (index, chunk) in chunks {
-            let chunk_pos = index.0;
-            if is_chunk_in_range(chunk_pos) {
+            if index.is_in_range(center, load_distance) {
                 // Still in range
                 new_chunks.insert(index, chunk);
             } else {
                 // Remove
                 shared.world_view.remove_chunk(index);
+                shared.edits.chunk_removed(index);
             }
         }
         shared.world.chunks = new_chunks;
@@ -140,25 +135,20 @@ impl WorldManager {
         &self.context
     }
 
-    /// Starts to generate all chunks within `load_distance` (config parameter)
-    /// around `pos`.
+    /// Starts to load all chunks within `load_distance` around `pos`, once
+    /// the player moved far enough since the last time.
     fn load_world_around(&self, pos: Point2f) {
-        let axial_pos = AxialPoint::from_real(pos);
-        let chunk_pos = AxialPoint::new(
-            axial_pos.q / CHUNK_SIZE as i32,
-            axial_pos.r / CHUNK_SIZE as i32,
-        );
-
         let mut shared = self.shared.borrow_mut();
-        if shared.player_chunk.0 != chunk_pos {
-            shared.player_chunk = ChunkIndex(chunk_pos);
+        if shared.range_center.distance(pos) >= RANGE_UPDATE_DISTANCE {
+            shared.range_center = pos;
             debug!(
                 "player moved to chunk {:?} (player at {:?})",
-                chunk_pos, pos
+                ChunkIndex::at(pos),
+                pos
             );
             drop(shared);
 
-            self.update_player_chunk();
+            self.update_chunks_in_range();
         }
     }
 
@@ -185,16 +175,23 @@ impl WorldManager {
                 Ok(val) => val,
             };
 
-            changed = true;
-            shared
-                .world_view
-                .refresh_chunk(pos, &chunk, self.context.get_facade());
-
             shared.sent_requests.remove(&pos);
+            // The chunk is requested again once the player moved a bit
+            let chunk = match chunk {
+                Some(chunk) => chunk,
+                None => continue,
+            };
+
+            changed = true;
             let res = shared.world.add_chunk(pos, chunk);
             if res.is_err() {
                 warn!("chunk at {:?} already exists!", pos);
             }
+
+            // Edits might have arrived while the chunk was on its way
+            let shared = &mut *shared;
+            shared.edits.chunk_added(pos, &mut shared.world);
+            self.refresh_chunk(shared, pos);
         }
 
         if changed {
@@ -202,57 +199,118 @@ impl WorldManager {
         }
     }
 
-    pub fn recalculate_chunk(&mut self, pos: AxialPoint) {
-        use std::ops::DerefMut;
+    /// Called when the server sent a chunk. Chunks we already have are
+    /// replaced right away, all others are returned, so that they can be
+    /// passed to the chunk provider.
+    pub fn chunk_received(&self, index: ChunkIndex, version: u32, chunk: Chunk) -> Option<Chunk> {
+        let mut shared = self.shared.borrow_mut();
+        let shared = &mut *shared;
+        shared.edits.chunk_received(index, version);
+        if !shared.world.chunks.contains_key(&index) {
+            return Some(chunk);
+        }
 
-        let mut shared_tmp = self.shared.borrow_mut();
-        let shared = shared_tmp.deref_mut();
+        shared.world.replace_chunk(index, chunk);
+        shared.edits.chunk_added(index, &mut shared.world);
+        self.refresh_chunk(shared, index);
+        None
+    }
 
-        let chunk_pos = AxialPoint::new(pos.q / CHUNK_SIZE as i32, pos.r / CHUNK_SIZE as i32);
-        let index = ChunkIndex(chunk_pos);
+    /// Applies our own edit to the world. Returns the id the edit has to be
+    /// sent to the server with.
+    pub fn edit(&self, edit: PillarEdit) -> Result<u32, EditError> {
+        let mut shared = self.shared.borrow_mut();
+        let shared = &mut *shared;
+        let id = shared.edits.edit(edit, &mut shared.world)?;
+        self.refresh_chunk(shared, edit.chunk());
+        Ok(id)
+    }
 
-        shared.world_view.refresh_chunk(
-            index,
-            shared.world.chunk_at(index).unwrap(),
-            self.context.get_facade(),
-        );
+    /// Applies an edit the server broadcast. Returns the chunk which has to
+    /// be requested again if we missed edits of it.
+    pub fn server_edit(
+        &self,
+        version: u32,
+        edit: PillarEdit,
+        origin: Option<u32>,
+    ) -> Option<ChunkIndex> {
+        let mut shared = self.shared.borrow_mut();
+        let shared = &mut *shared;
+        match shared
+            .edits
+            .server_edit(version, edit, origin, &mut shared.world)
+        {
+            EditUpdate::Unchanged => None,
+            EditUpdate::Changed(index) => {
+                self.refresh_chunk(shared, index);
+                None
+            }
+            EditUpdate::Resync(index) => Some(index),
+        }
+    }
+
+    /// Reverts our edit the server rejected.
+    pub fn edit_rejected(&self, id: u32) {
+        let mut shared = self.shared.borrow_mut();
+        let shared = &mut *shared;
+        if let Some(index) = shared.edits.rejected(id, &mut shared.world) {
+            self.refresh_chunk(shared, index);
+        }
+    }
+
+    /// Rebuilds the view of the given chunk.
+    fn refresh_chunk(&self, shared: &mut Shared, index: ChunkIndex) {
+        if let Some(chunk) = shared.world.chunks.get(&index) {
+            shared
+                .world_view
+                .refresh_chunk(index, chunk, self.context.get_facade());
+        }
     }
 }
 
 fn worker_thread(
     provider: Box<dyn ChunkProvider>,
     commands: Receiver<ChunkIndex>,
-    chunks: Sender<(ChunkIndex, Chunk)>,
+    chunks: Sender<(ChunkIndex, Option<Chunk>)>,
 ) {
+    // Requests arrive nearest first, and are handled in that order
+    let mut queue = VecDeque::new();
     loop {
-        let requested_chunk = match commands.recv() {
-            Err(_) => {
-                // The other side has hung up, so we can stop working, too.
-                break;
+        if queue.is_empty() {
+            match commands.recv() {
+                Err(_) => {
+                    // The other side has hung up, so we can stop working, too.
+                    break;
+                }
+                Ok(index) => queue.push_back(index),
             }
-            Ok(index) => index,
-        };
+        }
+        queue.extend(commands.try_iter());
+
+        // Lets the provider fetch the next chunks while we wait for the
+        // first one
+        provider.prefetch_chunks(queue.make_contiguous());
+        let requested_chunk = queue.pop_front().unwrap();
 
         debug!(
             "chunk provider thread: received request to generate chunk {:?}",
             requested_chunk.0
         );
 
-        match provider.load_chunk(requested_chunk) {
-            Some(chunk) => {
-                debug!(
-                    "chunk provider thread: chunk at {:?} successfully loaded",
-                    requested_chunk
-                );
-                chunks
-                    .send((requested_chunk, chunk))
-                    .expect("main thread has hung up");
-            }
+        let chunk = provider.load_chunk(requested_chunk);
+        match chunk {
+            Some(_) => debug!(
+                "chunk provider thread: chunk at {:?} successfully loaded",
+                requested_chunk
+            ),
             None => warn!(
                 "chunk provider thread: failed to load chunk at {:?}",
                 requested_chunk
             ),
         }
+        chunks
+            .send((requested_chunk, chunk))
+            .expect("main thread has hung up");
     }
 
     info!("chunk provider thread: now stopping ...")
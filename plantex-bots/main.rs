@@ -0,0 +1,33 @@
+#[macro_use]
+extern crate log;
+extern crate env_logger;
+
+use log::LogLevelFilter;
+
+fn main() {
+    // Initialize logger (by default error, warning and info logs are shown)
+    env_logger::LogBuilder::new()
+        .filter(None, LogLevelFilter::Info)
+        .parse(&std::env::var("RUST_LOG").unwrap_or_default())
+        .init()
+        .expect("logger initialization failed");
+
+    let config = match bots::BotConfig::load_config() {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    info!(
+        "running {} bots against {} for {:?}",
+        config.bots, config.server, config.duration
+    );
+    let report = bots::run_bots(&config);
+    println!("{}", report);
+
+    if report.logged_in() < report.bots.len() || !report.disconnects().is_empty() {
+        std::process::exit(2);
+    }
+}
@@ -0,0 +1,20 @@
+extern crate env_logger;
+
+use log::LogLevelFilter;
+
+fn main() {
+    // Initialize logger (by default error, warning and info logs are shown)
+    env_logger::LogBuilder::new()
+        .filter(None, LogLevelFilter::Info)
+        .parse(&std::env::var("RUST_LOG").unwrap_or_default())
+        .init()
+        .expect("logger initialization failed");
+
+    match client::export::run_export_tool() {
+        Ok(output) => println!("{}", output),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -4,6 +4,7 @@ extern crate log;
 extern crate env_logger;
 
 use log::LogLevelFilter;
+use std::io::{self, Write};
 use std::net::TcpListener;
 
 fn main() {
@@ -14,8 +15,25 @@ fn main() {
         .init()
         .expect("logger initialization failed");
 
-    let listener = TcpListener::bind("0.0.0.0:0").unwrap();
+    let config = match server::Config::load_config() {
+        Ok(v) => v,
+        Err(e) => {
+            writeln!(io::stderr(), "{}", e).unwrap();
+            std::process::exit(1);
+        }
+    };
+
+    let listener = match TcpListener::bind(config.socket_addr()) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("can't listen on {}: {}", config.socket_addr(), e);
+            std::process::exit(1);
+        }
+    };
     info!("listening on {}", listener.local_addr().unwrap());
 
-    server::start_server(listener).unwrap();
+    if let Err(e) = server::start_server(listener, config) {
+        error!("server failed: {}", e);
+        std::process::exit(1);
+    }
 }
@@ -16,11 +16,6 @@ fn main() {
         .init()
         .expect("logger initialization failed");
 
-    info!("Launching local server");
-    let addr = server::start_local_server();
-
-    info!("~~~~~~~~~~ Plantex started ~~~~~~~~~~");
-
     let conf = match client::Config::load_config() {
         Ok(v) => v,
         Err(e) => {
@@ -29,8 +24,45 @@ fn main() {
         }
     };
 
+    if conf.discover {
+        list_servers();
+        return;
+    }
+
+    let local_server = match conf.server {
+        Some(_) => None,
+        None => {
+            info!("Launching local server");
+            let local_server = server::start_local_server(server::Config {
+                seed: conf.seed,
+                // The player may control their own world
+                admins: vec![conf.player_name.clone()],
+                lan_discovery: false,
+                ..server::Config::default()
+            });
+            match local_server {
+                Ok(local_server) => Some(local_server),
+                Err(e) => {
+                    eprintln!("Starting the local server failed: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    };
+    let addr = match local_server {
+        Some(ref local_server) => local_server.addr(),
+        None => conf.server.unwrap(),
+    };
+
+    info!("~~~~~~~~~~ Plantex started ~~~~~~~~~~");
+
     let res = client::start_game(conf, addr);
 
+    // The local world is saved before quitting
+    if let Some(local_server) = local_server {
+        local_server.stop();
+    }
+
     // Check if any error occured
     if res.is_err() {
         // Maybe the user disabled all logs, so we mention that the logs
@@ -43,3 +75,36 @@ fn main() {
         std::process::exit(1);
     }
 }
+
+/// Prints the servers in the local network.
+fn list_servers() {
+    let servers = match client::discover_servers() {
+        Ok(servers) => servers,
+        Err(e) => {
+            eprintln!("Looking for servers failed: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if servers.is_empty() {
+        println!("No servers found");
+    }
+    for server in servers {
+        let status = server.status;
+        let compatible = if status.version == base::net::PROTOCOL_VERSION {
+            ""
+        } else {
+            " [incompatible version]"
+        };
+        println!(
+            "{}  {} ({}/{} players, seed {}){}\n    {}",
+            server.addr,
+            status.name,
+            status.players,
+            status.max_players,
+            status.seed,
+            compatible,
+            status.motd
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use base::math::*;
+use base::world::ChunkIndex;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+/// How much more chunks behind the player are delayed than chunks in front
+/// of them. With 1, a chunk behind the player is treated as if it was twice
+/// as far away.
+const VIEW_DIRECTION_WEIGHT: f32 = 1.0;
+
+/// Counts what happened to the chunks of a queue.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ChunkQueueStats {
+    /// Chunks added to the queue.
+    pub queued: u64,
+    /// Chunks sent to the player.
+    pub sent: u64,
+    /// Chunks removed from the queue before they were sent, because the
+    /// player left them behind.
+    pub dropped: u64,
+}
+
+impl ChunkQueueStats {
+    /// Adds the counters of `other` to these.
+    pub fn add(&mut self, other: ChunkQueueStats) {
+        self.queued += other.queued;
+        self.sent += other.sent;
+        self.dropped += other.dropped;
+    }
+}
+
+/// The chunks waiting to be sent to a single player.
+///
+/// Sending all chunks around a player at once, like after joining, would
+/// fill the connection for seconds and delay everything else. Instead, a
+/// limited number of bytes is sent every tick, starting with the chunks
+/// closest to the player and in front of them.
+///
+/// The queue belongs to the connection, so a player who reconnects gets all
+/// chunks they request again.
+pub struct ChunkQueue {
+    queued: HashSet<ChunkIndex>,
+    /// The frame which didn't fit into the bytes of the last tick, with its
+    /// chunk and the chunk's version, so that it isn't encoded again.
+    held: Option<(ChunkIndex, u32, Vec<u8>)>,
+    stats: ChunkQueueStats,
+}
+
+impl ChunkQueue {
+    pub fn new() -> Self {
+        ChunkQueue {
+            queued: HashSet::new(),
+            held: None,
+            stats: ChunkQueueStats::default(),
+        }
+    }
+
+    /// Adds the chunk to the queue, if it's not already queued.
+    pub fn push(&mut self, index: ChunkIndex) {
+        if self.queued.insert(index) {
+            self.stats.queued += 1;
+        }
+    }
+
+    pub fn contains(&self, index: ChunkIndex) -> bool {
+        self.queued.contains(&index)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queued.is_empty()
+    }
+
+    /// Removes the chunk from the queue without sending it.
+    pub fn drop_chunk(&mut self, index: ChunkIndex) {
+        if self.queued.remove(&index) {
+            self.stats.dropped += 1;
+        }
+        if self.held.as_ref().map_or(false, |held| held.0 == index) {
+            self.held = None;
+        }
+    }
+
+    pub fn stats(&self) -> ChunkQueueStats {
+        self.stats
+    }
+
+    /// Takes the most important chunks for a player at `pos` looking into the
+    /// direction `phi` (see `Controls`) from the queue and returns their
+    /// frames.
+    ///
+    /// `version` returns the version of a chunk, or `None` if it's not loaded
+    /// yet, in which case it stays queued. `encode` returns the encoded frame
+    /// of a loaded chunk. Frames are taken until they are `max_bytes` long in
+    /// total, but at least one frame is taken, so that large chunks aren't
+    /// stuck forever. The frame which doesn't fit anymore is kept for the next
+    /// call, as long as its chunk isn't changed.
+    pub fn take_frames<V, E>(
+        &mut self,
+        pos: Point3f,
+        phi: f32,
+        max_bytes: usize,
+        mut version: V,
+        mut encode: E,
+    ) -> Vec<Vec<u8>>
+    where
+        V: FnMut(ChunkIndex) -> Option<u32>,
+        E: FnMut(ChunkIndex) -> Option<Vec<u8>>,
+    {
+        let mut frames = Vec::new();
+        let mut bytes = 0;
+        for index in self.by_priority(pos, phi) {
+            let version = match version(index) {
+                Some(version) => version,
+                None => continue,
+            };
+            let frame = match self.held.take() {
+                Some((held, held_version, frame)) if held == index && held_version == version => {
+                    frame
+                }
+                held => {
+                    self.held = held;
+                    match encode(index) {
+                        Some(frame) => frame,
+                        None => continue,
+                    }
+                }
+            };
+            if !frames.is_empty() && bytes + frame.len() > max_bytes {
+                self.held = Some((index, version, frame));
+                break;
+            }
+            bytes += frame.len();
+            frames.push(frame);
+            self.queued.remove(&index);
+            self.stats.sent += 1;
+        }
+        frames
+    }
+
+    /// Returns all queued chunks, the most important first.
+    fn by_priority(&self, pos: Point3f, phi: f32) -> Vec<ChunkIndex> {
+        let pos = Point2f::new(pos.x, pos.y);
+        let view = Vector2f::new(phi.cos(), phi.sin());
+        let mut chunks: Vec<_> = self
+            .queued
+            .iter()
+            .map(|&index| {
+                let offset = index.center() - pos;
+                let distance = offset.magnitude();
+                // -1 straight ahead, 1 straight behind
+                let behind = if distance > 0.0 {
+                    -offset.dot(view) / distance
+                } else {
+                    -1.0
+                };
+                let priority = distance * (1.0 + VIEW_DIRECTION_WEIGHT * (behind + 1.0) / 2.0);
+                (priority, index)
+            })
+            .collect();
+        chunks.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        chunks.into_iter().map(|(_, index)| index).collect()
+    }
+}
+
+#[test]
+fn close_chunks_in_front_are_sent_first() {
+    use std::f32::consts::PI;
+
+    let chunk = |q, r| ChunkIndex(AxialPoint::new(q, r));
+    let mut queue = ChunkQueue::new();
+    for &(q, r) in &[(3, 0), (-1, 0), (1, 0), (0, 0), (0, 0)] {
+        queue.push(chunk(q, r));
+    }
+    assert_eq!(queue.queued.len(), 4);
+
+    // Standing in the center of chunk (0, 0), chunks (1, 0) and (-1, 0) are
+    // equally far away. Looking along the x axis means looking at (1, 0).
+    let pos = chunk(0, 0).center();
+    let pos = Point3f::new(pos.x, pos.y, 50.0);
+    assert_eq!(
+        queue.by_priority(pos, 0.0),
+        vec![chunk(0, 0), chunk(1, 0), chunk(-1, 0), chunk(3, 0)]
+    );
+    assert_eq!(
+        queue.by_priority(pos, PI),
+        vec![chunk(0, 0), chunk(-1, 0), chunk(1, 0), chunk(3, 0)]
+    );
+
+    let frame = |index: ChunkIndex| Some(vec![index.0.q as u8; 10]);
+    let frames = queue.take_frames(pos, 0.0, 25, |_| Some(0), frame);
+    assert_eq!(frames, vec![vec![0; 10], vec![1; 10]]);
+
+    // Chunks which aren't loaded stay, at least one frame is always sent
+    let version = |index| if index == chunk(-1, 0) { None } else { Some(0) };
+    let frames = queue.take_frames(pos, 0.0, 5, version, |_| Some(vec![3; 10]));
+    assert_eq!(frames, vec![vec![3; 10]]);
+    assert!(queue.contains(chunk(-1, 0)));
+
+    queue.drop_chunk(chunk(-1, 0));
+    queue.drop_chunk(chunk(-1, 0));
+    assert!(queue.is_empty());
+    assert_eq!(
+        queue.stats(),
+        ChunkQueueStats {
+            queued: 4,
+            sent: 3,
+            dropped: 1,
+        }
+    );
+}
+
+#[test]
+fn frames_over_the_budget_are_encoded_once() {
+    let chunk = |q, r| ChunkIndex(AxialPoint::new(q, r));
+    let mut queue = ChunkQueue::new();
+    queue.push(chunk(0, 0));
+    queue.push(chunk(1, 0));
+    queue.push(chunk(2, 0));
+    let pos = chunk(0, 0).center();
+    let pos = Point3f::new(pos.x, pos.y, 50.0);
+
+    let mut encoded = Vec::new();
+    let mut take = |queue: &mut ChunkQueue, version| {
+        queue.take_frames(
+            pos,
+            0.0,
+            10,
+            |_| Some(version),
+            |index| {
+                encoded.push(index);
+                Some(vec![version as u8; 10])
+            },
+        )
+    };
+    assert_eq!(take(&mut queue, 0), vec![vec![0; 10]]);
+    assert_eq!(take(&mut queue, 0), vec![vec![0; 10]]);
+    // The held frame is outdated after an edit
+    assert_eq!(take(&mut queue, 1), vec![vec![1; 10]]);
+    assert!(queue.is_empty());
+    drop(take);
+    assert_eq!(
+        encoded,
+        vec![chunk(0, 0), chunk(1, 0), chunk(2, 0), chunk(2, 0)]
+    );
+}
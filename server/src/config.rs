@@ -0,0 +1,659 @@
+extern crate clap;
+extern crate toml;
+
+use self::clap::{App, Arg, ArgMatches};
+use self::toml::Value;
+use base::net::{DISCOVERY_PORT, MAX_STATUS_TEXT_LEN};
+use std::error::Error as StdError;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+
+/// Port the server listens on by default.
+pub const DEFAULT_PORT: u16 = 34255;
+
+/// Name of the config file which is read if no other file is given.
+const DEFAULT_CONFIG_FILE: &str = "server.toml";
+
+/// The tick rate has to be between 1 and this.
+const MAX_TICK_RATE: u32 = 1000;
+
+/// Settings of a server.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Address the server listens on.
+    pub address: IpAddr,
+    /// Port the server listens on. With 0 a random free port is used.
+    pub port: u16,
+    /// Whether the server answers status queries of clients looking for
+    /// servers in the local network.
+    pub lan_discovery: bool,
+    /// UDP port status queries are answered on.
+    pub discovery_port: u16,
+    /// Seed the world is generated from.
+    pub seed: u64,
+    /// Directory the world is saved in.
+    pub save_dir: PathBuf,
+    /// Changed chunks are saved every this many seconds. With 0 they are
+    /// only saved when they are unloaded and when the server stops.
+    pub autosave_interval: u64,
+    /// Whether a world generated by an older world generator is opened
+    /// anyway. Only set on the command line, since it's a one-time decision.
+    pub upgrade_world: bool,
+    /// Players trying to join a full server are rejected.
+    pub max_players: usize,
+    /// Every player is sent at most this many bytes of chunks per tick,
+    /// unless a single chunk is larger.
+    pub chunk_bytes_per_tick: usize,
+    /// Players only receive chunks within this many chunk lengths around
+    /// them.
+    pub view_radius: f32,
+    /// Number of server ticks per second.
+    pub tick_rate: u32,
+    /// Name shown to players joining the server.
+    pub server_name: String,
+    /// Message of the day, sent to every player who joins.
+    pub motd: String,
+    /// Names of the players allowed to run admin commands, once they sent
+    /// the admin password.
+    pub admins: Vec<String>,
+    /// Password admins have to send before they may run admin commands. If
+    /// it's empty, admin commands can only be typed into the console.
+    pub admin_password: String,
+    /// Whether all players may fly. Admins always may.
+    pub allow_flying: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            address: IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
+            port: DEFAULT_PORT,
+            lan_discovery: true,
+            discovery_port: DISCOVERY_PORT,
+            seed: 42,
+            save_dir: PathBuf::from("world"),
+            autosave_interval: 300,
+            upgrade_world: false,
+            max_players: 32,
+            // About 1 MiB/s at 60 ticks per second
+            chunk_bytes_per_tick: 16 * 1024,
+            // Clients load all chunks within this radius
+            view_radius: 12.0,
+            tick_rate: 60,
+            server_name: "Plantex Server".to_string(),
+            motd: "Welcome to Plantex!".to_string(),
+            admins: Vec::new(),
+            admin_password: String::new(),
+            allow_flying: false,
+        }
+    }
+}
+
+impl Config {
+    /// Creates the config in three steps, like the client does:
+    /// 1. loads the default config
+    /// 2. overrides it from the TOML config file
+    /// 3. overrides it from the command line
+    pub fn load_config() -> Result<Config, Box<dyn StdError>> {
+        let matches = app().get_matches();
+
+        if matches.is_present("Write_Config_File") {
+            if Path::new(DEFAULT_CONFIG_FILE).exists() {
+                return Err(format!("{} already exists", DEFAULT_CONFIG_FILE).into());
+            }
+            let mut f = File::create(DEFAULT_CONFIG_FILE)?;
+            f.write_all(Config::default().to_toml().as_bytes())?;
+        }
+
+        let t_conf = config_toml(Config::default(), &matches)?;
+        let conf_final = config_command(t_conf, &matches)?;
+
+        debug!("final config: {:?}", conf_final);
+        Ok(conf_final)
+    }
+
+    /// Returns the address the server listens on.
+    pub fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.address, self.port)
+    }
+
+    /// Returns the config in the format of the config file.
+    fn to_toml(&self) -> String {
+        format!(
+            r#"[Network]
+address = "{}"
+port = {}
+max_players = {}
+chunk_bytes_per_tick = {}
+lan_discovery = {}
+discovery_port = {}
+
+[World]
+seed = {}
+save_dir = {:?}
+autosave_interval = {}
+view_radius = {:?}
+
+[Server]
+name = {:?}
+motd = {:?}
+tick_rate = {}
+admins = {:?}
+admin_password = {:?}
+allow_flying = {}
+"#,
+            self.address,
+            self.port,
+            self.max_players,
+            self.chunk_bytes_per_tick,
+            self.lan_discovery,
+            self.discovery_port,
+            self.seed,
+            self.save_dir.display().to_string(),
+            self.autosave_interval,
+            self.view_radius,
+            self.server_name,
+            self.motd,
+            self.tick_rate,
+            self.admins,
+            self.admin_password,
+            self.allow_flying
+        )
+    }
+}
+
+fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new("Plantex Server")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Dedicated server for Plantex")
+        .arg(
+            Arg::with_name("Address")
+                .help("'IP address to listen on'")
+                .takes_value(true)
+                .long("address"),
+        )
+        .arg(
+            Arg::with_name("Port")
+                .help("'Port to listen on, 0 for a random port'")
+                .takes_value(true)
+                .long("port"),
+        )
+        .arg(
+            Arg::with_name("DiscoveryPort")
+                .help("'UDP port to answer status queries on'")
+                .takes_value(true)
+                .long("discovery-port"),
+        )
+        .arg(
+            Arg::with_name("NoDiscovery")
+                .help("'Disables the LAN discovery'")
+                .long("no-discovery"),
+        )
+        .arg(
+            Arg::with_name("Seed")
+                .help("'Takes a specified seed to generate map'")
+                .takes_value(true)
+                .long("seed"),
+        )
+        .arg(
+            Arg::with_name("SaveDir")
+                .help("'Directory the world is saved in'")
+                .takes_value(true)
+                .long("save-dir"),
+        )
+        .arg(
+            Arg::with_name("AutosaveInterval")
+                .help("'Seconds between saves of changed chunks, 0 to disable'")
+                .takes_value(true)
+                .long("autosave-interval"),
+        )
+        .arg(
+            Arg::with_name("UpgradeWorld")
+                .help("'Opens a world generated by an older version of the game'")
+                .long("upgrade-world"),
+        )
+        .arg(
+            Arg::with_name("MaxPlayers")
+                .help("'Maximum number of players'")
+                .takes_value(true)
+                .long("max-players"),
+        )
+        .arg(
+            Arg::with_name("ChunkBytesPerTick")
+                .help("'Bytes of chunks sent to each player per tick at most'")
+                .takes_value(true)
+                .long("chunk-bytes-per-tick"),
+        )
+        .arg(
+            Arg::with_name("ViewRadius")
+                .help("'Radius around players in which chunks are sent, in chunks'")
+                .takes_value(true)
+                .long("view-radius"),
+        )
+        .arg(
+            Arg::with_name("TickRate")
+                .help("'Server ticks per second'")
+                .takes_value(true)
+                .long("tick-rate"),
+        )
+        .arg(
+            Arg::with_name("Name")
+                .help("'Name shown to players'")
+                .takes_value(true)
+                .long("name"),
+        )
+        .arg(
+            Arg::with_name("Motd")
+                .help("'Message of the day'")
+                .takes_value(true)
+                .long("motd"),
+        )
+        .arg(
+            Arg::with_name("Admin")
+                .help("'Name of a player allowed to run admin commands'")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .long("admin"),
+        )
+        .arg(
+            Arg::with_name("AdminPassword")
+                .help("'Password admins have to send before running admin commands'")
+                .takes_value(true)
+                .long("admin-password"),
+        )
+        .arg(
+            Arg::with_name("AllowFlying")
+                .help("'Lets all players fly, not only admins'")
+                .long("allow-flying"),
+        )
+        .arg(
+            Arg::with_name("File")
+                .help("Takes config file")
+                .takes_value(true)
+                .long("config-file"),
+        )
+        .arg(
+            Arg::with_name("Write_Config_File")
+                .help("'Writes Config File with default values'")
+                .long("write-config"),
+        )
+}
+
+/// read configuration from toml-file
+/// overwrite default values with toml-file values
+/// return updated config
+fn config_toml(default_config: Config, matches: &ArgMatches) -> Result<Config, Box<dyn StdError>> {
+    // default name for toml-file
+    let mut name = DEFAULT_CONFIG_FILE;
+
+    // if user defined another toml-file, try to read from that
+    if let Some(file) = matches.value_of("File") {
+        let is_toml = Path::new(file)
+            .extension()
+            .map_or(false, |ext| ext == "toml");
+        if is_toml && Path::new(file).exists() {
+            name = file;
+        } else {
+            return Err("invalid File in command line".into());
+        }
+    }
+
+    // only proceed if toml-file exists
+    if !Path::new(name).exists() {
+        return Ok(default_config);
+    }
+
+    let mut f = File::open(name)?;
+    let mut s = String::new();
+    f.read_to_string(&mut s)?;
+    parse_toml(default_config, &s)
+}
+
+/// Overwrites all values of the config which are given in the TOML string.
+/// Values missing in the string are kept.
+fn parse_toml(mut config: Config, s: &str) -> Result<Config, Box<dyn StdError>> {
+    let value: Value = match s.parse() {
+        Ok(n) => n,
+        _ => return Err("corrupted config file".into()),
+    };
+
+    // listen address
+    if let Some(address) = value.lookup("Network.address") {
+        match address.as_str().map(|a| a.parse()) {
+            Some(Ok(n)) => config.address = n,
+            _ => return Err("address in config file is invalid".into()),
+        }
+    }
+
+    // port
+    if let Some(port) = value.lookup("Network.port") {
+        match port.as_integer() {
+            Some(n) if n >= 0 && n <= i64::from(u16::max_value()) => config.port = n as u16,
+            _ => return Err("port in config file is invalid".into()),
+        }
+    }
+
+    // LAN discovery
+    if let Some(lan_discovery) = value.lookup("Network.lan_discovery") {
+        match lan_discovery.as_bool() {
+            Some(n) => config.lan_discovery = n,
+            None => return Err("lan_discovery in config file is invalid".into()),
+        }
+    }
+
+    // discovery port
+    if let Some(port) = value.lookup("Network.discovery_port") {
+        match port.as_integer() {
+            Some(n) if n >= 0 && n <= i64::from(u16::max_value()) => {
+                config.discovery_port = n as u16
+            }
+            _ => return Err("discovery_port in config file is invalid".into()),
+        }
+    }
+
+    // maximum number of players
+    if let Some(max_players) = value.lookup("Network.max_players") {
+        match max_players.as_integer() {
+            Some(n) if n >= 1 => config.max_players = n as usize,
+            Some(_) => return Err("max_players has to be at least 1".into()),
+            None => return Err("max_players in config file is invalid".into()),
+        }
+    }
+
+    // chunk bandwidth
+    if let Some(bytes) = value.lookup("Network.chunk_bytes_per_tick") {
+        match bytes.as_integer() {
+            Some(n) if n >= 1 => config.chunk_bytes_per_tick = n as usize,
+            _ => return Err("chunk_bytes_per_tick in config file is invalid".into()),
+        }
+    }
+
+    // world seed
+    if let Some(seed) = value.lookup("World.seed") {
+        match seed.as_integer() {
+            Some(n) if n >= 0 => config.seed = n as u64,
+            Some(_) => return Err("seed can not be negative".into()),
+            None => return Err("seed in config file is invalid".into()),
+        }
+    }
+
+    // save directory
+    if let Some(save_dir) = value.lookup("World.save_dir") {
+        match save_dir.as_str() {
+            Some(n) if !n.trim().is_empty() => config.save_dir = PathBuf::from(n),
+            _ => return Err("save_dir in config file is invalid".into()),
+        }
+    }
+
+    // autosave interval
+    if let Some(interval) = value.lookup("World.autosave_interval") {
+        match interval.as_integer() {
+            Some(n) if n >= 0 => config.autosave_interval = n as u64,
+            _ => return Err("autosave_interval in config file is invalid".into()),
+        }
+    }
+
+    // view radius
+    if let Some(view_radius) = value.lookup("World.view_radius") {
+        match view_radius.as_float() {
+            Some(n) if n > 0.0 => config.view_radius = n as f32,
+            Some(_) => return Err("view_radius has to be positive".into()),
+            None => return Err("view_radius in config file is invalid".into()),
+        }
+    }
+
+    // server name
+    if let Some(name) = value.lookup("Server.name") {
+        match name.as_str() {
+            Some(n) if n.len() > MAX_STATUS_TEXT_LEN => {
+                return Err("name in config file is too long".into())
+            }
+            Some(n) if !n.trim().is_empty() => config.server_name = n.to_string(),
+            _ => return Err("name in config file is invalid".into()),
+        }
+    }
+
+    // message of the day, may be empty
+    if let Some(motd) = value.lookup("Server.motd") {
+        match motd.as_str() {
+            Some(n) if n.len() > MAX_STATUS_TEXT_LEN => {
+                return Err("motd in config file is too long".into())
+            }
+            Some(n) => config.motd = n.to_string(),
+            None => return Err("motd in config file is invalid".into()),
+        }
+    }
+
+    // tick rate
+    if let Some(tick_rate) = value.lookup("Server.tick_rate") {
+        match tick_rate.as_integer() {
+            Some(n) if n >= 1 && n <= i64::from(MAX_TICK_RATE) => config.tick_rate = n as u32,
+            _ => return Err("tick_rate in config file is invalid".into()),
+        }
+    }
+
+    // names of the admins
+    if let Some(admins) = value.lookup("Server.admins") {
+        let names = admins
+            .as_slice()
+            .map(|a| a.iter().map(|n| n.as_str()).collect::<Option<Vec<_>>>());
+        match names {
+            Some(Some(names)) => config.admins = names.iter().map(|n| n.to_string()).collect(),
+            _ => return Err("admins in config file are invalid".into()),
+        }
+    }
+
+    // password of the admins
+    if let Some(password) = value.lookup("Server.admin_password") {
+        match password.as_str() {
+            Some(password) => config.admin_password = password.to_string(),
+            None => return Err("admin_password in config file is invalid".into()),
+        }
+    }
+
+    // flying
+    if let Some(allow_flying) = value.lookup("Server.allow_flying") {
+        match allow_flying.as_bool() {
+            Some(n) => config.allow_flying = n,
+            None => return Err("allow_flying in config file is invalid".into()),
+        }
+    }
+
+    Ok(config)
+}
+
+/// read configuration from command line
+/// overwrite config values with command line values
+/// return updated config
+fn config_command(
+    mut toml_config: Config,
+    matches: &ArgMatches,
+) -> Result<Config, Box<dyn StdError>> {
+    // listen address
+    if let Some(address) = matches.value_of("Address") {
+        match address.parse() {
+            Ok(n) => toml_config.address = n,
+            _ => return Err("Address from command line is invalid".into()),
+        }
+    }
+
+    // port
+    if let Some(port) = matches.value_of("Port") {
+        match port.parse() {
+            Ok(n) => toml_config.port = n,
+            _ => return Err("Port from command line is invalid".into()),
+        }
+    }
+
+    // LAN discovery
+    if let Some(port) = matches.value_of("DiscoveryPort") {
+        match port.parse() {
+            Ok(n) => toml_config.discovery_port = n,
+            _ => return Err("Discovery port from command line is invalid".into()),
+        }
+    }
+    if matches.is_present("NoDiscovery") {
+        toml_config.lan_discovery = false;
+    }
+
+    // world seed
+    if let Some(seed) = matches.value_of("Seed") {
+        match seed.parse::<u64>() {
+            Ok(n) => toml_config.seed = n,
+            _ => return Err("Seed from command line is invalid".into()),
+        }
+    }
+
+    // save directory
+    if let Some(save_dir) = matches.value_of("SaveDir") {
+        if save_dir.trim().is_empty() {
+            return Err("Save directory from command line is empty".into());
+        }
+        toml_config.save_dir = PathBuf::from(save_dir);
+    }
+
+    // autosave interval
+    if let Some(interval) = matches.value_of("AutosaveInterval") {
+        match interval.parse::<u64>() {
+            Ok(n) => toml_config.autosave_interval = n,
+            _ => return Err("Autosave interval from command line is invalid".into()),
+        }
+    }
+
+    if matches.is_present("UpgradeWorld") {
+        toml_config.upgrade_world = true;
+    }
+
+    // maximum number of players
+    if let Some(max_players) = matches.value_of("MaxPlayers") {
+        match max_players.parse::<usize>() {
+            Ok(n) if n >= 1 => toml_config.max_players = n,
+            _ => return Err("Maximum number of players from command line is invalid".into()),
+        }
+    }
+
+    // chunk bandwidth
+    if let Some(bytes) = matches.value_of("ChunkBytesPerTick") {
+        match bytes.parse::<usize>() {
+            Ok(n) if n >= 1 => toml_config.chunk_bytes_per_tick = n,
+            _ => return Err("Chunk bytes per tick from command line is invalid".into()),
+        }
+    }
+
+    // view radius
+    if let Some(view_radius) = matches.value_of("ViewRadius") {
+        match view_radius.parse::<f32>() {
+            Ok(n) if n > 0.0 && n.is_finite() => toml_config.view_radius = n,
+            _ => return Err("View radius from command line is invalid".into()),
+        }
+    }
+
+    // tick rate
+    if let Some(tick_rate) = matches.value_of("TickRate") {
+        match tick_rate.parse::<u32>() {
+            Ok(n) if n >= 1 && n <= MAX_TICK_RATE => toml_config.tick_rate = n,
+            _ => return Err("Tick rate from command line is invalid".into()),
+        }
+    }
+
+    // server name
+    if let Some(name) = matches.value_of("Name") {
+        if name.trim().is_empty() {
+            return Err("Server name from command line is empty".into());
+        }
+        if name.len() > MAX_STATUS_TEXT_LEN {
+            return Err("Server name from command line is too long".into());
+        }
+        toml_config.server_name = name.to_string();
+    }
+
+    // message of the day
+    if let Some(motd) = matches.value_of("Motd") {
+        if motd.len() > MAX_STATUS_TEXT_LEN {
+            return Err("Message of the day from command line is too long".into());
+        }
+        toml_config.motd = motd.to_string();
+    }
+
+    // additional admins
+    if let Some(admins) = matches.values_of("Admin") {
+        toml_config.admins.extend(admins.map(|a| a.to_string()));
+    }
+    if let Some(password) = matches.value_of("AdminPassword") {
+        toml_config.admin_password = password.to_string();
+    }
+    if matches.is_present("AllowFlying") {
+        toml_config.allow_flying = true;
+    }
+
+    Ok(toml_config)
+}
+
+#[test]
+fn written_config_is_read_back() {
+    let config = Config {
+        port: 0,
+        lan_discovery: false,
+        discovery_port: 4000,
+        seed: 7,
+        save_dir: PathBuf::from("saves/my \"world\""),
+        autosave_interval: 0,
+        view_radius: 4.5,
+        chunk_bytes_per_tick: 1000,
+        motd: "".to_string(),
+        admins: vec!["Lukas".to_string(), "\"quoted\"".to_string()],
+        admin_password: "open \"sesame\"".to_string(),
+        allow_flying: true,
+        ..Config::default()
+    };
+    let read = parse_toml(Config::default(), &config.to_toml()).unwrap();
+    assert_eq!(format!("{:?}", read), format!("{:?}", config));
+
+    // Missing values are kept, invalid ones rejected
+    let partial = parse_toml(config.clone(), "[Server]\ntick_rate = 30").unwrap();
+    assert_eq!(partial.tick_rate, 30);
+    assert_eq!(partial.seed, 7);
+    assert!(parse_toml(Config::default(), "[Network]\nport = 70000").is_err());
+    assert!(parse_toml(Config::default(), "[Network]\naddress = \"nope\"").is_err());
+    assert!(parse_toml(Config::default(), "[World]\nview_radius = -1.0").is_err());
+    let motd = format!(
+        "[Server]\nmotd = \"{}\"",
+        "x".repeat(MAX_STATUS_TEXT_LEN + 1)
+    );
+    assert!(parse_toml(Config::default(), &motd).is_err());
+}
+
+#[test]
+fn command_line_overrides_config() {
+    let matches = app().get_matches_from(vec![
+        "plantex-server",
+        "--address",
+        "127.0.0.1",
+        "--port",
+        "4000",
+        "--max-players",
+        "3",
+        "--motd",
+        "hi",
+        "--admin",
+        "a",
+        "--admin",
+        "b",
+        "--admin-password",
+        "secret",
+        "--allow-flying",
+    ]);
+    let config = config_command(Config::default(), &matches).unwrap();
+    assert_eq!(config.socket_addr(), "127.0.0.1:4000".parse().unwrap());
+    assert_eq!(config.max_players, 3);
+    assert_eq!(config.motd, "hi");
+    assert_eq!(config.admins, vec!["a".to_string(), "b".to_string()]);
+    assert_eq!(config.admin_password, "secret");
+    assert!(config.allow_flying);
+
+    let matches = app().get_matches_from(vec!["plantex-server", "--tick-rate", "0"]);
+    assert!(config_command(Config::default(), &matches).is_err());
+}
@@ -0,0 +1,43 @@
+use base::net::Command;
+use std::io::{self, BufRead};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+/// Spawns a thread which reads admin commands from stdin, one per line, and
+/// forwards them to the returned channel. Invalid lines are answered right
+/// away.
+///
+/// The thread stops at the end of the input or once the receiver is dropped
+/// and the next command arrives.
+pub fn spawn_console() -> io::Result<Receiver<Command>> {
+    let (sender, recv) = channel();
+
+    thread::Builder::new()
+        .name("Console".to_string())
+        .spawn(move || {
+            let stdin = io::stdin();
+            for line in stdin.lock().lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(e) => {
+                        error!("can't read console input: {}", e);
+                        break;
+                    }
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                match Command::parse(&line) {
+                    Ok(command) => {
+                        if sender.send(command).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => println!("{} (type 'help' to list all commands)", e),
+                }
+            }
+        })?;
+
+    Ok(recv)
+}
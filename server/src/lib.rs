@@ -1,33 +1,79 @@
+extern crate base;
 #[macro_use]
 extern crate log;
 
+mod chunk_queue;
+mod config;
+mod console;
+mod rate_limit;
 mod server;
+mod slab;
+mod snapshot_tool;
+mod tick;
+mod world_manager;
 
+pub use config::{Config, DEFAULT_PORT};
+pub use snapshot_tool::run_snapshot_tool;
+
+use base::net::Command;
 use server::Server;
 use std::io;
 use std::net::{SocketAddr, TcpListener};
-use std::thread;
+use std::sync::mpsc::{channel, Sender};
+use std::thread::{self, JoinHandle};
 
-/// Starts a Plantex server listening for connections on the given
-/// `TcpListener`.
-pub fn start_server(listener: TcpListener) -> io::Result<()> {
+/// Starts a dedicated Plantex server listening for connections on the given
+/// `TcpListener`. Admin commands typed into stdin are run by the server.
+pub fn start_server(listener: TcpListener, config: Config) -> io::Result<()> {
     info!("starting server on {}", listener.local_addr()?);
 
-    let server = Server::new(listener);
+    let mut server = Server::new(listener, config)?;
+    server.set_console(console::spawn_console()?);
     server.run()
 }
 
-/// Starts a server in a different thread, returns the address to connect to.
-pub fn start_local_server() -> SocketAddr {
-    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
-    let addr = listener.local_addr().unwrap();
+/// A server running in a different thread of this process.
+pub struct LocalServer {
+    addr: SocketAddr,
+    commands: Sender<Command>,
+    thread: JoinHandle<()>,
+}
+
+impl LocalServer {
+    /// Returns the address to connect to.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Stops the server and waits until the world is saved.
+    pub fn stop(self) {
+        // The server only stops by itself if it failed
+        let _ = self.commands.send(Command::Stop);
+        if self.thread.join().is_err() {
+            error!("local server panicked");
+        }
+    }
+}
+
+/// Starts a server in a different thread.
+pub fn start_local_server(config: Config) -> io::Result<LocalServer> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let mut server = Server::new(listener, config)?;
+    let (commands, recv) = channel();
+    server.set_console(recv);
 
-    thread::Builder::new()
+    let thread = thread::Builder::new()
         .name("Plantex Local Server".to_string())
         .spawn(move || {
-            start_server(listener).unwrap();
-        })
-        .unwrap();
+            if let Err(e) = server.run() {
+                error!("local server failed: {}", e);
+            }
+        })?;
 
-    addr
+    Ok(LocalServer {
+        addr: addr,
+        commands: commands,
+        thread: thread,
+    })
 }
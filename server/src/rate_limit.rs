@@ -0,0 +1,86 @@
+use std::cmp;
+use std::time::Instant;
+
+/// Limits how often something may happen, allowing short bursts.
+///
+/// Every event costs one token. Tokens refill at a fixed rate up to the
+/// burst size.
+pub struct RateLimit {
+    /// Number of events allowed in a burst.
+    burst: f32,
+    /// Tokens refilled per second.
+    rate: f32,
+    tokens: f32,
+    last_update: Instant,
+}
+
+impl RateLimit {
+    pub fn new(burst: u32, rate: f32) -> Self {
+        RateLimit {
+            burst: burst as f32,
+            rate: rate,
+            tokens: burst as f32,
+            last_update: Instant::now(),
+        }
+    }
+
+    /// Returns whether the event happening at `now` is allowed.
+    pub fn allow(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Takes up to `amount` tokens at `now` and returns how many were left to
+    /// take.
+    pub fn take(&mut self, now: Instant, amount: f32) -> f32 {
+        self.refill(now);
+        let taken = amount.max(0.0).min(self.tokens);
+        self.tokens -= taken;
+        taken
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_update);
+        self.last_update = cmp::max(self.last_update, now);
+        self.tokens = (self.tokens + elapsed.as_secs_f32() * self.rate).min(self.burst);
+    }
+}
+
+#[test]
+fn bursts_are_allowed_and_tokens_refill() {
+    use std::time::Duration;
+
+    let mut limit = RateLimit::new(3, 2.0);
+    let start = Instant::now();
+
+    assert!((0..3).all(|_| limit.allow(start)));
+    assert!(!limit.allow(start));
+
+    // Half a second refills one token
+    let later = start + Duration::from_millis(500);
+    assert!(limit.allow(later));
+    assert!(!limit.allow(later));
+
+    // Tokens don't pile up beyond the burst size
+    let much_later = later + Duration::from_secs(60);
+    assert!((0..3).all(|_| limit.allow(much_later)));
+    assert!(!limit.allow(much_later));
+}
+
+#[test]
+fn partial_amounts_are_taken() {
+    use std::time::Duration;
+
+    let mut limit = RateLimit::new(1, 1.0);
+    let start = Instant::now();
+
+    assert_eq!(limit.take(start, 0.75), 0.75);
+    assert_eq!(limit.take(start, 0.5), 0.25);
+    assert_eq!(limit.take(start, 0.5), 0.0);
+    assert_eq!(limit.take(start + Duration::from_millis(500), 1.0), 0.5);
+}
@@ -1,12 +1,109 @@
+use base::math::*;
+use base::movement::{self, Body, MoveInput};
+use base::net::{encode_frame, PROTOCOL_VERSION};
+use base::net::{encode_status_reply, is_status_query, ServerStatus, TeleportTarget};
+use base::net::{Command, Connection, KnownPlayers, Message, PlayerState, Snapshot, Welcome};
+use base::net::{COMMAND_HELP, MAX_CHAT_LEN, MAX_MOVE_INPUTS};
+use base::time::{GameTime, DAY_LENGTH, MAX_TIME_SPEED};
+use base::weather::{WeatherModel, WeatherState};
+use base::world::{ChunkIndex, Level, PillarEdit, PillarIndex, RegionProvider, REGION_DIR};
+use base::world::{SnapshotInfo, Snapshots, WorldLock};
+use chunk_queue::{ChunkQueue, ChunkQueueStats};
+use config::Config;
+use rate_limit::RateLimit;
+use slab::{Slab, SlabId};
+use std::cmp;
+use std::collections::HashSet;
 use std::io;
-use std::net::{TcpListener, TcpStream};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
 use std::sync::mpsc::{channel, Receiver, TryRecvError};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tick::TickTimer;
+use world_manager::WorldManager;
+
+/// How often tick statistics are logged.
+const STATS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How often the time is sent to all players.
+const TIME_SYNC_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often players are pinged.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Players are disconnected if we don't receive anything from them for this
+/// long.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Player names may be at most this many characters long.
+const MAX_NAME_LEN: usize = 32;
+
+/// Number of player snapshots sent per second.
+const SNAPSHOT_RATE: u32 = 10;
+
+/// Players can only edit pillars at most this far away from them.
+const MAX_EDIT_DISTANCE: f32 = 16.0;
+
+/// Players may send this many chat messages and commands at once...
+const CHAT_BURST: u32 = 5;
+
+/// ...and one more every second.
+const CHAT_RATE: f32 = 1.0;
+
+/// The inputs of a player may simulate this many seconds more than passed
+/// since they joined, to make up for delays of the network.
+const INPUT_BURST: u32 = 2;
+
+/// At most this many status queries are answered per tick, the others wait.
+const MAX_STATUS_QUERIES_PER_TICK: usize = 16;
+
+/// Identifies a player for as long as they are connected.
+pub type PlayerId = SlabId;
 
 /// A player connected to the server.
 struct Player {
-    conn: TcpStream,
+    conn: Connection,
+    /// Messages received by the player's reader thread.
+    incoming: Receiver<io::Result<Message>>,
+    /// Whether the player already sent a valid `Login`.
+    logged_in: bool,
+    /// Name the player logged in with, empty before.
+    name: String,
+    /// Whether the player is one of the admins and sent the admin password.
+    is_admin: bool,
+    /// Position and orientation of the player.
+    state: PlayerState,
+    /// The player's body, moved by the inputs they send.
+    body: Body,
+    /// Sequence number of the last input we applied.
+    last_input: u32,
+    /// Limits the time simulated by the inputs of the player to the real
+    /// time passed, one token per second.
+    input_budget: RateLimit,
+    /// What the player knows about the other players.
+    known_players: KnownPlayers,
+    /// The weather we last sent the player.
+    weather: Option<WeatherState>,
+    /// Limits the chat messages and commands of the player.
+    chat_limit: RateLimit,
+    /// Chunks the player asked for. Edits of these chunks are sent to the
+    /// player.
+    subscribed_chunks: HashSet<ChunkIndex>,
+    /// Requested chunks which weren't sent yet. They are sent once they are
+    /// loaded, a few per tick.
+    chunk_queue: ChunkQueue,
+    /// When we received the last message from the player.
+    last_seen: Instant,
+    /// Set when the connection failed or was closed. The player is removed
+    /// at the end of the current tick.
+    disconnected: bool,
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        // Stops the reader thread
+        self.conn.shutdown();
+    }
 }
 
 pub struct Server {
@@ -14,12 +111,65 @@ pub struct Server {
     /// thread.
     connections: Receiver<TcpStream>,
     /// Currently connected players.
-    players: Vec<Player>,
+    players: Slab<Player>,
+    /// Port players connect to.
+    port: u16,
+    /// Answers status queries of clients looking for servers.
+    discovery: Option<UdpSocket>,
+    world_manager: WorldManager,
+    /// Keeps others from changing the saved world while the server uses it.
+    _world_lock: WorldLock,
+    /// The saved state of the world. Time, weather and the positions of the
+    /// players online are only updated when it is saved.
+    level: Level,
+    config: Config,
+    idle_timeout: Duration,
+    /// Real time simulated by a single tick.
+    tick_length: Duration,
+    /// Number of ticks run or skipped so far, the server's clock.
+    ticks: u64,
+    /// Snapshots are sent every this many ticks.
+    snapshot_interval: u64,
+    time: GameTime,
+    next_time_sync: Instant,
+    /// When changed chunks are saved next, `None` without autosaves.
+    next_autosave: Option<Instant>,
+    weather: WeatherModel,
+    /// Weather set by an admin, used everywhere instead of the weather
+    /// model.
+    weather_override: Option<WeatherState>,
+    /// Admin commands typed into the console.
+    console: Option<Receiver<Command>>,
+    /// Set by the `stop` command, stops the server at the end of the tick.
+    stopping: bool,
+    /// Chunk counters of all players who left.
+    chunk_stats: ChunkQueueStats,
+    /// Where new players start.
+    spawn: Point3f,
+    next_heartbeat: Instant,
+    /// Number sent with the next `Ping`.
+    ping_counter: u32,
 }
 
 impl Server {
-    pub fn new(listener: TcpListener) -> Self {
+    /// Creates a server for the world in the save directory of the config, or
+    /// for a new world if there is none.
+    pub fn new(listener: TcpListener, mut config: Config) -> io::Result<Self> {
+        let world_lock = WorldLock::acquire(&config.save_dir)?;
+        let level = Level::open_or_create(&config.save_dir, config.seed, config.upgrade_world)?;
+        if level.seed != config.seed {
+            info!("using the seed {} of the saved world", level.seed);
+            config.seed = level.seed;
+        }
+        let regions = RegionProvider::open(config.save_dir.join(REGION_DIR))?;
+        let next_autosave = autosave_interval(&config).map(|interval| Instant::now() + interval);
         let (sender, recv) = channel();
+        let port = listener.local_addr().map(|addr| addr.port()).unwrap_or(0);
+        let discovery = if config.lan_discovery {
+            bind_discovery(&config)
+        } else {
+            None
+        };
 
         thread::Builder::new()
             .name("TCP listener".to_string())
@@ -40,59 +190,2059 @@ impl Server {
             })
             .unwrap();
 
-        Server {
+        Ok(Server {
             connections: recv,
-            players: Vec::new(),
-        }
+            players: Slab::new(),
+            port: port,
+            discovery: discovery,
+            world_manager: WorldManager::new(config.seed, config.view_radius, regions),
+            weather: WeatherModel::with_seed(config.seed),
+            weather_override: level.weather,
+            console: None,
+            stopping: false,
+            chunk_stats: ChunkQueueStats::default(),
+            tick_length: Duration::from_secs(1) / config.tick_rate,
+            ticks: 0,
+            snapshot_interval: u64::from(cmp::max(1, config.tick_rate / SNAPSHOT_RATE)),
+            config: config,
+            idle_timeout: IDLE_TIMEOUT,
+            time: level.time,
+            level: level,
+            _world_lock: world_lock,
+            next_time_sync: Instant::now() + TIME_SYNC_INTERVAL,
+            next_autosave: next_autosave,
+            spawn: Point3f::new(15.0, 10.0, 50.0),
+            next_heartbeat: Instant::now() + HEARTBEAT_INTERVAL,
+            ping_counter: 0,
+        })
+    }
+
+    /// Runs the commands received from the given channel every tick.
+    pub fn set_console(&mut self, commands: Receiver<Command>) {
+        self.console = Some(commands);
     }
 
     /// Runs the server's main loop.
     pub fn run(mut self) -> io::Result<()> {
+        let mut timer = TickTimer::new(self.config.tick_rate);
+        let mut next_stats = Instant::now() + STATS_INTERVAL;
+
         loop {
-            loop {
-                match self.connections.try_recv() {
-                    Ok(stream) => {
-                        let id = self.players.len();
-                        self.players.push(Player { conn: stream });
-                        self.handle_new_player(id);
-                    }
-                    Err(TryRecvError::Empty) => break,
-                    Err(TryRecvError::Disconnected) => {
-                        info!("tcp listener thread exited, killing server");
-                        return Ok(());
-                    }
+            let skipped = timer.wait_for_tick();
+            if skipped > 0 {
+                self.skip_ticks(skipped);
+            }
+            let tick_start = Instant::now();
+
+            if !self.tick() {
+                break;
+            }
+
+            timer.end_tick(tick_start.elapsed());
+
+            if next_stats <= Instant::now() {
+                let stats = timer.take_stats();
+                debug!(
+                    "tick #{}: {} ticks, {:?} on average, {:?} max, {} overruns, {} skipped",
+                    timer.tick(),
+                    stats.ticks,
+                    stats.average_duration(),
+                    stats.max_duration,
+                    stats.overruns,
+                    stats.skipped,
+                );
+                let chunks = self.chunk_stats();
+                debug!(
+                    "chunks: {} queued, {} sent, {} dropped, {} waiting",
+                    chunks.queued,
+                    chunks.sent,
+                    chunks.dropped,
+                    chunks.queued - chunks.sent - chunks.dropped,
+                );
+                debug!(
+                    "world: {} chunks loaded, {} changed since the last save",
+                    self.world_manager.get_world().chunks.len(),
+                    self.world_manager.dirty_chunks(),
+                );
+                if stats.overruns > 0 {
+                    warn!(
+                        "{} of {} ticks took longer than {:?}",
+                        stats.overruns,
+                        stats.ticks,
+                        timer.tick_length()
+                    );
                 }
+                next_stats = Instant::now() + STATS_INTERVAL;
             }
+        }
 
-            // Sleep for 1/60th of a second
-            // FIXME Put in a proper rate limit
-            thread::sleep(Duration::new(0, 1000000 / 60));
+        self.shutdown("server stopped");
+        Ok(())
+    }
+
+    /// Starts saving all changed chunks, without waiting for them to be
+    /// written.
+    fn autosave(&mut self) {
+        if let Err(e) = self.save_level() {
+            error!("saving the level failed: {}", e);
+        }
+        let saved = self.world_manager.save();
+        if saved > 0 {
+            info!("autosaving {} changed chunks", saved);
+        }
+        self.next_autosave = autosave_interval(&self.config).map(|i| Instant::now() + i);
+    }
 
-            // self.world_manager.update_world(self.player.get_camera().position);
+    /// Saves the level and all changed chunks and waits until they are
+    /// written. Returns the number of saved chunks or why saving failed.
+    fn save_world(&mut self) -> Result<usize, String> {
+        let saved = self.world_manager.save();
+        let level = self.save_level().map_err(|e| format!("level: {}", e));
+        match level.and(self.world_manager.flush()) {
+            Ok(()) => {
+                info!("saved {} changed chunks", saved);
+                Ok(saved)
+            }
+            Err(e) => {
+                error!("saving the world failed: {}", e);
+                Err(format!("saving the world failed: {}", e))
+            }
+        }
+    }
+
+    /// Saves the world and takes a snapshot of it.
+    fn create_snapshot(&mut self, name: &str) -> Result<SnapshotInfo, String> {
+        self.save_world()?;
+        // Nothing is written to the world until we are done
+        Snapshots::new(&self.config.save_dir)
+            .create(name)
+            .map_err(|e| format!("taking the snapshot failed: {}", e))
+    }
+
+    /// Writes the level file with the current time, weather and positions of
+    /// the players online.
+    fn save_level(&mut self) -> io::Result<()> {
+        self.level.time = self.time;
+        self.level.weather = self.weather_override;
+        for (_, player) in self.players.iter().filter(|&(_, p)| p.logged_in) {
+            self.level
+                .players
+                .insert(player.name.clone(), player.state.position);
+        }
+        self.level.save(&self.config.save_dir)
+    }
+
+    /// Advances the clocks by ticks which were skipped because the server
+    /// fell behind, so that they keep up with the real time.
+    fn skip_ticks(&mut self, count: u64) {
+        self.ticks += count;
+        self.time
+            .advance(duration_to_secs(self.tick_length) * count as f32);
+    }
+
+    /// Runs a single tick. Returns `false` if the server should stop.
+    fn tick(&mut self) -> bool {
+        if !self.accept_new_players() {
+            info!("tcp listener thread exited, killing server");
+            return false;
+        }
+        self.handle_messages();
+        self.run_console_commands();
+        self.answer_status_queries();
+        self.time.advance(duration_to_secs(self.tick_length));
+        if self.next_time_sync <= Instant::now() {
+            self.sync_time();
+        }
+        self.check_heartbeats();
+        self.send_queued_chunks();
+        if self.next_autosave.map_or(false, |t| t <= Instant::now()) {
+            self.autosave();
+        }
+        if self.ticks % self.snapshot_interval == 0 {
+            self.send_snapshots();
+            self.update_weather();
+        }
+        self.remove_disconnected_players();
+        self.ticks += 1;
+        !self.stopping
+    }
+
+    /// Disconnects all players and saves the world.
+    fn shutdown(&mut self, reason: &str) {
+        if !self.players.is_empty() {
+            info!("disconnecting {} players", self.players.len());
+        }
+        for id in self.players.ids() {
+            self.kick(id, reason);
+        }
+        // Chunks of the players are unloaded and saved here
+        self.remove_disconnected_players();
+        // Errors are logged already
+        let _ = self.save_world();
+    }
+
+    /// Handles all new connections. Returns `false` if the listener thread
+    /// stopped.
+    fn accept_new_players(&mut self) -> bool {
+        loop {
+            match self.connections.try_recv() {
+                Ok(stream) => self.handle_new_player(stream),
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => return false,
+            }
         }
     }
 
     /// Calls a closure with a mutable reference to the given player.
     ///
     /// If the closure returns `Err`, the player will be disconnected.
-    fn with_player<F>(&mut self, id: usize, f: F)
+    fn with_player<F>(&mut self, id: PlayerId, f: F)
     where
         F: FnOnce(&mut Player) -> io::Result<()>,
     {
-        match f(&mut self.players[id]) {
-            Ok(()) => return,
-            Err(e) => {
-                error!("lost connection to player #{} - {}", id, e);
+        let player = match self.players.get_mut(id) {
+            Some(player) => player,
+            None => return,
+        };
+        if player.disconnected {
+            return;
+        }
+
+        if let Err(e) = f(player) {
+            error!("lost connection to player {} - {}", id, e);
+            player.disconnected = true;
+        }
+    }
+
+    /// Sends a message to all players that are logged in.
+    fn broadcast(&mut self, msg: &Message) {
+        for id in self.players.ids() {
+            if self.players[id].logged_in {
+                self.with_player(id, |player| player.conn.send(msg));
+            }
+        }
+    }
+
+    fn handle_new_player(&mut self, stream: TcpStream) {
+        let conn = Connection::new(stream);
+
+        let player = conn
+            .peer_addr()
+            .and_then(|addr| conn.spawn_reader(format!("Reader {}", addr)))
+            .map(|incoming| Player {
+                conn: conn,
+                incoming: incoming,
+                logged_in: false,
+                name: String::new(),
+                is_admin: false,
+                state: PlayerState {
+                    position: Point3f::new(0.0, 0.0, 0.0),
+                    theta: 0.0,
+                    phi: 0.0,
+                },
+                body: Body::new(Point3f::new(0.0, 0.0, 0.0)),
+                last_input: 0,
+                input_budget: RateLimit::new(INPUT_BURST, 1.0),
+                known_players: KnownPlayers::new(),
+                weather: None,
+                chat_limit: RateLimit::new(CHAT_BURST, CHAT_RATE),
+                subscribed_chunks: HashSet::new(),
+                chunk_queue: ChunkQueue::new(),
+                last_seen: Instant::now(),
+                disconnected: false,
+            });
+
+        match player {
+            Ok(player) => {
+                let addr = player.conn.peer_addr();
+                let id = self.players.insert(player);
+                info!("player {} connected from {:?}", id, addr);
+            }
+            Err(e) => error!("failed to set up connection to new player - {}", e),
+        }
+    }
+
+    /// Handles all messages the players sent since the last call.
+    fn handle_messages(&mut self) {
+        for id in self.players.ids() {
+            while !self.players[id].disconnected {
+                match self.players[id].incoming.try_recv() {
+                    Ok(Ok(msg)) => {
+                        self.players[id].last_seen = Instant::now();
+                        self.handle_message(id, msg);
+                    }
+                    Ok(Err(e)) => {
+                        info!("lost connection to player {} - {}", id, e);
+                        self.players[id].disconnected = true;
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        error!("reader thread of player {} stopped", id);
+                        self.players[id].disconnected = true;
+                    }
+                }
+            }
+        }
+    }
+
+    fn handle_message(&mut self, id: PlayerId, msg: Message) {
+        if !self.players[id].logged_in {
+            match msg {
+                Message::Login { version, name } => self.login(id, version, name),
+                _ => self.kick(id, "expected login"),
+            }
+            return;
+        }
+
+        match msg {
+            Message::RequestChunk(index) => self.request_chunk(id, index),
+            Message::Move(inputs) => self.apply_inputs(id, inputs),
+            Message::PillarEdit { id: edit_id, edit } => self.edit_pillar(id, edit_id, edit),
+            Message::Chat { text, .. } => self.chat(id, &text),
+            Message::Command(command) => self.player_command(id, command),
+            Message::Ping(n) => self.with_player(id, |player| player.conn.send(&Message::Pong(n))),
+            // Receiving anything already resets the idle timeout
+            Message::Pong(_) => {}
+            Message::Disconnect { reason } => {
+                info!("player {} disconnected: {}", id, reason);
+                self.players[id].disconnected = true;
             }
+            _ => warn!("player {} sent a message we can't handle", id),
+        }
+    }
+
+    /// Sends the chat message of the player to everyone.
+    fn chat(&mut self, id: PlayerId, text: &str) {
+        if !self.check_chat_limit(id) {
+            return;
+        }
+        let text = text.trim();
+        if text.is_empty() {
+            return;
+        }
+        if text.chars().count() > MAX_CHAT_LEN {
+            self.tell(id, "your message is too long");
+            return;
+        }
+        if text.chars().any(|c| c.is_control()) {
+            self.tell(id, "your message contains invalid characters");
+            return;
         }
 
-        self.players.remove(id);
+        let sender = self.players[id].name.clone();
+        info!("<{}> {}", sender, text);
+        self.broadcast(&Message::Chat {
+            sender: sender,
+            text: text.to_string(),
+        });
     }
 
-    fn handle_new_player(&mut self, id: usize) {
+    /// Runs the command sent by the player if they are allowed to.
+    fn player_command(&mut self, id: PlayerId, command: Command) {
+        if !self.check_chat_limit(id) {
+            return;
+        }
+        // Passwords don't belong into the log
+        if let Command::Auth(password) = command {
+            let reply = self.authenticate(id, &password);
+            self.tell(id, reply);
+            return;
+        }
+        let reply = if !command.needs_admin() || self.is_admin(id) {
+            info!("player {} runs {:?}", id, command);
+            self.run_command(Some(id), command)
+        } else {
+            warn!("player {} isn't allowed to run {:?}", id, command);
+            "you are not allowed to run this command".to_string()
+        };
+        self.tell(id, &reply);
+    }
+
+    fn is_admin(&self, id: PlayerId) -> bool {
+        self.players[id].is_admin
+    }
+
+    /// Makes the player an admin if they are listed as one and the password
+    /// is right. Returns the answer for the player.
+    fn authenticate(&mut self, id: PlayerId, password: &str) -> &'static str {
+        if self.config.admin_password.is_empty() {
+            return "admin commands can only be run in the server console";
+        }
+        let player = &mut self.players[id];
+        if !self.config.admins.contains(&player.name) || password != self.config.admin_password {
+            warn!("player {} sent a wrong admin password", id);
+            return "you are not an admin or the password is wrong";
+        }
+        if !player.is_admin {
+            info!("player {} is an admin now", id);
+            player.is_admin = true;
+            // Admins may always fly
+            if !self.config.allow_flying {
+                self.with_player(id, |player| player.conn.send(&Message::MayFly(true)));
+            }
+        }
+        "you may run admin commands now"
+    }
+
+    /// Returns whether the player may fly through the world.
+    fn may_fly(&self, id: PlayerId) -> bool {
+        self.config.allow_flying || self.is_admin(id)
+    }
+
+    /// Returns whether the player may send another chat message or command
+    /// now, and tells them to slow down otherwise.
+    fn check_chat_limit(&mut self, id: PlayerId) -> bool {
+        if self.players[id].chat_limit.allow(Instant::now()) {
+            return true;
+        }
+        debug!("player {} sends chat messages too fast", id);
+        self.tell(id, "you are sending messages too fast");
+        false
+    }
+
+    /// Sends a chat message of the server to the player.
+    fn tell(&mut self, id: PlayerId, text: &str) {
+        let msg = Message::Chat {
+            sender: self.config.server_name.clone(),
+            text: text.to_string(),
+        };
+        self.with_player(id, |player| player.conn.send(&msg));
+    }
+
+    /// Returns the player logged in with the given name.
+    fn player_by_name(&self, name: &str) -> Option<PlayerId> {
+        self.players
+            .iter()
+            .find(|&(_, p)| p.logged_in && !p.disconnected && p.name == name)
+            .map(|(id, _)| id)
+    }
+
+    /// Answers all status queries received since the last call.
+    fn answer_status_queries(&mut self) {
+        let socket = match self.discovery {
+            Some(ref socket) => socket,
+            None => return,
+        };
+
+        let mut buf = [0; 64];
+        for _ in 0..MAX_STATUS_QUERIES_PER_TICK {
+            let from = match socket.recv_from(&mut buf) {
+                Ok((len, from)) if is_status_query(&buf[..len]) => from,
+                Ok((_, from)) => {
+                    debug!("ignoring invalid status query from {}", from);
+                    continue;
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    // Some systems report unreachable peers of earlier
+                    // replies here
+                    debug!("can't receive status queries: {}", e);
+                    return;
+                }
+            };
+            let reply = encode_status_reply(&self.status());
+            if let Err(e) = socket.send_to(&reply, from) {
+                debug!("can't send status to {}: {}", from, e);
+            }
+        }
+    }
+
+    /// Returns what clients looking for servers get to know.
+    fn status(&self) -> ServerStatus {
+        ServerStatus {
+            name: self.config.server_name.clone(),
+            motd: self.config.motd.clone(),
+            players: self.players.iter().filter(|&(_, p)| p.logged_in).count() as u32,
+            max_players: self.config.max_players as u32,
+            version: PROTOCOL_VERSION,
+            seed: self.config.seed,
+            port: self.port,
+        }
+    }
+
+    /// Runs all commands typed into the console since the last call and
+    /// prints their replies.
+    fn run_console_commands(&mut self) {
+        loop {
+            let command = match self.console.as_ref().map(|c| c.try_recv()) {
+                Some(Ok(command)) => command,
+                _ => return,
+            };
+            let reply = self.run_command(None, command);
+            println!("{}", reply);
+        }
+    }
+
+    /// Runs an admin command and returns the answer for the admin. `issuer`
+    /// is the player who runs the command, `None` for the console.
+    pub fn run_command(&mut self, issuer: Option<PlayerId>, command: Command) -> String {
+        match command {
+            Command::Help => COMMAND_HELP.to_string(),
+            Command::List => {
+                let names: Vec<_> = self
+                    .players
+                    .iter()
+                    .filter(|&(_, p)| p.logged_in && !p.disconnected)
+                    .map(|(_, p)| p.name.as_str())
+                    .collect();
+                format!("{} players online: {}", names.len(), names.join(", "))
+            }
+            Command::Kick(name) => match self.player_by_name(&name) {
+                Some(id) => {
+                    self.kick(id, "kicked by an admin");
+                    format!("kicked {}", name)
+                }
+                None => format!("there is no player named '{}'", name),
+            },
+            Command::Say(text) => {
+                info!("[{}] {}", self.config.server_name, text);
+                let msg = Message::Chat {
+                    sender: self.config.server_name.clone(),
+                    text: text,
+                };
+                self.broadcast(&msg);
+                "message sent".to_string()
+            }
+            Command::SetTime(time) => {
+                if !(0.0..DAY_LENGTH).contains(&time) {
+                    return format!("time has to be between 0 and {}", DAY_LENGTH);
+                }
+                self.time.time_on_day = time;
+                self.sync_time();
+                format!("time set to {}", time)
+            }
+            Command::SetTimeSpeed(speed) => {
+                if !(0.0..=MAX_TIME_SPEED).contains(&speed) {
+                    return format!("time speed has to be between 0 and {}", MAX_TIME_SPEED);
+                }
+                self.set_time_speed(speed);
+                format!("time speed set to {}", speed)
+            }
+            Command::SetWeather(weather) => {
+                // Players get the new weather with the next snapshot
+                self.weather_override = weather;
+                match weather {
+                    Some(weather) => format!("weather set to {:?}", weather),
+                    None => "every region decides its weather again".to_string(),
+                }
+            }
+            Command::Save => match self.save_world() {
+                Ok(saved) => format!("saved {} changed chunks", saved),
+                Err(e) => e,
+            },
+            Command::CreateSnapshot(name) => match self.create_snapshot(&name) {
+                Ok(info) => format!("created snapshot {}", info),
+                Err(e) => e,
+            },
+            Command::ListSnapshots => match Snapshots::new(&self.config.save_dir).list() {
+                Ok(ref list) if list.is_empty() => "there are no snapshots".to_string(),
+                Ok(list) => {
+                    let lines: Vec<_> = list.iter().map(|info| info.to_string()).collect();
+                    format!("{} snapshots:\n{}", list.len(), lines.join("\n"))
+                }
+                Err(e) => format!("listing the snapshots failed: {}", e),
+            },
+            Command::RestoreSnapshot(id) => format!(
+                "snapshots can't be restored while the server is running, stop it and run \
+                 'plantex-snapshots --save-dir {} restore {}'",
+                self.config.save_dir.display(),
+                id
+            ),
+            Command::PruneSnapshots(keep) => {
+                let snapshots = Snapshots::new(&self.config.save_dir);
+                let damaged = snapshots.damaged().map_or(0, |damaged| damaged.len());
+                match snapshots.prune(keep as usize) {
+                    Ok(pruned) => format!("deleted {} snapshots", pruned.len() + damaged),
+                    Err(e) => format!("pruning the snapshots failed: {}", e),
+                }
+            }
+            Command::Stop => {
+                self.stopping = true;
+                "stopping the server".to_string()
+            }
+            Command::Seed => format!("seed: {}", self.config.seed),
+            Command::Teleport(target) => {
+                let id = match issuer {
+                    Some(id) => id,
+                    None => return "only players can teleport".to_string(),
+                };
+                let pos = match target {
+                    TeleportTarget::Position(pos) => pos,
+                    TeleportTarget::Player(name) => match self.player_by_name(&name) {
+                        Some(other) => self.players[other].state.position,
+                        None => return format!("there is no player named '{}'", name),
+                    },
+                };
+                if !(pos.x.is_finite() && pos.y.is_finite() && pos.z.is_finite()) {
+                    return "invalid position".to_string();
+                }
+                self.teleport(id, pos);
+                format!("teleported to {:.1}, {:.1}, {:.1}", pos.x, pos.y, pos.z)
+            }
+            // Players' passwords are checked before, see `player_command`
+            Command::Auth(_) => "the console needs no password".to_string(),
+        }
+    }
+
+    /// Moves the player to the given position. The player's client learns
+    /// about it like about any other correction of its prediction.
+    fn teleport(&mut self, id: PlayerId, pos: Point3f) {
+        let player = &mut self.players[id];
+        player.body = Body::new(pos);
+        let state = PlayerState {
+            position: pos,
+            ..player.state
+        };
+        let ack = Message::MoveAck {
+            seq: player.last_input,
+            body: player.body,
+        };
+        self.with_player(id, |player| player.conn.send(&ack));
+        self.move_player(id, state);
+    }
+
+    /// Lets time pass at the given speed.
+    pub fn set_time_speed(&mut self, speed: f32) {
+        self.time.speed = speed;
+        self.sync_time();
+    }
+
+    /// Sends all players the current time.
+    fn sync_time(&mut self) {
+        self.broadcast(&Message::TimeSync(self.time));
+        self.next_time_sync = Instant::now() + TIME_SYNC_INTERVAL;
+    }
+
+    /// Checks the login of the player and sends them everything they need to
+    /// know about the world, or rejects them.
+    fn login(&mut self, id: PlayerId, version: u16, name: String) {
+        if version != PROTOCOL_VERSION {
+            let reason = format!(
+                "incompatible protocol version: the server uses version {}, but your \
+                 client uses version {}",
+                PROTOCOL_VERSION, version
+            );
+            self.kick(id, &reason);
+            return;
+        }
+
+        let name = name.trim().to_string();
+        if let Err(reason) = self.check_name(&name) {
+            self.kick(id, reason);
+            return;
+        }
+        let online = self.players.iter().filter(|&(_, p)| p.logged_in).count();
+        if online >= self.config.max_players {
+            self.kick(id, "the server is full");
+            return;
+        }
+
+        // Returning players continue where they left
+        let spawn = self.level.players.get(&name).cloned().unwrap_or(self.spawn);
+        let welcome = Welcome {
+            seed: self.config.seed,
+            spawn: spawn,
+            time: self.time,
+            server_name: self.config.server_name.clone(),
+            view_radius: self.config.view_radius,
+            // Admins may fly once they sent the admin password
+            may_fly: self.config.allow_flying,
+        };
         self.with_player(id, |player| {
-            info!("client connected from {}", player.conn.peer_addr()?);
-            Ok(())
+            player.logged_in = true;
+            player.name = name;
+            player.state.position = welcome.spawn;
+            player.body = Body::new(welcome.spawn);
+            player.conn.send(&Message::Welcome(welcome))
+        });
+        if !self.config.motd.is_empty() {
+            let motd = self.config.motd.clone();
+            self.tell(id, &motd);
+        }
+        info!("player {} logged in as '{}'", id, self.players[id].name);
+    }
+
+    /// Returns why the name can't be used, if it can't.
+    fn check_name(&self, name: &str) -> Result<(), &'static str> {
+        if name.is_empty() {
+            return Err("player name is empty");
+        }
+        if name.chars().count() > MAX_NAME_LEN {
+            return Err("player name is too long");
+        }
+        if name.chars().any(|c| c.is_control()) {
+            return Err("player name contains invalid characters");
+        }
+        if self
+            .players
+            .iter()
+            .any(|(_, p)| p.logged_in && p.name == name)
+        {
+            return Err("player name is already taken");
+        }
+        Ok(())
+    }
+
+    /// Pings all players regularly and disconnects players we haven't heard
+    /// of for too long.
+    fn check_heartbeats(&mut self) {
+        let now = Instant::now();
+        for id in self.players.ids() {
+            if now.duration_since(self.players[id].last_seen) > self.idle_timeout {
+                self.kick(id, "timed out");
+            }
+        }
+
+        if self.next_heartbeat <= now {
+            self.ping_counter = self.ping_counter.wrapping_add(1);
+            self.broadcast(&Message::Ping(self.ping_counter));
+            self.next_heartbeat = now + HEARTBEAT_INTERVAL;
+        }
+    }
+
+    /// Subscribes the player to the chunk and queues it for sending.
+    fn request_chunk(&mut self, id: PlayerId, index: ChunkIndex) {
+        if !self
+            .world_manager
+            .is_in_range(self.players[id].state.position, index)
+        {
+            // Tell the player, so that they don't wait for the chunk
+            debug!("player {} requested chunk {:?} out of range", id, index);
+            self.with_player(id, |player| {
+                player.conn.send(&Message::ChunkUnavailable(index))
+            });
+            return;
+        }
+        let player = &mut self.players[id];
+        if player.subscribed_chunks.insert(index) {
+            self.world_manager.subscribe(index);
+        }
+
+        // The player might request an already subscribed chunk again if our
+        // answer took too long, so it's sent again
+        player.chunk_queue.push(index);
+    }
+
+    /// Moves the player according to their inputs and tells them where they
+    /// ended up.
+    fn apply_inputs(&mut self, id: PlayerId, mut inputs: Vec<MoveInput>) {
+        if inputs.len() > MAX_MOVE_INPUTS {
+            debug!("player {} sent {} inputs at once", id, inputs.len());
+            inputs.truncate(MAX_MOVE_INPUTS);
+        }
+        let may_fly = self.may_fly(id);
+        let world = self.world_manager.get_world();
+        let player = &mut self.players[id];
+        let mut state = player.state;
+        let now = Instant::now();
+
+        for mut input in inputs {
+            // Clients might send inputs again we already applied
+            if input.seq <= player.last_input {
+                continue;
+            }
+            player.last_input = input.seq;
+
+            // Inputs beyond the time the player had are dropped
+            let delta = input.delta.min(movement::MAX_INPUT_DELTA);
+            input.delta = player.input_budget.take(now, delta);
+            if input.delta <= 0.0 {
+                continue;
+            }
+            input.controls.fly &= may_fly;
+            movement::step(&mut player.body, &input, world);
+            state = PlayerState {
+                position: player.body.position,
+                theta: input.controls.theta,
+                phi: input.controls.phi,
+            };
+        }
+
+        let ack = Message::MoveAck {
+            seq: player.last_input,
+            body: player.body,
+        };
+        self.with_player(id, |player| player.conn.send(&ack));
+        self.move_player(id, state);
+    }
+
+    /// Applies the edit requested by the player and sends it to everyone who
+    /// has the changed chunk, or tells the player that it was rejected.
+    fn edit_pillar(&mut self, id: PlayerId, edit_id: u32, edit: PillarEdit) {
+        let player = &self.players[id];
+        let pos = edit.pos.0.to_real();
+        let target = Point3f::new(pos.x, pos.y, edit.height.to_real());
+        let res = if !player.subscribed_chunks.contains(&edit.chunk()) {
+            Err("chunk not subscribed".to_string())
+        } else if player.state.position.distance(target) > MAX_EDIT_DISTANCE {
+            Err("too far away".to_string())
+        } else {
+            self.world_manager
+                .apply_edit(&edit)
+                .map_err(|e| e.to_string())
+        };
+
+        let version = match res {
+            Ok(version) => version,
+            Err(reason) => {
+                debug!("rejected edit {:?} of player {}: {}", edit, id, reason);
+                self.with_player(id, |player| {
+                    player.conn.send(&Message::EditRejected(edit_id))
+                });
+                return;
+            }
+        };
+
+        for other in self.players.ids() {
+            let player = &self.players[other];
+            // Queued chunks are sent with the edit already applied
+            if !player.subscribed_chunks.contains(&edit.chunk())
+                || (other != id && player.chunk_queue.contains(edit.chunk()))
+            {
+                continue;
+            }
+            let msg = Message::ChunkEdit {
+                version: version,
+                edit: edit,
+                origin: if other == id { Some(edit_id) } else { None },
+            };
+            self.with_player(other, |player| player.conn.send(&msg));
+        }
+    }
+
+    /// Updates the position of the player and unsubscribes them from all
+    /// chunks which are out of range now.
+    fn move_player(&mut self, id: PlayerId, state: PlayerState) {
+        let world_manager = &mut self.world_manager;
+        let player = &mut self.players[id];
+        let pos = state.position;
+        player.state = state;
+
+        let out_of_range: Vec<_> = player
+            .subscribed_chunks
+            .iter()
+            .cloned()
+            .filter(|&index| !world_manager.is_in_range(pos, index))
+            .collect();
+        for index in out_of_range {
+            player.subscribed_chunks.remove(&index);
+            player.chunk_queue.drop_chunk(index);
+            world_manager.unsubscribe(index);
+        }
+    }
+
+    /// Sends every player the most important of their queued chunks which
+    /// are loaded, as many as fit into the bytes a player gets per tick.
+    fn send_queued_chunks(&mut self) {
+        self.world_manager.update_world();
+        for index in self.world_manager.take_unavailable() {
+            for id in self.players.ids() {
+                let player = &mut self.players[id];
+                if player.subscribed_chunks.remove(&index) {
+                    player.chunk_queue.drop_chunk(index);
+                    self.with_player(id, |player| {
+                        player.conn.send(&Message::ChunkUnavailable(index))
+                    });
+                }
+            }
+        }
+        let max_bytes = self.config.chunk_bytes_per_tick;
+
+        for id in self.players.ids() {
+            let frames = {
+                let world_manager = &self.world_manager;
+                let player = &mut self.players[id];
+                if player.chunk_queue.is_empty() {
+                    continue;
+                }
+                let state = player.state;
+                player.chunk_queue.take_frames(
+                    state.position,
+                    state.phi,
+                    max_bytes,
+                    |index| {
+                        world_manager.get_world().chunk_at(index)?;
+                        Some(world_manager.version(index))
+                    },
+                    |index| {
+                        let chunk = world_manager.get_world().chunk_at(index)?.clone();
+                        let version = world_manager.version(index);
+                        let msg = Message::ChunkData(index, version, chunk);
+                        // Chunks are far smaller than the frame limit
+                        encode_frame(&msg).ok()
+                    },
+                )
+            };
+            self.with_player(id, |player| {
+                for frame in &frames {
+                    player.conn.send_frame(frame)?;
+                }
+                Ok(())
+            });
+        }
+    }
+
+    /// Returns the chunk counters of all players, including the ones who
+    /// left.
+    fn chunk_stats(&self) -> ChunkQueueStats {
+        let mut stats = self.chunk_stats;
+        for (_, player) in self.players.iter() {
+            stats.add(player.chunk_queue.stats());
+        }
+        stats
+    }
+
+    /// Sends every player the changes of all other players since their last
+    /// snapshot. Snapshots are sent even if nothing changed, as clients
+    /// derive their clock from them.
+    fn send_snapshots(&mut self) {
+        let time = self.ticks * self.tick_length.as_nanos() as u64 / 1_000_000;
+        let states: Vec<_> = self
+            .players
+            .iter()
+            .filter(|&(_, p)| p.logged_in && !p.disconnected)
+            .map(|(id, p)| (id, p.name.clone(), p.state))
+            .collect();
+
+        for id in self.players.ids() {
+            if !self.players[id].logged_in {
+                continue;
+            }
+            self.with_player(id, |player| {
+                let others = states
+                    .iter()
+                    .filter(|&&(other, _, _)| other != id)
+                    .map(|&(other, ref name, state)| (other.to_u64(), name.as_str(), state));
+                let snapshot = Snapshot {
+                    time: time,
+                    players: player.known_players.diff(others),
+                };
+                player.conn.send(&Message::Snapshot(snapshot))
+            });
+        }
+    }
+
+    /// Sends every player the weather at their position if it changed.
+    fn update_weather(&mut self) {
+        for id in self.players.ids() {
+            let player = &self.players[id];
+            if !player.logged_in {
+                continue;
+            }
+            let pos = player.state.position;
+            let pos = PillarIndex(AxialPoint::from_real(Point2f::new(pos.x, pos.y)));
+            // We need the biome, so the weather only changes in loaded chunks
+            let pillar = self.world_manager.get_world().pillar_at(pos);
+            let weather = match (self.weather_override, pillar) {
+                (Some(weather), _) => weather,
+                (None, Some(pillar)) => self.weather.weather_at(pos, pillar.biome(), &self.time),
+                (None, None) => continue,
+            };
+
+            if player.weather != Some(weather) {
+                self.with_player(id, |player| {
+                    player.weather = Some(weather);
+                    player.conn.send(&Message::WeatherSync(weather))
+                });
+            }
+        }
+    }
+
+    /// Removes all disconnected players.
+    fn remove_disconnected_players(&mut self) {
+        for id in self.players.ids() {
+            if self.players[id].disconnected {
+                let player = self.players.remove(id).unwrap();
+                self.player_left(id, player);
+            }
+        }
+    }
+
+    /// Called exactly once for every player after they are removed. Releases
+    /// everything the player held; the connection is closed once `player` is
+    /// dropped.
+    fn player_left(&mut self, id: PlayerId, player: Player) {
+        for &index in &player.subscribed_chunks {
+            self.world_manager.unsubscribe(index);
+        }
+        let chunks = player.chunk_queue.stats();
+        debug!(
+            "chunks of player {}: {} queued, {} sent, {} dropped",
+            id, chunks.queued, chunks.sent, chunks.dropped
+        );
+        self.chunk_stats.add(chunks);
+        if player.logged_in {
+            self.level
+                .players
+                .insert(player.name.clone(), player.state.position);
+        }
+
+        info!(
+            "player {} ('{}') left ({} players online)",
+            id,
+            player.name,
+            self.players.len()
+        );
+    }
+
+    /// Tells the player why we close the connection and disconnects them.
+    fn kick(&mut self, id: PlayerId, reason: &str) {
+        info!("kicking player {}: {}", id, reason);
+        self.with_player(id, |player| {
+            player.conn.send(&Message::Disconnect {
+                reason: reason.to_string(),
+            })
+        });
+
+        self.players[id].disconnected = true;
+    }
+}
+
+/// Opens the socket status queries are answered on. Returns `None` if that's
+/// not possible, the server works without it.
+fn bind_discovery(config: &Config) -> Option<UdpSocket> {
+    let addr = SocketAddr::new(config.address, config.discovery_port);
+    let socket = UdpSocket::bind(addr).and_then(|socket| {
+        socket.set_nonblocking(true)?;
+        Ok(socket)
+    });
+    match socket {
+        Ok(socket) => {
+            info!("answering status queries on {}", addr);
+            Some(socket)
+        }
+        Err(e) => {
+            warn!("LAN discovery disabled, can't listen on {}: {}", addr, e);
+            None
+        }
+    }
+}
+
+/// Returns the time between autosaves, `None` if they are disabled.
+fn autosave_interval(config: &Config) -> Option<Duration> {
+    match config.autosave_interval {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    }
+}
+
+/// Converts the duration to seconds.
+fn duration_to_secs(d: Duration) -> f32 {
+    d.as_secs() as f32 + d.subsec_nanos() as f32 / 1_000_000_000.0
+}
+
+/// Creates a server listening on a random local port.
+#[cfg(test)]
+fn test_server() -> (Server, ::std::net::SocketAddr) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    (Server::new(listener, test_config()).unwrap(), addr)
+}
+
+/// Admin password of test servers.
+#[cfg(test)]
+const TEST_ADMIN_PASSWORD: &str = "secret";
+
+/// Returns the config of test servers, saving in a new temporary directory.
+#[cfg(test)]
+fn test_config() -> Config {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+    let dir = format!(
+        "plantex-server-test-{}-{}",
+        ::std::process::id(),
+        NEXT_DIR.fetch_add(1, Ordering::SeqCst)
+    );
+    Config {
+        lan_discovery: false,
+        // Test players fly, so that they aren't stopped by the terrain
+        allow_flying: true,
+        admin_password: TEST_ADMIN_PASSWORD.to_string(),
+        save_dir: ::std::env::temp_dir().join(dir),
+        ..Config::default()
+    }
+}
+
+/// Runs ticks until the condition holds, panics if that takes too long.
+#[cfg(test)]
+fn tick_until<F: FnMut(&Server) -> bool>(server: &mut Server, mut cond: F) {
+    let timeout = Instant::now() + Duration::from_secs(10);
+    while !cond(server) {
+        assert!(Instant::now() < timeout, "condition not met in time");
+        assert!(server.tick());
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[cfg(test)]
+fn connect_player(addr: ::std::net::SocketAddr, name: &str) -> Connection {
+    let conn = Connection::connect(addr).unwrap();
+    conn.send(&Message::Login {
+        version: PROTOCOL_VERSION,
+        name: name.into(),
+    })
+    .unwrap();
+    conn
+}
+
+/// Connects a player and sends the admin password. The player is only an
+/// admin if the server lists their name as admin.
+#[cfg(test)]
+fn connect_admin(addr: ::std::net::SocketAddr, name: &str) -> Connection {
+    let conn = connect_player(addr, name);
+    conn.send(&Message::Command(Command::Auth(TEST_ADMIN_PASSWORD.into())))
+        .unwrap();
+    conn
+}
+
+/// An input flying forward for 0.1s.
+#[cfg(test)]
+fn fly_input(seq: u32, phi: f32) -> MoveInput {
+    MoveInput {
+        seq: seq,
+        delta: 0.1,
+        controls: movement::Controls {
+            forward: 1.0,
+            fly: true,
+            theta: 1.5,
+            phi: phi,
+            ..movement::Controls::default()
+        },
+    }
+}
+
+#[test]
+fn many_players_connect_and_disconnect() {
+    let (mut server, addr) = test_server();
+    let mut old_ids = HashSet::new();
+
+    for _ in 0..5 {
+        let conns: Vec<_> = (0..10)
+            .map(|i| connect_player(addr, &format!("Bot {}", i)))
+            .collect();
+        tick_until(&mut server, |s| {
+            s.players.len() == 10 && s.players.iter().all(|(_, p)| p.logged_in)
+        });
+
+        let ids: HashSet<_> = server.players.ids().into_iter().collect();
+        assert!(ids.is_disjoint(&old_ids), "player ids were reused");
+
+        for conn in &conns {
+            match conn.recv().unwrap() {
+                Message::Welcome(welcome) => assert_eq!(welcome.seed, 42),
+                msg => panic!("unexpected message {:?}", msg),
+            }
+        }
+
+        // Half of the players leave gracefully, the others just vanish
+        let (polite, rude) = conns.split_at(5);
+        for conn in polite {
+            conn.send(&Message::Disconnect {
+                reason: "bye".into(),
+            })
+            .unwrap();
+        }
+        for conn in rude {
+            conn.shutdown();
+        }
+
+        tick_until(&mut server, |s| s.players.is_empty());
+        old_ids.extend(ids);
+    }
+}
+
+#[test]
+fn remaining_players_keep_their_ids() {
+    let (mut server, addr) = test_server();
+
+    let first = connect_player(addr, "first");
+    tick_until(&mut server, |s| s.players.len() == 1);
+    let second = connect_player(addr, "second");
+    tick_until(&mut server, |s| s.players.len() == 2);
+    let ids = server.players.ids();
+
+    first.shutdown();
+    tick_until(&mut server, |s| s.players.len() == 1);
+    assert!(server.players.get(ids[0]).is_none());
+    assert!(server.players.get(ids[1]).is_some());
+    drop(second);
+}
+
+#[test]
+fn idle_players_are_kicked() {
+    let (mut server, addr) = test_server();
+    server.idle_timeout = Duration::from_millis(50);
+
+    let conn = connect_player(addr, "idle");
+    tick_until(&mut server, |s| s.players.len() == 1);
+    tick_until(&mut server, |s| s.players.is_empty());
+
+    let mut reason = None;
+    while let Ok(msg) = conn.recv() {
+        if let Message::Disconnect { reason: r } = msg {
+            reason = Some(r);
+        }
+    }
+    assert_eq!(reason, Some("timed out".to_string()));
+}
+
+#[test]
+fn wrong_protocol_version_is_rejected() {
+    let (mut server, addr) = test_server();
+
+    let conn = Connection::connect(addr).unwrap();
+    conn.send(&Message::Login {
+        version: PROTOCOL_VERSION + 1,
+        name: "future".into(),
+    })
+    .unwrap();
+
+    // The player might be kicked in the same tick they connected
+    let mut connected = false;
+    tick_until(&mut server, |s| {
+        connected |= !s.players.is_empty();
+        connected && s.players.is_empty()
+    });
+
+    match conn.recv().unwrap() {
+        Message::Disconnect { reason } => assert!(reason.contains("version")),
+        msg => panic!("unexpected message {:?}", msg),
+    }
+}
+
+#[test]
+fn duplicate_names_are_rejected() {
+    let (mut server, addr) = test_server();
+
+    let _first = connect_player(addr, "Lukas");
+    tick_until(&mut server, |s| s.players.iter().any(|(_, p)| p.logged_in));
+
+    let second = connect_player(addr, " Lukas ");
+    let mut connected = false;
+    tick_until(&mut server, |s| {
+        connected |= s.players.len() == 2;
+        connected && s.players.len() == 1
+    });
+
+    match second.recv().unwrap() {
+        Message::Disconnect { reason } => assert!(reason.contains("taken")),
+        msg => panic!("unexpected message {:?}", msg),
+    }
+}
+
+#[test]
+fn players_see_each_other_move() {
+    use base::net::RemotePlayers;
+    use base::world::World;
+
+    let (mut server, addr) = test_server();
+    let walker = connect_player(addr, "walker");
+    let watcher = connect_player(addr, "watcher");
+    let messages = watcher.spawn_reader("watcher".into()).unwrap();
+    tick_until(&mut server, |s| {
+        s.players.len() == 2 && s.players.iter().all(|(_, p)| p.logged_in)
+    });
+
+    let input = fly_input(1, 2.0);
+    walker.send(&Message::Move(vec![input])).unwrap();
+    let mut target = Body::new(server.spawn);
+    movement::step(&mut target, &input, &World::empty());
+
+    let mut remote = RemotePlayers::new();
+    let receive_snapshots = |remote: &mut RemotePlayers| {
+        while let Ok(msg) = messages.try_recv() {
+            if let Message::Snapshot(snapshot) = msg.unwrap() {
+                remote.apply(snapshot, Instant::now());
+            }
+        }
+    };
+    tick_until(&mut server, |_| {
+        receive_snapshots(&mut remote);
+        // Far enough in the future to see the latest state
+        let states = remote.states(Instant::now() + Duration::from_secs(1));
+        states.len() == 1
+            && (states[0].position - target.position).magnitude() < 0.1
+            && states[0].phi == 2.0
+    });
+
+    walker.shutdown();
+    tick_until(&mut server, |_| {
+        receive_snapshots(&mut remote);
+        remote.is_empty()
+    });
+}
+
+#[test]
+fn skipped_ticks_advance_the_clocks() {
+    let (mut server, addr) = test_server();
+    let conn = connect_player(addr, "player");
+    let messages = conn.spawn_reader("player".into()).unwrap();
+    tick_until(&mut server, |s| s.players.iter().any(|(_, p)| p.logged_in));
+
+    // Ten seconds worth of ticks are skipped
+    let before = server.time.seconds();
+    let skipped = u64::from(server.config.tick_rate) * 10;
+    server.skip_ticks(skipped);
+    let advanced = server.time.seconds() - before;
+    assert!((advanced - 10.0 * f64::from(server.time.speed)).abs() < 0.1);
+
+    let mut latest = 0;
+    tick_until(&mut server, |_| {
+        while let Ok(msg) = messages.try_recv() {
+            if let Message::Snapshot(snapshot) = msg.unwrap() {
+                latest = snapshot.time;
+            }
+        }
+        latest >= 10_000
+    });
+}
+
+#[test]
+fn inputs_are_applied_once() {
+    use base::world::World;
+
+    let (mut server, addr) = test_server();
+    let conn = connect_player(addr, "mover");
+    let messages = conn.spawn_reader("mover".into()).unwrap();
+    tick_until(&mut server, |s| s.players.iter().any(|(_, p)| p.logged_in));
+
+    // The first input is sent twice
+    let inputs = vec![fly_input(1, 0.0), fly_input(2, 0.5)];
+    conn.send(&Message::Move(inputs.clone())).unwrap();
+    conn.send(&Message::Move(inputs[1..].to_vec())).unwrap();
+    conn.send(&Message::Move(inputs[..1].to_vec())).unwrap();
+
+    let mut expected = Body::new(server.spawn);
+    for input in &inputs {
+        movement::step(&mut expected, input, &World::empty());
+    }
+
+    let mut acks = Vec::new();
+    tick_until(&mut server, |_| {
+        while let Ok(msg) = messages.try_recv() {
+            if let Message::MoveAck { seq, body } = msg.unwrap() {
+                acks.push((seq, body));
+            }
+        }
+        acks.len() == 3
+    });
+    assert_eq!(acks, vec![(2, expected); 3]);
+}
+
+#[test]
+fn inputs_are_limited() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let config = Config {
+        allow_flying: false,
+        ..test_config()
+    };
+    let mut server = Server::new(listener, config).unwrap();
+    let _conn = connect_player(addr, "climber");
+    tick_until(&mut server, |s| s.players.iter().any(|(_, p)| p.logged_in));
+    let id = server.players.ids()[0];
+
+    let climb = |seqs: ::std::ops::Range<u32>| -> Vec<_> {
+        seqs.map(|seq| {
+            let mut input = fly_input(seq, 0.0);
+            input.controls.forward = 0.0;
+            input.controls.up = 1.0;
+            input
         })
+        .collect()
+    };
+
+    // Only admins may fly, others don't rise
+    let start = server.players[id].body.position.z;
+    server.apply_inputs(id, climb(1..11));
+    assert_eq!(server.players[id].last_input, 10);
+    // Flying for a second would lift them by 12 units
+    assert!(server.players[id].body.position.z - start < 1.0);
+
+    // One of the two seconds the player had is left, further inputs are
+    // dropped
+    server.players[id].is_admin = true;
+    let start = server.players[id].body.position.z;
+    server.apply_inputs(id, climb(11..MAX_MOVE_INPUTS as u32 + 21));
+    assert_eq!(server.players[id].last_input, MAX_MOVE_INPUTS as u32 + 10);
+    let risen = server.players[id].body.position.z - start;
+    assert!(risen > 11.0 && risen < 14.0, "rose by {}", risen);
+}
+
+#[test]
+fn racing_edits_are_resolved_by_the_server() {
+    use base::world::{EditKind, GroundMaterial, PillarIndex};
+
+    let (mut server, addr) = test_server();
+    let first = connect_player(addr, "first");
+    let second = connect_player(addr, "second");
+    let first_messages = first.spawn_reader("first".into()).unwrap();
+    let second_messages = second.spawn_reader("second".into()).unwrap();
+    tick_until(&mut server, |s| {
+        s.players.len() == 2 && s.players.iter().all(|(_, p)| p.logged_in)
+    });
+
+    let spawn = server.spawn;
+    let pos = PillarIndex(AxialPoint::from_real(Point2f::new(spawn.x, spawn.y)));
+    for conn in &[&first, &second] {
+        conn.send(&Message::RequestChunk(pos.chunk())).unwrap();
+    }
+    let mut loaded = 0;
+    tick_until(&mut server, |_| {
+        for messages in &[&first_messages, &second_messages] {
+            while let Ok(msg) = messages.try_recv() {
+                if let Message::ChunkData(_, version, _) = msg.unwrap() {
+                    assert_eq!(version, 0);
+                    loaded += 1;
+                }
+            }
+        }
+        loaded == 2
+    });
+
+    // Both players add a step on top of the same pillar
+    let top = server
+        .world_manager
+        .get_world()
+        .pillar_at(pos)
+        .unwrap()
+        .sections()
+        .last()
+        .unwrap()
+        .top;
+    let edit = PillarEdit {
+        pos: pos,
+        height: top,
+        kind: EditKind::Add(GroundMaterial::Stone),
+    };
+    for id in server.players.ids() {
+        server.players[id].state.position.z = top.to_real();
+    }
+    first
+        .send(&Message::PillarEdit { id: 3, edit: edit })
+        .unwrap();
+    tick_until(&mut server, |s| s.world_manager.version(edit.chunk()) == 1);
+    second
+        .send(&Message::PillarEdit { id: 4, edit: edit })
+        .unwrap();
+
+    let mut first_received = Vec::new();
+    let mut second_received = Vec::new();
+    tick_until(&mut server, |_| {
+        let receive = |messages: &Receiver<io::Result<Message>>, out: &mut Vec<Message>| {
+            while let Ok(msg) = messages.try_recv() {
+                match msg.unwrap() {
+                    msg @ Message::ChunkEdit { .. } | msg @ Message::EditRejected(_) => {
+                        out.push(msg)
+                    }
+                    _ => {}
+                }
+            }
+        };
+        receive(&first_messages, &mut first_received);
+        receive(&second_messages, &mut second_received);
+        first_received.len() == 1 && second_received.len() == 2
+    });
+
+    assert_eq!(
+        first_received,
+        vec![Message::ChunkEdit {
+            version: 1,
+            edit: edit,
+            origin: Some(3),
+        }]
+    );
+    assert_eq!(
+        second_received,
+        vec![
+            Message::ChunkEdit {
+                version: 1,
+                edit: edit,
+                origin: None,
+            },
+            Message::EditRejected(4),
+        ]
+    );
+    // The changed chunk is written to the save directory
+    assert_eq!(
+        server.run_command(None, Command::Save),
+        "saved 1 changed chunks"
+    );
+    let regions = RegionProvider::open(server.config.save_dir.join(REGION_DIR)).unwrap();
+    assert!(regions.contains(edit.chunk()).unwrap());
+    assert_eq!(
+        server.run_command(None, Command::Save),
+        "saved 0 changed chunks"
+    );
+}
+
+#[test]
+fn chunks_are_queued_by_priority() {
+    use std::f32::consts::PI;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let config = Config {
+        // Only a single chunk per tick
+        chunk_bytes_per_tick: 1,
+        ..test_config()
+    };
+    let mut server = Server::new(listener, config).unwrap();
+    let conn = connect_player(addr, "player");
+    let messages = conn.spawn_reader("player".into()).unwrap();
+    tick_until(&mut server, |s| s.players.iter().any(|(_, p)| p.logged_in));
+    let id = server.players.ids()[0];
+
+    // The chunks are loaded first, so that they are sent in the order of
+    // their priority, not in the order they are generated in
+    let spawn = server.spawn;
+    let center = PillarIndex(AxialPoint::from_real(Point2f::new(spawn.x, spawn.y))).chunk();
+    let chunks: Vec<_> = [(0, 0), (1, 0), (-1, 0)]
+        .iter()
+        .map(|&(q, r)| ChunkIndex(center.0 + AxialVector::new(q, r)))
+        .collect();
+    for &index in &chunks {
+        server.world_manager.subscribe(index);
+    }
+    while server.world_manager.get_world().chunks.len() < chunks.len() {
+        server.world_manager.update_world();
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    // The player looks into the direction of chunk (-1, 0), which is further
+    // away than chunk (1, 0). The chunks are queued at once, like requests
+    // arriving in the same tick.
+    server.players[id].state.phi = PI;
+    for &index in &chunks {
+        server.request_chunk(id, index);
+    }
+    let mut received = Vec::new();
+    tick_until(&mut server, |_| {
+        while let Ok(msg) = messages.try_recv() {
+            if let Message::ChunkData(index, _, _) = msg.unwrap() {
+                received.push(index);
+            }
+        }
+        received.len() == 3
+    });
+    assert_eq!(received, vec![chunks[0], chunks[2], chunks[1]]);
+
+    // Chunks the player left behind before they were sent are dropped
+    let far = ChunkIndex(center.0 + AxialVector::new(10, 0));
+    server.players[id].subscribed_chunks.insert(far);
+    server.players[id].chunk_queue.push(far);
+    let state = server.players[id].state;
+    server.move_player(id, state);
+    assert!(server.players[id].chunk_queue.is_empty());
+    assert_eq!(
+        server.chunk_stats(),
+        ChunkQueueStats {
+            queued: 4,
+            sent: 3,
+            dropped: 1,
+        }
+    );
+
+    // After reconnecting, the player gets their chunks again
+    conn.send(&Message::Disconnect {
+        reason: "reconnecting".into(),
+    })
+    .unwrap();
+    tick_until(&mut server, |s| s.players.is_empty());
+    let conn = connect_player(addr, "player");
+    let messages = conn.spawn_reader("player".into()).unwrap();
+    tick_until(&mut server, |s| s.players.iter().any(|(_, p)| p.logged_in));
+    conn.send(&Message::RequestChunk(chunks[0])).unwrap();
+    tick_until(&mut server, |_| match messages.try_recv() {
+        Ok(Ok(Message::ChunkData(index, _, _))) => index == chunks[0],
+        _ => false,
+    });
+    assert_eq!(server.chunk_stats().sent, 4);
+}
+
+#[test]
+fn far_chunks_are_refused() {
+    let (mut server, addr) = test_server();
+    let conn = connect_player(addr, "player");
+    let messages = conn.spawn_reader("player".into()).unwrap();
+    tick_until(&mut server, |s| s.players.iter().any(|(_, p)| p.logged_in));
+    match messages.recv().unwrap().unwrap() {
+        Message::Welcome(welcome) => assert_eq!(welcome.view_radius, server.config.view_radius),
+        msg => panic!("expected a welcome, got {:?}", msg),
+    }
+
+    let spawn = server.spawn;
+    let center = PillarIndex(AxialPoint::from_real(Point2f::new(spawn.x, spawn.y))).chunk();
+    let far = ChunkIndex(center.0 + AxialVector::new(100, 0));
+    conn.send(&Message::RequestChunk(far)).unwrap();
+    let mut refused = false;
+    tick_until(&mut server, |_| {
+        while let Ok(msg) = messages.try_recv() {
+            match msg.unwrap() {
+                Message::ChunkUnavailable(index) => refused = index == far,
+                Message::ChunkData(index, _, _) => panic!("chunk {:?} was sent", index),
+                _ => {}
+            }
+        }
+        refused
+    });
+    assert!(!server.world_manager.get_world().chunks.contains_key(&far));
+}
+
+#[test]
+fn unavailable_chunks_are_reported() {
+    use base::gen::WorldGenerator;
+    use base::world::ChunkProvider;
+    use std::fs;
+
+    // Damage the saved spawn chunk before the server reads the region file
+    let config = test_config();
+    let regions = RegionProvider::open(config.save_dir.join(REGION_DIR)).unwrap();
+    let spawn = PillarIndex(AxialPoint::from_real(Point2f::new(15.0, 10.0))).chunk();
+    let chunk = WorldGenerator::with_seed(42).load_chunk(spawn).unwrap();
+    regions.save_chunk(spawn, &chunk).unwrap();
+    let path = fs::read_dir(regions.dir())
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    let mut data = fs::read(&path).unwrap();
+    let len = data.len();
+    data[len - 3] ^= 0xff;
+    fs::write(&path, &data).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut server = Server::new(listener, config).unwrap();
+    assert_eq!(server.spawn, Point3f::new(15.0, 10.0, 50.0));
+    let conn = connect_player(addr, "player");
+    let messages = conn.spawn_reader("player".into()).unwrap();
+    tick_until(&mut server, |s| s.players.iter().any(|(_, p)| p.logged_in));
+
+    // Every request tries to load the chunk again
+    for _ in 0..2 {
+        conn.send(&Message::RequestChunk(spawn)).unwrap();
+        let mut refused = false;
+        tick_until(&mut server, |_| {
+            while let Ok(msg) = messages.try_recv() {
+                match msg.unwrap() {
+                    Message::ChunkUnavailable(index) => refused = index == spawn,
+                    Message::ChunkData(index, _, _) => panic!("chunk {:?} was sent", index),
+                    _ => {}
+                }
+            }
+            refused
+        });
+        let (_, player) = server.players.iter().next().unwrap();
+        assert!(!player.subscribed_chunks.contains(&spawn));
+        assert!(!server.world_manager.get_world().chunks.contains_key(&spawn));
+    }
+    assert_eq!(fs::read(&path).unwrap(), data);
+}
+
+#[test]
+fn time_speed_changes_are_broadcast() {
+    let (mut server, addr) = test_server();
+    let requester = connect_player(addr, "requester");
+    let watcher = connect_player(addr, "watcher");
+    let messages = watcher.spawn_reader("watcher".into()).unwrap();
+    tick_until(&mut server, |s| {
+        s.players.len() == 2 && s.players.iter().all(|(_, p)| p.logged_in)
+    });
+
+    server.config.admins.push("requester".into());
+    requester
+        .send(&Message::Command(Command::Auth(TEST_ADMIN_PASSWORD.into())))
+        .unwrap();
+
+    // Invalid speeds are ignored
+    let speed = |speed| Message::Command(Command::SetTimeSpeed(speed));
+    requester.send(&speed(-1.0)).unwrap();
+    requester.send(&speed(100.0)).unwrap();
+    tick_until(&mut server, |_| match messages.try_recv() {
+        Ok(Ok(Message::TimeSync(time))) => {
+            assert_eq!(time.speed, 100.0);
+            true
+        }
+        _ => false,
+    });
+}
+
+#[test]
+fn players_get_the_weather_of_their_region() {
+    use base::weather::WeatherModel;
+
+    let (mut server, addr) = test_server();
+    let conn = connect_player(addr, "wet");
+    let messages = conn.spawn_reader("wet".into()).unwrap();
+    tick_until(&mut server, |s| s.players.iter().any(|(_, p)| p.logged_in));
+
+    let spawn = server.spawn;
+    let pos = PillarIndex(AxialPoint::from_real(Point2f::new(spawn.x, spawn.y)));
+    conn.send(&Message::RequestChunk(pos.chunk())).unwrap();
+    let mut weather = None;
+    tick_until(&mut server, |_| {
+        while let Ok(msg) = messages.try_recv() {
+            if let Message::WeatherSync(state) = msg.unwrap() {
+                weather = Some(state);
+            }
+        }
+        weather.is_some()
+    });
+
+    let biome = server
+        .world_manager
+        .get_world()
+        .pillar_at(pos)
+        .unwrap()
+        .biome()
+        .clone();
+    let model = WeatherModel::with_seed(server.config.seed);
+    assert_eq!(weather, Some(model.weather_at(pos, &biome, &server.time)));
+}
+
+#[test]
+fn full_servers_reject_players() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let config = Config {
+        max_players: 1,
+        motd: "be nice".into(),
+        ..test_config()
+    };
+    let mut server = Server::new(listener, config).unwrap();
+
+    let first = connect_player(addr, "first");
+    tick_until(&mut server, |s| s.players.iter().any(|(_, p)| p.logged_in));
+    assert!(matches!(first.recv().unwrap(), Message::Welcome(_)));
+    match first.recv().unwrap() {
+        Message::Chat { text, .. } => assert_eq!(text, "be nice"),
+        msg => panic!("expected the motd, got {:?}", msg),
+    }
+
+    let second = connect_player(addr, "second");
+    let messages = second.spawn_reader("second".into()).unwrap();
+    let mut reason = None;
+    tick_until(&mut server, |_| {
+        if let Ok(Ok(Message::Disconnect { reason: r })) = messages.try_recv() {
+            reason = Some(r);
+        }
+        reason.is_some()
+    });
+    assert_eq!(reason, Some("the server is full".to_string()));
+}
+
+#[test]
+fn only_admins_run_commands() {
+    let (mut server, addr) = test_server();
+    server.config.admins.push("admin".into());
+    let admin = connect_player(addr, "admin");
+    let guest = connect_player(addr, "guest");
+    let admin_messages = admin.spawn_reader("admin".into()).unwrap();
+    let guest_messages = guest.spawn_reader("guest".into()).unwrap();
+    tick_until(&mut server, |s| {
+        s.players.len() == 2 && s.players.iter().all(|(_, p)| p.logged_in)
+    });
+
+    // Returns the next chat message sent by the server to the player
+    let motd = server.config.motd.clone();
+    let reply = |server: &mut Server, messages: &Receiver<io::Result<Message>>| {
+        let mut text = None;
+        tick_until(server, |_| {
+            while let Ok(msg) = messages.try_recv() {
+                if let Message::Chat { text: t, .. } = msg.unwrap() {
+                    if t != motd {
+                        text = Some(t);
+                    }
+                }
+            }
+            text.is_some()
+        });
+        text.unwrap()
+    };
+
+    guest.send(&Message::Command(Command::Stop)).unwrap();
+    assert_eq!(
+        reply(&mut server, &guest_messages),
+        "you are not allowed to run this command"
+    );
+    assert!(!server.stopping);
+
+    // The name of an admin isn't enough, they need the password
+    admin.send(&Message::Command(Command::Stop)).unwrap();
+    assert_eq!(
+        reply(&mut server, &admin_messages),
+        "you are not allowed to run this command"
+    );
+    assert!(!server.stopping);
+    let auth = |password: &str| Message::Command(Command::Auth(password.into()));
+    admin.send(&auth("wrong")).unwrap();
+    assert_eq!(
+        reply(&mut server, &admin_messages),
+        "you are not an admin or the password is wrong"
+    );
+    guest.send(&auth(TEST_ADMIN_PASSWORD)).unwrap();
+    assert_eq!(
+        reply(&mut server, &guest_messages),
+        "you are not an admin or the password is wrong"
+    );
+    admin.send(&auth(TEST_ADMIN_PASSWORD)).unwrap();
+    assert_eq!(
+        reply(&mut server, &admin_messages),
+        "you may run admin commands now"
+    );
+
+    admin.send(&Message::Command(Command::List)).unwrap();
+    assert_eq!(
+        reply(&mut server, &admin_messages),
+        "2 players online: admin, guest"
+    );
+    admin
+        .send(&Message::Command(Command::Kick("guest".into())))
+        .unwrap();
+    assert_eq!(reply(&mut server, &admin_messages), "kicked guest");
+    tick_until(&mut server, |s| s.players.len() == 1);
+
+    // Without a password, nobody becomes an admin
+    server.config.admin_password.clear();
+    let id = server.player_by_name("admin").unwrap();
+    assert_eq!(
+        server.authenticate(id, ""),
+        "admin commands can only be run in the server console"
+    );
+
+    // Commands typed into the console
+    let (console, commands) = channel();
+    server.set_console(commands);
+    console.send(Command::Stop).unwrap();
+    assert!(!server.tick());
+}
+
+#[test]
+fn chat_is_broadcast_and_rate_limited() {
+    let (mut server, addr) = test_server();
+    server.config.motd = String::new();
+    let talker = connect_player(addr, "talker");
+    let listener = connect_player(addr, "listener");
+    let messages = listener.spawn_reader("listener".into()).unwrap();
+    tick_until(&mut server, |s| {
+        s.players.len() == 2 && s.players.iter().all(|(_, p)| p.logged_in)
+    });
+
+    for i in 0..CHAT_BURST + 3 {
+        let msg = Message::Chat {
+            sender: "someone else".into(),
+            text: format!("message {}", i),
+        };
+        talker.send(&msg).unwrap();
+    }
+    let mut received = Vec::new();
+    tick_until(&mut server, |_| {
+        while let Ok(msg) = messages.try_recv() {
+            if let Message::Chat { sender, text } = msg.unwrap() {
+                assert_eq!(sender, "talker");
+                received.push(text);
+            }
+        }
+        received.len() >= CHAT_BURST as usize
+    });
+    // Give the rejected messages a chance to arrive, too
+    for _ in 0..10 {
+        server.tick();
+    }
+    while let Ok(msg) = messages.try_recv() {
+        if let Message::Chat { text, .. } = msg.unwrap() {
+            received.push(text);
+        }
     }
+    assert_eq!(received.len(), CHAT_BURST as usize);
+    assert_eq!(received[0], "message 0");
+}
+
+#[test]
+fn admins_can_teleport() {
+    use base::net::TeleportTarget;
+
+    let (mut server, addr) = test_server();
+    server.config.admins.push("admin".into());
+    let admin = connect_admin(addr, "admin");
+    let _other = connect_player(addr, "other");
+    tick_until(&mut server, |s| {
+        s.players.len() == 2 && s.players.iter().all(|(_, p)| p.logged_in)
+    });
+    let id = |s: &Server, name: &str| s.player_by_name(name).unwrap();
+    let target = Point3f::new(100.0, -20.0, 60.0);
+    let other = id(&server, "other");
+    server.players[other].state.position = target;
+
+    let tp = Command::Teleport(TeleportTarget::Player("other".into()));
+    admin.send(&Message::Command(tp)).unwrap();
+    tick_until(&mut server, |s| {
+        s.players[id(s, "admin")].state.position == target
+    });
+    assert_eq!(server.players[id(&server, "admin")].body.position, target);
+}
+
+#[test]
+fn servers_are_discovered_on_loopback() {
+    use base::net::{discover, query_status};
+
+    let mut servers: Vec<_> = ["first", "second"]
+        .iter()
+        .map(|name| {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let config = Config {
+                address: "127.0.0.1".parse().unwrap(),
+                lan_discovery: true,
+                discovery_port: 0,
+                server_name: name.to_string(),
+                ..test_config()
+            };
+            Server::new(listener, config).unwrap()
+        })
+        .collect();
+    let targets: Vec<_> = servers
+        .iter()
+        .map(|s| s.discovery.as_ref().unwrap().local_addr().unwrap())
+        .collect();
+    let game_addr = SocketAddr::new("127.0.0.1".parse().unwrap(), servers[1].port);
+    let _player = connect_player(game_addr, "player");
+    tick_until(&mut servers[1], |s| {
+        s.players.iter().any(|(_, p)| p.logged_in)
+    });
+
+    // Servers only answer while they tick
+    let query_targets = targets.clone();
+    let query = thread::spawn(move || {
+        let found = discover(&query_targets, Duration::from_millis(500)).unwrap();
+        let single = query_status(query_targets[0], Duration::from_millis(500)).unwrap();
+        (found, single)
+    });
+    while !query.is_finished() {
+        for server in &mut servers {
+            server.tick();
+        }
+        thread::sleep(Duration::from_millis(5));
+    }
+    let (mut found, single) = query.join().unwrap();
+
+    found.sort_by(|a, b| a.status.name.cmp(&b.status.name));
+    assert_eq!(found.len(), 2);
+    assert_eq!(found[0].status.name, "first");
+    assert_eq!(found[0].status.players, 0);
+    assert_eq!(found[1].status.name, "second");
+    assert_eq!(found[1].status.players, 1);
+    assert_eq!(found[1].status.version, PROTOCOL_VERSION);
+    assert_eq!(found[1].addr.port(), servers[1].port);
+    assert_eq!(single, found[0]);
+}
+
+#[test]
+fn status_queries_are_limited_per_tick() {
+    use base::net::decode_status_reply;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let config = Config {
+        address: "127.0.0.1".parse().unwrap(),
+        lan_discovery: true,
+        discovery_port: 0,
+        ..test_config()
+    };
+    let mut server = Server::new(listener, config).unwrap();
+    let target = server.discovery.as_ref().unwrap().local_addr().unwrap();
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+    for _ in 0..MAX_STATUS_QUERIES_PER_TICK + 3 {
+        socket.send_to(b"PLANTEX STATUS?", target).unwrap();
+    }
+    thread::sleep(Duration::from_millis(50));
+
+    let mut buf = [0; 4096];
+    let mut count_replies = || {
+        let mut replies = 0;
+        while let Ok(len) = socket.recv(&mut buf) {
+            assert!(decode_status_reply(&buf[..len]).is_ok());
+            replies += 1;
+        }
+        replies
+    };
+    server.answer_status_queries();
+    assert_eq!(count_replies(), MAX_STATUS_QUERIES_PER_TICK);
+    server.answer_status_queries();
+    assert_eq!(count_replies(), 3);
+}
+
+#[test]
+fn saved_worlds_are_restored() {
+    use base::weather::{Form, Strength};
+
+    let (mut server, addr) = test_server();
+    let _walker = connect_player(addr, "walker");
+    tick_until(&mut server, |s| s.player_by_name("walker").is_some());
+    let id = server.player_by_name("walker").unwrap();
+    let target = Point3f::new(100.0, -20.0, 60.0);
+    server.players[id].state.position = target;
+    let weather = WeatherState {
+        form: Form::Snow,
+        strength: Strength::Weak,
+    };
+    server.run_command(None, Command::SetTime(100.0));
+    server.run_command(None, Command::SetWeather(Some(weather)));
+    server.run_command(None, Command::Save);
+
+    // The seed of the config only applies to new worlds
+    let config = Config {
+        seed: 7,
+        ..server.config.clone()
+    };
+    drop(server);
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let mut server = Server::new(listener, config).unwrap();
+    assert_eq!(server.config.seed, 42);
+    assert_eq!(server.time.time_on_day, 100.0);
+    assert_eq!(server.weather_override, Some(weather));
+
+    let _walker = connect_player(addr, "walker");
+    tick_until(&mut server, |s| s.player_by_name("walker").is_some());
+    let id = server.player_by_name("walker").unwrap();
+    assert_eq!(server.players[id].state.position, target);
+    ::std::fs::remove_dir_all(&server.config.save_dir).unwrap();
+}
+
+#[test]
+fn snapshots_are_only_restored_while_stopped() {
+    let (mut server, _) = test_server();
+    let save_dir = server.config.save_dir.clone();
+    let reply = server.run_command(None, Command::CreateSnapshot("first".into()));
+    assert!(
+        reply.starts_with("created snapshot #1 'first'"),
+        "{}",
+        reply
+    );
+    let reply = server.run_command(None, Command::ListSnapshots);
+    assert!(reply.starts_with("1 snapshots:\n#1 'first'"), "{}", reply);
+
+    let reply = server.run_command(None, Command::RestoreSnapshot(1));
+    assert!(reply.contains("can't be restored"), "{}", reply);
+    let snapshots = Snapshots::new(&save_dir);
+    let err = snapshots.restore(1).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+    drop(server);
+    assert_eq!(snapshots.restore(1).unwrap().name, "first");
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let config = Config {
+        save_dir: save_dir.clone(),
+        ..test_config()
+    };
+    let mut server = Server::new(listener, config).unwrap();
+    let reply = server.run_command(None, Command::PruneSnapshots(0));
+    assert_eq!(reply, "deleted 1 snapshots");
+    drop(server);
+    ::std::fs::remove_dir_all(&save_dir).unwrap();
 }
@@ -0,0 +1,177 @@
+use std::fmt;
+use std::ops::{Index, IndexMut};
+
+/// A stable handle to a value stored in a `Slab`.
+///
+/// Handles stay valid until their value is removed. The slots of removed
+/// values are reused, but every reuse increases the slot's generation, so
+/// that old handles never refer to a new value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SlabId {
+    index: u32,
+    generation: u32,
+}
+
+impl SlabId {
+    /// Packs the handle into a single number, e.g. to send it over the
+    /// network.
+    pub fn to_u64(self) -> u64 {
+        (u64::from(self.generation) << 32) | u64::from(self.index)
+    }
+}
+
+impl fmt::Display for SlabId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{}.{}", self.index, self.generation)
+    }
+}
+
+struct Entry<T> {
+    generation: u32,
+    value: Option<T>,
+}
+
+/// A collection of values addressed by `SlabId`s.
+pub struct Slab<T> {
+    entries: Vec<Entry<T>>,
+    /// Indices of all empty entries.
+    free: Vec<u32>,
+    len: usize,
+}
+
+impl<T> Slab<T> {
+    pub fn new() -> Self {
+        Slab {
+            entries: Vec::new(),
+            free: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Stores the value and returns its handle.
+    pub fn insert(&mut self, value: T) -> SlabId {
+        self.len += 1;
+
+        match self.free.pop() {
+            Some(index) => {
+                let entry = &mut self.entries[index as usize];
+                entry.generation += 1;
+                entry.value = Some(value);
+                SlabId {
+                    index: index,
+                    generation: entry.generation,
+                }
+            }
+            None => {
+                self.entries.push(Entry {
+                    generation: 0,
+                    value: Some(value),
+                });
+                SlabId {
+                    index: (self.entries.len() - 1) as u32,
+                    generation: 0,
+                }
+            }
+        }
+    }
+
+    /// Removes the value with the given handle, if it still exists.
+    pub fn remove(&mut self, id: SlabId) -> Option<T> {
+        let value = match self.entries.get_mut(id.index as usize) {
+            Some(ref mut entry) if entry.generation == id.generation => entry.value.take(),
+            _ => None,
+        };
+
+        if value.is_some() {
+            self.len -= 1;
+            self.free.push(id.index);
+        }
+        value
+    }
+
+    pub fn get(&self, id: SlabId) -> Option<&T> {
+        self.entries
+            .get(id.index as usize)
+            .filter(|entry| entry.generation == id.generation)
+            .and_then(|entry| entry.value.as_ref())
+    }
+
+    pub fn get_mut(&mut self, id: SlabId) -> Option<&mut T> {
+        self.entries
+            .get_mut(id.index as usize)
+            .filter(|entry| entry.generation == id.generation)
+            .and_then(|entry| entry.value.as_mut())
+    }
+
+    /// Returns the handles of all values. Useful to iterate over the slab
+    /// while modifying it.
+    pub fn ids(&self) -> Vec<SlabId> {
+        self.iter().map(|(id, _)| id).collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (SlabId, &T)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| {
+                entry.value.as_ref().map(|value| {
+                    let id = SlabId {
+                        index: index as u32,
+                        generation: entry.generation,
+                    };
+                    (id, value)
+                })
+            })
+    }
+}
+
+impl<T> Index<SlabId> for Slab<T> {
+    type Output = T;
+
+    fn index(&self, id: SlabId) -> &T {
+        self.get(id).expect("invalid slab id")
+    }
+}
+
+impl<T> IndexMut<SlabId> for Slab<T> {
+    fn index_mut(&mut self, id: SlabId) -> &mut T {
+        self.get_mut(id).expect("invalid slab id")
+    }
+}
+
+#[test]
+fn ids_stay_valid() {
+    let mut slab = Slab::new();
+    let a = slab.insert('a');
+    let b = slab.insert('b');
+    let c = slab.insert('c');
+
+    assert_eq!(slab.remove(b), Some('b'));
+    assert_eq!(slab.get(a), Some(&'a'));
+    assert_eq!(slab.get(b), None);
+    assert_eq!(slab.get(c), Some(&'c'));
+    assert_eq!(slab.len(), 2);
+}
+
+#[test]
+fn reused_slots_get_new_ids() {
+    let mut slab = Slab::new();
+    let a = slab.insert(1);
+    slab.remove(a);
+    let b = slab.insert(2);
+
+    assert_ne!(a, b);
+    assert_eq!(slab.get(a), None);
+    assert_eq!(slab.remove(a), None);
+    assert_eq!(slab.get_mut(b), Some(&mut 2));
+    assert_eq!(slab.ids(), vec![b]);
+    assert_eq!(slab.len(), 1);
+}
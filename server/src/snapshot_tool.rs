@@ -0,0 +1,158 @@
+extern crate clap;
+
+use self::clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use base::world::{Snapshots, WorldLock};
+use std::error::Error as StdError;
+use std::path::Path;
+
+/// Runs the `plantex-snapshots` tool with the arguments of this process and
+/// returns what it has to say.
+pub fn run_snapshot_tool() -> Result<String, Box<dyn StdError>> {
+    run(&app().get_matches())
+}
+
+fn app<'a, 'b>() -> App<'a, 'b> {
+    App::new("Plantex Snapshots")
+        .version(env!("CARGO_PKG_VERSION"))
+        .about("Creates, lists, restores and prunes snapshots of a saved Plantex world")
+        .setting(AppSettings::SubcommandRequired)
+        .arg(
+            Arg::with_name("SaveDir")
+                .help("'Directory the world is saved in'")
+                .takes_value(true)
+                .default_value("world")
+                .long("save-dir"),
+        )
+        .subcommand(
+            SubCommand::with_name("create")
+                .about("Takes a snapshot of the world")
+                .arg(Arg::with_name("Name").help("'Name of the snapshot'")),
+        )
+        .subcommand(SubCommand::with_name("list").about("Lists all snapshots"))
+        .subcommand(
+            SubCommand::with_name("restore")
+                .about("Replaces the world with a snapshot, the server has to be stopped")
+                .arg(
+                    Arg::with_name("Id")
+                        .help("'Number of the snapshot'")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("prune")
+                .about("Deletes all but the newest snapshots")
+                .arg(
+                    Arg::with_name("Keep")
+                        .help("'Number of snapshots to keep'")
+                        .required(true),
+                ),
+        )
+}
+
+fn run(matches: &ArgMatches) -> Result<String, Box<dyn StdError>> {
+    let save_dir = Path::new(matches.value_of("SaveDir").unwrap_or("world"));
+    let snapshots = Snapshots::new(save_dir);
+
+    match matches.subcommand() {
+        ("create", Some(sub)) => {
+            // A running server takes snapshots itself, with its console
+            // command
+            let _lock = WorldLock::acquire(save_dir)?;
+            let info = snapshots.create(sub.value_of("Name").unwrap_or(""))?;
+            Ok(format!("created snapshot {}", info))
+        }
+        ("list", _) => {
+            let list = snapshots.list()?;
+            let damaged = snapshots.damaged()?;
+            let mut reply = if list.is_empty() {
+                "there are no snapshots".to_string()
+            } else {
+                let lines: Vec<_> = list.iter().map(|info| info.to_string()).collect();
+                format!("{} snapshots:\n{}", list.len(), lines.join("\n"))
+            };
+            if !damaged.is_empty() {
+                let ids: Vec<_> = damaged.iter().map(|id| id.to_string()).collect();
+                reply += &format!(
+                    "\nthe manifests of snapshots {} are damaged, 'prune' deletes them",
+                    ids.join(", ")
+                );
+            }
+            Ok(reply)
+        }
+        ("restore", Some(sub)) => {
+            let id = match sub.value_of("Id").unwrap_or("").parse::<u32>() {
+                Ok(id) => id,
+                Err(_) => return Err("the id of a snapshot has to be a number".into()),
+            };
+            let info = snapshots.restore(id)?;
+            Ok(format!("restored snapshot {}", info))
+        }
+        ("prune", Some(sub)) => {
+            let keep = match sub.value_of("Keep").unwrap_or("").parse::<usize>() {
+                Ok(keep) => keep,
+                Err(_) => return Err("the number of snapshots to keep has to be a number".into()),
+            };
+            let _lock = WorldLock::acquire(save_dir)?;
+            let damaged = snapshots.damaged()?.len();
+            let pruned = snapshots.prune(keep)?;
+            Ok(format!("deleted {} snapshots", pruned.len() + damaged))
+        }
+        _ => Err("unknown command, see --help".into()),
+    }
+}
+
+#[test]
+fn snapshots_are_managed_from_the_command_line() {
+    use base::world::{Level, LEVEL_FILE, SNAPSHOT_DIR};
+    use std::fs;
+
+    let dir =
+        ::std::env::temp_dir().join(format!("plantex-snapshot-tool-{}", ::std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let level = Level::new(3);
+    level.save(&dir).unwrap();
+    let run_args = |args: &[&str]| {
+        let mut all = vec!["plantex-snapshots", "--save-dir", dir.to_str().unwrap()];
+        all.extend_from_slice(args);
+        run(&app().get_matches_from(all)).map_err(|e| e.to_string())
+    };
+
+    assert_eq!(run_args(&["list"]).unwrap(), "there are no snapshots");
+    let reply = run_args(&["create", "before"]).unwrap();
+    assert!(
+        reply.starts_with("created snapshot #1 'before'"),
+        "{}",
+        reply
+    );
+    run_args(&["create"]).unwrap();
+    let reply = run_args(&["list"]).unwrap();
+    assert!(reply.starts_with("2 snapshots:\n#1 'before'"), "{}", reply);
+
+    Level::new(4).save(&dir).unwrap();
+    run_args(&["restore", "1"]).unwrap();
+    assert_eq!(Level::load(&dir).unwrap(), level);
+    assert!(run_args(&["restore", "7"]).is_err());
+    assert!(run_args(&["restore", "first"]).is_err());
+
+    // Nothing but listing works while a server has the world open
+    let lock = WorldLock::acquire(&dir).unwrap();
+    assert!(run_args(&["list"]).is_ok());
+    assert!(run_args(&["create"]).is_err());
+    assert!(run_args(&["restore", "1"]).is_err());
+    assert!(run_args(&["prune", "0"]).is_err());
+    drop(lock);
+
+    // Damaged manifests are reported and pruned
+    fs::write(dir.join(SNAPSHOT_DIR).join("1.snapshot"), b"garbage").unwrap();
+    let reply = run_args(&["list"]).unwrap();
+    assert!(reply.starts_with("1 snapshots:\n#2"), "{}", reply);
+    assert!(
+        reply.ends_with("snapshots 1 are damaged, 'prune' deletes them"),
+        "{}",
+        reply
+    );
+    assert_eq!(run_args(&["prune", "1"]).unwrap(), "deleted 1 snapshots");
+    assert_eq!(run_args(&["list"]).unwrap().lines().count(), 2);
+    assert!(dir.join(LEVEL_FILE).exists());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,161 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// If the server falls behind by more than this many ticks, the missed ticks
+/// are skipped instead of being run back to back.
+const MAX_CATCH_UP_TICKS: u32 = 10;
+
+/// Schedules server ticks at a fixed rate.
+///
+/// Ticks start at multiples of the tick length. When a tick takes too long,
+/// the following ticks start immediately until the server caught up again.
+pub struct TickTimer {
+    tick_length: Duration,
+    /// When the next tick should start.
+    next_tick: Instant,
+    /// Number of the current tick.
+    tick: u64,
+    stats: TickStats,
+}
+
+/// Statistics about the ticks run since the last call to
+/// `TickTimer::take_stats()`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TickStats {
+    /// Number of ticks run.
+    pub ticks: u64,
+    /// Time spent in all ticks combined.
+    pub total_duration: Duration,
+    /// Duration of the longest tick.
+    pub max_duration: Duration,
+    /// Number of ticks which took longer than the tick length.
+    pub overruns: u64,
+    /// Number of ticks skipped because the server was too far behind.
+    pub skipped: u64,
+}
+
+impl TickStats {
+    /// Returns the average duration of a tick.
+    pub fn average_duration(&self) -> Duration {
+        if self.ticks == 0 {
+            Duration::from_secs(0)
+        } else {
+            self.total_duration / self.ticks as u32
+        }
+    }
+}
+
+impl TickTimer {
+    /// Creates a timer running `tick_rate` ticks per second. The first tick
+    /// is due immediately.
+    pub fn new(tick_rate: u32) -> Self {
+        assert!(tick_rate > 0, "tick rate has to be positive");
+
+        TickTimer {
+            tick_length: Duration::from_secs(1) / tick_rate,
+            next_tick: Instant::now(),
+            tick: 0,
+            stats: TickStats::default(),
+        }
+    }
+
+    pub fn tick_length(&self) -> Duration {
+        self.tick_length
+    }
+
+    /// Returns the number of the current tick.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Blocks until the next tick is due and starts it. Returns the number
+    /// of ticks skipped before it.
+    pub fn wait_for_tick(&mut self) -> u64 {
+        let now = Instant::now();
+        if self.next_tick > now {
+            thread::sleep(self.next_tick - now);
+        }
+        self.start_tick(Instant::now())
+    }
+
+    /// Starts the next tick. Skips ticks if the server is too far behind and
+    /// returns how many.
+    fn start_tick(&mut self, now: Instant) -> u64 {
+        let lag = if now > self.next_tick {
+            now - self.next_tick
+        } else {
+            Duration::from_secs(0)
+        };
+
+        let mut skipped = 0;
+        if lag > self.tick_length * MAX_CATCH_UP_TICKS {
+            skipped = (lag.as_nanos() / self.tick_length.as_nanos()) as u64;
+            warn!("server is {:?} behind, skipping {} ticks", lag, skipped);
+
+            self.stats.skipped += skipped;
+            self.tick += skipped;
+            self.next_tick = now;
+        }
+
+        self.tick += 1;
+        self.next_tick += self.tick_length;
+        skipped
+    }
+
+    /// Records how long the current tick took.
+    pub fn end_tick(&mut self, duration: Duration) {
+        self.stats.ticks += 1;
+        self.stats.total_duration += duration;
+        if duration > self.stats.max_duration {
+            self.stats.max_duration = duration;
+        }
+        if duration > self.tick_length {
+            self.stats.overruns += 1;
+        }
+    }
+
+    /// Returns the statistics collected since the last call and resets them.
+    pub fn take_stats(&mut self) -> TickStats {
+        ::std::mem::replace(&mut self.stats, TickStats::default())
+    }
+}
+
+#[test]
+fn late_ticks_are_caught_up() {
+    let mut timer = TickTimer::new(10);
+    let start = timer.next_tick;
+
+    // Two ticks late: the next ticks are due immediately
+    assert_eq!(timer.start_tick(start + Duration::from_millis(200)), 0);
+    assert_eq!(timer.tick(), 1);
+    assert!(timer.next_tick < start + Duration::from_millis(200));
+    assert_eq!(timer.take_stats().skipped, 0);
+}
+
+#[test]
+fn ticks_are_skipped_when_too_far_behind() {
+    let mut timer = TickTimer::new(10);
+    let start = timer.next_tick;
+    let now = start + Duration::from_millis(5050);
+
+    assert_eq!(timer.start_tick(now), 50);
+    assert_eq!(timer.tick(), 51);
+    assert_eq!(timer.next_tick, now + Duration::from_millis(100));
+    assert_eq!(timer.take_stats().skipped, 50);
+}
+
+#[test]
+fn tick_stats() {
+    let mut timer = TickTimer::new(10);
+    timer.end_tick(Duration::from_millis(20));
+    timer.end_tick(Duration::from_millis(150));
+    timer.end_tick(Duration::from_millis(10));
+
+    let stats = timer.take_stats();
+    assert_eq!(stats.ticks, 3);
+    assert_eq!(stats.overruns, 1);
+    assert_eq!(stats.max_duration, Duration::from_millis(150));
+    assert_eq!(stats.average_duration(), Duration::from_millis(60));
+
+    assert_eq!(timer.take_stats(), TickStats::default());
+}
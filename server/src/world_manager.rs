@@ -0,0 +1,388 @@
+use base::gen::WorldGenerator;
+use base::math::*;
+use base::world::CHUNK_RANGE_MARGIN;
+use base::world::{Chunk, ChunkIndex, ChunkProvider, EditError, PillarEdit, World};
+use base::world::{FallbackProvider, RegionProvider};
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::thread;
+
+/// Work for the worker thread. Jobs are done in order, so a chunk which is
+/// loaded again right after it was unloaded includes the saved changes.
+enum Job {
+    Load(ChunkIndex),
+    Save(ChunkIndex, Chunk),
+    /// Answered once all earlier jobs are done, with the first error since
+    /// the last flush.
+    Flush(Sender<Result<(), String>>),
+}
+
+/// Owns the authoritative world of the server.
+///
+/// Players subscribe to the chunks they need. A chunk stays loaded as long as
+/// at least one player is subscribed to it, so overlapping players share the
+/// same chunk. Chunks that aren't loaded yet are read from the region files
+/// or generated in a worker thread. Changed chunks are written back by the
+/// same thread when they are unloaded or `save` is called.
+pub struct WorldManager {
+    world: World,
+    jobs: Sender<Job>,
+    generated_chunks: Receiver<(ChunkIndex, Option<Chunk>)>,
+    /// Chunks which were sent to the worker thread, but not received yet.
+    pending_chunks: HashSet<ChunkIndex>,
+    /// Number of subscribed players for every loaded or pending chunk.
+    subscribers: HashMap<ChunkIndex, usize>,
+    /// Number of edits applied to every chunk. Versions are kept when
+    /// chunks are unloaded, so that they only ever increase.
+    versions: HashMap<ChunkIndex, u32>,
+    /// Loaded chunks which were edited since they were loaded or saved.
+    dirty: HashSet<ChunkIndex>,
+    /// Chunks which couldn't be loaded, see `take_unavailable`.
+    unavailable: Vec<ChunkIndex>,
+    /// Players can only subscribe to chunks within this many chunk lengths.
+    view_radius: f32,
+}
+
+impl WorldManager {
+    /// Creates a world manager loading and saving chunks in the given region
+    /// files. Chunks which were never saved are generated from the seed.
+    pub fn new(seed: u64, view_radius: f32, regions: RegionProvider) -> Self {
+        let (job_sender, job_recv) = channel();
+        let (chunk_sender, chunk_recv) = channel();
+
+        // The worker thread quits once the world manager and thus the job
+        // sender is dropped, after finishing the remaining jobs.
+        thread::Builder::new()
+            .name("Chunk loader".to_string())
+            .spawn(move || {
+                let provider = FallbackProvider::new(regions, WorldGenerator::with_seed(seed));
+                let mut failure = None;
+                for job in job_recv {
+                    match job {
+                        Job::Load(index) => {
+                            // Saves have to be done even if nobody waits for
+                            // chunks anymore
+                            let _ = chunk_sender.send((index, provider.load_chunk(index)));
+                        }
+                        Job::Save(index, chunk) => {
+                            if let Err(e) = provider.primary().save_chunk(index, &chunk) {
+                                error!("failed to save chunk {:?}: {}", index, e);
+                                if failure.is_none() {
+                                    failure = Some(format!("chunk {:?}: {}", index, e));
+                                }
+                            }
+                        }
+                        Job::Flush(reply) => {
+                            let _ = reply.send(failure.take().map_or(Ok(()), Err));
+                        }
+                    }
+                }
+            })
+            .unwrap();
+
+        WorldManager {
+            world: World::empty(),
+            jobs: job_sender,
+            generated_chunks: chunk_recv,
+            pending_chunks: HashSet::new(),
+            subscribers: HashMap::new(),
+            versions: HashMap::new(),
+            dirty: HashSet::new(),
+            unavailable: Vec::new(),
+            view_radius: view_radius,
+        }
+    }
+
+    pub fn get_world(&self) -> &World {
+        &self.world
+    }
+
+    /// Returns the number of edits applied to the given chunk so far.
+    pub fn version(&self, index: ChunkIndex) -> u32 {
+        self.versions.get(&index).cloned().unwrap_or(0)
+    }
+
+    /// Applies the edit to the world and returns the new version of the
+    /// changed chunk.
+    pub fn apply_edit(&mut self, edit: &PillarEdit) -> Result<u32, EditError> {
+        edit.apply(&mut self.world)?;
+        self.dirty.insert(edit.chunk());
+        let version = self.versions.entry(edit.chunk()).or_insert(0);
+        *version += 1;
+        Ok(*version)
+    }
+
+    /// Returns whether a player at `pos` is close enough to the given chunk to
+    /// subscribe to it. Clients ask for the chunks within the view radius of
+    /// a position they had a moment ago, so a margin is added.
+    pub fn is_in_range(&self, pos: Point3f, index: ChunkIndex) -> bool {
+        index.is_in_range(
+            Point2f::new(pos.x, pos.y),
+            self.view_radius + CHUNK_RANGE_MARGIN,
+        )
+    }
+
+    /// Adds a subscriber to the given chunk and starts generating it if it's
+    /// not loaded yet.
+    pub fn subscribe(&mut self, index: ChunkIndex) {
+        *self.subscribers.entry(index).or_insert(0) += 1;
+
+        if self.world.chunks.contains_key(&index) || !self.pending_chunks.insert(index) {
+            return;
+        }
+        if self.jobs.send(Job::Load(index)).is_err() {
+            error!("chunk loader thread stopped");
+        }
+    }
+
+    /// Removes a subscriber from the given chunk. The chunk is unloaded once
+    /// nobody is subscribed anymore, and saved if it was changed.
+    pub fn unsubscribe(&mut self, index: ChunkIndex) {
+        let remaining = match self.subscribers.get_mut(&index) {
+            Some(count) => {
+                *count -= 1;
+                *count
+            }
+            None => {
+                warn!("unsubscribed from chunk {:?} nobody subscribed to", index);
+                return;
+            }
+        };
+
+        if remaining == 0 {
+            self.subscribers.remove(&index);
+            if let Some(chunk) = self.world.remove_chunk(index) {
+                debug!("unloaded chunk {:?}", index);
+                if self.dirty.remove(&index) {
+                    self.send_job(Job::Save(index, chunk));
+                }
+            }
+        }
+    }
+
+    /// Returns the number of loaded chunks which were changed since they
+    /// were loaded or saved.
+    pub fn dirty_chunks(&self) -> usize {
+        self.dirty.len()
+    }
+
+    /// Starts saving all changed chunks in the worker thread and returns
+    /// their number. Use `flush` to wait until they are written.
+    pub fn save(&mut self) -> usize {
+        let dirty: Vec<_> = self.dirty.drain().collect();
+        for &index in &dirty {
+            if let Some(chunk) = self.world.chunks.get(&index) {
+                self.send_job(Job::Save(index, chunk.clone()));
+            }
+        }
+        dirty.len()
+    }
+
+    /// Waits until all chunks saved so far are written. Returns the first
+    /// error since the last flush.
+    pub fn flush(&self) -> Result<(), String> {
+        let (sender, recv) = channel();
+        self.send_job(Job::Flush(sender));
+        recv.recv()
+            .unwrap_or_else(|_| Err("chunk loader thread stopped".to_string()))
+    }
+
+    fn send_job(&self, job: Job) {
+        if self.jobs.send(job).is_err() {
+            error!("chunk loader thread stopped");
+        }
+    }
+
+    /// Returns the chunks which couldn't be loaded since the last call. All
+    /// subscriptions to them were dropped, the subscribers have to be told.
+    pub fn take_unavailable(&mut self) -> Vec<ChunkIndex> {
+        ::std::mem::replace(&mut self.unavailable, Vec::new())
+    }
+
+    /// Adds all chunks generated since the last call to the world and returns
+    /// their positions.
+    ///
+    /// Chunks nobody is subscribed to anymore are discarded.
+    pub fn update_world(&mut self) -> Vec<ChunkIndex> {
+        let mut loaded = Vec::new();
+        loop {
+            let (index, chunk) = match self.generated_chunks.try_recv() {
+                Ok(val) => val,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    error!("chunk loader thread stopped");
+                    break;
+                }
+            };
+            self.pending_chunks.remove(&index);
+
+            match chunk {
+                Some(chunk) => {
+                    if self.subscribers.contains_key(&index) {
+                        self.world.replace_chunk(index, chunk);
+                        loaded.push(index);
+                    }
+                }
+                // A damaged chunk is left out, so that it can't be
+                // overwritten. Subscribing again loads it again.
+                None => {
+                    error!("chunk {:?} can't be loaded, leaving it out", index);
+                    if self.subscribers.remove(&index).is_some() {
+                        self.unavailable.push(index);
+                    }
+                }
+            }
+        }
+
+        if !loaded.is_empty() {
+            debug!("{} chunks loaded", self.world.chunks.len());
+        }
+        loaded
+    }
+}
+
+/// Opens region files in an empty temporary directory.
+#[cfg(test)]
+fn test_regions(name: &str) -> RegionProvider {
+    let dir =
+        ::std::env::temp_dir().join(format!("plantex-server-{}-{}", name, ::std::process::id()));
+    let _ = ::std::fs::remove_dir_all(&dir);
+    RegionProvider::open(dir).unwrap()
+}
+
+/// Waits until the worker thread loaded at least one chunk.
+#[cfg(test)]
+fn wait_for_chunks(world_manager: &mut WorldManager) {
+    use std::time::{Duration, Instant};
+
+    let timeout = Instant::now() + Duration::from_secs(10);
+    while world_manager.update_world().is_empty() {
+        assert!(Instant::now() < timeout, "chunk was not loaded in time");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn chunks_are_shared_between_subscribers() {
+    let mut world_manager = WorldManager::new(42, 1.0, test_regions("shared"));
+    let index = ChunkIndex(AxialPoint::new(0, 0));
+    world_manager.subscribe(index);
+    world_manager.subscribe(index);
+    wait_for_chunks(&mut world_manager);
+
+    world_manager.unsubscribe(index);
+    assert!(world_manager.get_world().chunk_at(index).is_some());
+    world_manager.unsubscribe(index);
+    assert!(world_manager.get_world().chunk_at(index).is_none());
+}
+
+#[test]
+fn view_radius_is_respected() {
+    use base::world::{chunks_in_range, CHUNK_SIZE};
+
+    let world_manager = WorldManager::new(42, 2.0, test_regions("range"));
+    let pos = Point3f::new(0.0, 0.0, 10.0);
+    assert!(world_manager.is_in_range(pos, ChunkIndex(AxialPoint::new(0, 0))));
+    assert!(world_manager.is_in_range(pos, ChunkIndex(AxialPoint::new(-1, 0))));
+    assert!(!world_manager.is_in_range(pos, ChunkIndex(AxialPoint::new(5, 5))));
+
+    // Chunks a client at a slightly older position wants are in range
+    let old = Point2f::new(0.0, 0.0);
+    let now = Point3f::new(CHUNK_SIZE as f32 / 2.0, 0.0, 10.0);
+    for index in chunks_in_range(old, 2.0) {
+        assert!(world_manager.is_in_range(now, index));
+    }
+}
+
+#[test]
+fn changed_chunks_are_saved() {
+    use base::world::{EditKind, GroundMaterial, PillarIndex};
+
+    let regions = test_regions("save");
+    let dir = regions.dir().to_path_buf();
+    let mut world_manager = WorldManager::new(42, 1.0, regions);
+    let index = ChunkIndex(AxialPoint::new(0, 0));
+    let untouched = ChunkIndex(AxialPoint::new(1, 0));
+    world_manager.subscribe(index);
+    world_manager.subscribe(untouched);
+    wait_for_chunks(&mut world_manager);
+    while world_manager.get_world().chunks.len() < 2 {
+        wait_for_chunks(&mut world_manager);
+    }
+
+    let pos = PillarIndex(AxialPoint::new(3, 4));
+    let add_step = |world_manager: &mut WorldManager| {
+        let world = world_manager.get_world();
+        let top = world.pillar_at(pos).unwrap().sections().last().unwrap().top;
+        let edit = PillarEdit {
+            pos: pos,
+            height: top,
+            kind: EditKind::Add(GroundMaterial::Stone),
+        };
+        world_manager.apply_edit(&edit).unwrap();
+        world_manager.get_world().pillar_at(pos).unwrap().clone()
+    };
+    let edited = add_step(&mut world_manager);
+    assert_eq!(world_manager.dirty_chunks(), 1);
+
+    // Unloading saves the changed chunk, loading it again reads it back
+    world_manager.unsubscribe(index);
+    world_manager.unsubscribe(untouched);
+    assert_eq!(world_manager.dirty_chunks(), 0);
+    world_manager.subscribe(index);
+    wait_for_chunks(&mut world_manager);
+    assert_eq!(world_manager.get_world().pillar_at(pos), Some(&edited));
+
+    // Saving writes the chunk without unloading it
+    let edited = add_step(&mut world_manager);
+    assert_eq!(world_manager.save(), 1);
+    assert_eq!(world_manager.save(), 0);
+    assert_eq!(world_manager.flush(), Ok(()));
+    assert!(world_manager.get_world().chunk_at(index).is_some());
+
+    let regions = RegionProvider::open(&dir).unwrap();
+    assert!(!regions.contains(untouched).unwrap());
+    let chunk = regions.read_chunk(index).unwrap().unwrap();
+    assert_eq!(chunk, world_manager.get_world().chunks[&index]);
+    assert_eq!(world_manager.get_world().pillar_at(pos), Some(&edited));
+    ::std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn damaged_chunks_are_not_replaced() {
+    use base::world::{EditKind, PillarIndex};
+    use std::fs;
+
+    let regions = test_regions("damaged");
+    let dir = regions.dir().to_path_buf();
+    let index = ChunkIndex(AxialPoint::new(0, 0));
+    let chunk = WorldGenerator::with_seed(42).load_chunk(index).unwrap();
+    regions.save_chunk(index, &chunk).unwrap();
+    let path = fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+    let mut data = fs::read(&path).unwrap();
+    let len = data.len();
+    data[len - 3] ^= 0xff;
+    fs::write(&path, &data).unwrap();
+
+    // The damaged chunk is loaded before the other one, but not generated
+    let mut world_manager = WorldManager::new(42, 1.0, RegionProvider::open(&dir).unwrap());
+    let other = ChunkIndex(AxialPoint::new(1, 0));
+    world_manager.subscribe(index);
+    world_manager.subscribe(other);
+    wait_for_chunks(&mut world_manager);
+    assert!(world_manager.get_world().chunk_at(index).is_none());
+    assert!(world_manager.get_world().chunk_at(other).is_some());
+    assert_eq!(world_manager.take_unavailable(), vec![index]);
+    assert_eq!(world_manager.take_unavailable(), vec![]);
+
+    // So it can't be edited, and isn't saved
+    let edit = PillarEdit {
+        pos: PillarIndex(AxialPoint::new(3, 4)),
+        height: Default::default(),
+        kind: EditKind::Remove,
+    };
+    assert!(world_manager.apply_edit(&edit).is_err());
+    world_manager.save();
+    assert_eq!(world_manager.flush(), Ok(()));
+    assert_eq!(fs::read(&path).unwrap(), data);
+}
@@ -0,0 +1,42 @@
+//! Lets a few bots play on a local server.
+
+extern crate bots;
+extern crate server;
+
+use bots::BotConfig;
+use std::time::Duration;
+use std::{env, fs, process};
+
+#[test]
+fn bots_play_on_a_local_server() {
+    let save_dir = env::temp_dir().join(format!("plantex-bots-test-{}", process::id()));
+    let local_server = server::start_local_server(server::Config {
+        lan_discovery: false,
+        // Bots request all chunks the server sends them
+        view_radius: 2.0,
+        save_dir: save_dir.clone(),
+        ..server::Config::default()
+    })
+    .unwrap();
+    let addr = local_server.addr();
+    let config = BotConfig {
+        server: addr,
+        bots: 3,
+        duration: Duration::from_secs(3),
+        edit_interval: Some(Duration::from_millis(500)),
+        ..BotConfig::default()
+    };
+    let report = bots::run_bots(&config);
+
+    assert_eq!(report.logged_in(), 3, "{}", report);
+    assert!(report.disconnects().is_empty(), "{}", report);
+    assert!(report.chunks_received() > 0, "{}", report);
+    assert!(report.move_latency().is_some(), "{}", report);
+    assert!(report.bots.iter().all(|b| b.edits_sent > 0), "{}", report);
+
+    // The edited chunks are saved when the server stops
+    local_server.stop();
+    let regions = fs::read_dir(save_dir.join("regions")).unwrap().count();
+    assert!(regions > 0);
+    fs::remove_dir_all(&save_dir).unwrap();
+}